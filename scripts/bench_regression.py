@@ -0,0 +1,95 @@
+#!/usr/bin/env python3
+"""Guards against performance regressions, using the results of `cargo bench`.
+
+Usage:
+    cargo bench --workspace
+    scripts/bench_regression.py save    # record the results as the baseline
+    scripts/bench_regression.py check   # fail if any benchmark got slower
+
+The baseline is stored in `benches/baseline.json`, at the root of the workspace, as the
+mean time, in nanoseconds, of each benchmark. A benchmark fails the check if its mean
+time exceeds the baseline's by more than `--threshold` (20% by default). Benchmarks
+missing from either side are reported, but don't fail it.
+"""
+
+import argparse
+import json
+import pathlib
+import sys
+
+ROOT = pathlib.Path(__file__).resolve().parent.parent
+CRITERION_DIR = ROOT / "target" / "criterion"
+BASELINE = ROOT / "benches" / "baseline.json"
+
+
+def latest_results():
+    """Returns the mean time of each benchmark from the latest `cargo bench` run."""
+    results = {}
+
+    for bench in CRITERION_DIR.glob("**/new/benchmark.json"):
+        full_id = json.loads(bench.read_text())["full_id"]
+        estimates = json.loads((bench.parent / "estimates.json").read_text())
+        results[full_id] = estimates["mean"]["point_estimate"]
+
+    if not results:
+        sys.exit(f"no benchmark results found in {CRITERION_DIR}, run `cargo bench` first")
+
+    return results
+
+
+def save(_args):
+    results = latest_results()
+    BASELINE.parent.mkdir(exist_ok=True)
+    BASELINE.write_text(json.dumps(results, indent=4, sort_keys=True) + "\n")
+    print(f"saved {len(results)} benchmarks to {BASELINE.relative_to(ROOT)}")
+
+
+def check(args):
+    if not BASELINE.exists():
+        sys.exit(f"no baseline found at {BASELINE.relative_to(ROOT)}, run `save` first")
+
+    baseline = json.loads(BASELINE.read_text())
+    results = latest_results()
+    n_regressions = 0
+
+    for full_id in sorted(baseline.keys() | results.keys()):
+        if full_id not in results:
+            print(f"missing    {full_id}")
+            continue
+        if full_id not in baseline:
+            print(f"new        {full_id}")
+            continue
+
+        ratio = results[full_id] / baseline[full_id]
+        regressed = ratio > 1 + args.threshold
+        n_regressions += regressed
+
+        status = "REGRESSED" if regressed else "ok"
+        print(f"{status:<10} {full_id}: {(ratio - 1) * 100:+.1f}%")
+
+    if n_regressions:
+        sys.exit(f"{n_regressions} benchmark(s) regressed by more than {args.threshold:.0%}")
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument(
+        "--threshold",
+        type=float,
+        default=0.2,
+        help="maximum allowed slowdown, as a fraction of the baseline (default: 0.2)",
+    )
+    commands = parser.add_subparsers(required=True)
+    commands.add_parser("save", help="record the latest results as the baseline").set_defaults(
+        run=save
+    )
+    commands.add_parser("check", help="compare the latest results to the baseline").set_defaults(
+        run=check
+    )
+
+    args = parser.parse_args()
+    args.run(args)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,238 @@
+//! Single entry point to the Syfala protocol's stable API.
+//!
+//! This crate only re-exports the stable items of the other crates of this repository,
+//! under coherent names:
+//!
+//! - [`proto`]: the protocol's message model, and stream formats.
+//! - [`net`]: sending and receiving protocol messages over network sockets, along with
+//!   the typestate-based client implementation.
+//! - [`audio`]: the audio pipeline, converting samples to and from bytes, and passing
+//!   them between threads, through lock-free queues.
+//!
+//! Lower level, or internal, items (raw message encoding and decoding, traffic replay,
+//! queue chunk helpers...) are only re-exported in the `legacy` module, with the
+//! `legacy` feature enabled, they may change without notice.
+//!
+//! Backends (JACK, CoreAudio) are applications built on top of these, and aren't
+//! re-exported.
+//!
+//! ## Features
+//!
+//! - `legacy`: re-exports low-level and internal items in the `legacy` module.
+//! - `wav`: re-exports WAVE file sources and sinks in [`audio`].
+//! - `eventfd`: re-exports the Linux `eventfd` based waker in [`audio::queue`].
+//!
+//! ## Quick start
+//!
+//! A client application typically:
+//!
+//! 1. Binds a [`ClientSocket`](net::client::ClientSocket), and periodically sends
+//!    discovery messages.
+//! 2. Implements the generic client's context, and IO state contexts, accepting
+//!    connections, and requesting IO to start when it's ready.
+//! 3. Drives a [`GenericClient`](net::client::generic::GenericClient) with the
+//!    socket's receive loop, receiving audio in the active IO state's `on_audio`
+//!    callback, usually forwarding it to the audio thread through an
+//!    [`IndexedTx`](audio::queue::IndexedTx).
+//!
+//! The following client connects to the first server it discovers, starts IO as soon as
+//! it is connected, and collects the audio it receives into a `Vec`:
+//!
+//! ```no_run
+//! use std::net::{SocketAddr, UdpSocket};
+//! use syfala::{
+//!     net::client::{
+//!         Client as _, ClientSocket,
+//!         generic::{
+//!             ClientContext, GenericClient, IOActiveContext, IOInactiveContext,
+//!             IOStartPendingContext, IOStopPendingContext,
+//!         },
+//!     },
+//!     proto::{
+//!         AudioMessageHeader,
+//!         format::StreamFormats,
+//!         message::{Error, StreamSelection},
+//!     },
+//! };
+//!
+//! #[derive(Default)]
+//! struct Context {
+//!     received: Vec<u8>,
+//! }
+//!
+//! struct Connection;
+//!
+//! impl ClientContext for Context {
+//!     type IOInactive = Connection;
+//!
+//!     fn connect(
+//!         &mut self,
+//!         _addr: SocketAddr,
+//!         _formats: StreamFormats,
+//!     ) -> Result<Connection, Error> {
+//!         Ok(Connection)
+//!     }
+//!
+//!     fn unknown_message(&mut self, _addr: SocketAddr) {}
+//! }
+//!
+//! impl IOInactiveContext for Connection {
+//!     type Context = Context;
+//!     type IOStartPending = Self;
+//!
+//!     // ready as soon as connected
+//!     fn poll_start_io(self, _cx: &mut Context) -> Result<Self, Self> {
+//!         Ok(self)
+//!     }
+//! }
+//!
+//! impl IOStartPendingContext for Connection {
+//!     type Context = Context;
+//!     type IOActive = Self;
+//!
+//!     fn start_io(self, _cx: &mut Context, _selection: StreamSelection) -> Self {
+//!         self
+//!     }
+//!
+//!     fn start_io_refused(self, _cx: &mut Context) -> Self {
+//!         self
+//!     }
+//!
+//!     fn start_io_failed(&mut self, _cx: &mut Context) {}
+//! }
+//!
+//! impl IOActiveContext for Connection {
+//!     type Context = Context;
+//!     type IOStopPending = Self;
+//!
+//!     fn on_audio(
+//!         &mut self,
+//!         cx: &mut Context,
+//!         _timestamp: std::time::Instant,
+//!         _header: AudioMessageHeader,
+//!         data: &[u8],
+//!     ) {
+//!         cx.received.extend_from_slice(data);
+//!     }
+//!
+//!     fn poll_stop_io(self, _cx: &mut Context) -> Result<Self, Self> {
+//!         Err(self)
+//!     }
+//! }
+//!
+//! impl IOStopPendingContext for Connection {
+//!     type Context = Context;
+//!
+//!     fn stop_io(self, _cx: &mut Context) -> Self {
+//!         self
+//!     }
+//!
+//!     fn stop_io_refused(self, _cx: &mut Context) -> Self {
+//!         self
+//!     }
+//!
+//!     fn stop_io_failed(&mut self, _cx: &mut Context) {}
+//! }
+//!
+//! let sock = UdpSocket::bind("0.0.0.0:0")?;
+//! sock.set_broadcast(true)?;
+//! let sock = ClientSocket::new(sock);
+//!
+//! std::thread::scope(|s| {
+//!     s.spawn(|| {
+//!         let period = std::time::Duration::from_secs(1);
+//!         sock.start_discovery_beacon(period, "255.255.255.255:6910".parse().unwrap())
+//!     });
+//!
+//!     GenericClient::new(Context::default()).start(&sock)
+//! })?;
+//! # Ok::<_, std::io::Error>(())
+//! ```
+
+/// The protocol's message model, and stream formats.
+pub mod proto {
+    pub use syfala_proto::{AudioMessageHeader, AudioStreamMessageHeader, format, message};
+}
+
+/// Sending and receiving protocol messages over network sockets.
+pub mod net {
+    pub use syfala_network::{
+        DestinationError, MAX_UDP_PAYLOAD_SIZE, SendError, SyncUdpSock,
+        audio::{AudioStreamSender, StreamDemux},
+    };
+
+    /// Client side of the protocol.
+    pub mod client {
+        pub use syfala_network::udp::client::{AudioWriter, Client, ClientSocket};
+
+        /// Typestate-based client, handling connections and IO state changes of any
+        /// number of servers.
+        pub mod generic {
+            #[allow(deprecated)]
+            pub use syfala_network::udp::client::generic::IOStopPendingConxtext;
+            pub use syfala_network::udp::client::generic::{
+                Active, AudioRejection, ClientContext, Clock, ConnectRateLimit,
+                ConnectedServerHandle, GenericClient, IOActiveContext, IOInactiveContext,
+                IOStartPendingContext, IOStateKind, IOStopPendingContext, Inactive, MockClock,
+                RequestLatency, SendError, SendStats, StartPending, StopPending, StreamGate,
+                SystemClock,
+            };
+        }
+    }
+
+    /// Server side of the protocol.
+    pub mod server {
+        pub use syfala_network::udp::server::{ServerSocket, ServerState, new_epoch};
+    }
+
+    /// Observation of the messages going through sockets.
+    pub mod observer {
+        pub use syfala_network::observer::{CountingObserver, MessageKind, WireObserver};
+    }
+}
+
+/// The audio pipeline: converting samples to and from bytes, and passing them between
+/// threads.
+pub mod audio {
+    pub use syfala_utils::{
+        AudioPacketConsumer, AudioPacketFramePadder, AudioPacketProducer, AudioPacketSamplePadder,
+        ByteStreamFramer, ConvertingSink, ConvertingSource, Dither, DriftCompensator,
+        FramePadderIter, IndexedAudioByteStreamSender, IndexedAudioSampleStreamReceiver,
+        InfeasibleLatency, JitterBuffer, JitterBufferStats, LatencyBudget, LatencyPlan,
+        PadderStats, RateEstimator, SampleByteStream, SampleByteStreamIter, SampleConvert,
+        SampleFromBytes, SampleSink, SampleSize, SampleSource, SampleStreamFramer, SampleToBytes,
+        SampleTypeSilence, StartPolicy, convert_sample,
+    };
+    #[cfg(feature = "wav")]
+    pub use syfala_utils::{WavFileSink, WavFileSource, WavSample};
+
+    /// Lock-free queues passing samples between threads, indexed so that lost or late
+    /// samples are accounted for.
+    pub mod queue {
+        #[cfg(all(target_os = "linux", feature = "eventfd"))]
+        pub use syfala_utils::queue::EventFdWaker;
+        pub use syfala_utils::queue::{
+            CondvarWaker, Counter, DriftDirection, DriftError, FractionalPeriodicCounter,
+            FramedByteRx, FramedByteTx, FramedPushError, GenericCounter, IndexedQueueStats,
+            IndexedRx, IndexedTx, PeriodicCounter, RecvCount, WakeStrategy, Waker,
+            framed_byte_queue, rtrb,
+        };
+    }
+}
+
+/// Low-level and internal items, outside of the stable API.
+#[cfg(feature = "legacy")]
+pub mod legacy {
+    pub use syfala_network::{
+        AUDIO_MESSAGE_HEADER_SIZE, AUDIO_STREAM_MESSAGE_HEADER_SIZE, FormatsRef, ServerMessageRef,
+        StreamFormatsRef, client_message_decode, client_message_encode,
+        client_message_encode_uninit, postcard, replay, server_message_decode,
+        server_message_decode_ref, server_message_encode, server_message_encode_uninit,
+    };
+    pub use syfala_proto::serde;
+    pub use syfala_utils::{
+        UninitCursor,
+        queue::{ChunkReadGuard, ChunkWriteGuard, consumer_get_all, producer_get_all, shift_iter},
+        samples_from_bytes,
+    };
+}
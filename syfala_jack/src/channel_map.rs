@@ -0,0 +1,165 @@
+//! Selection and reordering of a stream's channels.
+
+use crate::utils;
+
+use core::{fmt, num};
+
+/// Maps the channels of a network stream to JACK ports.
+///
+/// The `i`-th port carries the stream's channel [`sources()[i]`](Self::sources)
+/// (0-based). Channels can be left out, reordered, or duplicated.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChannelMap {
+    sources: Box<[u32]>,
+}
+
+impl ChannelMap {
+    /// Creates a new channel map, from the source channel of each port.
+    ///
+    /// Returns `None` if `sources` is empty.
+    pub fn new(sources: impl IntoIterator<Item = u32>) -> Option<Self> {
+        let sources: Box<[u32]> = sources.into_iter().collect();
+
+        (!sources.is_empty()).then_some(Self { sources })
+    }
+
+    /// Returns the map assigning each of `n_channels` channels to its own port, in order.
+    pub fn identity(n_channels: num::NonZeroU32) -> Self {
+        Self {
+            sources: (0..n_channels.get()).collect(),
+        }
+    }
+
+    /// Returns the source channel of each port.
+    #[inline(always)]
+    pub fn sources(&self) -> &[u32] {
+        &self.sources
+    }
+
+    /// Returns the number of ports.
+    #[inline(always)]
+    pub fn n_ports(&self) -> num::NonZeroUsize {
+        num::NonZeroUsize::new(self.sources.len()).unwrap()
+    }
+
+    /// Checks that every source channel exists in a stream of `n_channels` channels.
+    pub fn validate(&self, n_channels: num::NonZeroU32) -> Result<(), ChannelMapError> {
+        match self
+            .sources
+            .iter()
+            .position(|&channel| channel >= n_channels.get())
+        {
+            Some(port) => Err(ChannelMapError {
+                port,
+                channel: self.sources[port],
+                n_channels,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Error returned when a [`ChannelMap`] refers to a channel a stream doesn't have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChannelMapError {
+    /// The first port with an invalid source channel.
+    pub port: usize,
+    /// Its source channel.
+    pub channel: u32,
+    /// The number of channels of the stream.
+    pub n_channels: num::NonZeroU32,
+}
+
+impl fmt::Display for ChannelMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "port {} is mapped to channel {}, but the stream only has {} channels",
+            self.port, self.channel, self.n_channels,
+        )
+    }
+}
+
+impl core::error::Error for ChannelMapError {}
+
+/// [`SampleSink`](utils::SampleSink) adapter applying a [`ChannelMap`] to interleaved
+/// frames, before forwarding them to the underlying sink.
+///
+/// Channels left out by the map are never forwarded. Samples are buffered until their
+/// whole frame is received, the stream is thus expected to start at a frame boundary
+/// (which is the case with an [`AudioPacketFramePadder`](utils::AudioPacketFramePadder)).
+#[derive(Debug, Clone)]
+pub struct ChannelMappingSink<S, T> {
+    sink: S,
+    map: ChannelMap,
+    /// The frame being received.
+    frame: Box<[T]>,
+    /// Number of samples of the frame received so far.
+    frame_pos: usize,
+}
+
+impl<S, T: Copy + Default> ChannelMappingSink<S, T> {
+    /// Wraps `sink`, receiving frames of `n_channels` channels, and forwarding the
+    /// channels selected by `map`.
+    ///
+    /// Returns an error if `map` refers to channels outside of `0..n_channels`.
+    pub fn new(
+        sink: S,
+        map: ChannelMap,
+        n_channels: num::NonZeroU32,
+    ) -> Result<Self, ChannelMapError> {
+        map.validate(n_channels)?;
+
+        Ok(Self {
+            sink,
+            map,
+            frame: core::iter::repeat_n(T::default(), n_channels.get().try_into().unwrap())
+                .collect(),
+            frame_pos: 0,
+        })
+    }
+}
+
+impl<S, T> ChannelMappingSink<S, T> {
+    /// Returns the channel map.
+    #[inline(always)]
+    pub fn map(&self) -> &ChannelMap {
+        &self.map
+    }
+
+    /// Returns a reference to the underlying sink.
+    #[inline(always)]
+    pub fn inner(&self) -> &S {
+        &self.sink
+    }
+
+    /// Returns a mutable reference to the underlying sink.
+    #[inline(always)]
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+}
+
+impl<S: utils::SampleSink<Sample = T>, T: Copy> utils::SampleSink for ChannelMappingSink<S, T> {
+    type Sample = T;
+
+    fn consume_samples(&mut self, spls: impl IntoIterator<Item = Self::Sample>) {
+        for sample in spls {
+            self.frame[self.frame_pos] = sample;
+            self.frame_pos += 1;
+
+            if self.frame_pos == self.frame.len() {
+                self.frame_pos = 0;
+
+                // source channels have been validated upon creation
+                let frame = &self.frame;
+                self.sink.consume_samples(
+                    self.map
+                        .sources
+                        .iter()
+                        .map(|&channel| frame[usize::try_from(channel).unwrap()]),
+                );
+            }
+        }
+    }
+}
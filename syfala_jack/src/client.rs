@@ -0,0 +1,1180 @@
+//! Application layer of the generic UDP client, exposing servers' audio through JACK.
+//!
+//! Implements [`ClientContext`] and its IO typestates, to be driven by a
+//! [`GenericClient`](network::udp::client::generic::GenericClient). Each connected
+//! server gets its own JACK client, with one output port per (mapped) channel of each
+//! of the server's input streams, or, in mixing mode, a slot of a [`JackMixer`] shared
+//! by all servers. IO is requested as soon as any of these ports is connected, and
+//! stopped once all of them are disconnected.
+
+use crate::{
+    AutoConnectSummary, ChannelMap, ChannelMappingSink, ClientNames, DriftRecoveries,
+    DuplexProcessHandler, EventNotifier, JACK_SAMPLE_TYPE, JackConfig, JackEvent, JackRx,
+    JackSample, LatencyProbe, LatencyRegistration, LatencyReporter, MixerHandle,
+    MixingProcessHandler, Monitor, PeerReport, PortPattern, QueueStatsProbe, ResyncRequest,
+    StreamReport, XrunCounter, network, utils,
+};
+
+use network::{
+    audio::StreamDemux,
+    proto::{
+        AudioMessageHeader,
+        format::StreamFormats,
+        message::{Error, StreamSelection},
+    },
+    udp::client::generic::{
+        ClientContext, IOActiveContext, IOInactiveContext, IOStartPendingContext,
+        IOStopPendingContext,
+    },
+};
+use std::{cell::RefCell, fmt, mem, net, num, rc::Rc, sync::Arc, time};
+use utils::{
+    AudioPacketFramePadder, IndexedAudioByteStreamSender, JitterBuffer, LatencyPlan,
+    queue::{GenericCounter, IndexedRx, rtrb},
+};
+
+/// Converts incoming audio packets of one stream into samples of the mapped channels,
+/// pushed into its queue.
+type StreamSender = IndexedAudioByteStreamSender<
+    ChannelMappingSink<rtrb::Producer<JackSample>, JackSample>,
+    AudioPacketFramePadder<JackSample>,
+>;
+
+/// Process handler of a server's JACK client.
+type ServerProcessHandler = DuplexProcessHandler<GenericCounter, GenericCounter>;
+
+/// Process handler of the JACK client shared by all servers, in mixing mode.
+type MixerProcessHandler = MixingProcessHandler<GenericCounter>;
+
+/// Capacity of the queues of a server's JACK client's configuration changes.
+const EVENT_QUEUE_LEN: usize = 16;
+
+/// Jitter buffers placed in front of each stream's sender, reordering packets.
+#[derive(Debug)]
+struct JitterStage {
+    buffers: Box<[JitterBuffer<time::Instant>]>,
+    /// How long packets are held, waiting for the ones before them.
+    hold_time: time::Duration,
+}
+
+impl JitterStage {
+    /// Creates one jitter buffer per stream, sized from `plan`.
+    fn new(n_streams: usize, plan: &LatencyPlan) -> Self {
+        Self {
+            buffers: (0..n_streams)
+                .map(|_| {
+                    JitterBuffer::new(
+                        plan.jitter_buffer_capacity.get(),
+                        plan.jitter_buffer_packet_len.get(),
+                    )
+                })
+                .collect(),
+            hold_time: plan.hold_time,
+        }
+    }
+
+    /// Passes the payload of an audio message, received at `timestamp`, to the jitter
+    /// buffer of its stream, or, if it's out of range or unselected, to `senders`, which
+    /// ignore it.
+    fn on_audio(
+        &mut self,
+        senders: &mut StreamDemux<StreamSender>,
+        timestamp: time::Instant,
+        header: AudioMessageHeader,
+        data: &[u8],
+    ) {
+        self.poll(senders, timestamp);
+
+        let stream_idx = usize::try_from(header.stream_idx).ok();
+
+        let stream = stream_idx
+            .filter(|_| senders.selection().contains(header.stream_idx))
+            .and_then(|idx| {
+                Some((
+                    self.buffers.get_mut(idx)?,
+                    senders.consumers_mut().get_mut(idx)?,
+                ))
+            });
+
+        match stream {
+            Some((buffer, sender)) => buffer.push(
+                header.stream_msg.byte_idx,
+                data,
+                timestamp + self.hold_time,
+                sender,
+            ),
+            None => {
+                senders.on_audio(header, data);
+            }
+        }
+    }
+
+    /// Releases the packets whose hold time elapsed by `now`.
+    fn poll(&mut self, senders: &mut StreamDemux<StreamSender>, now: time::Instant) {
+        for (buffer, sender) in self.buffers.iter_mut().zip(senders.consumers_mut()) {
+            buffer.poll(now, sender);
+        }
+    }
+
+    /// Releases all held packets, and forgets the expected positions, e.g. when IO
+    /// restarts.
+    fn flush(&mut self, senders: &mut StreamDemux<StreamSender>) {
+        for (buffer, sender) in self.buffers.iter_mut().zip(senders.consumers_mut()) {
+            buffer.flush(sender);
+        }
+    }
+}
+
+/// Error returned when a [`LatencyPlan`] can't be honoured by a [`JackClientContext`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UnsupportedLatencyPlan {
+    /// The plan's prefill, in frames.
+    pub prefill_frames: num::NonZeroUsize,
+    /// The plan's queue capacity, in frames.
+    pub ring_frames: num::NonZeroUsize,
+}
+
+impl fmt::Display for UnsupportedLatencyPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "a prefill of {} frames doesn't fit in queues of {} frames",
+            self.prefill_frames, self.ring_frames,
+        )
+    }
+}
+
+impl core::error::Error for UnsupportedLatencyPlan {}
+
+/// Notification handler of a server's JACK client, counting xruns and forwarding sample
+/// rate changes.
+struct ServerNotifications {
+    xruns: XrunCounter,
+    events: EventNotifier,
+}
+
+impl jack::NotificationHandler for ServerNotifications {
+    fn xrun(&mut self, client: &jack::Client) -> jack::Control {
+        jack::NotificationHandler::xrun(&mut self.xruns, client)
+    }
+
+    fn sample_rate(&mut self, client: &jack::Client, srate: jack::Frames) -> jack::Control {
+        jack::NotificationHandler::sample_rate(&mut self.events, client, srate)
+    }
+}
+
+/// A JACK client name, released when dropped.
+#[derive(Debug)]
+struct ClaimedName {
+    name: String,
+    names: Rc<RefCell<ClientNames>>,
+}
+
+impl Drop for ClaimedName {
+    fn drop(&mut self) {
+        self.names.borrow_mut().release(&self.name);
+    }
+}
+
+/// An active JACK client, closed when dropped, along with the registration of the
+/// reporter answering its latency requests, released once the client is closed.
+struct ServerClient {
+    client: mem::ManuallyDrop<jack::AsyncClient<ServerNotifications, ServerProcessHandler>>,
+    latency: mem::ManuallyDrop<LatencyRegistration>,
+}
+
+impl Drop for ServerClient {
+    fn drop(&mut self) {
+        // SAFETY: neither field is used after this
+        let (client, latency) = unsafe {
+            (
+                mem::ManuallyDrop::take(&mut self.client),
+                mem::ManuallyDrop::take(&mut self.latency),
+            )
+        };
+
+        // deactivates, and closes, the client
+        drop(client);
+
+        // SAFETY: the client is closed
+        unsafe { latency.release() };
+    }
+}
+
+/// A JACK client summing the audio of all servers into one set of output ports, see
+/// [`JackClientContext::with_mixer`].
+///
+/// Each server occupies one of its slots, and must have a single input stream, with as
+/// many (mapped) channels as the mixer has ports. The latency of its ports isn't
+/// reported, as it differs from one server to the next.
+pub struct JackMixer {
+    client: jack::AsyncClient<ServerNotifications, MixerProcessHandler>,
+    handle: MixerHandle<GenericCounter>,
+    /// Full names of the client's ports.
+    port_names: Box<[String]>,
+    drift_recoveries: DriftRecoveries,
+    xruns: XrunCounter,
+    /// Sample rate changes of the JACK server, reported by the notification handler.
+    rate_events: rtrb::Consumer<JackEvent>,
+    /// Configuration of the JACK server, the servers' streams must be at its sample rate
+    /// when the mixer was created.
+    config: JackConfig,
+}
+
+impl JackMixer {
+    /// Creates, and activates, a JACK client named `name`, with `n_ports` output ports,
+    /// mixing up to `n_slots` servers.
+    pub fn new(name: &str, n_ports: num::NonZeroU32, n_slots: usize) -> Result<Self, jack::Error> {
+        let (client, _status) = jack::Client::new(name, jack::ClientOptions::NO_START_SERVER)?;
+
+        let ports = (1..=n_ports.get())
+            .map(|port| client.register_port(&format!("out_{port}"), jack::AudioOut::default()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let port_names = ports.iter().filter_map(|port| port.name().ok()).collect();
+
+        // can't fail, there is at least one port
+        let (handler, handle) = MixerProcessHandler::new(ports, n_slots).unwrap();
+        let drift_recoveries = handler.drift_recoveries();
+
+        let (rate_events_tx, rate_events) = rtrb::RingBuffer::new(EVENT_QUEUE_LEN);
+        let xruns = XrunCounter::default();
+
+        let notifications = ServerNotifications {
+            xruns: xruns.clone(),
+            events: EventNotifier::new(rate_events_tx),
+        };
+
+        let sample_rate = client.sample_rate();
+
+        // sample rates always fit in a u32
+        let config = JackConfig::new(
+            sample_rate,
+            sample_rate.try_into().unwrap(),
+            client.buffer_size(),
+        );
+
+        let client = client.activate_async(notifications, handler)?;
+
+        Ok(Self {
+            client,
+            handle,
+            port_names,
+            drift_recoveries,
+            xruns,
+            rate_events,
+            config,
+        })
+    }
+
+    /// Returns the name of the JACK client.
+    #[inline(always)]
+    pub fn name(&self) -> &str {
+        self.client.as_client().name()
+    }
+
+    /// Returns the full names of the JACK client's ports.
+    #[inline(always)]
+    pub fn port_names(&self) -> &[String] {
+        &self.port_names
+    }
+
+    /// Returns the number of servers currently mixed.
+    #[inline(always)]
+    pub fn n_servers(&self) -> usize {
+        self.handle.n_peers()
+    }
+
+    /// Connects the JACK client's ports to the ports matching `pattern`, see
+    /// [`auto_connect`](crate::auto_connect).
+    #[inline(always)]
+    pub fn auto_connect(&self, pattern: &PortPattern) -> AutoConnectSummary {
+        crate::auto_connect(&self.client, &self.port_names, pattern)
+    }
+
+    /// Processes the JACK server's sample rate changes, and returns whether it still is
+    /// the streams'.
+    fn poll_events(&mut self) -> bool {
+        self.config.apply_queued(&mut self.rate_events);
+        self.config.rate_matches()
+    }
+}
+
+impl fmt::Debug for JackMixer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JackMixer")
+            .field("name", &self.name())
+            .field("port_names", &self.port_names)
+            .field("n_servers", &self.n_servers())
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A server's slot in a [`JackMixer`], freed when dropped.
+struct MixedServer {
+    mixer: Rc<RefCell<JackMixer>>,
+    slot: usize,
+    /// The mixer's name.
+    name: String,
+}
+
+impl Drop for MixedServer {
+    fn drop(&mut self) {
+        if !self.mixer.borrow_mut().handle.remove_peer(self.slot) {
+            log::warn!("{}: couldn't free slot {}", self.name, self.slot);
+        }
+    }
+}
+
+/// Where a server's audio goes.
+enum ServerOutput {
+    /// The server's own JACK client.
+    Client {
+        client: ServerClient,
+        name: ClaimedName,
+        /// Configuration changes of the JACK server, reported by the process and the
+        /// notification handlers.
+        events: [rtrb::Consumer<JackEvent>; 2],
+        /// Configuration of the JACK server, the streams' sample rate is that of the
+        /// JACK server when the server connected.
+        config: JackConfig,
+    },
+    /// A slot of the shared mixer.
+    Mixer(MixedServer),
+}
+
+/// [`ClientContext`] exposing the audio of connected servers through JACK.
+///
+/// Only servers without output streams are accepted for now, i.e. audio only flows
+/// from servers to JACK. Their input streams must carry [`JACK_SAMPLE_TYPE`] samples, at
+/// the JACK server's sample rate.
+///
+/// By default, each channel gets its own port. A [`ChannelMap`] can be set per stream
+/// to only bring up ports for some channels, servers whose streams don't have the mapped
+/// channels are then refused.
+///
+/// The ports of each server report the latency added by their queues, see
+/// [`LatencyReporter`].
+///
+/// Stream formats can't change during a connection, so if the JACK server's sample rate
+/// changes, IO is stopped, and only started again once the rate is back to the streams'.
+/// If its period size changes, the server's paths are resynchronized.
+///
+/// Optionally, a [`Monitor`] periodically reports each server's statistics, and each
+/// server's ports are connected to other clients' ports as soon as it connects.
+///
+/// Servers can also all be mixed into the ports of a single JACK client, see
+/// [`with_mixer`](Self::with_mixer).
+///
+/// Buffering can also be planned from a latency target, see
+/// [`from_latency_plan`](Self::from_latency_plan).
+#[derive(Debug)]
+pub struct JackClientContext {
+    names: Rc<RefCell<ClientNames>>,
+    queue_frames: num::NonZeroUsize,
+    /// Plan of the buffering stages, if any.
+    plan: Option<LatencyPlan>,
+    /// Channel map of each input stream, by index, `None` for the identity map.
+    channel_maps: Vec<Option<ChannelMap>>,
+    monitor: Option<Monitor>,
+    /// Ports each server's ports are connected to, if any.
+    auto_connect: Option<PortPattern>,
+    /// The JACK client all servers are mixed into, if any.
+    mixer: Option<Rc<RefCell<JackMixer>>>,
+}
+
+impl JackClientContext {
+    /// Creates a new context, allocating queues of `queue_frames` frames for each
+    /// stream.
+    #[inline(always)]
+    pub fn new(queue_frames: num::NonZeroUsize) -> Self {
+        Self {
+            names: Rc::default(),
+            queue_frames,
+            plan: None,
+            channel_maps: Vec::new(),
+            monitor: None,
+            auto_connect: None,
+            mixer: None,
+        }
+    }
+
+    /// Creates a new context, buffering each stream as planned in `plan`, e.g. from a
+    /// [`LatencyBudget`](utils::LatencyBudget):
+    ///
+    /// - Queues of [`ring_frames`](LatencyPlan::ring_frames) frames are allocated.
+    /// - Playback only starts once [`prefill_frames`](LatencyPlan::prefill_frames) frames
+    ///   are queued, see [`JackRx::with_prefill`].
+    /// - If the plan has a jitter allowance, packets go through a [`JitterBuffer`] sized
+    ///   from it, before being queued.
+    ///
+    /// The [`flush_max_age`](LatencyPlan::flush_max_age) is the servers' to apply, as
+    /// they are the ones sending audio.
+    ///
+    /// Returns an error if the prefill doesn't fit in the queues.
+    pub fn from_latency_plan(plan: &LatencyPlan) -> Result<Self, UnsupportedLatencyPlan> {
+        if plan.prefill_frames > plan.ring_frames {
+            return Err(UnsupportedLatencyPlan {
+                prefill_frames: plan.prefill_frames,
+                ring_frames: plan.ring_frames,
+            });
+        }
+
+        Ok(Self {
+            plan: Some(*plan),
+            ..Self::new(plan.ring_frames)
+        })
+    }
+
+    /// Returns the plan of the buffering stages, if the context was created from one.
+    #[inline(always)]
+    pub fn latency_plan(&self) -> Option<&LatencyPlan> {
+        self.plan.as_ref()
+    }
+
+    /// Connects the ports of each server, as soon as it connects, to the ports matching
+    /// `pattern`, see [`auto_connect`](crate::auto_connect), e.g.
+    /// [`PortPattern::system_playback`].
+    ///
+    /// Servers' audio only flows to JACK, so only [`Playback`](crate::PortDirection)
+    /// patterns can be connected to. IO then starts right away.
+    #[inline(always)]
+    pub fn with_auto_connect(mut self, pattern: PortPattern) -> Self {
+        self.auto_connect = Some(pattern);
+        self
+    }
+
+    /// Mixes all servers into the ports of `mixer`, instead of giving each its own JACK
+    /// client.
+    ///
+    /// Servers are refused once all of the mixer's slots are taken. The mixer's ports
+    /// aren't connected automatically, see [`JackMixer::auto_connect`].
+    #[inline(always)]
+    pub fn with_mixer(mut self, mixer: JackMixer) -> Self {
+        self.mixer = Some(Rc::new(RefCell::new(mixer)));
+        self
+    }
+
+    /// Reports each server's statistics through `monitor`.
+    #[inline(always)]
+    pub fn with_monitor(mut self, monitor: Monitor) -> Self {
+        self.monitor = Some(monitor);
+        self
+    }
+
+    /// Delivers a report of `server`, if a monitor is set, and its period has elapsed
+    /// since the previous one.
+    fn poll_monitor(&mut self, server: &mut JackServer) {
+        let Some(monitor) = &mut self.monitor else {
+            return;
+        };
+
+        let now = time::Instant::now();
+        let interval = now.saturating_duration_since(server.last_report);
+
+        if interval < monitor.period() {
+            return;
+        }
+
+        server.last_report = now;
+        monitor.deliver(server.report(interval));
+    }
+
+    /// Only exposes the channels of the input stream `stream_idx` selected by `map`.
+    pub fn with_channel_map(mut self, stream_idx: usize, map: ChannelMap) -> Self {
+        if self.channel_maps.len() <= stream_idx {
+            self.channel_maps.resize(stream_idx.strict_add(1), None);
+        }
+
+        self.channel_maps[stream_idx] = Some(map);
+        self
+    }
+
+    /// Returns the channel map of the input stream `stream_idx`, if any was set.
+    #[inline(always)]
+    pub fn channel_map(&self, stream_idx: usize) -> Option<&ChannelMap> {
+        self.channel_maps.get(stream_idx).and_then(Option::as_ref)
+    }
+
+    /// Returns the sender of a stream of `n_channels` channels, mapped by `map`, and the
+    /// queue it pushes the mapped channels' samples into.
+    fn stream_queue(
+        &self,
+        map: ChannelMap,
+        n_channels: num::NonZeroU32,
+    ) -> (StreamSender, IndexedRx<GenericCounter, JackSample>) {
+        // only mapped channels go through the queue
+        let queue_len = self.queue_frames.get().strict_mul(map.n_ports().get());
+        let (tx, rx) = rtrb::RingBuffer::new(queue_len);
+
+        let padder = AudioPacketFramePadder::new(n_channels.try_into().unwrap());
+        // can't fail, the map has been validated by the caller
+        let sink = ChannelMappingSink::new(tx, map, n_channels).unwrap();
+
+        (
+            StreamSender::new(sink, padder),
+            IndexedRx::new(rx, GenericCounter::new()),
+        )
+    }
+
+    /// Returns the number of frames to queue before playback starts.
+    #[inline(always)]
+    fn prefill_frames(&self) -> usize {
+        self.plan.map_or(0, |plan| plan.prefill_frames.get())
+    }
+
+    /// Returns the jitter buffers of `n_streams` streams, if planned.
+    fn jitter_stage(&self, n_streams: usize) -> Option<JitterStage> {
+        // without a jitter allowance, packets would never be held
+        self.plan
+            .filter(|plan| !plan.hold_time.is_zero())
+            .map(|plan| JitterStage::new(n_streams, &plan))
+    }
+
+    /// Exposes a server's audio through its own JACK client.
+    fn connect_client(
+        &self,
+        addr: net::SocketAddr,
+        stream_formats: &StreamFormats,
+    ) -> Result<JackServer, Error> {
+        let name = ClaimedName {
+            name: self.names.borrow_mut().claim(addr),
+            names: Rc::clone(&self.names),
+        };
+
+        let (client, _status) = jack::Client::new(&name.name, jack::ClientOptions::NO_START_SERVER)
+            .map_err(|_| Error::Failure(()))?;
+
+        let sample_rate = client.sample_rate();
+
+        // usize to f64 conversions are exact for any realistic sample rate
+        if stream_formats
+            .inputs
+            .iter()
+            .any(|format| *format.sample_rate.get() != sample_rate as f64)
+        {
+            return Err(Error::Refusal(()));
+        }
+
+        let mut port_names = Vec::new();
+        let mut senders = Vec::with_capacity(stream_formats.inputs.len());
+        let mut rxs = Vec::with_capacity(stream_formats.inputs.len());
+        let mut fill_levels = Vec::with_capacity(stream_formats.inputs.len());
+        let mut queue_stats = Vec::with_capacity(stream_formats.inputs.len());
+        let mut latency_reporter = LatencyReporter::new();
+
+        for (stream_idx, format) in stream_formats.inputs.iter().enumerate() {
+            let n_channels = format.channel_count.0;
+
+            let map = self
+                .channel_map(stream_idx)
+                .cloned()
+                .unwrap_or_else(|| ChannelMap::identity(n_channels));
+
+            // validate before registering any port
+            map.validate(n_channels).map_err(|_| Error::Refusal(()))?;
+
+            let ports = map
+                .sources()
+                .iter()
+                .map(|&channel| {
+                    client.register_port(
+                        &format!("stream{stream_idx}_{}", channel.strict_add(1)),
+                        jack::AudioOut::default(),
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| Error::Failure(()))?;
+
+            port_names.extend(ports.iter().filter_map(|port| port.name().ok()));
+
+            let (sender, rx) = self.stream_queue(map, n_channels);
+
+            senders.push(sender);
+            // maps are never empty
+            let rx = JackRx::new(ports, rx)
+                .unwrap()
+                .with_prefill(self.prefill_frames());
+            fill_levels.push(rx.latency_probe());
+            queue_stats.push(rx.queue_stats_probe());
+            latency_reporter = latency_reporter.with_rx(&rx);
+            rxs.push(rx);
+        }
+
+        // must be registered before activation
+        let latency = Arc::new(latency_reporter)
+            .register(&client)
+            .map_err(|_| Error::Failure(()))?;
+
+        // one sender per input stream
+        let mut senders = senders.into_iter();
+        let senders = StreamDemux::new(stream_formats, |_, _| senders.next().unwrap());
+
+        // JACK reports period size changes to the process handler, and sample rate
+        // changes to the notification handler, each forwards them over its own queue
+        let (size_events_tx, size_events) = rtrb::RingBuffer::new(EVENT_QUEUE_LEN);
+        let (rate_events_tx, rate_events) = rtrb::RingBuffer::new(EVENT_QUEUE_LEN);
+
+        let handler = ServerProcessHandler::new([], rxs).with_events(size_events_tx);
+        let drift_recoveries = handler.drift_recoveries();
+        let resync = handler.resync_request();
+        let xruns = XrunCounter::default();
+
+        let notifications = ServerNotifications {
+            xruns: xruns.clone(),
+            events: EventNotifier::new(rate_events_tx),
+        };
+
+        // sample rates always fit in a u32
+        let config = JackConfig::new(
+            sample_rate,
+            sample_rate.try_into().unwrap(),
+            client.buffer_size(),
+        );
+
+        // the latency registration is leaked if activation fails, as the client may not
+        // have been closed
+        let client = client
+            .activate_async(notifications, handler)
+            .map_err(|_| Error::Failure(()))?;
+
+        let auto_connected = self
+            .auto_connect
+            .as_ref()
+            .map(|pattern| crate::auto_connect(&client, &port_names, pattern));
+
+        if let Some(plan) = &self.plan {
+            log::info!("{}: started, latency plan: {plan}", name.name);
+        }
+
+        Ok(JackServer {
+            addr,
+            output: ServerOutput::Client {
+                client: ServerClient {
+                    client: mem::ManuallyDrop::new(client),
+                    latency: mem::ManuallyDrop::new(latency),
+                },
+                name,
+                events: [size_events, rate_events],
+                config,
+            },
+            senders,
+            jitter: self.jitter_stage(stream_formats.inputs.len()),
+            port_names: port_names.into(),
+            fill_levels: fill_levels.into(),
+            queue_stats: queue_stats.into(),
+            drift_recoveries,
+            resync,
+            xruns,
+            auto_connected,
+            last_report: time::Instant::now(),
+        })
+    }
+
+    /// Mixes a server's audio into the ports of `mixer`.
+    fn connect_mixed(
+        &self,
+        addr: net::SocketAddr,
+        stream_formats: &StreamFormats,
+        mixer: &Rc<RefCell<JackMixer>>,
+    ) -> Result<JackServer, Error> {
+        // each server is mixed as a single set of channels
+        let [format] = &*stream_formats.inputs else {
+            return Err(Error::Refusal(()));
+        };
+
+        let mut shared = mixer.borrow_mut();
+
+        // usize to f64 conversions are exact for any realistic sample rate
+        if *format.sample_rate.get() != shared.config.sample_rate() as f64 {
+            return Err(Error::Refusal(()));
+        }
+
+        let n_channels = format.channel_count.0;
+
+        let map = self
+            .channel_map(0)
+            .cloned()
+            .unwrap_or_else(|| ChannelMap::identity(n_channels));
+
+        map.validate(n_channels).map_err(|_| Error::Refusal(()))?;
+
+        if usize::try_from(shared.handle.n_ports().get()) != Ok(map.n_ports().get()) {
+            return Err(Error::Refusal(()));
+        }
+
+        let (sender, rx) = self.stream_queue(map, n_channels);
+
+        // all slots are taken
+        let slot = shared
+            .handle
+            .add_peer(rx, self.prefill_frames())
+            .map_err(|_| Error::Refusal(()))?;
+
+        let mut sender = Some(sender);
+        let senders = StreamDemux::new(stream_formats, |_, _| sender.take().unwrap());
+
+        if let Some(plan) = &self.plan {
+            log::info!("{addr}: mixed into {}, latency plan: {plan}", shared.name());
+        }
+
+        Ok(JackServer {
+            addr,
+            output: ServerOutput::Mixer(MixedServer {
+                mixer: Rc::clone(mixer),
+                slot: slot.idx,
+                name: shared.name().into(),
+            }),
+            senders,
+            jitter: self.jitter_stage(1),
+            port_names: shared.port_names.clone(),
+            fill_levels: Box::new([slot.fill_level]),
+            queue_stats: Box::new([slot.queue_stats]),
+            drift_recoveries: shared.drift_recoveries.clone(),
+            resync: slot.resync,
+            xruns: shared.xruns.clone(),
+            auto_connected: None,
+            last_report: time::Instant::now(),
+        })
+    }
+}
+
+impl ClientContext for JackClientContext {
+    type IOInactive = JackInactive;
+
+    fn connect(
+        &mut self,
+        addr: net::SocketAddr,
+        stream_formats: StreamFormats,
+    ) -> Result<Self::IOInactive, Error> {
+        if !stream_formats.outputs.is_empty() {
+            return Err(Error::Refusal(()));
+        }
+
+        if stream_formats
+            .inputs
+            .iter()
+            .any(|format| format.sample_type != JACK_SAMPLE_TYPE)
+        {
+            return Err(Error::Refusal(()));
+        }
+
+        let server = match &self.mixer {
+            Some(mixer) => self.connect_mixed(addr, &stream_formats, mixer)?,
+            None => self.connect_client(addr, &stream_formats)?,
+        };
+
+        Ok(JackInactive(server))
+    }
+
+    fn unknown_message(&mut self, _addr: net::SocketAddr) {}
+}
+
+/// A connected server, and the JACK client exposing its audio.
+pub struct JackServer {
+    addr: net::SocketAddr,
+    output: ServerOutput,
+    /// One sender per input stream of the server, along with the streams IO was started
+    /// with.
+    senders: StreamDemux<StreamSender>,
+    /// Jitter buffers in front of the senders, if planned.
+    jitter: Option<JitterStage>,
+    /// Full names of the JACK client's ports.
+    port_names: Box<[String]>,
+    /// Fill level of each input stream's queue.
+    fill_levels: Box<[LatencyProbe]>,
+    /// Statistics of each input stream's queue.
+    queue_stats: Box<[QueueStatsProbe]>,
+    drift_recoveries: DriftRecoveries,
+    /// The client is activated, and runs, before IO starts, the process handler must
+    /// thus be resynchronized when it does, so that the first samples received aren't
+    /// deemed late.
+    resync: ResyncRequest,
+    xruns: XrunCounter,
+    /// Outcome of the automatic connection of the JACK client's ports, if enabled.
+    auto_connected: Option<AutoConnectSummary>,
+    /// When the previous report was made (or when the server connected).
+    last_report: time::Instant,
+}
+
+impl JackServer {
+    /// Returns the name of the JACK client, shared by all servers in mixing mode.
+    #[inline(always)]
+    pub fn name(&self) -> &str {
+        match &self.output {
+            ServerOutput::Client { name, .. } => &name.name,
+            ServerOutput::Mixer(mixed) => &mixed.name,
+        }
+    }
+
+    /// Returns the full names of the JACK client's ports.
+    #[inline(always)]
+    pub fn port_names(&self) -> &[String] {
+        &self.port_names
+    }
+
+    /// Returns the server's address.
+    #[inline(always)]
+    pub fn addr(&self) -> net::SocketAddr {
+        self.addr
+    }
+
+    /// Returns the outcome of the automatic connection of the JACK client's ports, made
+    /// when the server connected, if enabled, see
+    /// [`JackClientContext::with_auto_connect`].
+    ///
+    /// Always `None` in mixing mode.
+    #[inline(always)]
+    pub fn auto_connected(&self) -> Option<AutoConnectSummary> {
+        self.auto_connected
+    }
+
+    /// Returns a report of the statistics gathered over the last `interval`, and resets
+    /// the padders' and queues' counters.
+    fn report(&mut self, interval: time::Duration) -> PeerReport {
+        let streams = self
+            .senders
+            .consumers_mut()
+            .iter_mut()
+            .zip(&self.fill_levels)
+            .zip(&self.queue_stats)
+            .map(|((sender, fill_level), queue)| {
+                let padder = sender.stats();
+                sender.reset_stats();
+
+                StreamReport {
+                    padder,
+                    queue: queue.take(),
+                    buffered_frames: fill_level.buffered_frames(),
+                }
+            })
+            .collect();
+
+        PeerReport {
+            addr: self.addr,
+            client_name: self.name().into(),
+            interval,
+            streams,
+            drift_recoveries: self.drift_recoveries.get(),
+            xruns: self.xruns.get(),
+        }
+    }
+
+    /// Processes the JACK server's configuration changes, and returns whether its sample
+    /// rate still is the streams'.
+    ///
+    /// The paths are resynchronized if the period size changed.
+    fn poll_events(&mut self) -> bool {
+        match &mut self.output {
+            ServerOutput::Client { events, config, .. } => {
+                for events in events {
+                    if config.apply_queued(events) {
+                        self.resync.request();
+                    }
+                }
+
+                config.rate_matches()
+            }
+            // the mixer doesn't depend on the period size
+            ServerOutput::Mixer(mixed) => mixed.mixer.borrow_mut().poll_events(),
+        }
+    }
+
+    /// Returns the JACK server's configuration, as of the last processed changes.
+    #[inline(always)]
+    pub fn jack_config(&self) -> JackConfig {
+        match &self.output {
+            ServerOutput::Client { config, .. } => *config,
+            ServerOutput::Mixer(mixed) => mixed.mixer.borrow().config,
+        }
+    }
+
+    /// Returns `true` if any of the JACK client's ports is connected.
+    pub fn is_connected(&self) -> bool {
+        let any_connected = |client: &jack::Client| {
+            self.port_names
+                .iter()
+                .filter_map(|name| client.port_by_name(name))
+                .any(|port| port.connected_count().is_ok_and(|n| n > 0))
+        };
+
+        match &self.output {
+            ServerOutput::Client { client, .. } => any_connected(client.client.as_client()),
+            ServerOutput::Mixer(mixed) => any_connected(mixed.mixer.borrow().client.as_client()),
+        }
+    }
+}
+
+/// A connected server with inactive IO.
+pub struct JackInactive(pub JackServer);
+
+/// A connected server whose IO start request is pending.
+pub struct JackStartPending(pub JackServer);
+
+/// A connected server with active IO.
+pub struct JackActive(pub JackServer);
+
+/// A connected server whose IO stop request is pending.
+pub struct JackStopPending(pub JackServer);
+
+impl IOInactiveContext for JackInactive {
+    type Context = JackClientContext;
+    type IOStartPending = JackStartPending;
+
+    fn poll_start_io(mut self, cx: &mut Self::Context) -> Result<Self::IOStartPending, Self> {
+        cx.poll_monitor(&mut self.0);
+
+        if self.0.poll_events() && self.0.is_connected() {
+            Ok(JackStartPending(self.0))
+        } else {
+            Err(self)
+        }
+    }
+
+    fn io_started_by_server(
+        mut self,
+        _cx: &mut Self::Context,
+    ) -> Result<Self::IOStartPending, Self> {
+        if self.0.poll_events() {
+            Ok(JackStartPending(self.0))
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl IOStartPendingContext for JackStartPending {
+    type Context = JackClientContext;
+    type IOActive = JackActive;
+
+    fn start_io(mut self, _cx: &mut Self::Context, selection: StreamSelection) -> Self::IOActive {
+        let server = &mut self.0;
+
+        // packets held from the previous session are skipped by the resynchronization
+        if let Some(jitter) = &mut server.jitter {
+            jitter.flush(&mut server.senders);
+        }
+
+        server.senders.select(selection);
+        server.resync.request();
+        JackActive(self.0)
+    }
+
+    fn start_io_refused(self, _cx: &mut Self::Context) -> JackInactive {
+        JackInactive(self.0)
+    }
+
+    fn start_io_failed(&mut self, _cx: &mut Self::Context) {}
+}
+
+impl IOActiveContext for JackActive {
+    type Context = JackClientContext;
+    type IOStopPending = JackStopPending;
+
+    fn on_audio(
+        &mut self,
+        cx: &mut Self::Context,
+        timestamp: std::time::Instant,
+        header: AudioMessageHeader,
+        data: &[u8],
+    ) {
+        // audio may keep the receive loop from ever timing out, and thus polling
+        cx.poll_monitor(&mut self.0);
+
+        let server = &mut self.0;
+
+        match &mut server.jitter {
+            Some(jitter) => jitter.on_audio(&mut server.senders, timestamp, header, data),
+            None => {
+                server.senders.on_audio(header, data);
+            }
+        }
+    }
+
+    fn poll_stop_io(mut self, cx: &mut Self::Context) -> Result<Self::IOStopPending, Self> {
+        cx.poll_monitor(&mut self.0);
+
+        let server = &mut self.0;
+
+        if let Some(jitter) = &mut server.jitter {
+            jitter.poll(&mut server.senders, time::Instant::now());
+        }
+
+        if self.0.poll_events() && self.0.is_connected() {
+            Err(self)
+        } else {
+            Ok(JackStopPending(self.0))
+        }
+    }
+
+    fn io_stopped_by_server(self, _cx: &mut Self::Context) -> Result<Self::IOStopPending, Self> {
+        Ok(JackStopPending(self.0))
+    }
+}
+
+impl IOStopPendingContext for JackStopPending {
+    type Context = JackClientContext;
+
+    fn stop_io(self, _cx: &mut Self::Context) -> JackInactive {
+        JackInactive(self.0)
+    }
+
+    fn stop_io_refused(self, _cx: &mut Self::Context) -> JackActive {
+        JackActive(self.0)
+    }
+
+    fn stop_io_failed(&mut self, _cx: &mut Self::Context) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network::proto::{AudioStreamMessageHeader, format::Format};
+    use std::iter;
+
+    /// Size of a stereo frame, in bytes.
+    const FRAME_LEN: u64 = 8;
+
+    /// Returns the senders of two stereo streams, and the queues they push into.
+    fn senders() -> (StreamDemux<StreamSender>, Vec<rtrb::Consumer<JackSample>>) {
+        let formats = StreamFormats {
+            inputs: vec![Format::standard(); 2].into(),
+            outputs: Box::new([]),
+        };
+
+        let mut rxs = Vec::new();
+
+        let senders = StreamDemux::new(&formats, |_, format| {
+            let n_channels = format.channel_count.0;
+            let (tx, rx) = rtrb::RingBuffer::new(64);
+            rxs.push(rx);
+
+            let map = ChannelMap::identity(n_channels);
+            let sink = ChannelMappingSink::new(tx, map, n_channels).unwrap();
+            StreamSender::new(
+                sink,
+                AudioPacketFramePadder::new(n_channels.try_into().unwrap()),
+            )
+        });
+
+        (senders, rxs)
+    }
+
+    /// Returns a message carrying the `frame_idx`-th frame of the stream `stream_idx`,
+    /// whose samples are `frame_idx` and its opposite.
+    fn frame(stream_idx: u32, frame_idx: u64) -> (AudioMessageHeader, Vec<u8>) {
+        let spl = frame_idx as JackSample;
+
+        let header = AudioMessageHeader {
+            stream_idx,
+            stream_msg: AudioStreamMessageHeader {
+                byte_idx: frame_idx * FRAME_LEN,
+                n_bytes: FRAME_LEN as u32,
+            },
+        };
+
+        (
+            header,
+            [spl, -spl].iter().flat_map(|s| s.to_le_bytes()).collect(),
+        )
+    }
+
+    /// Plans 20ms of latency for stereo streams at 48kHz, sent a frame at a time, with
+    /// 1ms of jitter.
+    fn plan() -> LatencyPlan {
+        utils::LatencyBudget {
+            target: time::Duration::from_millis(20),
+            sample_rate: num::NonZeroU32::new(48000).unwrap(),
+            n_channels: num::NonZeroUsize::new(2).unwrap(),
+            sample_size: num::NonZeroUsize::new(4).unwrap(),
+            chunk_frames: num::NonZeroUsize::MIN,
+            jitter: time::Duration::from_millis(1),
+        }
+        .plan()
+        .unwrap()
+    }
+
+    /// Pops all queued samples.
+    fn drain(rx: &mut rtrb::Consumer<JackSample>) -> Vec<JackSample> {
+        iter::from_fn(|| rx.pop().ok()).collect()
+    }
+
+    #[test]
+    fn jitter_stage_reorders() {
+        let (mut senders, mut rxs) = senders();
+        let plan = plan();
+        let mut jitter = JitterStage::new(2, &plan);
+        let start = time::Instant::now();
+
+        let mut recv = |senders: &mut _, frame_idx, elapsed| {
+            let (header, data) = frame(0, frame_idx);
+            jitter.on_audio(senders, start + elapsed, header, &data);
+        };
+
+        // in order, passed through
+        recv(&mut senders, 0, time::Duration::ZERO);
+        assert_eq!(drain(&mut rxs[0]), [0., 0.]);
+
+        // early, held until the frame before it arrives
+        recv(&mut senders, 2, time::Duration::ZERO);
+        assert_eq!(drain(&mut rxs[0]), []);
+        recv(&mut senders, 1, time::Duration::from_micros(500));
+        assert_eq!(drain(&mut rxs[0]), [1., -1., 2., -2.]);
+
+        // the frame before never arrives, released after the hold time, the gap padded
+        recv(&mut senders, 4, time::Duration::ZERO);
+        jitter.poll(
+            &mut senders,
+            start + plan.hold_time - time::Duration::from_nanos(1),
+        );
+        assert_eq!(drain(&mut rxs[0]), []);
+        jitter.poll(&mut senders, start + plan.hold_time);
+        assert_eq!(drain(&mut rxs[0]), [0., 0., 4., -4.]);
+
+        // other streams are untouched
+        assert_eq!(drain(&mut rxs[1]), []);
+    }
+
+    #[test]
+    fn jitter_stage_ignores_unknown_streams() {
+        let (mut senders, mut rxs) = senders();
+        let mut jitter = JitterStage::new(2, &plan());
+        let now = time::Instant::now();
+
+        let (header, data) = frame(2, 0);
+        jitter.on_audio(&mut senders, now, header, &data);
+        assert_eq!(senders.n_out_of_range(), 1);
+
+        senders.select(StreamSelection::Mask(0b10));
+        let (header, data) = frame(0, 0);
+        jitter.on_audio(&mut senders, now, header, &data);
+        assert_eq!(senders.n_unselected(), 1);
+        assert_eq!(drain(&mut rxs[0]), []);
+
+        let (header, data) = frame(1, 0);
+        jitter.on_audio(&mut senders, now, header, &data);
+        assert_eq!(drain(&mut rxs[1]), [0., 0.]);
+    }
+
+    #[test]
+    fn latency_plan_applied_or_rejected() {
+        let plan = plan();
+        let cx = JackClientContext::from_latency_plan(&plan).unwrap();
+        assert_eq!(cx.queue_frames, plan.ring_frames);
+        assert_eq!(cx.latency_plan(), Some(&plan));
+
+        let too_long = LatencyPlan {
+            prefill_frames: plan.ring_frames.saturating_add(1),
+            ..plan
+        };
+
+        assert_eq!(
+            JackClientContext::from_latency_plan(&too_long).unwrap_err(),
+            UnsupportedLatencyPlan {
+                prefill_frames: too_long.prefill_frames,
+                ring_frames: plan.ring_frames,
+            }
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Automatic connection of registered ports to other clients' ports.
+
+/// Role of the ports matched by a [`PortPattern`], which determines the direction of
+/// connections made to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PortDirection {
+    /// Matched ports are inputs (e.g. `system:playback_*`), our (output) ports are
+    /// connected to them.
+    Playback,
+    /// Matched ports are outputs (e.g. `system:capture_*`), they are connected to our
+    /// (input) ports.
+    Capture,
+}
+
+/// Describes a set of ports of other clients to connect to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PortPattern {
+    /// Regular expression, matched by JACK against full port names.
+    pub name: String,
+    /// Role of the matched ports.
+    pub direction: PortDirection,
+}
+
+impl PortPattern {
+    /// The system's playback ports, `system:playback_*`.
+    pub fn system_playback() -> Self {
+        Self {
+            name: "^system:playback_".into(),
+            direction: PortDirection::Playback,
+        }
+    }
+
+    /// The system's capture ports, `system:capture_*`.
+    pub fn system_capture() -> Self {
+        Self {
+            name: "^system:capture_".into(),
+            direction: PortDirection::Capture,
+        }
+    }
+}
+
+/// Outcome of [`auto_connect`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AutoConnectSummary {
+    /// Number of our ports.
+    pub n_ports: usize,
+    /// Number of ports matching the pattern.
+    pub n_targets: usize,
+    /// Number of connections successfully made.
+    pub n_connected: usize,
+}
+
+impl AutoConnectSummary {
+    /// Returns `true` if every port has been connected, and no matched port was left
+    /// unconnected.
+    #[inline(always)]
+    pub fn is_complete(&self) -> bool {
+        self.n_ports == self.n_targets && self.n_connected == self.n_ports
+    }
+}
+
+/// Connects each of `ports` (given by their full names) to the port matching `pattern`
+/// with the same index.
+///
+/// Connections can only be made on an activated client, hence the [`jack::AsyncClient`].
+/// Since JACK drops connections with a client's ports when it is closed, this must be
+/// called again whenever the client is replaced.
+///
+/// Mismatched port counts and failed connections are not errors: as many ports as
+/// possible are connected, and the returned summary reports the outcome. Mismatched
+/// port counts are logged as a warning.
+pub fn auto_connect<N, P>(
+    client: &jack::AsyncClient<N, P>,
+    ports: impl IntoIterator<Item = impl AsRef<str>>,
+    pattern: &PortPattern,
+) -> AutoConnectSummary {
+    let client = client.as_client();
+
+    let flags = match pattern.direction {
+        PortDirection::Playback => jack::PortFlags::IS_INPUT,
+        PortDirection::Capture => jack::PortFlags::IS_OUTPUT,
+    };
+
+    let targets = client.ports(Some(&pattern.name), None, flags);
+
+    let mut summary = AutoConnectSummary {
+        n_targets: targets.len(),
+        ..Default::default()
+    };
+
+    let mut targets = targets.iter();
+
+    for port in ports {
+        summary.n_ports += 1;
+
+        let Some(target) = targets.next() else {
+            continue;
+        };
+
+        let (src, dst) = match pattern.direction {
+            PortDirection::Playback => (port.as_ref(), target.as_str()),
+            PortDirection::Capture => (target.as_str(), port.as_ref()),
+        };
+
+        if client.connect_ports_by_name(src, dst).is_ok() {
+            summary.n_connected += 1;
+        }
+    }
+
+    if summary.n_ports != summary.n_targets {
+        log::warn!(
+            "{}: {} ports, but {} ports match {:?}, only connecting the first {}",
+            client.name(),
+            summary.n_ports,
+            summary.n_targets,
+            pattern.name,
+            summary.n_ports.min(summary.n_targets),
+        );
+    }
+
+    summary
+}
@@ -0,0 +1,167 @@
+//! Forwarding of JACK server configuration changes out of JACK's threads.
+
+use crate::utils;
+
+use core::mem;
+
+/// A change in the JACK server's configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JackEvent {
+    /// The period (buffer) size changed, in frames.
+    BufferSize(jack::Frames),
+    /// The sample rate changed, in Hz.
+    SampleRate(jack::Frames),
+}
+
+/// [`jack::NotificationHandler`] forwarding [`JackEvent::SampleRate`] events over a ring
+/// buffer.
+///
+/// JACK reports buffer size changes to the process handler instead, see
+/// [`DuplexProcessHandler::with_events`](crate::DuplexProcessHandler::with_events).
+///
+/// Events are dropped if the ring buffer is full.
+#[derive(Debug)]
+pub struct EventNotifier {
+    events: utils::queue::rtrb::Producer<JackEvent>,
+}
+
+impl EventNotifier {
+    /// Creates a new notifier, pushing events into `events`.
+    #[inline(always)]
+    pub fn new(events: utils::queue::rtrb::Producer<JackEvent>) -> Self {
+        Self { events }
+    }
+}
+
+impl jack::NotificationHandler for EventNotifier {
+    fn sample_rate(&mut self, _: &jack::Client, srate: jack::Frames) -> jack::Control {
+        let _ = self.events.push(JackEvent::SampleRate(srate));
+        jack::Control::Continue
+    }
+}
+
+/// The JACK server's configuration, kept up to date by applying [`JackEvent`]s, and
+/// compared to that of the streams exchanged with a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JackConfig {
+    /// Sample rate of the streams, in Hz.
+    stream_rate: usize,
+    /// Current sample rate of the JACK server, in Hz.
+    sample_rate: jack::Frames,
+    /// Current period size of the JACK server, in frames.
+    buffer_size: jack::Frames,
+}
+
+impl JackConfig {
+    /// Creates a new configuration, for streams at `stream_rate` Hz, and a JACK server
+    /// running at `sample_rate` Hz, with periods of `buffer_size` frames.
+    #[inline(always)]
+    pub fn new(stream_rate: usize, sample_rate: jack::Frames, buffer_size: jack::Frames) -> Self {
+        Self {
+            stream_rate,
+            sample_rate,
+            buffer_size,
+        }
+    }
+
+    /// Returns the current sample rate of the JACK server, in Hz.
+    #[inline(always)]
+    pub fn sample_rate(&self) -> jack::Frames {
+        self.sample_rate
+    }
+
+    /// Returns the current period size of the JACK server, in frames.
+    #[inline(always)]
+    pub fn buffer_size(&self) -> jack::Frames {
+        self.buffer_size
+    }
+
+    /// Returns whether the JACK server's sample rate is the streams'.
+    #[inline(always)]
+    pub fn rate_matches(&self) -> bool {
+        usize::try_from(self.sample_rate).is_ok_and(|rate| rate == self.stream_rate)
+    }
+
+    /// Applies `event`, and returns whether the paths of the process handler must be
+    /// resynchronized, i.e. whether the period size changed.
+    ///
+    /// Sample indices are derived from absolute frame times, but the frame counter
+    /// isn't reliable across reconfigurations, and the queues' fill levels were planned
+    /// for the previous period size.
+    pub fn apply(&mut self, event: JackEvent) -> bool {
+        match event {
+            JackEvent::BufferSize(size) => mem::replace(&mut self.buffer_size, size) != size,
+            JackEvent::SampleRate(rate) => {
+                self.sample_rate = rate;
+                false
+            }
+        }
+    }
+
+    /// Applies all the events queued in `events`, and returns whether the paths of the
+    /// process handler must be resynchronized, see [`apply`](Self::apply).
+    pub fn apply_queued(&mut self, events: &mut utils::queue::rtrb::Consumer<JackEvent>) -> bool {
+        let mut resync = false;
+
+        while let Ok(event) = events.pop() {
+            resync |= self.apply(event);
+        }
+
+        resync
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_rate_changes() {
+        let mut config = JackConfig::new(48000, 48000, 256);
+        assert!(config.rate_matches());
+
+        assert!(!config.apply(JackEvent::SampleRate(44100)));
+        assert!(!config.rate_matches());
+        assert_eq!(config.sample_rate(), 44100);
+
+        // back to the streams' rate
+        assert!(!config.apply(JackEvent::SampleRate(48000)));
+        assert!(config.rate_matches());
+
+        // the period size is left untouched
+        assert_eq!(config.buffer_size(), 256);
+    }
+
+    #[test]
+    fn buffer_size_changes() {
+        let mut config = JackConfig::new(48000, 48000, 256);
+
+        assert!(config.apply(JackEvent::BufferSize(128)));
+        assert_eq!(config.buffer_size(), 128);
+
+        // JACK also reports the initial period size, which requires nothing
+        assert!(!config.apply(JackEvent::BufferSize(128)));
+
+        assert!(config.apply(JackEvent::BufferSize(1024)));
+        assert_eq!(config.buffer_size(), 1024);
+        assert!(config.rate_matches());
+    }
+
+    #[test]
+    fn apply_queued() {
+        let (mut tx, mut rx) = utils::queue::rtrb::RingBuffer::new(4);
+        let mut config = JackConfig::new(48000, 48000, 256);
+
+        assert!(!config.apply_queued(&mut rx));
+
+        tx.push(JackEvent::BufferSize(64)).unwrap();
+        tx.push(JackEvent::SampleRate(96000)).unwrap();
+        tx.push(JackEvent::BufferSize(256)).unwrap();
+
+        // the period size changed, even though it ends up the same
+        assert!(config.apply_queued(&mut rx));
+        assert_eq!(config.buffer_size(), 256);
+        assert!(!config.rate_matches());
+        assert!(rx.is_empty());
+    }
+}
@@ -1,12 +1,19 @@
-use core::{iter, mem, num, ptr};
+//! Conversion between JACK's per-port buffers and interleaved sample streams.
+
+use core::{iter, marker, mem, num, ptr};
 
 // One might argue this is a bit hacky
 
-/// Allows interleaving samples from a set of jack ports,
-/// but allocates space for the pointers only once.
+/// Allows interleaving samples from a set of jack ports, or deinterleaving samples into
+/// them, but allocates space for the pointers only once.
 /// (To avoid allocating in RT threads)
+///
+/// For input ports, [`interleave`](Self::interleave) yields references to the samples
+/// of each port, in interleaved order. For output ports, it yields mutable references
+/// instead, through which the ports' buffers can be written (or accumulated into), see
+/// also [`deinterleave`](Interleaver::deinterleave).
 #[repr(transparent)]
-pub(crate) struct Interleaver<Spec> {
+pub struct Interleaver<Spec> {
     ptrs: [(jack::Port<Spec>, ptr::NonNull<f32>)],
 }
 
@@ -14,8 +21,11 @@ pub(crate) struct Interleaver<Spec> {
 unsafe impl<Spec> Send for Interleaver<Spec> {}
 
 impl<Spec> Interleaver<Spec> {
+    /// Creates a new interleaver over the given ports.
+    ///
+    /// Returns `None` if there are no ports, or more than `u32::MAX`.
     #[inline(always)]
-    pub(crate) fn new(ports: impl IntoIterator<Item = jack::Port<Spec>>) -> Option<Box<Self>> {
+    pub fn new(ports: impl IntoIterator<Item = jack::Port<Spec>>) -> Option<Box<Self>> {
         let boxed_slice = Box::from_iter(iter::zip(
             ports,
             iter::repeat_with(ptr::NonNull::<f32>::dangling),
@@ -25,55 +35,89 @@ impl<Spec> Interleaver<Spec> {
         let _len = num::NonZeroU32::new(boxed_slice.len().try_into().ok()?)?;
 
         // SAFETY: We are a `#[repr(transparent)]` struct
-        Some(unsafe { mem::transmute(boxed_slice) })
+        Some(unsafe {
+            mem::transmute::<Box<[(jack::Port<Spec>, ptr::NonNull<f32>)]>, Box<Self>>(boxed_slice)
+        })
     }
 
+    /// Returns the number of ports.
     #[inline(always)]
-    pub(crate) fn n_ports(&self) -> num::NonZeroU32 {
+    pub fn n_ports(&self) -> num::NonZeroU32 {
         // we return none when we create an interleaver with a channel count of 0
         // or when it's length exceeds u32::MAX
         num::NonZeroU32::new(self.ptrs.len().try_into().unwrap()).unwrap()
     }
+
+    /// Returns the full names of the ports, skipping those that couldn't be retrieved.
+    pub(crate) fn port_names(&self) -> impl Iterator<Item = String> {
+        self.ptrs.iter().filter_map(|(port, _ptr)| port.name().ok())
+    }
 }
 
 // See this: (https://predr.ag/blog/definitive-guide-to-sealed-traits-in-rust/)
 mod private {
-    pub(crate) trait Sealed {}
+    pub trait Sealed {}
     impl Sealed for jack::AudioIn {}
     impl Sealed for jack::AudioOut {}
 }
 
-pub(crate) trait ToJackPointer: private::Sealed {
+/// Port types whose buffers can be accessed during a process cycle.
+///
+/// This trait is sealed, it is only implemented for [`jack::AudioIn`] and
+/// [`jack::AudioOut`].
+pub trait ToJackPointer: private::Sealed {
+    /// Returns a pointer to the port's buffer, along with its length.
     fn to_jack_buf_ptr(
         port: &mut jack::Port<Self>,
         scope: &jack::ProcessScope,
-    ) -> ptr::NonNull<f32>
+    ) -> (ptr::NonNull<f32>, usize)
     where
         Self: Sized;
 }
 
 impl ToJackPointer for jack::AudioIn {
     #[inline(always)]
-    fn to_jack_buf_ptr(port: &mut jack::Port<Self>, scope: &jack::ProcessScope) -> ptr::NonNull<f32>
+    fn to_jack_buf_ptr(
+        port: &mut jack::Port<Self>,
+        scope: &jack::ProcessScope,
+    ) -> (ptr::NonNull<f32>, usize)
     where
         Self: Sized,
     {
-        ptr::NonNull::new(port.as_slice(scope).as_ptr().cast_mut()).unwrap()
+        let buf = port.as_slice(scope);
+        (
+            ptr::NonNull::new(buf.as_ptr().cast_mut()).unwrap(),
+            buf.len(),
+        )
     }
 }
 
 impl ToJackPointer for jack::AudioOut {
     #[inline(always)]
-    fn to_jack_buf_ptr(port: &mut jack::Port<Self>, scope: &jack::ProcessScope) -> ptr::NonNull<f32>
+    fn to_jack_buf_ptr(
+        port: &mut jack::Port<Self>,
+        scope: &jack::ProcessScope,
+    ) -> (ptr::NonNull<f32>, usize)
     where
         Self: Sized,
     {
-        ptr::NonNull::new(port.as_mut_slice(scope).as_ptr().cast_mut()).unwrap()
+        let buf = port.as_mut_slice(scope);
+        (ptr::NonNull::new(buf.as_mut_ptr()).unwrap(), buf.len())
     }
 }
 
-pub(crate) trait FromJackPointer: private::Sealed {
+/// Port types whose buffer pointers can be turned into sample references.
+///
+/// This trait is sealed, it is only implemented for [`jack::AudioIn`] and
+/// [`jack::AudioOut`].
+pub trait FromJackPointer: private::Sealed {
+    /// `&f32` for input ports, `&mut f32` for output ports.
     type Output<'a>;
+
+    /// # Safety
+    ///
+    /// `ptr` must point to a valid sample of a port buffer, accessed (mutably, for
+    /// output ports) by no one else for the lifetime `'a`.
     unsafe fn get_ref<'a>(ptr: ptr::NonNull<f32>) -> Self::Output<'a>;
 }
 
@@ -98,34 +142,108 @@ impl FromJackPointer for jack::AudioOut {
 }
 
 impl<Spec: FromJackPointer + ToJackPointer> Interleaver<Spec> {
+    /// Returns an iterator over the samples of all ports, in interleaved order.
+    ///
+    /// JACK gives all ports buffers of `n_frames` samples, but, defensively, only as many
+    /// frames as the shortest buffer holds are yielded.
     #[inline(always)]
-    pub(crate) fn interleave(
+    pub fn interleave(
         &mut self,
         process_scope: &jack::ProcessScope,
     ) -> impl ExactSizeIterator<Item = Spec::Output<'_>> {
         // Write the pointers into our list
 
+        let mut n_frames = usize::try_from(process_scope.n_frames()).unwrap();
+
         for (port, ptr) in &mut self.ptrs.iter_mut() {
-            *ptr = Spec::to_jack_buf_ptr(port, process_scope);
+            let (buf_ptr, len) = Spec::to_jack_buf_ptr(port, process_scope);
+            *ptr = buf_ptr;
+            n_frames = n_frames.min(len);
         }
 
         // Then return the iterator
 
-        Interleaved {
-            remaining_frames: usize::try_from(process_scope.n_frames()).unwrap(),
-            current_index: 0,
-            ptrs: &mut self.ptrs,
-        }
+        // SAFETY: all pointers point to buffers of at least n_frames samples, which we
+        // have exclusive access to during the process cycle
+        unsafe { Interleaved::<Spec, _>::new(&mut self.ptrs, n_frames) }
+    }
+}
+
+impl Interleaver<jack::AudioOut> {
+    /// Distributes the interleaved `samples` across the ports' buffers.
+    ///
+    /// If `samples` runs out before all buffers are filled, including in the middle of a
+    /// frame, the remaining samples are set to silence. Extra samples are left
+    /// unconsumed.
+    ///
+    /// Returns the number of samples taken from `samples`.
+    #[inline]
+    pub fn deinterleave(
+        &mut self,
+        process_scope: &jack::ProcessScope,
+        samples: impl IntoIterator<Item = f32>,
+    ) -> usize {
+        deinterleave_into(self.interleave(process_scope), samples)
+    }
+}
+
+/// Writes `samples` into `dests`, then silence once `samples` runs out.
+///
+/// Returns the number of samples taken from `samples`.
+#[inline(always)]
+fn deinterleave_into<'a>(
+    dests: impl Iterator<Item = &'a mut f32>,
+    samples: impl IntoIterator<Item = f32>,
+) -> usize {
+    let mut samples = samples.into_iter().fuse();
+    let mut n_written = 0;
+
+    for dest in dests {
+        *dest = match samples.next() {
+            Some(sample) => {
+                n_written += 1;
+                sample
+            }
+            None => 0.,
+        };
     }
+
+    n_written
 }
 
-pub(crate) struct Interleaved<'a, Spec> {
+/// Iterator returned by [`Interleaver::interleave`].
+///
+/// `P` is the type the buffer pointers are stored along with, the ports they belong to.
+pub struct Interleaved<'a, Spec, P = jack::Port<Spec>> {
     remaining_frames: usize,
     current_index: usize,
-    ptrs: &'a mut [(jack::Port<Spec>, ptr::NonNull<f32>)],
+    ptrs: &'a mut [(P, ptr::NonNull<f32>)],
+    _spec: marker::PhantomData<Spec>,
+}
+
+impl<'a, Spec, P> Interleaved<'a, Spec, P> {
+    /// Creates an iterator over the first `n_frames` samples of each of the buffers
+    /// pointed to by `ptrs`, in interleaved order.
+    ///
+    /// # Safety
+    ///
+    /// Each pointer must point to a buffer of at least `n_frames` samples, accessed
+    /// (mutably, for output ports) by no one else for the lifetime `'a`. `ptrs` must not
+    /// be empty.
+    #[inline(always)]
+    unsafe fn new(ptrs: &'a mut [(P, ptr::NonNull<f32>)], n_frames: usize) -> Self {
+        debug_assert!(!ptrs.is_empty());
+
+        Self {
+            remaining_frames: n_frames,
+            current_index: 0,
+            ptrs,
+            _spec: marker::PhantomData,
+        }
+    }
 }
 
-impl<'a, Spec: FromJackPointer> Iterator for Interleaved<'a, Spec> {
+impl<'a, Spec: FromJackPointer, P> Iterator for Interleaved<'a, Spec, P> {
     type Item = Spec::Output<'a>;
 
     #[inline(always)]
@@ -153,13 +271,131 @@ impl<'a, Spec: FromJackPointer> Iterator for Interleaved<'a, Spec> {
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let len = self.remaining_frames * self.ptrs.len();
+        // current_index samples of the current frame have already been yielded
+        let len = self
+            .remaining_frames
+            .strict_mul(self.ptrs.len())
+            .strict_sub(self.current_index);
         (len, Some(len))
     }
 }
 
-impl<'a, Spec: FromJackPointer> ExactSizeIterator for Interleaved<'a, Spec> {
+impl<'a, Spec: FromJackPointer, P> ExactSizeIterator for Interleaved<'a, Spec, P> {
     fn len(&self) -> usize {
         self.size_hint().0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const N_FRAMES: usize = 5;
+
+    /// Returns port buffers of `n_channels` channels and `n_frames` frames, where each
+    /// sample is `100 * channel + frame`.
+    fn fake_bufs(n_channels: usize, n_frames: usize) -> Vec<Vec<f32>> {
+        (0..n_channels)
+            .map(|c| (0..n_frames).map(|f| (100 * c + f) as f32).collect())
+            .collect()
+    }
+
+    /// Returns pointers to `bufs`, stored the way the interleaver stores them, along with
+    /// the length of the shortest buffer.
+    fn fake_ports(bufs: &mut [Vec<f32>]) -> (Vec<((), ptr::NonNull<f32>)>, usize) {
+        let n_frames = bufs.iter().map(Vec::len).min().unwrap();
+
+        let ptrs = bufs
+            .iter_mut()
+            .map(|buf| ((), ptr::NonNull::new(buf.as_mut_ptr()).unwrap()))
+            .collect();
+
+        (ptrs, n_frames)
+    }
+
+    #[test]
+    fn interleave() {
+        for n_channels in [1, 2, 8] {
+            let mut bufs = fake_bufs(n_channels, N_FRAMES);
+            let (mut ptrs, n_frames) = fake_ports(&mut bufs);
+
+            // SAFETY: the buffers hold n_frames samples, and aren't accessed meanwhile
+            let mut samples = unsafe { Interleaved::<jack::AudioIn, _>::new(&mut ptrs, n_frames) };
+            let mut interleaved = Vec::new();
+
+            for n_left in (0..n_channels * N_FRAMES).rev() {
+                interleaved.push(*samples.next().unwrap());
+                assert_eq!(samples.len(), n_left);
+            }
+
+            assert!(samples.next().is_none());
+
+            let expected: Vec<_> = (0..N_FRAMES)
+                .flat_map(|f| (0..n_channels).map(move |c| (100 * c + f) as f32))
+                .collect();
+
+            assert_eq!(interleaved, expected);
+        }
+    }
+
+    #[test]
+    fn deinterleave_partial_frame() {
+        for n_channels in [1, 2, 8] {
+            // the last frame is missing half of its samples (all of them, with 1 channel)
+            let n_samples = n_channels * N_FRAMES - n_channels.div_ceil(2);
+
+            let mut bufs = vec![vec![f32::NAN; N_FRAMES]; n_channels];
+            let (mut ptrs, n_frames) = fake_ports(&mut bufs);
+
+            // SAFETY: same as above
+            let dests = unsafe { Interleaved::<jack::AudioOut, _>::new(&mut ptrs, n_frames) };
+            let n_written = deinterleave_into(dests, (0..n_samples).map(|i| i as f32));
+
+            assert_eq!(n_written, n_samples);
+
+            for (c, buf) in bufs.iter().enumerate() {
+                for (f, &sample) in buf.iter().enumerate() {
+                    let i = f * n_channels + c;
+                    let expected = if i < n_samples { i as f32 } else { 0. };
+                    assert_eq!(sample, expected, "channel {c}, frame {f}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn deinterleave_leaves_extra_samples() {
+        let mut bufs = vec![vec![f32::NAN; N_FRAMES]; 2];
+        let (mut ptrs, n_frames) = fake_ports(&mut bufs);
+
+        let mut samples = (0..).map(|i| i as f32);
+
+        // SAFETY: same as above
+        let dests = unsafe { Interleaved::<jack::AudioOut, _>::new(&mut ptrs, n_frames) };
+        assert_eq!(deinterleave_into(dests, samples.by_ref()), 2 * N_FRAMES);
+
+        assert_eq!(samples.next(), Some((2 * N_FRAMES) as f32));
+        assert_eq!(bufs[0], [0., 2., 4., 6., 8.]);
+        assert_eq!(bufs[1], [1., 3., 5., 7., 9.]);
+    }
+
+    #[test]
+    fn differing_buffer_lengths() {
+        let mut bufs = fake_bufs(3, N_FRAMES);
+        bufs[1].truncate(3);
+        bufs[2].truncate(4);
+
+        let (mut ptrs, n_frames) = fake_ports(&mut bufs);
+        assert_eq!(n_frames, 3);
+
+        // SAFETY: same as above
+        let dests = unsafe { Interleaved::<jack::AudioOut, _>::new(&mut ptrs, n_frames) };
+        assert_eq!(dests.len(), 9);
+        assert_eq!(deinterleave_into(dests, iter::repeat(-1.)), 9);
+
+        // only the frames all buffers hold are written
+        assert_eq!(bufs[0], [-1., -1., -1., 3., 4.]);
+        assert_eq!(bufs[1], [-1., -1., -1.]);
+        assert_eq!(bufs[2], [-1., -1., -1., 203.]);
+    }
+}
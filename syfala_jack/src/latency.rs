@@ -0,0 +1,258 @@
+//! Reporting of the latency added by network paths to JACK.
+
+use crate::{JackRx, JackTx};
+
+use core::{ffi, mem, ptr};
+use std::sync::{Arc, atomic};
+
+/// Shared handle to the number of frames buffered in a path's queue, updated by the
+/// [`DuplexProcessHandler`](crate::DuplexProcessHandler) at the end of every cycle.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyProbe(Arc<atomic::AtomicU32>);
+
+impl LatencyProbe {
+    /// Returns the last observed number of buffered frames.
+    #[inline(always)]
+    pub fn buffered_frames(&self) -> jack::Frames {
+        self.0.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Sets the number of buffered frames.
+    ///
+    /// Done by the process handler, but can also be used to simulate a fill level.
+    #[inline(always)]
+    pub fn set_buffered_frames(&self, frames: jack::Frames) {
+        self.0.store(frames, atomic::Ordering::Relaxed);
+    }
+}
+
+/// Returns the `(min, max)` latency range added by a path, given the number of frames
+/// currently buffered in its queue, and the size of one network chunk, in frames.
+#[inline(always)]
+pub fn path_latency(
+    buffered_frames: jack::Frames,
+    chunk_frames: jack::Frames,
+) -> (jack::Frames, jack::Frames) {
+    let latency = buffered_frames.saturating_add(chunk_frames);
+    (latency, latency)
+}
+
+/// A path whose latency is reported.
+#[derive(Debug)]
+struct ReportedPath {
+    /// Full names of the path's ports.
+    ports: Box<[String]>,
+    probe: LatencyProbe,
+    mode: jack::LatencyType,
+}
+
+/// Reports the latency added by network paths to the ports carrying them.
+///
+/// Receive paths' output ports report a capture latency (audio coming into the graph
+/// is that late), and transmit paths' input ports report a playback latency (audio
+/// leaving the graph will be heard that late).
+///
+/// Latencies are computed with [`path_latency`], using the JACK period size as the
+/// network chunk size, so they follow buffer size changes. JACK requests them whenever
+/// the graph changes, use [`register`](Self::register) to handle such requests.
+#[derive(Debug, Default)]
+pub struct LatencyReporter {
+    paths: Vec<ReportedPath>,
+}
+
+impl LatencyReporter {
+    /// Creates a new reporter, with no paths.
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports the capture latency of `rx`'s output ports.
+    pub fn with_rx<C>(mut self, rx: &JackRx<C>) -> Self {
+        self.paths.push(ReportedPath {
+            ports: rx.interleaver.port_names().collect(),
+            probe: rx.latency_probe(),
+            mode: jack::LatencyType::Capture,
+        });
+        self
+    }
+
+    /// Reports the playback latency of `tx`'s input ports.
+    pub fn with_tx<C>(mut self, tx: &JackTx<C>) -> Self {
+        self.paths.push(ReportedPath {
+            ports: tx.interleaver.port_names().collect(),
+            probe: tx.latency_probe(),
+            mode: jack::LatencyType::Playback,
+        });
+        self
+    }
+
+    /// Returns the latency range of every path of the given mode, in the order they
+    /// were added, for a network chunk size of `chunk_frames`.
+    pub fn latencies(
+        &self,
+        mode: jack::LatencyType,
+        chunk_frames: jack::Frames,
+    ) -> impl Iterator<Item = (jack::Frames, jack::Frames)> {
+        self.paths
+            .iter()
+            .filter(move |path| path.mode == mode)
+            .map(move |path| path_latency(path.probe.buffered_frames(), chunk_frames))
+    }
+
+    /// Sets the latency range of the ports of every path of the given mode.
+    pub fn report(&self, client: &jack::Client, mode: jack::LatencyType) {
+        let chunk_frames = client.buffer_size();
+
+        for path in self.paths.iter().filter(|path| path.mode == mode) {
+            let range = path_latency(path.probe.buffered_frames(), chunk_frames);
+
+            for port in path
+                .ports
+                .iter()
+                .filter_map(|name| client.port_by_name(name))
+            {
+                port.set_latency_range(mode, range);
+            }
+        }
+    }
+
+    /// Makes `self` answer `client`'s latency requests.
+    ///
+    /// The `jack` crate's [`NotificationHandler`](jack::NotificationHandler) doesn't
+    /// expose JACK's latency callback, so it is registered directly, and must be before
+    /// the client is activated. JACK may call it until the client is closed, the returned
+    /// registration, holding a reference to `self`, must thus only be released then.
+    pub fn register(
+        self: Arc<Self>,
+        client: &jack::Client,
+    ) -> Result<LatencyRegistration, jack::Error> {
+        let registration = Box::into_raw(Box::new(Registration {
+            reporter: self,
+            client: client.raw(),
+        }));
+
+        // SAFETY: `registration` is valid, and only freed once the client is closed
+        let res = unsafe {
+            jack::jack_sys::jack_set_latency_callback(
+                client.raw(),
+                Some(latency_callback),
+                registration.cast(),
+            )
+        };
+
+        match res {
+            // `Box::into_raw` never returns a null pointer
+            0 => Ok(LatencyRegistration(
+                ptr::NonNull::new(registration).unwrap(),
+            )),
+            _ => {
+                // SAFETY: JACK refused the callback, so it doesn't hold `registration`
+                drop(unsafe { Box::from_raw(registration) });
+                Err(jack::Error::CallbackRegistrationError)
+            }
+        }
+    }
+}
+
+/// A [`LatencyReporter`] registered with a JACK client, see
+/// [`LatencyReporter::register`].
+///
+/// Dropping it leaks the registration (and the reporter), use [`release`](Self::release)
+/// once the client is closed instead.
+#[derive(Debug)]
+#[must_use]
+pub struct LatencyRegistration(ptr::NonNull<Registration>);
+
+impl LatencyRegistration {
+    /// Releases the registration, and its reference to the reporter.
+    ///
+    /// # Safety
+    ///
+    /// The client the reporter was registered with must have been closed, so that JACK
+    /// doesn't call the latency callback anymore.
+    #[inline(always)]
+    pub unsafe fn release(self) {
+        // SAFETY: the pointer was created in `LatencyReporter::register`, and the caller
+        // guarantees that JACK doesn't hold it anymore
+        drop(unsafe { Box::from_raw(self.0.as_ptr()) });
+    }
+}
+
+/// Data handed to JACK's latency callback.
+struct Registration {
+    reporter: Arc<LatencyReporter>,
+    client: *mut jack::jack_sys::jack_client_t,
+}
+
+unsafe extern "C" fn latency_callback(
+    mode: jack::jack_sys::jack_latency_callback_mode_t,
+    arg: *mut ffi::c_void,
+) {
+    // SAFETY: `arg` was created in `LatencyReporter::register`, and is only freed once
+    // the client is closed
+    let registration = unsafe { &*arg.cast::<Registration>() };
+
+    let mode = if mode == jack::jack_sys::JackCaptureLatency {
+        jack::LatencyType::Capture
+    } else {
+        jack::LatencyType::Playback
+    };
+
+    // SAFETY: JACK only calls this while the client is open. The client is borrowed,
+    // and must not be closed here, hence the `ManuallyDrop`
+    let client = mem::ManuallyDrop::new(unsafe { jack::Client::from_raw(registration.client) });
+
+    registration.reporter.report(&client, mode);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jack::LatencyType::{Capture, Playback};
+
+    fn path(mode: jack::LatencyType) -> (LatencyProbe, ReportedPath) {
+        let probe = LatencyProbe::default();
+
+        let path = ReportedPath {
+            ports: Box::new([]),
+            probe: probe.clone(),
+            mode,
+        };
+
+        (probe, path)
+    }
+
+    #[test]
+    fn one_chunk_added() {
+        assert_eq!(path_latency(0, 256), (256, 256));
+        assert_eq!(path_latency(1000, 256), (1256, 1256));
+        assert_eq!(path_latency(u32::MAX - 10, 256), (u32::MAX, u32::MAX));
+    }
+
+    #[test]
+    fn simulated_fill_levels() {
+        let (rx_probe, rx_path) = path(Capture);
+        let (tx_probe, tx_path) = path(Playback);
+        let (rx2_probe, rx2_path) = path(Capture);
+
+        let reporter = LatencyReporter {
+            paths: vec![rx_path, tx_path, rx2_path],
+        };
+
+        rx_probe.set_buffered_frames(480);
+        tx_probe.set_buffered_frames(64);
+        rx2_probe.set_buffered_frames(0);
+
+        let capture: Vec<_> = reporter.latencies(Capture, 128).collect();
+        let playback: Vec<_> = reporter.latencies(Playback, 128).collect();
+        assert_eq!(capture, [(608, 608), (128, 128)]);
+        assert_eq!(playback, [(192, 192)]);
+
+        // the fill level changes, and so does the buffer size
+        rx_probe.set_buffered_frames(100);
+
+        let capture: Vec<_> = reporter.latencies(Capture, 512).collect();
+        assert_eq!(capture, [(612, 612), (512, 512)]);
+    }
+}
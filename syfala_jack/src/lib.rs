@@ -1,9 +1,35 @@
-use core::cell;
+use std::sync::{Arc, atomic};
 
 pub use syfala_network as network;
 pub use syfala_utils as utils;
 
-mod interleaver;
+pub mod interleaver;
+
+mod connect;
+pub use connect::*;
+
+mod events;
+pub use events::*;
+
+mod latency;
+pub use latency::*;
+
+mod mixer;
+pub use mixer::*;
+
+mod names;
+pub use names::*;
+
+mod channel_map;
+pub use channel_map::*;
+
+mod monitor;
+pub use monitor::*;
+
+#[cfg(feature = "generic")]
+mod client;
+#[cfg(feature = "generic")]
+pub use client::*;
 
 /// The only audio sample format supported by JACK.
 ///
@@ -26,6 +52,10 @@ pub type JackSample = f32;
 pub struct JackTx<C> {
     interleaver: Box<interleaver::Interleaver<jack::AudioIn>>,
     tx: utils::queue::IndexedTx<C, JackSample>,
+    /// Queue index corresponding to the handler's reference frame.
+    base_idx: u64,
+    latency: LatencyProbe,
+    stats: QueueStatsProbe,
 }
 
 impl<C> JackTx<C> {
@@ -38,7 +68,25 @@ impl<C> JackTx<C> {
     ) -> Option<Self> {
         let interleaver = interleaver::Interleaver::new(ports)?;
 
-        Some(Self { interleaver, tx })
+        Some(Self {
+            interleaver,
+            tx,
+            base_idx: 0,
+            latency: LatencyProbe::default(),
+            stats: QueueStatsProbe::default(),
+        })
+    }
+
+    /// Returns a handle to the number of frames queued for sending.
+    #[inline(always)]
+    pub fn latency_probe(&self) -> LatencyProbe {
+        self.latency.clone()
+    }
+
+    /// Returns a handle to the statistics of the queue, accumulated every cycle.
+    #[inline(always)]
+    pub fn queue_stats_probe(&self) -> QueueStatsProbe {
+        self.stats.clone()
     }
 }
 
@@ -49,9 +97,17 @@ impl<C> JackTx<C> {
 /// each port receives its corresponding channel.
 ///
 /// Padding is applied automatically when samples are missing.
+///
+/// Optionally, playback only starts once enough samples are queued, see
+/// [`with_prefill`](Self::with_prefill).
 pub struct JackRx<C> {
     rx: utils::queue::IndexedRx<C, JackSample>,
     interleaver: Box<interleaver::Interleaver<jack::AudioOut>>,
+    /// Queue index corresponding to the handler's reference frame.
+    base_idx: u64,
+    prefill: Prefill,
+    latency: LatencyProbe,
+    stats: QueueStatsProbe,
 }
 
 impl<C> JackRx<C> {
@@ -64,7 +120,335 @@ impl<C> JackRx<C> {
     ) -> Option<Self> {
         let interleaver = interleaver::Interleaver::new(ports)?;
 
-        Some(Self { rx, interleaver })
+        Some(Self {
+            rx,
+            interleaver,
+            base_idx: 0,
+            prefill: Prefill::default(),
+            latency: LatencyProbe::default(),
+            stats: QueueStatsProbe::default(),
+        })
+    }
+
+    /// Outputs silence, after each resynchronization, until `frames` frames are queued,
+    /// playback then trails the received samples by that many frames.
+    #[inline(always)]
+    pub fn with_prefill(mut self, frames: usize) -> Self {
+        let n_ports = u64::from(self.interleaver.n_ports().get());
+        self.prefill = Prefill::new(u64::try_from(frames).unwrap().strict_mul(n_ports));
+        self
+    }
+
+    /// Returns a handle to the number of frames received, waiting to be played.
+    #[inline(always)]
+    pub fn latency_probe(&self) -> LatencyProbe {
+        self.latency.clone()
+    }
+
+    /// Returns a handle to the statistics of the queue, accumulated every cycle.
+    #[inline(always)]
+    pub fn queue_stats_probe(&self) -> QueueStatsProbe {
+        self.stats.clone()
+    }
+}
+
+/// Shared handle to the number of drift recoveries performed by a
+/// [`DuplexProcessHandler`], readable from outside the real-time thread.
+#[derive(Debug, Clone, Default)]
+pub struct DriftRecoveries(Arc<atomic::AtomicU64>);
+
+impl DriftRecoveries {
+    /// Returns the number of recoveries performed so far.
+    #[inline(always)]
+    pub fn get(&self) -> u64 {
+        self.0.load(atomic::Ordering::Relaxed)
+    }
+
+    #[inline(always)]
+    fn increment(&self) {
+        self.0.fetch_add(1, atomic::Ordering::Relaxed);
+    }
+}
+
+/// Shared handle to the number of [`DriftError`](utils::queue::DriftError)s encountered
+/// by a [`DuplexProcessHandler`], readable from outside the real-time thread.
+#[derive(Debug, Clone, Default)]
+pub struct DriftErrors(Arc<atomic::AtomicU64>);
+
+impl DriftErrors {
+    /// Returns the number of drift errors encountered so far.
+    #[inline(always)]
+    pub fn get(&self) -> u64 {
+        self.0.load(atomic::Ordering::Relaxed)
+    }
+
+    #[inline(always)]
+    fn increment(&self) {
+        self.0.fetch_add(1, atomic::Ordering::Relaxed);
+    }
+}
+
+/// Shared handle requesting a [`DuplexProcessHandler`] to resynchronize all of its paths
+/// at the start of its next cycle, usable from outside the real-time thread.
+///
+/// Unlike recoveries, requested resynchronizations aren't counted.
+#[derive(Debug, Clone, Default)]
+pub struct ResyncRequest(Arc<atomic::AtomicBool>);
+
+impl ResyncRequest {
+    /// Requests a resynchronization.
+    #[inline(always)]
+    pub fn request(&self) {
+        self.0.store(true, atomic::Ordering::Relaxed);
+    }
+
+    /// Returns whether a resynchronization was requested, and clears the request.
+    #[inline(always)]
+    fn take(&self) -> bool {
+        self.0.swap(false, atomic::Ordering::Relaxed)
+    }
+}
+
+/// A transmit or receive path of a [`DuplexProcessHandler`], as driven by a
+/// [`CycleSync`], transferring a cycle's samples through `Cx`.
+trait SyncedPath<Cx> {
+    /// Returns the number of samples per frame.
+    fn n_ports(&self) -> core::num::NonZeroU32;
+
+    /// Returns the queue index corresponding to the reference frame.
+    fn base_idx(&self) -> u64;
+
+    /// Realigns the path to a new reference frame.
+    fn resync(&mut self);
+
+    /// Transfers a cycle's samples, the first one at the queue index `idx`, or, if it
+    /// is `None`, signals that it overflowed.
+    ///
+    /// Returns `false` if the samples couldn't be transferred, because of drift.
+    fn transfer(&mut self, idx: Option<u64>, cx: &Cx) -> bool;
+}
+
+impl<C: utils::queue::Counter> SyncedPath<jack::ProcessScope> for JackTx<C> {
+    #[inline(always)]
+    fn n_ports(&self) -> core::num::NonZeroU32 {
+        self.interleaver.n_ports()
+    }
+
+    #[inline(always)]
+    fn base_idx(&self) -> u64 {
+        self.base_idx
+    }
+
+    #[inline(always)]
+    fn resync(&mut self) {
+        self.base_idx = tx_resync_idx(&self.tx);
+    }
+
+    #[inline(always)]
+    fn transfer(&mut self, idx: Option<u64>, scope: &jack::ProcessScope) -> bool {
+        // On huge drift, we just don't send anything this cycle.
+        idx.is_some_and(|idx| {
+            self.tx
+                .send(idx, self.interleaver.interleave(scope).copied(), || 0.)
+                .is_ok()
+        })
+    }
+}
+
+impl<C: utils::queue::Counter> SyncedPath<jack::ProcessScope> for JackRx<C> {
+    #[inline(always)]
+    fn n_ports(&self) -> core::num::NonZeroU32 {
+        self.interleaver.n_ports()
+    }
+
+    #[inline(always)]
+    fn base_idx(&self) -> u64 {
+        self.base_idx
+    }
+
+    #[inline(always)]
+    fn resync(&mut self) {
+        self.base_idx = rx_resync_idx(&self.rx);
+        self.prefill.reset();
+    }
+
+    #[inline(always)]
+    fn transfer(&mut self, idx: Option<u64>, scope: &jack::ProcessScope) -> bool {
+        let n_spls = u64::from(scope.n_frames()).strict_mul(self.n_ports().get().into());
+
+        let idx = match idx {
+            Some(idx) => match self.prefill.idx(idx, self.base_idx, &self.rx, n_spls) {
+                Some(idx) => Some(idx),
+                // still prefilling, output silence, without drifting
+                None => {
+                    self.interleaver.deinterleave(scope, core::iter::empty());
+                    return true;
+                }
+            },
+            None => None,
+        };
+
+        match idx.and_then(|idx| self.rx.recv(idx, || 0.).ok()) {
+            Some(samples) => {
+                self.interleaver.deinterleave(scope, samples);
+                true
+            }
+            // Same as above, output silence this cycle
+            None => {
+                self.interleaver.deinterleave(scope, core::iter::empty());
+                false
+            }
+        }
+    }
+}
+
+/// Holds a receive path's playback back, after each resynchronization, until enough
+/// samples are queued.
+#[derive(Debug, Default, Clone, Copy)]
+struct Prefill {
+    /// Number of samples to queue before playback starts.
+    n_spls: u64,
+    /// Whether playback started since the last resynchronization.
+    primed: bool,
+    /// Number of samples of the cycles spent waiting, the indices requested afterwards are
+    /// offset by as much.
+    n_held: u64,
+}
+
+impl Prefill {
+    #[inline(always)]
+    fn new(n_spls: u64) -> Self {
+        Self {
+            n_spls,
+            ..Self::default()
+        }
+    }
+
+    /// Starts waiting again.
+    #[inline(always)]
+    fn reset(&mut self) {
+        self.primed = false;
+        self.n_held = 0;
+    }
+
+    /// Returns the queue index to receive a cycle of `n_spls` samples from, `idx` being
+    /// the one computed from the reference frame, `base_idx` being the reference frame's.
+    ///
+    /// Returns `None` if not enough samples are queued past `base_idx` yet, the cycle
+    /// must then output silence.
+    #[inline(always)]
+    fn idx<C: utils::queue::Counter, T>(
+        &mut self,
+        idx: u64,
+        base_idx: u64,
+        rx: &utils::queue::IndexedRx<C, T>,
+        n_spls: u64,
+    ) -> Option<u64> {
+        if !self.primed {
+            if rx_resync_idx(rx).saturating_sub(base_idx) < self.n_spls {
+                self.n_held = self.n_held.saturating_add(n_spls);
+                return None;
+            }
+
+            self.primed = true;
+        }
+
+        Some(idx.saturating_sub(self.n_held))
+    }
+}
+
+/// Keeps the paths of a [`DuplexProcessHandler`] aligned to JACK's frame counter, and
+/// recovers from its discontinuities.
+///
+/// Doesn't depend on JACK itself, the paths transfer their samples through a context
+/// passed along, the process scope.
+#[derive(Debug, Default)]
+struct CycleSync {
+    /// The reference frame index, captured on the first cycle (and upon recoveries) and
+    /// used to compute stable sample indices for subsequent cycles.
+    start_frame_idx: Option<u64>,
+    drift_errors: DriftErrors,
+    drift_recoveries: DriftRecoveries,
+    resync_request: ResyncRequest,
+}
+
+impl CycleSync {
+    /// Makes `frame_idx` the new reference frame, and realigns all paths to it.
+    fn resync<Cx>(
+        &mut self,
+        frame_idx: u64,
+        txs: &mut [impl SyncedPath<Cx>],
+        rxs: &mut [impl SyncedPath<Cx>],
+    ) {
+        self.start_frame_idx = Some(frame_idx);
+        txs.iter_mut().for_each(SyncedPath::resync);
+        rxs.iter_mut().for_each(SyncedPath::resync);
+    }
+
+    /// Same as [`resync`](Self::resync), but counted as a recovery.
+    fn recover<Cx>(
+        &mut self,
+        frame_idx: u64,
+        txs: &mut [impl SyncedPath<Cx>],
+        rxs: &mut [impl SyncedPath<Cx>],
+    ) {
+        self.resync(frame_idx, txs, rxs);
+        self.drift_recoveries.increment();
+    }
+
+    /// Runs the cycle of `n_frames` frames starting at the frame `this_cycle_frame_idx`:
+    /// senders, then receivers, transfer their samples.
+    ///
+    /// If the frame counter went backwards, the current cycle becomes the reference
+    /// frame. If any path drifted too far, the next one does.
+    fn cycle<Cx>(
+        &mut self,
+        this_cycle_frame_idx: u64,
+        n_frames: u64,
+        cx: &Cx,
+        txs: &mut [impl SyncedPath<Cx>],
+        rxs: &mut [impl SyncedPath<Cx>],
+    ) {
+        if self.resync_request.take() {
+            self.resync(this_cycle_frame_idx, txs, rxs);
+        }
+
+        let first_cycle_frame_idx = *self.start_frame_idx.get_or_insert(this_cycle_frame_idx);
+
+        // Never panic in the process callback, it would take down the whole JACK server.
+        let frame_idx = match this_cycle_frame_idx.checked_sub(first_cycle_frame_idx) {
+            Some(idx) => idx,
+            None => {
+                self.recover(this_cycle_frame_idx, txs, rxs);
+                0
+            }
+        };
+
+        let mut drifted = false;
+
+        for tx in txs.iter_mut() {
+            let idx = cycle_spl_idx(frame_idx, tx.base_idx(), tx.n_ports());
+
+            if !tx.transfer(idx, cx) {
+                self.drift_errors.increment();
+                drifted = true;
+            }
+        }
+
+        for rx in rxs.iter_mut() {
+            let idx = cycle_spl_idx(frame_idx, rx.base_idx(), rx.n_ports());
+
+            if !rx.transfer(idx, cx) {
+                self.drift_errors.increment();
+                drifted = true;
+            }
+        }
+
+        if drifted {
+            // this cycle's samples have already been accounted for, so the next cycle
+            // becomes the reference
+            self.recover(this_cycle_frame_idx.strict_add(n_frames), txs, rxs);
+        }
     }
 }
 
@@ -73,12 +457,23 @@ impl<C> JackRx<C> {
 /// This handler manages multiple transmit and receive paths and keeps
 /// them synchronized using the frame-based indices provided by JACK, during
 /// process cycles.
+///
+/// If JACK's frame counter jumps backwards, or too far for the queues to compensate
+/// (e.g. after a suspend/resume, or a transport relocation), all paths are
+/// resynchronized: the reference frame is moved to the current cycle, senders resume
+/// from their current position, and receivers skip to the tail of their queue (and
+/// prefill it again, see [`JackRx::with_prefill`]).
+/// Such recoveries are counted, see [`drift_recoveries`](Self::drift_recoveries), along
+/// with the drift errors that triggered them, see [`drift_errors`](Self::drift_errors).
+///
+/// Paths can also be resynchronized on demand, see [`resync_request`](Self::resync_request),
+/// e.g. when the peer starts streaming after the client has been running for a while.
 pub struct DuplexProcessHandler<TxCounter, RxCounter> {
     txs: Box<[JackTx<TxCounter>]>,
     rxs: Box<[JackRx<RxCounter>]>,
-    /// The fixed reference frame index captured on the first process call
-    /// and used to compute stable sample indices for all subsequent cycles.
-    start_frame_idx: cell::OnceCell<u64>,
+    sync: CycleSync,
+    /// Where buffer size changes are reported, if anywhere.
+    events: Option<utils::queue::rtrb::Producer<JackEvent>>,
 }
 
 impl<TxCounter, RxCounter> DuplexProcessHandler<TxCounter, RxCounter> {
@@ -91,9 +486,75 @@ impl<TxCounter, RxCounter> DuplexProcessHandler<TxCounter, RxCounter> {
         Self {
             txs: inputs.into_iter().collect(),
             rxs: outputs.into_iter().collect(),
-            start_frame_idx: cell::OnceCell::new(),
+            sync: CycleSync::default(),
+            events: None,
         }
     }
+
+    /// Reports buffer size changes as [`JackEvent::BufferSize`] events pushed into
+    /// `events`, dropping them if it is full.
+    ///
+    /// Use an [`EventNotifier`] to be notified of sample rate changes.
+    #[inline(always)]
+    pub fn with_events(mut self, events: utils::queue::rtrb::Producer<JackEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Returns a handle to the number of drift errors encountered so far, counted once
+    /// per path and per cycle.
+    ///
+    /// It can be kept after the handler has been moved into a JACK client.
+    #[inline(always)]
+    pub fn drift_errors(&self) -> DriftErrors {
+        self.sync.drift_errors.clone()
+    }
+
+    /// Returns a handle to the number of drift recoveries performed so far.
+    ///
+    /// It can be kept after the handler has been moved into a JACK client.
+    #[inline(always)]
+    pub fn drift_recoveries(&self) -> DriftRecoveries {
+        self.sync.drift_recoveries.clone()
+    }
+
+    /// Returns a handle used to request a resynchronization of all paths, performed at
+    /// the start of the next cycle, the same way as a recovery.
+    ///
+    /// It can be kept after the handler has been moved into a JACK client.
+    #[inline(always)]
+    pub fn resync_request(&self) -> ResyncRequest {
+        self.sync.resync_request.clone()
+    }
+}
+
+/// Returns the index of the first sample of the cycle starting `frame_idx` frames after
+/// the reference frame, or `None` if it overflows.
+#[inline(always)]
+fn cycle_spl_idx(frame_idx: u64, base_idx: u64, n_ports: core::num::NonZeroU32) -> Option<u64> {
+    frame_idx
+        .checked_mul(n_ports.get().into())
+        .and_then(|idx| idx.checked_add(base_idx))
+}
+
+/// Returns the index a sender resumes from, after a recovery: its current position.
+#[inline(always)]
+fn tx_resync_idx<C: utils::queue::Counter, T>(tx: &utils::queue::IndexedTx<C, T>) -> u64 {
+    tx.current()
+}
+
+/// Returns the index a receiver resumes from, after a recovery: the tail of its queue,
+/// so that any queued samples are skipped.
+#[inline(always)]
+fn rx_resync_idx<C: utils::queue::Counter, T>(rx: &utils::queue::IndexedRx<C, T>) -> u64 {
+    rx.current()
+        .strict_add(rx.available_slots().try_into().unwrap())
+}
+
+/// Converts a number of queued samples to a number of frames, saturating.
+#[inline(always)]
+fn buffered_frames(n_samples: usize, n_ports: core::num::NonZeroU32) -> jack::Frames {
+    u32::try_from(n_samples).unwrap_or(u32::MAX) / n_ports
 }
 
 impl<RxCounter: Send + utils::queue::Counter, TxCounter: Send + utils::queue::Counter>
@@ -107,20 +568,39 @@ impl<RxCounter: Send + utils::queue::Counter, TxCounter: Send + utils::queue::Co
         // Beware: at the time of writing, in Pipewire's JACK shim, this
         // counter is completely unreliable, (can decrease or jump randomly)
         let this_cycle_frame_idx = u64::from(scope.last_frame_time());
-        let &first_cycle_frame_idx = self.start_frame_idx.get_or_init(|| this_cycle_frame_idx);
 
-        let frame_idx = this_cycle_frame_idx.strict_sub(first_cycle_frame_idx);
+        self.sync.cycle(
+            this_cycle_frame_idx,
+            scope.n_frames().into(),
+            scope,
+            &mut self.txs,
+            &mut self.rxs,
+        );
 
-        for JackTx { tx, interleaver } in self.txs.iter_mut() {
-            let spl_idx = frame_idx.strict_mul(interleaver.n_ports().get().try_into().unwrap());
-            tx.send(spl_idx, interleaver.interleave(scope).copied(), || 0.);
+        for JackTx {
+            tx,
+            interleaver,
+            latency,
+            stats,
+            ..
+        } in &mut self.txs
+        {
+            let n_queued = tx.capacity().strict_sub(tx.available_slots());
+            latency.set_buffered_frames(buffered_frames(n_queued, interleaver.n_ports()));
+            stats.accumulate(tx.take_stats());
         }
 
-        for JackRx { rx, interleaver } in &mut self.rxs {
-            let spl_idx = frame_idx.strict_mul(interleaver.n_ports().get().try_into().unwrap());
-            for (dest, src) in interleaver.interleave(scope).zip(rx.recv(spl_idx, || 0.)) {
-                *dest = src
-            }
+        for JackRx {
+            rx,
+            interleaver,
+            latency,
+            stats,
+            ..
+        } in &mut self.rxs
+        {
+            let n_buffered = rx.available_slots();
+            latency.set_buffered_frames(buffered_frames(n_buffered, interleaver.n_ports()));
+            stats.accumulate(rx.take_stats());
         }
 
         jack::Control::Continue
@@ -128,10 +608,290 @@ impl<RxCounter: Send + utils::queue::Counter, TxCounter: Send + utils::queue::Co
 
     /// Called when the JACK buffer size changes.
     ///
-    /// The current implementation ignores this event, but a real system
-    /// would typically tear down and rebuild internal buffering to
-    /// accommodate the new size.
-    fn buffer_size(&mut self, _: &jack::Client, _size: jack::Frames) -> jack::Control {
+    /// Sample indices are derived from absolute frame times, so they remain valid. The
+    /// change is only forwarded (see [`with_events`](DuplexProcessHandler::with_events)),
+    /// for the application to adjust any period-dependent buffering.
+    fn buffer_size(&mut self, _: &jack::Client, size: jack::Frames) -> jack::Control {
+        if let Some(events) = &mut self.events {
+            let _ = events.push(JackEvent::BufferSize(size));
+        }
+
         jack::Control::Continue
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::queue::{DriftDirection, GenericCounter, IndexedRx, IndexedTx, rtrb};
+
+    const N_PORTS: core::num::NonZeroU32 = core::num::NonZeroU32::new(2).unwrap();
+
+    /// Number of frames per cycle.
+    const N_FRAMES: u64 = 4;
+
+    /// Number of samples per cycle.
+    const N_SPLS: u64 = N_FRAMES * N_PORTS.get() as u64;
+
+    /// A sender, sending a ramp, without JACK ports.
+    struct TestTx {
+        tx: IndexedTx<GenericCounter, JackSample>,
+        base_idx: u64,
+        /// Value of the next sample sent.
+        next_spl: JackSample,
+    }
+
+    /// A receiver, without JACK ports.
+    struct TestRx {
+        rx: IndexedRx<GenericCounter, JackSample>,
+        base_idx: u64,
+        prefill: Prefill,
+        /// Samples received during the last cycle, padding excluded.
+        received: Vec<JackSample>,
+    }
+
+    impl SyncedPath<()> for TestTx {
+        fn n_ports(&self) -> core::num::NonZeroU32 {
+            N_PORTS
+        }
+
+        fn base_idx(&self) -> u64 {
+            self.base_idx
+        }
+
+        fn resync(&mut self) {
+            self.base_idx = tx_resync_idx(&self.tx);
+        }
+
+        fn transfer(&mut self, idx: Option<u64>, _: &()) -> bool {
+            let first = self.next_spl;
+            self.next_spl += N_SPLS as JackSample;
+            let spls = (0..N_SPLS).map(|i| first + i as JackSample);
+
+            idx.is_some_and(|idx| self.tx.send(idx, spls, || 0.).is_ok())
+        }
+    }
+
+    impl SyncedPath<()> for TestRx {
+        fn n_ports(&self) -> core::num::NonZeroU32 {
+            N_PORTS
+        }
+
+        fn base_idx(&self) -> u64 {
+            self.base_idx
+        }
+
+        fn resync(&mut self) {
+            self.base_idx = rx_resync_idx(&self.rx);
+            self.prefill.reset();
+        }
+
+        fn transfer(&mut self, idx: Option<u64>, _: &()) -> bool {
+            self.received.clear();
+
+            let (prefill, base_idx) = (&mut self.prefill, self.base_idx);
+
+            let idx = match idx.map(|idx| prefill.idx(idx, base_idx, &self.rx, N_SPLS)) {
+                // still prefilling
+                Some(None) => return true,
+                idx => idx.flatten(),
+            };
+
+            // like deinterleaving, at most a cycle's worth of samples are taken
+            match idx.and_then(|idx| self.rx.recv(idx, || f32::NAN).ok()) {
+                Some(spls) => {
+                    let spls = spls.into_iter().take(N_SPLS as usize);
+                    self.received.extend(spls.filter(|s| !s.is_nan()));
+                    true
+                }
+                None => false,
+            }
+        }
+    }
+
+    /// A sender looped back into a receiver, driven like the process handler drives its
+    /// paths.
+    struct Loopback {
+        sync: CycleSync,
+        tx: [TestTx; 1],
+        rx: [TestRx; 1],
+    }
+
+    impl Loopback {
+        fn new() -> Self {
+            let (tx, rx) = rtrb::RingBuffer::new(64);
+
+            Self {
+                sync: CycleSync::default(),
+                tx: [TestTx {
+                    tx: IndexedTx::new(tx, GenericCounter::new()),
+                    base_idx: 0,
+                    next_spl: 0.,
+                }],
+                rx: [TestRx {
+                    rx: IndexedRx::new(rx, GenericCounter::new()),
+                    base_idx: 0,
+                    prefill: Prefill::default(),
+                    received: Vec::new(),
+                }],
+            }
+        }
+
+        /// Only starts playback once `n_spls` samples are queued.
+        fn with_prefill(mut self, n_spls: u64) -> Self {
+            self.rx[0].prefill = Prefill::new(n_spls);
+            self
+        }
+
+        /// Runs the cycle starting at `frame_time`, and returns the samples received.
+        fn cycle(&mut self, frame_time: u64) -> &[JackSample] {
+            self.sync
+                .cycle(frame_time, N_FRAMES, &(), &mut self.tx, &mut self.rx);
+            &self.rx[0].received
+        }
+
+        /// Same as [`cycle`](Self::cycle), but only the sender runs, as if the receiver
+        /// didn't exist.
+        fn send_only(&mut self, frame_time: u64) {
+            let no_rx: &mut [TestRx] = &mut [];
+            self.sync
+                .cycle(frame_time, N_FRAMES, &(), &mut self.tx, no_rx);
+        }
+
+        /// Same as [`cycle`](Self::cycle), but only the receiver runs.
+        fn recv_only(&mut self, frame_time: u64) -> &[JackSample] {
+            let no_tx: &mut [TestTx] = &mut [];
+            self.sync
+                .cycle(frame_time, N_FRAMES, &(), no_tx, &mut self.rx);
+            &self.rx[0].received
+        }
+
+        /// Returns the number of drift errors and recoveries so far.
+        fn drift(&self) -> (u64, u64) {
+            (
+                self.sync.drift_errors.get(),
+                self.sync.drift_recoveries.get(),
+            )
+        }
+    }
+
+    #[test]
+    fn buffered_frames_saturate() {
+        assert_eq!(buffered_frames(0, N_PORTS), 0);
+        assert_eq!(buffered_frames(513, N_PORTS), 256);
+        assert_eq!(buffered_frames(usize::MAX, N_PORTS), u32::MAX / 2);
+    }
+
+    #[test]
+    fn cycle_spl_idx_overflow() {
+        assert_eq!(cycle_spl_idx(3, 5, N_PORTS), Some(11));
+        assert_eq!(cycle_spl_idx(1 << 63, 0, N_PORTS), None);
+        assert_eq!(cycle_spl_idx(u64::MAX / 2, 2, N_PORTS), None);
+    }
+
+    #[test]
+    fn recover_after_backward_jump() {
+        let mut lb = Loopback::new();
+
+        for cycle in 0..3 {
+            lb.cycle(1000 + cycle * N_FRAMES);
+        }
+
+        // the frame counter jumps backwards, the current cycle becomes the reference
+        // frame, and the stream continues seamlessly, nothing is padded or skipped
+        assert_eq!(lb.cycle(500), [24., 25., 26., 27., 28., 29., 30., 31.]);
+        assert_eq!(lb.drift(), (0, 1));
+
+        let next_spls = lb.cycle(500 + N_FRAMES);
+        assert_eq!(next_spls, [32., 33., 34., 35., 36., 37., 38., 39.]);
+        assert_eq!(lb.drift(), (0, 1));
+
+        for stats in [lb.tx[0].tx.take_stats(), lb.rx[0].rx.take_stats()] {
+            assert_eq!((stats.padded, stats.skipped), (0, 0));
+        }
+    }
+
+    #[test]
+    fn recover_after_drift_error() {
+        let mut lb = Loopback::new();
+
+        lb.cycle(0);
+
+        // samples queued, but not yet received, when the jump happens
+        lb.send_only(N_FRAMES);
+
+        // the frame counter jumps too far for the queues to compensate, both paths
+        // drift, the samples of this cycle are never sent
+        let frame_time = u64::MAX / 2 - 1;
+        assert!(lb.cycle(frame_time).is_empty());
+        assert_eq!(lb.drift(), (2, 1));
+
+        let spl_idx = cycle_spl_idx(frame_time, 0, N_PORTS).unwrap();
+        let err = lb.tx[0].tx.send(spl_idx, [], || 0.).unwrap_err();
+        assert_eq!(err.direction, DriftDirection::Ahead);
+
+        // the next cycle is the reference frame, the sender resumes from its current
+        // position, the receiver skips the samples queued before the jump
+        let spls = lb.cycle(frame_time + N_FRAMES);
+        assert_eq!(spls, [24., 25., 26., 27., 28., 29., 30., 31.]);
+        assert_eq!(lb.drift(), (2, 1));
+
+        assert_eq!(lb.tx[0].tx.take_stats().padded, 0);
+        assert_eq!(lb.rx[0].rx.take_stats().skipped, 8);
+    }
+
+    #[test]
+    fn prefill_delays_playback() {
+        let mut lb = Loopback::new().with_prefill(2 * N_SPLS);
+        let ramp = |cycle: u64| (cycle * N_SPLS..(cycle + 1) * N_SPLS).map(|s| s as JackSample);
+
+        // only one cycle is queued
+        assert_eq!(lb.cycle(1000), []);
+
+        // the sender runs first, so two are during the second one, playback then trails
+        // the sender by a cycle
+        for cycle in 1..4 {
+            let spls = lb.cycle(1000 + cycle * N_FRAMES).to_vec();
+            assert!(spls.iter().copied().eq(ramp(cycle - 1)), "cycle {cycle}");
+        }
+
+        assert_eq!(lb.rx[0].rx.available_slots(), N_SPLS as usize);
+        assert_eq!(lb.drift(), (0, 0));
+
+        // resynchronizing skips the queued cycle, and prefills again
+        lb.sync.resync_request.request();
+        assert_eq!(lb.cycle(1000 + 4 * N_FRAMES), []);
+        let spls = lb.cycle(1000 + 5 * N_FRAMES).to_vec();
+        assert!(spls.iter().copied().eq(ramp(4)));
+        assert_eq!(lb.drift(), (0, 0));
+    }
+
+    #[test]
+    fn resync_when_io_starts() {
+        const N_IDLE_CYCLES: u64 = 10;
+        let io_start = N_IDLE_CYCLES * N_FRAMES;
+
+        let mut stale = Loopback::new();
+        let mut lb = Loopback::new();
+
+        // the client runs before the peer starts streaming, no data comes in
+        for cycle in 0..N_IDLE_CYCLES {
+            assert!(stale.recv_only(cycle * N_FRAMES).is_empty());
+            assert!(lb.recv_only(cycle * N_FRAMES).is_empty());
+        }
+
+        // without resynchronizing, the first samples are deemed late, and skipped
+        assert!(stale.cycle(io_start).is_empty());
+
+        // IO starts, the current cycle becomes the reference frame
+        lb.sync.resync_request.request();
+
+        assert_eq!(lb.cycle(io_start), [0., 1., 2., 3., 4., 5., 6., 7.]);
+        let next_spls = lb.cycle(io_start + N_FRAMES);
+        assert_eq!(next_spls, [8., 9., 10., 11., 12., 13., 14., 15.]);
+
+        // requested resynchronizations aren't recoveries
+        assert_eq!(lb.drift(), (0, 0));
+        assert_eq!(lb.rx[0].rx.take_stats().skipped, 0);
+    }
+}
@@ -0,0 +1,531 @@
+//! Mixing of several network peers into a single set of JACK output ports.
+
+use crate::{
+    DriftRecoveries, JackSample, LatencyProbe, Prefill, QueueStatsProbe, ResyncRequest,
+    buffered_frames, interleaver, rx_resync_idx, utils,
+};
+
+use core::num::NonZeroU32;
+use utils::queue::{Counter, IndexedRx, rtrb};
+
+/// Request sent from a [`MixerHandle`] to its [`MixingProcessHandler`].
+enum MixerCommand<C> {
+    Add { slot: usize, peer: MixerPeer<C> },
+    Remove { slot: usize },
+}
+
+/// A peer's receive queue, occupying a slot of a [`MixingProcessHandler`].
+struct MixerPeer<C> {
+    rx: IndexedRx<C, JackSample>,
+    /// The peer's reference frame index, `None` until its first process cycle.
+    start_frame_idx: Option<u64>,
+    /// Queue index corresponding to the reference frame.
+    base_idx: u64,
+    prefill: Prefill,
+    resync: ResyncRequest,
+    fill_level: LatencyProbe,
+    queue_stats: QueueStatsProbe,
+}
+
+impl<C: Counter> MixerPeer<C> {
+    /// Makes `frame_idx` the peer's new reference frame, skipping to the tail of
+    /// its queue.
+    fn resync(&mut self, frame_idx: u64) {
+        self.start_frame_idx = Some(frame_idx);
+        self.base_idx = rx_resync_idx(&self.rx);
+        self.prefill.reset();
+    }
+
+    /// Adds a cycle of `n_frames` frames, starting at `frame_idx`, of the peer's samples
+    /// to `out`.
+    ///
+    /// Returns `false` if the peer drifted too far, it then stays silent this cycle.
+    fn mix_into<'a>(
+        &mut self,
+        frame_idx: u64,
+        n_frames: u64,
+        n_ports: NonZeroU32,
+        out: impl Iterator<Item = &'a mut JackSample>,
+    ) -> bool {
+        let n_ports = u64::from(n_ports.get());
+
+        if self.resync.take() {
+            self.start_frame_idx = None;
+        }
+
+        let start_frame_idx = match self.start_frame_idx {
+            Some(idx) => idx,
+            None => {
+                self.resync(frame_idx);
+                frame_idx
+            }
+        };
+
+        // Never panic in the process callback, it would take down the whole JACK server.
+        let spl_idx = frame_idx
+            .checked_sub(start_frame_idx)
+            .and_then(|frame_idx| frame_idx.checked_mul(n_ports))
+            .and_then(|idx| idx.checked_add(self.base_idx));
+
+        let n_spls = n_frames.saturating_mul(n_ports);
+
+        let spl_idx = match spl_idx {
+            Some(idx) => match self.prefill.idx(idx, self.base_idx, &self.rx, n_spls) {
+                Some(idx) => Some(idx),
+                // still prefilling, silent, without drifting
+                None => return true,
+            },
+            None => None,
+        };
+
+        match spl_idx.and_then(|idx| self.rx.recv(idx, || 0.).ok()) {
+            Some(samples) => {
+                for (dest, src) in out.zip(samples) {
+                    *dest += src
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Where a [`Mix`] writes a cycle's samples.
+trait MixOutput {
+    /// Returns the cycle's output samples, in interleaved order.
+    fn samples(&mut self) -> impl Iterator<Item = &mut JackSample>;
+}
+
+/// The output ports of a [`MixingProcessHandler`], during a process cycle.
+struct Ports<'a> {
+    interleaver: &'a mut interleaver::Interleaver<jack::AudioOut>,
+    scope: &'a jack::ProcessScope,
+}
+
+impl MixOutput for Ports<'_> {
+    #[inline(always)]
+    fn samples(&mut self) -> impl Iterator<Item = &mut JackSample> {
+        self.interleaver.interleave(self.scope)
+    }
+}
+
+impl MixOutput for [JackSample] {
+    #[inline(always)]
+    fn samples(&mut self) -> impl Iterator<Item = &mut JackSample> {
+        self.iter_mut()
+    }
+}
+
+/// The peers of a [`MixingProcessHandler`], and how they're mixed, independently of
+/// JACK.
+struct Mix<C> {
+    n_ports: NonZeroU32,
+    peers: Box<[Option<MixerPeer<C>>]>,
+    commands: rtrb::Consumer<MixerCommand<C>>,
+    /// Where removed peers are sent back, to be dropped outside of the real-time thread.
+    retired: rtrb::Producer<MixerPeer<C>>,
+    drift_recoveries: DriftRecoveries,
+}
+
+impl<C> Mix<C> {
+    /// Creates an empty mix of frames of `n_ports` samples, with room for `n_slots`
+    /// peers, and the handle used to add and remove them.
+    fn new(n_ports: NonZeroU32, n_slots: usize) -> (Self, MixerHandle<C>) {
+        // Every command targets a distinct peer, and at most n_slots peers are in
+        // flight at once (see MixerHandle), so neither ring buffer can overflow.
+        let (commands_tx, commands_rx) = rtrb::RingBuffer::new(n_slots.strict_mul(2));
+        let (retired_tx, retired_rx) = rtrb::RingBuffer::new(n_slots);
+
+        let mix = Self {
+            n_ports,
+            peers: core::iter::repeat_with(|| None).take(n_slots).collect(),
+            commands: commands_rx,
+            retired: retired_tx,
+            drift_recoveries: DriftRecoveries::default(),
+        };
+
+        let handle = MixerHandle {
+            n_ports,
+            commands: commands_tx,
+            retired: retired_rx,
+            occupied: core::iter::repeat_n(false, n_slots).collect(),
+            n_in_flight: 0,
+        };
+
+        (mix, handle)
+    }
+
+    /// Applies pending additions and removals.
+    fn apply_commands(&mut self) {
+        while let Ok(command) = self.commands.pop() {
+            match command {
+                MixerCommand::Add { slot, peer } => self.peers[slot] = Some(peer),
+                MixerCommand::Remove { slot } => {
+                    if let Some(peer) = self.peers[slot].take() {
+                        // can't fail, see new
+                        let _ = self.retired.push(peer);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<C: Counter> Mix<C> {
+    /// Writes the sum of all peers' samples, for the cycle of `n_frames` frames starting
+    /// at `frame_idx`, to `out`.
+    ///
+    /// Peers that drifted too far are silent this cycle, and resynchronized on the next.
+    fn process(&mut self, frame_idx: u64, n_frames: u64, out: &mut (impl MixOutput + ?Sized)) {
+        self.apply_commands();
+
+        out.samples().for_each(|dest| *dest = 0.);
+
+        for peer in self.peers.iter_mut().flatten() {
+            if !peer.mix_into(frame_idx, n_frames, self.n_ports, out.samples()) {
+                peer.resync(frame_idx.saturating_add(n_frames));
+                self.drift_recoveries.increment();
+            }
+
+            let n_buffered = peer.rx.available_slots();
+            peer.fill_level
+                .set_buffered_frames(buffered_frames(n_buffered, self.n_ports));
+            peer.queue_stats.accumulate(peer.rx.take_stats());
+        }
+    }
+}
+
+/// A JACK process handler summing the streams of any number of peers into one set of
+/// output ports.
+///
+/// Peers occupy the slots of a fixed-capacity array, allocated upon creation, and are
+/// added and removed through a [`MixerHandle`], so that the process callback never
+/// allocates nor deallocates.
+///
+/// Each peer keeps its own reference frame, and is resynchronized independently of
+/// the others, skipping to the tail of its queue, when it drifts too far. Such
+/// recoveries are counted, see [`drift_recoveries`](Self::drift_recoveries).
+pub struct MixingProcessHandler<C> {
+    interleaver: Box<interleaver::Interleaver<jack::AudioOut>>,
+    mix: Mix<C>,
+}
+
+impl<C> MixingProcessHandler<C> {
+    /// Creates a new mixing handler, writing to `ports`, with room for `n_slots` peers,
+    /// and the handle used to add and remove them.
+    ///
+    /// Returns `None` if `ports` is empty.
+    pub fn new(
+        ports: impl IntoIterator<Item = jack::Port<jack::AudioOut>>,
+        n_slots: usize,
+    ) -> Option<(Self, MixerHandle<C>)> {
+        let interleaver = interleaver::Interleaver::new(ports)?;
+        let (mix, handle) = Mix::new(interleaver.n_ports(), n_slots);
+
+        Some((Self { interleaver, mix }, handle))
+    }
+
+    /// Returns a handle to the number of drift recoveries performed so far, across
+    /// all peers.
+    #[inline(always)]
+    pub fn drift_recoveries(&self) -> DriftRecoveries {
+        self.mix.drift_recoveries.clone()
+    }
+}
+
+impl<C: Send + Counter> jack::ProcessHandler for MixingProcessHandler<C> {
+    fn process(&mut self, _client: &jack::Client, scope: &jack::ProcessScope) -> jack::Control {
+        let mut ports = Ports {
+            interleaver: &mut self.interleaver,
+            scope,
+        };
+
+        self.mix.process(
+            scope.last_frame_time().into(),
+            scope.n_frames().into(),
+            &mut ports,
+        );
+
+        jack::Control::Continue
+    }
+}
+
+/// Shared handles to a peer of a [`MixingProcessHandler`], returned when it's added.
+#[derive(Debug, Clone)]
+pub struct MixerSlot {
+    /// Index of the slot the peer occupies, see [`MixerHandle::remove_peer`].
+    pub idx: usize,
+    /// Resynchronizes the peer, at the start of the next cycle.
+    pub resync: ResyncRequest,
+    /// Number of frames queued for the peer.
+    pub fill_level: LatencyProbe,
+    /// Statistics of the peer's queue, accumulated every cycle.
+    pub queue_stats: QueueStatsProbe,
+}
+
+/// Adds and removes the peers of a [`MixingProcessHandler`], from outside the real-time
+/// thread.
+///
+/// Removed peers are sent back to the handle, and dropped upon the next call to
+/// [`add_peer`](Self::add_peer), [`remove_peer`](Self::remove_peer), or
+/// [`collect_retired`](Self::collect_retired).
+pub struct MixerHandle<C> {
+    n_ports: NonZeroU32,
+    commands: rtrb::Producer<MixerCommand<C>>,
+    retired: rtrb::Consumer<MixerPeer<C>>,
+    /// Slot occupancy, as of the commands sent so far.
+    occupied: Box<[bool]>,
+    /// Number of peers handed to the process handler, and not collected back yet.
+    n_in_flight: usize,
+}
+
+impl<C> MixerHandle<C> {
+    /// Returns the maximum number of peers.
+    #[inline(always)]
+    pub fn n_slots(&self) -> usize {
+        self.occupied.len()
+    }
+
+    /// Returns the number of peers currently added.
+    #[inline(always)]
+    pub fn n_peers(&self) -> usize {
+        self.occupied.iter().filter(|&&o| o).count()
+    }
+
+    /// Returns the number of samples per frame, i.e. of output ports.
+    #[inline(always)]
+    pub fn n_ports(&self) -> NonZeroU32 {
+        self.n_ports
+    }
+
+    /// Drops the removed peers sent back by the process handler.
+    ///
+    /// Returns the number of peers dropped.
+    pub fn collect_retired(&mut self) -> usize {
+        let mut n_collected = 0;
+
+        while self.retired.pop().is_ok() {
+            n_collected += 1;
+        }
+
+        self.n_in_flight = self.n_in_flight.strict_sub(n_collected);
+        n_collected
+    }
+
+    /// Adds a peer, streaming interleaved frames through `rx`, to the mix. Its playback
+    /// only starts once `prefill_frames` frames are queued, see
+    /// [`JackRx::with_prefill`](crate::JackRx::with_prefill).
+    ///
+    /// Returns the slot it occupies, or gives `rx` back if all slots are taken, if too
+    /// many removed peers haven't been sent back yet, or if too many commands are
+    /// pending.
+    pub fn add_peer(
+        &mut self,
+        rx: IndexedRx<C, JackSample>,
+        prefill_frames: usize,
+    ) -> Result<MixerSlot, IndexedRx<C, JackSample>> {
+        self.collect_retired();
+
+        if self.n_in_flight >= self.n_slots() {
+            return Err(rx);
+        }
+
+        let Some(idx) = self.occupied.iter().position(|&o| !o) else {
+            return Err(rx);
+        };
+
+        if self.commands.is_full() {
+            return Err(rx);
+        }
+
+        let slot = MixerSlot {
+            idx,
+            resync: ResyncRequest::default(),
+            fill_level: LatencyProbe::default(),
+            queue_stats: QueueStatsProbe::default(),
+        };
+
+        let n_ports = u64::from(self.n_ports.get());
+
+        let peer = MixerPeer {
+            rx,
+            start_frame_idx: None,
+            base_idx: 0,
+            prefill: Prefill::new(u64::try_from(prefill_frames).unwrap().strict_mul(n_ports)),
+            resync: slot.resync.clone(),
+            fill_level: slot.fill_level.clone(),
+            queue_stats: slot.queue_stats.clone(),
+        };
+
+        // can't fail, we just checked
+        let _ = self.commands.push(MixerCommand::Add { slot: idx, peer });
+
+        self.occupied[idx] = true;
+        self.n_in_flight += 1;
+
+        Ok(slot)
+    }
+
+    /// Removes the peer occupying `slot` from the mix.
+    ///
+    /// Returns `false` if the slot was already free, or if too many commands are
+    /// pending.
+    pub fn remove_peer(&mut self, slot: usize) -> bool {
+        self.collect_retired();
+
+        if !self.occupied.get(slot).is_some_and(|&o| o) {
+            return false;
+        }
+
+        if self.commands.is_full() {
+            return false;
+        }
+
+        let _ = self.commands.push(MixerCommand::Remove { slot });
+
+        self.occupied[slot] = false;
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::queue::GenericCounter;
+
+    const STEREO: NonZeroU32 = NonZeroU32::new(2).unwrap();
+
+    /// Adds a peer to `handle`, returning its slot, and the queue feeding it.
+    fn add_peer(
+        handle: &mut MixerHandle<GenericCounter>,
+        prefill_frames: usize,
+    ) -> (MixerSlot, rtrb::Producer<JackSample>) {
+        let (tx, rx) = rtrb::RingBuffer::new(64);
+        let rx = IndexedRx::new(rx, GenericCounter::new());
+
+        match handle.add_peer(rx, prefill_frames) {
+            Ok(slot) => (slot, tx),
+            Err(_) => panic!("no free slot"),
+        }
+    }
+
+    fn push(tx: &mut rtrb::Producer<JackSample>, samples: &[JackSample]) {
+        for &spl in samples {
+            tx.push(spl).unwrap();
+        }
+    }
+
+    /// Runs a cycle of 2 stereo frames, starting at `frame_idx`, and returns its output.
+    fn cycle(mix: &mut Mix<GenericCounter>, frame_idx: u64) -> [JackSample; 4] {
+        // the mix must overwrite whatever the buffers held
+        let mut out = [f32::NAN; 4];
+        mix.process(frame_idx, 2, out.as_mut_slice());
+        out
+    }
+
+    #[test]
+    fn sums_peers() {
+        let (mut mix, mut handle) = Mix::new(STEREO, 2);
+        let (a, mut tx_a) = add_peer(&mut handle, 0);
+        let (b, mut tx_b) = add_peer(&mut handle, 0);
+        assert_eq!((a.idx, b.idx, handle.n_peers()), (0, 1, 2));
+
+        // all slots are taken
+        let (_tx, rx) = rtrb::RingBuffer::new(1);
+        assert!(
+            handle
+                .add_peer(IndexedRx::new(rx, GenericCounter::new()), 0)
+                .is_err()
+        );
+
+        // nothing queued yet, both peers start at frame 0
+        assert_eq!(cycle(&mut mix, 0), [0.; 4]);
+
+        push(&mut tx_a, &[1., 2., 3., 4., 5., 6., 7., 8.]);
+        push(&mut tx_b, &[10., 20., 30., 40., 50., 60., 70., 80.]);
+
+        // frames 0 and 1 were due in the previous cycle, and are skipped
+        assert_eq!(cycle(&mut mix, 2), [55., 66., 77., 88.]);
+        assert_eq!(a.fill_level.buffered_frames(), 0);
+        assert_eq!(a.queue_stats.take().skipped, 4);
+
+        // a peer running dry leaves the others playing
+        push(&mut tx_b, &[1., 2., 3., 4.]);
+        assert_eq!(cycle(&mut mix, 4), [1., 2., 3., 4.]);
+        assert_eq!(a.queue_stats.take().padded, 0);
+        assert_eq!(mix.drift_recoveries.get(), 0);
+    }
+
+    #[test]
+    fn removes_peers() {
+        let (mut mix, mut handle) = Mix::new(STEREO, 1);
+        let (a, mut tx_a) = add_peer(&mut handle, 0);
+        assert_eq!(cycle(&mut mix, 0), [0.; 4]);
+
+        assert!(handle.remove_peer(a.idx));
+        assert!(!handle.remove_peer(a.idx));
+        assert_eq!(handle.n_peers(), 0);
+
+        // the removed peer hasn't been sent back yet
+        let (_tx, rx) = rtrb::RingBuffer::new(1);
+        assert!(
+            handle
+                .add_peer(IndexedRx::new(rx, GenericCounter::new()), 0)
+                .is_err()
+        );
+
+        push(&mut tx_a, &[1.; 4]);
+        assert_eq!(cycle(&mut mix, 2), [0.; 4]);
+        assert_eq!(handle.collect_retired(), 1);
+
+        let (b, mut tx_b) = add_peer(&mut handle, 0);
+        assert_eq!(b.idx, a.idx);
+        assert_eq!(cycle(&mut mix, 4), [0.; 4]);
+        push(&mut tx_b, &[0., 0., 0., 0., 1., 2., 3., 4.]);
+        assert_eq!(cycle(&mut mix, 6), [1., 2., 3., 4.]);
+    }
+
+    #[test]
+    fn recovers_peers_independently() {
+        let (mut mix, mut handle) = Mix::new(STEREO, 2);
+        let (_a, mut tx_a) = add_peer(&mut handle, 0);
+        assert_eq!(cycle(&mut mix, 0), [0.; 4]);
+
+        // b's reference frame is 1 << 62, a's sample index overflows there
+        let (_b, mut tx_b) = add_peer(&mut handle, 0);
+        let far = 1 << 62;
+        assert_eq!(cycle(&mut mix, far), [0.; 4]);
+        assert_eq!(mix.drift_recoveries.get(), 1);
+
+        // a resumed after the drifted cycle, b kept its reference frame
+        push(&mut tx_a, &[1., 2., 3., 4.]);
+        push(&mut tx_b, &[0., 0., 0., 0., 10., 20., 30., 40.]);
+        assert_eq!(cycle(&mut mix, far + 2), [11., 22., 33., 44.]);
+        assert_eq!(mix.drift_recoveries.get(), 1);
+    }
+
+    #[test]
+    fn prefills_and_resyncs_peers() {
+        let (mut mix, mut handle) = Mix::new(STEREO, 1);
+        let (a, mut tx_a) = add_peer(&mut handle, 3);
+        assert_eq!(cycle(&mut mix, 0), [0.; 4]);
+
+        // 2 of the 3 frames to prefill
+        push(&mut tx_a, &[1., 2., 3., 4.]);
+        assert_eq!(cycle(&mut mix, 2), [0.; 4]);
+        assert_eq!(a.fill_level.buffered_frames(), 2);
+
+        push(&mut tx_a, &[5., 6., 7., 8.]);
+        assert_eq!(cycle(&mut mix, 4), [1., 2., 3., 4.]);
+
+        // requested resynchronizations skip the queued samples, and prefill again
+        a.resync.request();
+        push(&mut tx_a, &[9., 10.]);
+        assert_eq!(cycle(&mut mix, 6), [0.; 4]);
+
+        push(&mut tx_a, &[11., 12., 13., 14., 15., 16.]);
+        assert_eq!(cycle(&mut mix, 8), [11., 12., 13., 14.]);
+        assert_eq!(mix.drift_recoveries.get(), 0);
+    }
+}
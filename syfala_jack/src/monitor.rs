@@ -0,0 +1,231 @@
+//! Periodic reporting of streaming statistics.
+//!
+//! Real-time and network paths only bump counters (or store fill levels), a [`Monitor`]
+//! periodically reads them from the control thread, and aggregates them into reports.
+
+use crate::utils;
+
+use std::{
+    fmt, net,
+    sync::{Arc, atomic, mpsc},
+    time,
+};
+
+/// Shared handle to the number of xruns reported by JACK.
+///
+/// It is also a [`jack::NotificationHandler`], counting xruns of the client it is
+/// passed to.
+#[derive(Debug, Clone, Default)]
+pub struct XrunCounter(Arc<atomic::AtomicU64>);
+
+impl XrunCounter {
+    /// Returns the number of xruns counted so far.
+    #[inline(always)]
+    pub fn get(&self) -> u64 {
+        self.0.load(atomic::Ordering::Relaxed)
+    }
+}
+
+impl jack::NotificationHandler for XrunCounter {
+    fn xrun(&mut self, _: &jack::Client) -> jack::Control {
+        self.0.fetch_add(1, atomic::Ordering::Relaxed);
+        jack::Control::Continue
+    }
+}
+
+/// Shared handle to the [`IndexedQueueStats`](utils::queue::IndexedQueueStats) of a
+/// path's queue, accumulated by the process handler, and taken by the monitor.
+///
+/// Each counter is updated, and reset, on its own, so a report may miss, or count twice,
+/// part of the cycle racing with it.
+#[derive(Debug, Clone, Default)]
+pub struct QueueStatsProbe(Arc<QueueStatsCounters>);
+
+#[derive(Debug)]
+struct QueueStatsCounters {
+    transferred: atomic::AtomicU64,
+    skipped: atomic::AtomicU64,
+    padded: atomic::AtomicU64,
+    /// `usize::MAX` if none was observed.
+    min_slots: atomic::AtomicUsize,
+    /// One more than the highest number of slots observed, `0` if none was.
+    max_slots: atomic::AtomicUsize,
+}
+
+impl Default for QueueStatsCounters {
+    fn default() -> Self {
+        Self {
+            transferred: atomic::AtomicU64::new(0),
+            skipped: atomic::AtomicU64::new(0),
+            padded: atomic::AtomicU64::new(0),
+            min_slots: atomic::AtomicUsize::new(usize::MAX),
+            max_slots: atomic::AtomicUsize::new(0),
+        }
+    }
+}
+
+impl QueueStatsProbe {
+    /// Adds `stats` to the accumulated ones, without blocking.
+    ///
+    /// Done by the process handler, but can also be used to simulate a queue's activity.
+    #[inline]
+    pub fn accumulate(&self, stats: utils::queue::IndexedQueueStats) {
+        let counters = &*self.0;
+        let relaxed = atomic::Ordering::Relaxed;
+
+        counters.transferred.fetch_add(stats.transferred, relaxed);
+        counters.skipped.fetch_add(stats.skipped, relaxed);
+        counters.padded.fetch_add(stats.padded, relaxed);
+
+        if let Some(n) = stats.min_slots {
+            counters.min_slots.fetch_min(n, relaxed);
+        }
+
+        if let Some(n) = stats.max_slots {
+            counters.max_slots.fetch_max(n.saturating_add(1), relaxed);
+        }
+    }
+
+    /// Returns the stats accumulated since the previous call, and resets them.
+    pub fn take(&self) -> utils::queue::IndexedQueueStats {
+        let counters = &*self.0;
+        let relaxed = atomic::Ordering::Relaxed;
+
+        utils::queue::IndexedQueueStats {
+            transferred: counters.transferred.swap(0, relaxed),
+            skipped: counters.skipped.swap(0, relaxed),
+            padded: counters.padded.swap(0, relaxed),
+            min_slots: Some(counters.min_slots.swap(usize::MAX, relaxed))
+                .filter(|&n| n != usize::MAX),
+            max_slots: counters.max_slots.swap(0, relaxed).checked_sub(1),
+        }
+    }
+}
+
+/// Statistics of one of a peer's streams, over a report's interval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StreamReport {
+    /// Padder counters, accumulated over the interval.
+    pub padder: utils::PadderStats,
+    /// Counters of the stream's queue, on the process handler's side, accumulated over
+    /// the interval.
+    pub queue: utils::queue::IndexedQueueStats,
+    /// Number of frames buffered in the stream's queue, when the report was made.
+    pub buffered_frames: jack::Frames,
+}
+
+/// Statistics of a peer, over the interval since its previous report.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PeerReport {
+    /// The peer's address.
+    pub addr: net::SocketAddr,
+    /// Name of the JACK client exposing the peer's audio.
+    pub client_name: String,
+    /// Time elapsed since the previous report (or since the peer connected).
+    pub interval: time::Duration,
+    /// One report per stream.
+    pub streams: Box<[StreamReport]>,
+    /// Drift recoveries of the peer's process handler, since it connected.
+    pub drift_recoveries: u64,
+    /// Xruns of the peer's JACK client, since it connected.
+    pub xruns: u64,
+}
+
+/// Where a [`Monitor`] delivers its reports.
+pub enum ReportSink {
+    /// Reports are passed to a callback.
+    Callback(Box<dyn FnMut(&PeerReport)>),
+    /// Reports are sent over a channel, they are dropped if the receiver is gone.
+    Channel(mpsc::Sender<PeerReport>),
+}
+
+impl fmt::Debug for ReportSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Callback(_) => f.write_str("Callback(..)"),
+            Self::Channel(tx) => f.debug_tuple("Channel").field(tx).finish(),
+        }
+    }
+}
+
+/// Periodically produces a [`PeerReport`] for each peer.
+#[derive(Debug)]
+pub struct Monitor {
+    period: time::Duration,
+    sink: ReportSink,
+}
+
+impl Monitor {
+    /// Creates a new monitor, reporting every `period`, to `sink`.
+    #[inline(always)]
+    pub fn new(period: time::Duration, sink: ReportSink) -> Self {
+        Self { period, sink }
+    }
+
+    /// Creates a new monitor, reporting every second, to `sink`.
+    #[inline(always)]
+    pub fn every_second(sink: ReportSink) -> Self {
+        Self::new(time::Duration::from_secs(1), sink)
+    }
+
+    /// Returns the reporting period.
+    #[inline(always)]
+    pub fn period(&self) -> time::Duration {
+        self.period
+    }
+
+    /// Delivers `report` to the sink.
+    pub(crate) fn deliver(&mut self, report: PeerReport) {
+        match &mut self.sink {
+            ReportSink::Callback(f) => f(&report),
+            ReportSink::Channel(tx) => {
+                let _ = tx.send(report);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::queue::IndexedQueueStats;
+
+    #[test]
+    fn queue_stats_accumulate() {
+        let probe = QueueStatsProbe::default();
+        assert_eq!(probe.take(), IndexedQueueStats::default());
+
+        probe.accumulate(IndexedQueueStats {
+            transferred: 64,
+            skipped: 2,
+            padded: 0,
+            min_slots: Some(10),
+            max_slots: Some(40),
+        });
+
+        // from a cycle that didn't touch the queue
+        probe.accumulate(IndexedQueueStats::default());
+
+        probe.accumulate(IndexedQueueStats {
+            transferred: 32,
+            skipped: 0,
+            padded: 5,
+            min_slots: Some(0),
+            max_slots: Some(20),
+        });
+
+        assert_eq!(
+            probe.clone().take(),
+            IndexedQueueStats {
+                transferred: 96,
+                skipped: 2,
+                padded: 5,
+                min_slots: Some(0),
+                max_slots: Some(40),
+            }
+        );
+
+        // reset
+        assert_eq!(probe.take(), IndexedQueueStats::default());
+    }
+}
@@ -0,0 +1,163 @@
+//! Generation of JACK client names for network peers.
+
+use std::{collections::HashSet, net};
+
+/// Prefix of all generated client names.
+const PREFIX: &str = "syfala";
+
+/// Returns the maximum length, in bytes, of a JACK client name.
+#[inline(always)]
+fn max_name_len() -> usize {
+    // excludes the null terminator
+    *jack::CLIENT_NAME_SIZE
+}
+
+/// Builds the client name of the peer at `addr`, truncated to `max_len` bytes.
+///
+/// The name is `syfala_<ip>_<port>`, where every character of the IP address that
+/// isn't alphanumeric (e.g. `.`, and IPv6's `:`, which JACK uses as a separator) is
+/// replaced by an underscore.
+fn sanitized_name(addr: net::SocketAddr, max_len: usize) -> String {
+    let ip: String = addr
+        .ip()
+        .to_string()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    let mut name = format!("{PREFIX}_{ip}_{}", addr.port());
+    // the name is pure ASCII, so any length is a char boundary
+    name.truncate(max_len);
+    name
+}
+
+/// Returns the JACK client name of the peer at `addr`.
+///
+/// The name is `syfala_<ip>_<port>`, where every character of the IP address that
+/// isn't alphanumeric is replaced by an underscore, truncated to JACK's client name
+/// length limit.
+///
+/// Use [`ClientNames`] to keep names unique across peers.
+pub fn client_name_for(addr: net::SocketAddr) -> String {
+    sanitized_name(addr, max_name_len())
+}
+
+/// Set of JACK client names currently in use, ensuring generated names are unique.
+///
+/// Truncation can make the names of two different peers collide. In that case, a
+/// numeric suffix (`_2`, `_3`, ...) is appended, the name being truncated further to
+/// make room for it.
+#[derive(Debug, Clone, Default)]
+pub struct ClientNames {
+    taken: HashSet<String>,
+}
+
+impl ClientNames {
+    /// Creates an empty set of names.
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a unique client name for the peer at `addr`, and marks it as taken.
+    pub fn claim(&mut self, addr: net::SocketAddr) -> String {
+        self.claim_truncated(addr, max_name_len())
+    }
+
+    /// Same as [`claim`](Self::claim), with names truncated to `max_len` bytes.
+    fn claim_truncated(&mut self, addr: net::SocketAddr, max_len: usize) -> String {
+        let base = sanitized_name(addr, max_len);
+
+        let mut name = base.clone();
+
+        for i in 2u64.. {
+            if !self.taken.contains(&name) {
+                break;
+            }
+
+            let suffix = format!("_{i}");
+            name.clone_from(&base);
+            name.truncate(max_len.saturating_sub(suffix.len()));
+            name.push_str(&suffix);
+        }
+
+        self.taken.insert(name.clone());
+        name
+    }
+
+    /// Marks `name` as free again, returns whether it was taken.
+    #[inline(always)]
+    pub fn release(&mut self, name: &str) -> bool {
+        self.taken.remove(name)
+    }
+
+    /// Returns `true` if `name` is currently taken.
+    #[inline(always)]
+    pub fn is_taken(&self, name: &str) -> bool {
+        self.taken.contains(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> net::SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn sanitized_ipv4() {
+        let name = sanitized_name(addr("192.168.1.20:6910"), 64);
+        assert_eq!(name, "syfala_192_168_1_20_6910");
+    }
+
+    #[test]
+    fn sanitized_ipv6() {
+        let name = sanitized_name(addr("[fe80::1:abcd]:6910"), 64);
+        assert_eq!(name, "syfala_fe80__1_abcd_6910");
+
+        // zone ids aren't displayed by `IpAddr`
+        let name = sanitized_name(addr("[fe80::1%3]:80"), 64);
+        assert_eq!(name, "syfala_fe80__1_80");
+
+        let name = sanitized_name(addr("[::ffff:10.0.0.1]:1"), 64);
+        assert_eq!(name, "syfala___ffff_10_0_0_1_1");
+        assert!(!name.contains(':'));
+    }
+
+    #[test]
+    fn truncation_boundary() {
+        let a = addr("10.0.0.1:6910");
+        let full = "syfala_10_0_0_1_6910";
+
+        assert_eq!(sanitized_name(a, full.len() + 1), full);
+        assert_eq!(sanitized_name(a, full.len()), full);
+        assert_eq!(sanitized_name(a, full.len() - 1), &full[..full.len() - 1]);
+        assert_eq!(sanitized_name(a, 0), "");
+    }
+
+    #[test]
+    fn colliding_truncated_names() {
+        let mut names = ClientNames::new();
+
+        // both truncated to "syfala_10_0_0_1_69"
+        let first = names.claim_truncated(addr("10.0.0.1:6910"), 18);
+        let second = names.claim_truncated(addr("10.0.0.1:6911"), 18);
+        let third = names.claim_truncated(addr("10.0.0.1:6912"), 18);
+
+        assert_eq!(first, "syfala_10_0_0_1_69");
+        assert_eq!(second, "syfala_10_0_0_1__2");
+        assert_eq!(third, "syfala_10_0_0_1__3");
+        assert!([&first, &second, &third].iter().all(|n| n.len() <= 18));
+
+        assert!(names.release(&second));
+        assert!(!names.release(&second));
+        assert!(!names.is_taken(&second));
+        assert!(names.is_taken(&third));
+
+        // freed names are reused
+        let fourth = names.claim_truncated(addr("10.0.0.1:6913"), 18);
+        assert_eq!(fourth, second);
+    }
+}
@@ -0,0 +1,321 @@
+//! Per-message cost of dispatching incoming audio messages to the generic client's
+//! context, validation included, for servers with 8 and 64 input streams. Decoding the
+//! same messages, without dispatching them, is the baseline.
+//!
+//! The cost of validation alone is measured against an ungated baseline, only trimming
+//! payloads, and reported. Regressions are caught by `scripts/bench_regression.py`, like
+//! those of any other benchmark.
+
+use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
+use syfala_network::{
+    SyncUdpSock,
+    proto::{
+        AudioMessageHeader, AudioStreamMessageHeader,
+        format::{Format, StreamFormats},
+        message::{Capabilities, Error, Server, StreamSelection, server},
+    },
+    udp::client::{
+        Client as _, ClientSocket,
+        generic::{
+            AudioRejection, ClientContext, GenericClient, IOActiveContext, IOInactiveContext,
+            IOStartPendingContext, IOStopPendingContext, StreamGate,
+        },
+    },
+};
+
+/// Size of the payload of audio messages, fitting in a typical ethernet MTU.
+const AUDIO_PAYLOAD_LEN: usize = 1400;
+
+const SERVER_ADDR: core::net::SocketAddr =
+    core::net::SocketAddr::new(core::net::IpAddr::V4(core::net::Ipv4Addr::LOCALHOST), 6910);
+
+/// Socket discarding everything sent through it.
+struct NullSock;
+
+impl SyncUdpSock for NullSock {
+    fn send(&self, _bytes: &[u8], _dest_addr: core::net::SocketAddr) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn recv(
+        &self,
+        _bytes: &mut [u8],
+    ) -> std::io::Result<(usize, core::net::SocketAddr, std::time::Instant)> {
+        Err(std::io::ErrorKind::WouldBlock.into())
+    }
+
+    fn set_recv_timeout(&self, _timeout: Option<core::time::Duration>) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Context accepting every connection, immediately starting IO, and counting received
+/// payload bytes and rejected messages.
+#[derive(Default)]
+struct Context {
+    n_bytes: usize,
+    n_rejected: usize,
+}
+
+/// The state of a connected server, whatever its IO state.
+struct Connection;
+
+impl ClientContext for Context {
+    type IOInactive = Connection;
+
+    fn connect(
+        &mut self,
+        _addr: core::net::SocketAddr,
+        _stream_formats: StreamFormats,
+    ) -> Result<Connection, Error> {
+        Ok(Connection)
+    }
+
+    fn unknown_message(&mut self, _addr: core::net::SocketAddr) {}
+
+    fn audio_rejected(
+        &mut self,
+        _addr: core::net::SocketAddr,
+        _header: AudioMessageHeader,
+        _reason: AudioRejection,
+    ) {
+        self.n_rejected += 1;
+    }
+}
+
+impl IOInactiveContext for Connection {
+    type Context = Context;
+    type IOStartPending = Self;
+
+    fn poll_start_io(self, _cx: &mut Context) -> Result<Self, Self> {
+        Ok(self)
+    }
+}
+
+impl IOStartPendingContext for Connection {
+    type Context = Context;
+    type IOActive = Self;
+
+    fn start_io(self, _cx: &mut Context, _selection: StreamSelection) -> Self {
+        self
+    }
+
+    fn start_io_refused(self, _cx: &mut Context) -> Self {
+        self
+    }
+
+    fn start_io_failed(&mut self, _cx: &mut Context) {}
+}
+
+impl IOActiveContext for Connection {
+    type Context = Context;
+    type IOStopPending = Self;
+
+    fn on_audio(
+        &mut self,
+        cx: &mut Context,
+        _timestamp: std::time::Instant,
+        _header: AudioMessageHeader,
+        data: &[u8],
+    ) {
+        cx.n_bytes = cx.n_bytes.wrapping_add(black_box(data).len());
+    }
+
+    fn poll_stop_io(self, _cx: &mut Context) -> Result<Self, Self> {
+        Err(self)
+    }
+}
+
+impl IOStopPendingContext for Connection {
+    type Context = Context;
+
+    fn stop_io(self, _cx: &mut Context) -> Self {
+        self
+    }
+
+    fn stop_io_refused(self, _cx: &mut Context) -> Self {
+        self
+    }
+
+    fn stop_io_failed(&mut self, _cx: &mut Context) {}
+}
+
+fn encode(msg: Server, payload: &[u8]) -> Vec<u8> {
+    let mut datagram = syfala_network::server_message_encode(msg, vec![]).unwrap();
+    datagram.extend_from_slice(payload);
+    datagram
+}
+
+/// Returns a client with a connected server, with `n_streams` input streams and active
+/// IO.
+fn active_client(sock: &ClientSocket<NullSock>, n_streams: usize) -> GenericClient<Context> {
+    let mut client = GenericClient::new(Context::default());
+    let now = std::time::Instant::now();
+
+    let connect = Server::Connect {
+        epoch: 0xdead_beef,
+        capabilities: Capabilities::NONE,
+        formats: StreamFormats {
+            inputs: vec![Format::default(); n_streams].into(),
+            outputs: Box::new([]),
+        },
+    };
+
+    client
+        .on_datagram(sock, SERVER_ADDR, now, &encode(connect, &[]))
+        .unwrap();
+    // sends the IO start request
+    client.on_timeout(sock).unwrap();
+    client
+        .on_datagram(sock, SERVER_ADDR, now, &encode(Server::START_IO_OK, &[]))
+        .unwrap();
+
+    client
+}
+
+/// Returns two consecutive audio datagrams for each of `n_streams` streams, so that
+/// dispatching them in a loop never hits the duplicate check.
+fn audio_datagrams(n_streams: u32) -> Vec<Vec<u8>> {
+    let payload = [0x55; AUDIO_PAYLOAD_LEN];
+
+    (0..2u64)
+        .flat_map(|i| {
+            (0..n_streams).map(move |stream_idx| AudioMessageHeader {
+                stream_idx,
+                stream_msg: AudioStreamMessageHeader {
+                    byte_idx: i * AUDIO_PAYLOAD_LEN as u64,
+                    n_bytes: AUDIO_PAYLOAD_LEN as u32,
+                },
+            })
+        })
+        .map(|header| encode(Server::audio(header), &payload))
+        .collect()
+}
+
+/// Decodes audio datagrams into their headers and payloads.
+fn decode_audio(datagrams: &[Vec<u8>]) -> Vec<(AudioMessageHeader, &[u8])> {
+    datagrams
+        .iter()
+        .map(
+            |datagram| match syfala_network::server_message_decode(datagram).unwrap() {
+                (Server::Connected(server::Connected::Audio(header)), _, payload) => {
+                    (header, payload)
+                }
+                _ => unreachable!(),
+            },
+        )
+        .collect()
+}
+
+/// Trims the payload of an audio message to its advertised length, without validating
+/// anything else, the baseline validation is measured against.
+#[inline(always)]
+fn ungated<'a>(header: &AudioMessageHeader, payload: &'a [u8]) -> Option<&'a [u8]> {
+    usize::try_from(header.stream_msg.n_bytes)
+        .ok()
+        .and_then(|n| payload.get(..n))
+}
+
+/// Returns the smallest average cost of `f` per message, over a few rounds of passes
+/// through `messages`.
+fn per_message_cost(
+    messages: &[(AudioMessageHeader, &[u8])],
+    mut f: impl FnMut(&AudioMessageHeader, &[u8]) -> usize,
+) -> core::time::Duration {
+    const N_PASSES: u32 = 10_000;
+
+    (0..10)
+        .map(|_| {
+            let start = std::time::Instant::now();
+            for _ in 0..N_PASSES {
+                for (header, payload) in messages {
+                    black_box(f(black_box(header), black_box(payload)));
+                }
+            }
+            start.elapsed() / (N_PASSES * messages.len() as u32)
+        })
+        .min()
+        .unwrap()
+}
+
+fn bench_audio_gate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("audio_gate");
+    group.throughput(Throughput::Elements(1));
+
+    for n_streams in [8, 64] {
+        let datagrams = audio_datagrams(n_streams);
+        let messages = decode_audio(&datagrams);
+        let formats = vec![Format::default(); n_streams as usize];
+
+        group.bench_function(BenchmarkId::new("ungated", n_streams), |b| {
+            let mut messages = messages.iter().cycle();
+            b.iter(|| {
+                let (header, payload) = messages.next().unwrap();
+                ungated(black_box(header), black_box(payload))
+            })
+        });
+
+        group.bench_function(BenchmarkId::new("gated", n_streams), |b| {
+            let mut gate = StreamGate::new(&formats, true);
+            let mut messages = messages.iter().cycle();
+            b.iter(|| {
+                let (header, payload) = messages.next().unwrap();
+                gate.check(black_box(header), black_box(payload))
+            })
+        });
+
+        let baseline = per_message_cost(&messages, |header, payload| {
+            ungated(header, payload).map_or(0, <[u8]>::len)
+        });
+        let mut gate = StreamGate::new(&formats, true);
+        let gated = per_message_cost(&messages, |header, payload| {
+            gate.check(header, payload).map_or(0, <[u8]>::len)
+        });
+
+        println!(
+            "audio_gate/{n_streams}: validation costs {gated:?} per message, {:?} over the \
+            ungated baseline",
+            gated.saturating_sub(baseline),
+        );
+    }
+
+    group.finish();
+}
+
+fn bench_audio_dispatch(c: &mut Criterion) {
+    let sock = ClientSocket::new(NullSock);
+    let mut group = c.benchmark_group("audio_dispatch");
+    group.throughput(Throughput::Elements(1));
+
+    for n_streams in [8, 64] {
+        let datagrams = audio_datagrams(n_streams);
+
+        group.bench_function(BenchmarkId::new("decode", n_streams), |b| {
+            let mut datagrams = datagrams.iter().cycle();
+            b.iter(|| {
+                let datagram = datagrams.next().unwrap();
+                syfala_network::server_message_decode_ref(black_box(datagram)).unwrap()
+            })
+        });
+
+        group.bench_function(BenchmarkId::new("dispatch", n_streams), |b| {
+            let mut client = active_client(&sock, n_streams as usize);
+            let now = std::time::Instant::now();
+            let mut datagrams = datagrams.iter().cycle();
+            b.iter(|| {
+                let datagram = datagrams.next().unwrap();
+                client
+                    .on_datagram(&sock, SERVER_ADDR, now, black_box(datagram))
+                    .unwrap()
+            });
+            // every message must have made it through the gate
+            assert_eq!(client.callbacks().n_rejected, 0);
+            assert!(client.callbacks().n_bytes > 0);
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_audio_gate, bench_audio_dispatch);
+criterion_main!(benches);
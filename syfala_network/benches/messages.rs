@@ -0,0 +1,239 @@
+//! Encoding and decoding of every message variant, audio messages carrying a typical
+//! payload. The allocations made by both server message decoding paths are also counted.
+
+use core::{mem::MaybeUninit, sync::atomic};
+use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
+use std::alloc::{GlobalAlloc, Layout, System};
+use syfala_network::{
+    postcard,
+    proto::{
+        AudioMessageHeader, AudioStreamMessageHeader,
+        format::{Format, StreamFormats},
+        message::{Capabilities, Client, Server, StreamSelection, server},
+    },
+};
+
+/// Allocator counting allocations, to compare those of the decoding paths.
+struct CountingAlloc;
+
+static N_ALLOCS: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
+
+// SAFETY: all calls are forwarded to the system allocator
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        N_ALLOCS.fetch_add(1, atomic::Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        N_ALLOCS.fetch_add(1, atomic::Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+/// Returns the number of allocations made by `f`.
+fn count_allocs<R>(f: impl FnOnce() -> R) -> usize {
+    let start = N_ALLOCS.load(atomic::Ordering::Relaxed);
+    black_box(f());
+    N_ALLOCS.load(atomic::Ordering::Relaxed) - start
+}
+
+/// Size of the payload of audio messages, fitting in a typical ethernet MTU.
+const AUDIO_PAYLOAD_LEN: usize = 1400;
+
+const AUDIO_HEADER: AudioMessageHeader = AudioMessageHeader {
+    stream_idx: 0,
+    stream_msg: AudioStreamMessageHeader {
+        byte_idx: 1 << 32,
+        n_bytes: AUDIO_PAYLOAD_LEN as u32,
+    },
+};
+
+fn client_messages() -> [(&'static str, Client); 11] {
+    [
+        ("Discovery", Client::Discovery),
+        ("CONN_SUCCESS", Client::CONN_SUCCESS),
+        ("CONN_FAILED", Client::CONN_FAILED),
+        ("CONN_REFUSED", Client::CONN_REFUSED),
+        ("START_IO", Client::START_IO),
+        (
+            "start_io_selective",
+            Client::start_io(StreamSelection::Mask(0b1)),
+        ),
+        ("STOP_IO", Client::STOP_IO),
+        ("HEARTBEAT", Client::HEARTBEAT),
+        ("QUERY_STATUS", Client::QUERY_STATUS),
+        ("Disconnect", Client::Disconnect),
+        ("audio", Client::audio(AUDIO_HEADER)),
+    ]
+}
+
+fn server_messages() -> [(&'static str, Server); 11] {
+    [
+        (
+            "Connect",
+            Server::Connect {
+                epoch: 0xdead_beef,
+                capabilities: Capabilities::SELECTIVE_START,
+                formats: StreamFormats {
+                    inputs: Box::new([Format::default(); 2]),
+                    outputs: Box::new([Format::default(); 2]),
+                },
+            },
+        ),
+        ("START_IO_OK", Server::START_IO_OK),
+        ("START_IO_FAILED", Server::START_IO_FAILED),
+        ("START_IO_REFUSED", Server::START_IO_REFUSED),
+        ("STOP_IO_OK", Server::STOP_IO_OK),
+        ("STOP_IO_FAILED", Server::STOP_IO_FAILED),
+        ("STOP_IO_REFUSED", Server::STOP_IO_REFUSED),
+        ("HEARTBEAT", Server::HEARTBEAT),
+        (
+            "status",
+            Server::status(server::Status {
+                io_active: true,
+                uptime_ms: 90_000,
+                active_streams: 4,
+            }),
+        ),
+        ("Disconnect", Server::Disconnect),
+        ("audio", Server::audio(AUDIO_HEADER)),
+    ]
+}
+
+/// Encodes a whole datagram, like the sockets do: the message, then, for audio
+/// messages, the payload right after it.
+#[inline(always)]
+fn encode_datagram<M>(
+    msg: M,
+    payload: Option<&[u8]>,
+    buf: &mut [u8],
+    encode: impl FnOnce(M, &mut [u8]) -> postcard::Result<&mut [u8]>,
+) -> usize {
+    let buf_len = buf.len();
+    let rest = encode(msg, buf).unwrap();
+    let header_len = buf_len - rest.len();
+
+    let payload = payload.unwrap_or_default();
+    rest[..payload.len()].copy_from_slice(payload);
+
+    header_len + payload.len()
+}
+
+/// Same as [`encode_datagram`], but into an uninitialized buffer, like the sockets'
+/// send methods.
+#[inline(always)]
+fn encode_datagram_uninit<M>(
+    msg: M,
+    payload: Option<&[u8]>,
+    buf: &mut [MaybeUninit<u8>],
+    encode: impl FnOnce(M, &mut [MaybeUninit<u8>]) -> postcard::Result<&mut [u8]>,
+) -> usize {
+    let header_len = encode(msg, buf).unwrap().len();
+
+    let payload = payload.unwrap_or_default();
+    buf[header_len..][..payload.len()].write_copy_of_slice(payload);
+
+    header_len + payload.len()
+}
+
+fn bench_client(c: &mut Criterion) {
+    let payload = [0x55; AUDIO_PAYLOAD_LEN];
+    let mut group = c.benchmark_group("client_message");
+
+    for (name, msg) in client_messages() {
+        let payload = (msg == Client::audio(AUDIO_HEADER)).then_some(&payload[..]);
+
+        let mut datagram = [0; 2000];
+        let len = encode_datagram(msg, payload, &mut datagram, |m, b| {
+            syfala_network::client_message_encode(m, b)
+        });
+        let datagram = &datagram[..len];
+
+        group.throughput(Throughput::Bytes(len as u64));
+
+        group.bench_function(BenchmarkId::new("encode", name), |b| {
+            let mut buf = [0; 2000];
+            b.iter(|| {
+                encode_datagram(black_box(msg), payload, &mut buf, |m, b| {
+                    syfala_network::client_message_encode(m, b)
+                })
+            })
+        });
+
+        group.bench_function(BenchmarkId::new("encode_uninit", name), |b| {
+            let mut buf = [MaybeUninit::uninit(); 2000];
+            b.iter(|| {
+                encode_datagram_uninit(black_box(msg), payload, &mut buf, |m, b| {
+                    syfala_network::client_message_encode_uninit(m, b)
+                })
+            })
+        });
+
+        group.bench_function(BenchmarkId::new("decode", name), |b| {
+            b.iter(|| syfala_network::client_message_decode(black_box(datagram)).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_server(c: &mut Criterion) {
+    let payload = [0x55; AUDIO_PAYLOAD_LEN];
+    let mut group = c.benchmark_group("server_message");
+
+    for (name, msg) in server_messages() {
+        let payload = (msg == Server::audio(AUDIO_HEADER)).then_some(&payload[..]);
+
+        let mut datagram = [0; 2000];
+        let len = encode_datagram(msg.clone(), payload, &mut datagram, |m, b| {
+            syfala_network::server_message_encode(m, b)
+        });
+        let datagram = &datagram[..len];
+
+        group.throughput(Throughput::Bytes(len as u64));
+
+        group.bench_function(BenchmarkId::new("encode", name), |b| {
+            let mut buf = [0; 2000];
+            b.iter(|| {
+                encode_datagram(black_box(msg.clone()), payload, &mut buf, |m, b| {
+                    syfala_network::server_message_encode(m, b)
+                })
+            })
+        });
+
+        group.bench_function(BenchmarkId::new("encode_uninit", name), |b| {
+            let mut buf = [MaybeUninit::uninit(); 2000];
+            b.iter(|| {
+                encode_datagram_uninit(black_box(msg.clone()), payload, &mut buf, |m, b| {
+                    syfala_network::server_message_encode_uninit(m, b)
+                })
+            })
+        });
+
+        group.bench_function(BenchmarkId::new("decode", name), |b| {
+            b.iter(|| syfala_network::server_message_decode(black_box(datagram)).unwrap())
+        });
+
+        group.bench_function(BenchmarkId::new("decode_ref", name), |b| {
+            b.iter(|| syfala_network::server_message_decode_ref(black_box(datagram)).unwrap())
+        });
+
+        let n_allocs = count_allocs(|| syfala_network::server_message_decode(datagram));
+        let n_allocs_ref = count_allocs(|| syfala_network::server_message_decode_ref(datagram));
+        println!("server_message/{name}: {n_allocs} allocations, {n_allocs_ref} with decode_ref");
+        assert_eq!(n_allocs_ref, 0, "{name}: decode_ref allocated");
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_client, bench_server);
+criterion_main!(benches);
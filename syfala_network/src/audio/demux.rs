@@ -0,0 +1,163 @@
+//! Dispatching of incoming audio messages to per-stream consumers.
+
+use syfala_proto::{AudioMessageHeader, format, message::StreamSelection};
+use syfala_utils::AudioPacketConsumer;
+
+/// Dispatches incoming audio messages to one [`AudioPacketConsumer`] per input stream of
+/// a server.
+///
+/// Meant to be called from
+/// [`IOActiveContext::on_audio`](crate::udp::client::generic::IOActiveContext::on_audio).
+/// Messages for streams the server doesn't have, or that weren't
+/// [selected](Self::select), are counted, and otherwise ignored.
+///
+/// All consumers share the same type, use an enum of consumers to handle streams of
+/// different sample types.
+#[derive(Debug, Clone)]
+pub struct StreamDemux<C> {
+    consumers: Box<[C]>,
+    selection: StreamSelection,
+    n_out_of_range: u64,
+    n_unselected: u64,
+}
+
+impl<C> StreamDemux<C> {
+    /// Creates a new demultiplexer, with one consumer per input stream in `formats`,
+    /// created by calling `factory` with the stream's index and format.
+    pub fn new(
+        formats: &format::StreamFormats,
+        mut factory: impl FnMut(usize, &format::Format) -> C,
+    ) -> Self {
+        Self {
+            consumers: formats
+                .inputs
+                .iter()
+                .enumerate()
+                .map(|(i, format)| factory(i, format))
+                .collect(),
+            selection: StreamSelection::All,
+            n_out_of_range: 0,
+            n_unselected: 0,
+        }
+    }
+
+    /// Only dispatches messages for the streams in `selection` from now on, e.g. the
+    /// streams IO was started with. All streams are selected initially.
+    #[inline(always)]
+    pub fn select(&mut self, selection: StreamSelection) {
+        self.selection = selection;
+    }
+
+    /// Returns the currently selected streams.
+    #[inline(always)]
+    pub fn selection(&self) -> StreamSelection {
+        self.selection
+    }
+
+    /// Returns the consumer of each stream.
+    #[inline(always)]
+    pub fn consumers(&self) -> &[C] {
+        &self.consumers
+    }
+
+    /// Returns the consumer of each stream, mutably.
+    #[inline(always)]
+    pub fn consumers_mut(&mut self) -> &mut [C] {
+        &mut self.consumers
+    }
+
+    /// Returns the number of messages received so far for streams out of range.
+    #[inline(always)]
+    pub fn n_out_of_range(&self) -> u64 {
+        self.n_out_of_range
+    }
+
+    /// Returns the number of messages received so far for unselected streams.
+    #[inline(always)]
+    pub fn n_unselected(&self) -> u64 {
+        self.n_unselected
+    }
+
+    /// Returns the consumers.
+    #[inline(always)]
+    pub fn into_consumers(self) -> Box<[C]> {
+        self.consumers
+    }
+}
+
+impl<C: AudioPacketConsumer> StreamDemux<C> {
+    /// Passes the payload of an audio message to the consumer of its stream.
+    ///
+    /// Returns `false` if the stream is out of range, or unselected.
+    pub fn on_audio(&mut self, header: AudioMessageHeader, data: &[u8]) -> bool {
+        if !self.selection.contains(header.stream_idx) {
+            self.n_unselected = self.n_unselected.saturating_add(1);
+            return false;
+        }
+
+        let consumer = usize::try_from(header.stream_idx)
+            .ok()
+            .and_then(|idx| self.consumers.get_mut(idx));
+
+        match consumer {
+            Some(consumer) => {
+                consumer.consume_packet(header.stream_msg.byte_idx, data.iter().copied());
+                true
+            }
+            None => {
+                self.n_out_of_range = self.n_out_of_range.saturating_add(1);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syfala_proto::AudioStreamMessageHeader;
+
+    /// Records the byte indices of the packets it consumes.
+    #[derive(Debug, Default)]
+    struct Recorder(Vec<u64>);
+
+    impl AudioPacketConsumer for Recorder {
+        fn consume_packet(&mut self, byte_idx: u64, _bytes: impl IntoIterator<Item = u8>) {
+            self.0.push(byte_idx);
+        }
+    }
+
+    const fn header(stream_idx: u32, byte_idx: u64) -> AudioMessageHeader {
+        AudioMessageHeader {
+            stream_idx,
+            stream_msg: AudioStreamMessageHeader {
+                byte_idx,
+                n_bytes: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn drops_unselected() {
+        let formats = format::StreamFormats {
+            inputs: vec![format::Format::standard(); 3].into(),
+            outputs: Box::new([]),
+        };
+        let mut demux = StreamDemux::new(&formats, |_, _| Recorder::default());
+
+        // all streams are selected initially
+        assert!(demux.on_audio(header(0, 0), &[]));
+        assert!(!demux.on_audio(header(3, 0), &[]));
+
+        demux.select(StreamSelection::Mask(0b110));
+        assert!(!demux.on_audio(header(0, 8), &[]));
+        assert!(demux.on_audio(header(1, 8), &[]));
+        assert!(demux.on_audio(header(2, 8), &[]));
+        // unselected takes precedence over out of range
+        assert!(!demux.on_audio(header(64, 8), &[]));
+
+        let received: Vec<_> = demux.consumers().iter().map(|c| c.0.clone()).collect();
+        assert_eq!(received, [vec![0], vec![8], vec![8]]);
+        assert_eq!((demux.n_unselected(), demux.n_out_of_range()), (2, 1));
+    }
+}
@@ -0,0 +1,7 @@
+//! Sending and receiving of audio streams.
+
+mod demux;
+pub use demux::*;
+
+mod sender;
+pub use sender::*;
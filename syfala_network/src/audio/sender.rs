@@ -0,0 +1,184 @@
+//! Packetization of outgoing audio streams.
+
+use crate::udp::client::generic::ConnectedServerHandle;
+use core::num;
+use syfala_utils::AudioPacketProducer;
+
+/// Packetizes the bytes of an [`AudioPacketProducer`] into audio messages, for one of a
+/// server's output streams.
+///
+/// Messages carry at most `max_payload` bytes each. Pending bytes are only sent once
+/// they reach a multiple of `chunk_len` bytes (e.g. a whole number of frames), or
+/// `max_payload` bytes, whichever comes first, so that messages are aligned to chunk
+/// boundaries whenever possible.
+///
+/// The byte index of every message is the one reported by the producer, discontinuities
+/// in the produced stream (e.g. after the producer is reset) flush the pending bytes
+/// first.
+#[derive(Debug)]
+pub struct AudioStreamSender<P> {
+    producer: P,
+    packetizer: Packetizer,
+}
+
+/// The state of an [`AudioStreamSender`], apart from its producer.
+#[derive(Debug)]
+struct Packetizer {
+    stream_idx: u32,
+    chunk_len: num::NonZeroUsize,
+    /// Pending bytes, at most `max_payload` of them.
+    pending: Box<[u8]>,
+    n_pending: usize,
+    /// Byte index of the first pending byte.
+    byte_idx: u64,
+}
+
+impl Packetizer {
+    /// Sends the first `n` pending bytes in a single message, and keeps the rest
+    /// pending.
+    ///
+    /// The bytes are considered sent even if sending fails, like a lost datagram.
+    fn send_prefix(
+        &mut self,
+        server: &ConnectedServerHandle<'_, impl crate::SyncUdpSock>,
+        n: usize,
+    ) -> std::io::Result<()> {
+        if n == 0 {
+            return Ok(());
+        }
+
+        let res = server.send_audio(self.stream_idx, self.byte_idx, &self.pending[..n]);
+
+        self.pending.copy_within(n..self.n_pending, 0);
+        self.n_pending = self.n_pending.strict_sub(n);
+        self.byte_idx = self.byte_idx.strict_add(n.try_into().unwrap());
+
+        res
+    }
+
+    /// Returns the byte index following the last pending byte.
+    #[inline(always)]
+    fn end_idx(&self) -> u64 {
+        self.byte_idx.strict_add(self.n_pending.try_into().unwrap())
+    }
+}
+
+impl<P> AudioStreamSender<P> {
+    /// Creates a new sender, pulling bytes from `producer`, for the output stream
+    /// `stream_idx`, sending messages of at most `max_payload` bytes, aligned to
+    /// `chunk_len` bytes.
+    pub fn new(
+        producer: P,
+        stream_idx: u32,
+        max_payload: num::NonZeroUsize,
+        chunk_len: num::NonZeroUsize,
+    ) -> Self {
+        Self {
+            producer,
+            packetizer: Packetizer {
+                stream_idx,
+                chunk_len,
+                pending: core::iter::repeat_n(0, max_payload.get()).collect(),
+                n_pending: 0,
+                byte_idx: 0,
+            },
+        }
+    }
+
+    /// Returns the index of the stream the messages are sent to.
+    #[inline(always)]
+    pub fn stream_idx(&self) -> u32 {
+        self.packetizer.stream_idx
+    }
+
+    /// Returns the maximum number of payload bytes per message.
+    #[inline(always)]
+    pub fn max_payload(&self) -> num::NonZeroUsize {
+        num::NonZeroUsize::new(self.packetizer.pending.len()).unwrap()
+    }
+
+    /// Returns the chunk length messages are aligned to.
+    #[inline(always)]
+    pub fn chunk_len(&self) -> num::NonZeroUsize {
+        self.packetizer.chunk_len
+    }
+
+    /// Returns the number of bytes pending, i.e. pulled from the producer, but not
+    /// sent yet.
+    #[inline(always)]
+    pub fn n_pending(&self) -> usize {
+        self.packetizer.n_pending
+    }
+
+    /// Returns a reference to the producer.
+    #[inline(always)]
+    pub fn producer(&self) -> &P {
+        &self.producer
+    }
+
+    /// Returns a mutable reference to the producer.
+    #[inline(always)]
+    pub fn producer_mut(&mut self) -> &mut P {
+        &mut self.producer
+    }
+
+    /// Sends all pending bytes, regardless of chunk alignment.
+    #[inline(always)]
+    pub fn flush(
+        &mut self,
+        server: &ConnectedServerHandle<'_, impl crate::SyncUdpSock>,
+    ) -> std::io::Result<()> {
+        let n_pending = self.packetizer.n_pending;
+        self.packetizer.send_prefix(server, n_pending)
+    }
+}
+
+impl<P: AudioPacketProducer> AudioStreamSender<P> {
+    /// Pulls the next packet of bytes from the producer, and sends as many of them (and
+    /// of the previously pending ones) as the alignment rules allow.
+    ///
+    /// Sending continues even if sending a message fails, the last error is returned.
+    /// On success, returns the number of bytes pulled.
+    pub fn poll(
+        &mut self,
+        server: &ConnectedServerHandle<'_, impl crate::SyncUdpSock>,
+    ) -> std::io::Result<usize> {
+        let p = &mut self.packetizer;
+        let max_payload = p.pending.len();
+        let mut res = Ok(());
+        let mut n_pulled = 0;
+
+        let (start_idx, bytes) = self.producer.produce_packet();
+
+        if start_idx != p.end_idx() {
+            let n_pending = p.n_pending;
+            if let Err(e) = p.send_prefix(server, n_pending) {
+                res = Err(e);
+            }
+            p.byte_idx = start_idx;
+        }
+
+        for byte in bytes {
+            p.pending[p.n_pending] = byte;
+            p.n_pending += 1;
+            n_pulled += 1;
+
+            if p.n_pending == max_payload
+                && let Err(e) = p.send_prefix(server, max_payload)
+            {
+                res = Err(e);
+            }
+        }
+
+        // send every whole chunk pending
+        let chunk_len = u64::try_from(p.chunk_len.get()).unwrap();
+        let n_unaligned = usize::try_from(p.end_idx() % chunk_len).unwrap();
+        let n_aligned = p.n_pending.saturating_sub(n_unaligned);
+
+        if let Err(e) = p.send_prefix(server, n_aligned) {
+            res = Err(e);
+        }
+
+        res.map(|()| n_pulled)
+    }
+}
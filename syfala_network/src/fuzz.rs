@@ -0,0 +1,149 @@
+//! Harness functions for fuzzing the decoding of untrusted datagrams.
+//!
+//! Each function takes arbitrary bytes, as received from the network, and panics if
+//! decoding them panics, or breaks one of the decoders' guarantees. They are meant to be
+//! called from fuzz targets (e.g. `cargo-fuzz`'s `fuzz_target!`), and
+//! [`seed_corpus`] provides valid datagrams to start from.
+
+use crate::proto;
+
+/// Checks the invariants shared by all decoding functions: the decoded length and the
+/// remaining bytes make up the whole input.
+#[inline(always)]
+fn check_lengths(data: &[u8], n_decoded: usize, rest: &[u8]) {
+    assert_eq!(n_decoded.strict_add(rest.len()), data.len());
+    assert!(core::ptr::eq(rest, &data[n_decoded..]));
+}
+
+/// Decodes `data` as a client message.
+pub fn client_message_decode(data: &[u8]) {
+    if let Ok((_msg, n_decoded, rest)) = crate::client_message_decode(data) {
+        check_lengths(data, n_decoded, rest);
+    }
+}
+
+/// Decodes `data` as a server message, with both the owned and the borrowed decoding
+/// functions, and checks that they agree.
+pub fn server_message_decode(data: &[u8]) {
+    let owned = crate::server_message_decode(data);
+    let borrowed = crate::server_message_decode_ref(data);
+
+    match (owned, borrowed) {
+        (Ok((msg, n_decoded, rest)), Ok((msg_ref, n_decoded_ref, rest_ref))) => {
+            check_lengths(data, n_decoded, rest);
+            assert_eq!(n_decoded, n_decoded_ref);
+
+            let msg_ref = match msg_ref {
+                crate::ServerMessageRef::Connect {
+                    epoch,
+                    capabilities,
+                    formats,
+                } => {
+                    assert_eq!(formats.inputs.iter().len(), formats.inputs.len());
+                    assert_eq!(formats.outputs.iter().len(), formats.outputs.len());
+
+                    proto::message::Server::Connect {
+                        epoch,
+                        capabilities,
+                        formats: formats.to_stream_formats(),
+                    }
+                }
+                crate::ServerMessageRef::Other(msg) => msg,
+            };
+
+            assert_eq!(msg, msg_ref);
+            assert!(core::ptr::eq(rest, rest_ref));
+        }
+        (Err(_), Err(_)) => (),
+        (owned, borrowed) => panic!(
+            "decoders disagree: owned: {:?}, borrowed: {:?}",
+            owned.map(|(msg, ..)| msg),
+            borrowed.map(|(msg, ..)| msg),
+        ),
+    }
+}
+
+/// Returns valid encoded datagrams, covering every message variant.
+pub fn seed_corpus() -> Vec<Vec<u8>> {
+    use proto::{
+        AudioMessageHeader, AudioStreamMessageHeader,
+        format::{Format, StreamFormats},
+        message::{Capabilities, Client, Server, StreamSelection},
+    };
+
+    let header = AudioMessageHeader {
+        stream_idx: 1,
+        stream_msg: AudioStreamMessageHeader {
+            byte_idx: 4096,
+            n_bytes: 8,
+        },
+    };
+    let payload = [0x55; 8];
+
+    let client = [
+        Client::Discovery,
+        Client::CONN_SUCCESS,
+        Client::CONN_FAILED,
+        Client::CONN_REFUSED,
+        Client::START_IO,
+        Client::start_io(StreamSelection::Mask(0b101)),
+        Client::STOP_IO,
+        Client::HEARTBEAT,
+        Client::QUERY_STATUS,
+        Client::Disconnect,
+        Client::audio(header),
+    ]
+    .into_iter()
+    .map(|msg| {
+        let is_audio = msg == Client::audio(header);
+        let mut datagram = crate::client_message_encode(msg, Vec::new()).unwrap();
+        if is_audio {
+            datagram.extend_from_slice(&payload);
+        }
+        datagram
+    });
+
+    let server = [
+        Server::Connect {
+            epoch: 0xdead_beef,
+            capabilities: Capabilities::NONE,
+            formats: StreamFormats {
+                inputs: Box::new([Format::default(); 2]),
+                outputs: Box::new([Format::default()]),
+            },
+        },
+        Server::Connect {
+            epoch: 0xdead_beef,
+            capabilities: Capabilities::SELECTIVE_START,
+            formats: StreamFormats {
+                inputs: Box::new([Format::default(); 2]),
+                outputs: Box::new([Format::default()]),
+            },
+        },
+        Server::START_IO_OK,
+        Server::START_IO_FAILED,
+        Server::START_IO_REFUSED,
+        Server::STOP_IO_OK,
+        Server::STOP_IO_FAILED,
+        Server::STOP_IO_REFUSED,
+        Server::HEARTBEAT,
+        Server::status(proto::message::server::Status {
+            io_active: true,
+            uptime_ms: 90_000,
+            active_streams: 3,
+        }),
+        Server::Disconnect,
+        Server::audio(header),
+    ]
+    .into_iter()
+    .map(|msg| {
+        let is_audio = msg == Server::audio(header);
+        let mut datagram = crate::server_message_encode(msg, Vec::new()).unwrap();
+        if is_audio {
+            datagram.extend_from_slice(&payload);
+        }
+        datagram
+    });
+
+    client.chain(server).collect()
+}
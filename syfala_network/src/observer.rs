@@ -0,0 +1,271 @@
+//! Observation of wire-level events, e.g. to export metrics.
+//!
+//! Attach a [`WireObserver`] to a socket with
+//! [`ClientSocket::with_observer`](crate::udp::client::ClientSocket::with_observer) or
+//! [`ServerSocket::with_observer`](crate::udp::server::ServerSocket::with_observer).
+//! Sockets default to the `()` observer, whose methods are all empty, and thus compile
+//! down to nothing.
+
+use core::{
+    net::SocketAddr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::proto::message::{Client, Server, client, server};
+
+/// The kind of a protocol message, regardless of its direction and contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MessageKind {
+    Discovery,
+    Connect,
+    ConnectionResult,
+    IOStateChange,
+    IOStateChangeResult,
+    Heartbeat,
+    Disconnect,
+    Audio,
+    QueryStatus,
+    Status,
+}
+
+impl MessageKind {
+    /// The number of message kinds.
+    pub const COUNT: usize = 10;
+
+    /// All message kinds, in declaration order.
+    pub const ALL: [Self; Self::COUNT] = [
+        Self::Discovery,
+        Self::Connect,
+        Self::ConnectionResult,
+        Self::IOStateChange,
+        Self::IOStateChangeResult,
+        Self::Heartbeat,
+        Self::Disconnect,
+        Self::Audio,
+        Self::QueryStatus,
+        Self::Status,
+    ];
+
+    /// Returns the kind of a client message.
+    #[inline(always)]
+    pub const fn of_client(msg: &Client) -> Self {
+        match msg {
+            Client::Discovery => Self::Discovery,
+            Client::ConnectionResult(_) => Self::ConnectionResult,
+            Client::Connected(client::Connected::Control(ctrl)) => match ctrl {
+                client::Control::RequestIOStateChange(_) => Self::IOStateChange,
+                client::Control::Heartbeat => Self::Heartbeat,
+                client::Control::QueryStatus => Self::QueryStatus,
+            },
+            Client::Connected(client::Connected::Audio(_)) => Self::Audio,
+            Client::Disconnect => Self::Disconnect,
+        }
+    }
+
+    /// Returns the kind of a server message.
+    #[inline(always)]
+    pub const fn of_server(msg: &Server) -> Self {
+        match msg {
+            Server::Connect { .. } => Self::Connect,
+            Server::Connected(server::Connected::Control(ctrl)) => match ctrl {
+                server::Control::IOStateChangeResult(_) => Self::IOStateChangeResult,
+                server::Control::Heartbeat => Self::Heartbeat,
+                server::Control::Status(_) => Self::Status,
+            },
+            Server::Connected(server::Connected::Audio(_)) => Self::Audio,
+            Server::Disconnect => Self::Disconnect,
+        }
+    }
+}
+
+/// Callbacks invoked on wire-level events of a socket.
+///
+/// All methods do nothing by default. They are called from the socket's send and
+/// receive paths, including audio ones, keep them cheap and non-blocking.
+pub trait WireObserver {
+    /// Invoked after a datagram of `len` bytes has been sent to `addr`.
+    #[inline(always)]
+    fn datagram_sent(&self, addr: SocketAddr, len: usize) {
+        let _ = (addr, len);
+    }
+
+    /// Invoked after a datagram of `len` bytes has been received from `addr`.
+    #[inline(always)]
+    fn datagram_received(&self, addr: SocketAddr, len: usize) {
+        let _ = (addr, len);
+    }
+
+    /// Invoked when a datagram received from `addr` couldn't be decoded.
+    #[inline(always)]
+    fn decode_failed(&self, addr: SocketAddr) {
+        let _ = addr;
+    }
+
+    /// Invoked after a message of the given kind has been sent.
+    #[inline(always)]
+    fn message_sent(&self, kind: MessageKind) {
+        let _ = kind;
+    }
+
+    /// Invoked after a message of the given kind has been received and decoded.
+    #[inline(always)]
+    fn message_received(&self, kind: MessageKind) {
+        let _ = kind;
+    }
+}
+
+/// The default observer, ignoring all events.
+impl WireObserver for () {}
+
+impl<O: WireObserver + ?Sized> WireObserver for &O {
+    #[inline(always)]
+    fn datagram_sent(&self, addr: SocketAddr, len: usize) {
+        O::datagram_sent(self, addr, len);
+    }
+
+    #[inline(always)]
+    fn datagram_received(&self, addr: SocketAddr, len: usize) {
+        O::datagram_received(self, addr, len);
+    }
+
+    #[inline(always)]
+    fn decode_failed(&self, addr: SocketAddr) {
+        O::decode_failed(self, addr);
+    }
+
+    #[inline(always)]
+    fn message_sent(&self, kind: MessageKind) {
+        O::message_sent(self, kind);
+    }
+
+    #[inline(always)]
+    fn message_received(&self, kind: MessageKind) {
+        O::message_received(self, kind);
+    }
+}
+
+impl<O: WireObserver + ?Sized> WireObserver for std::sync::Arc<O> {
+    #[inline(always)]
+    fn datagram_sent(&self, addr: SocketAddr, len: usize) {
+        O::datagram_sent(self, addr, len);
+    }
+
+    #[inline(always)]
+    fn datagram_received(&self, addr: SocketAddr, len: usize) {
+        O::datagram_received(self, addr, len);
+    }
+
+    #[inline(always)]
+    fn decode_failed(&self, addr: SocketAddr) {
+        O::decode_failed(self, addr);
+    }
+
+    #[inline(always)]
+    fn message_sent(&self, kind: MessageKind) {
+        O::message_sent(self, kind);
+    }
+
+    #[inline(always)]
+    fn message_received(&self, kind: MessageKind) {
+        O::message_received(self, kind);
+    }
+}
+
+/// An observer counting events with atomic counters.
+///
+/// Counters can be read from any thread, e.g. by a metrics exporter, while the socket
+/// is in use. Share it through an [`Arc`](std::sync::Arc), or a reference.
+#[derive(Debug, Default)]
+pub struct CountingObserver {
+    datagrams_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    datagrams_received: AtomicU64,
+    bytes_received: AtomicU64,
+    decode_failures: AtomicU64,
+    messages_sent: [AtomicU64; MessageKind::COUNT],
+    messages_received: [AtomicU64; MessageKind::COUNT],
+}
+
+#[inline(always)]
+fn incr(counter: &AtomicU64, n: u64) {
+    counter.fetch_add(n, Ordering::Relaxed);
+}
+
+impl CountingObserver {
+    /// Creates a new observer, with all counters at zero.
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of datagrams sent.
+    #[inline(always)]
+    pub fn datagrams_sent(&self) -> u64 {
+        self.datagrams_sent.load(Ordering::Relaxed)
+    }
+
+    /// Returns the total size of the datagrams sent, in bytes.
+    #[inline(always)]
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of datagrams received.
+    #[inline(always)]
+    pub fn datagrams_received(&self) -> u64 {
+        self.datagrams_received.load(Ordering::Relaxed)
+    }
+
+    /// Returns the total size of the datagrams received, in bytes.
+    #[inline(always)]
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of received datagrams that couldn't be decoded.
+    #[inline(always)]
+    pub fn decode_failures(&self) -> u64 {
+        self.decode_failures.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of messages of the given kind sent.
+    #[inline(always)]
+    pub fn messages_sent(&self, kind: MessageKind) -> u64 {
+        self.messages_sent[kind as usize].load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of messages of the given kind received.
+    #[inline(always)]
+    pub fn messages_received(&self, kind: MessageKind) -> u64 {
+        self.messages_received[kind as usize].load(Ordering::Relaxed)
+    }
+}
+
+impl WireObserver for CountingObserver {
+    #[inline(always)]
+    fn datagram_sent(&self, _addr: SocketAddr, len: usize) {
+        incr(&self.datagrams_sent, 1);
+        incr(&self.bytes_sent, len as u64);
+    }
+
+    #[inline(always)]
+    fn datagram_received(&self, _addr: SocketAddr, len: usize) {
+        incr(&self.datagrams_received, 1);
+        incr(&self.bytes_received, len as u64);
+    }
+
+    #[inline(always)]
+    fn decode_failed(&self, _addr: SocketAddr) {
+        incr(&self.decode_failures, 1);
+    }
+
+    #[inline(always)]
+    fn message_sent(&self, kind: MessageKind) {
+        incr(&self.messages_sent[kind as usize], 1);
+    }
+
+    #[inline(always)]
+    fn message_received(&self, kind: MessageKind) {
+        incr(&self.messages_received[kind as usize], 1);
+    }
+}
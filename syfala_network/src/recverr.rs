@@ -0,0 +1,142 @@
+//! Linux socket error queues (`IP_RECVERR`), receiving the errors reported by the
+//! network about sent datagrams, e.g. ICMP port unreachable messages.
+
+use std::{io, os::fd::AsRawFd};
+
+/// Enables, or disables, queueing errors reported about the datagrams sent through `sock`.
+pub(crate) fn set_enabled(sock: &std::net::UdpSocket, enabled: bool) -> io::Result<()> {
+    let (level, name) = if sock.local_addr()?.is_ipv4() {
+        (libc::SOL_IP, libc::IP_RECVERR)
+    } else {
+        (libc::SOL_IPV6, libc::IPV6_RECVERR)
+    };
+
+    let value = libc::c_int::from(enabled);
+
+    // SAFETY: the option value is a valid c_int, whose size is given
+    let res = unsafe {
+        libc::setsockopt(
+            sock.as_raw_fd(),
+            level,
+            name,
+            (&raw const value).cast(),
+            size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Converts the socket address written by `recvmsg`, if it is an IP one.
+fn socket_addr(storage: &libc::sockaddr_storage) -> Option<core::net::SocketAddr> {
+    match libc::c_int::from(storage.ss_family) {
+        libc::AF_INET => {
+            // SAFETY: sockaddr_storage is large and aligned enough for any address
+            let addr = unsafe { &*(&raw const *storage).cast::<libc::sockaddr_in>() };
+            Some(core::net::SocketAddr::from((
+                u32::from_be(addr.sin_addr.s_addr).to_be_bytes(),
+                u16::from_be(addr.sin_port),
+            )))
+        }
+        libc::AF_INET6 => {
+            // SAFETY: same as above
+            let addr = unsafe { &*(&raw const *storage).cast::<libc::sockaddr_in6>() };
+            Some(core::net::SocketAddr::V6(core::net::SocketAddrV6::new(
+                addr.sin6_addr.s6_addr.into(),
+                u16::from_be(addr.sin6_port),
+                addr.sin6_flowinfo,
+                addr.sin6_scope_id,
+            )))
+        }
+        _ => None,
+    }
+}
+
+/// Receives the oldest ICMP error queued on `sock`, without blocking, discarding the
+/// other errors before it.
+///
+/// Returns `None` if there is none.
+pub(crate) fn recv(sock: &std::net::UdpSocket) -> io::Result<Option<crate::DestinationError>> {
+    loop {
+        match recv_any(sock)? {
+            Some(None) => continue,
+            res => return Ok(res.flatten()),
+        }
+    }
+}
+
+/// Receives the oldest error queued on `sock`, without blocking.
+///
+/// Returns `None` if there is none, `Some(None)` if it isn't an ICMP error about an IP
+/// destination.
+fn recv_any(sock: &std::net::UdpSocket) -> io::Result<Option<Option<crate::DestinationError>>> {
+    // SAFETY: all-zeroes is a valid sockaddr_storage
+    let mut name: libc::sockaddr_storage = unsafe { core::mem::zeroed() };
+    // large enough for a sock_extended_err, and the offender's address following it
+    let mut control = [0u64; 16];
+
+    // SAFETY: all-zeroes is a valid msghdr, with no buffers
+    let mut msg: libc::msghdr = unsafe { core::mem::zeroed() };
+    msg.msg_name = (&raw mut name).cast();
+    msg.msg_namelen = size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    // the original datagram's payload isn't needed, it is truncated
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = size_of_val(&control) as _;
+
+    // SAFETY: msg only points to the buffers above, whose sizes it gives
+    let res = unsafe {
+        libc::recvmsg(
+            sock.as_raw_fd(),
+            &raw mut msg,
+            libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT,
+        )
+    };
+
+    if res < 0 {
+        let e = io::Error::last_os_error();
+        return match e.kind() {
+            io::ErrorKind::WouldBlock => Ok(None),
+            _ => Err(e),
+        };
+    }
+
+    let Some(dest_addr) = socket_addr(&name) else {
+        return Ok(Some(None));
+    };
+
+    // SAFETY: msg was filled in by recvmsg, its control messages lie in `control`
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&raw const msg) };
+
+    while !cmsg.is_null() {
+        // SAFETY: non-null control message headers returned by the macros are valid
+        let (level, ty) = unsafe { ((*cmsg).cmsg_level, (*cmsg).cmsg_type) };
+
+        if (level, ty) == (libc::SOL_IP, libc::IP_RECVERR)
+            || (level, ty) == (libc::SOL_IPV6, libc::IPV6_RECVERR)
+        {
+            // SAFETY: these control messages start with a sock_extended_err, possibly
+            // unaligned in the buffer
+            let err = unsafe {
+                libc::CMSG_DATA(cmsg)
+                    .cast::<libc::sock_extended_err>()
+                    .read_unaligned()
+            };
+
+            let icmp = [libc::SO_EE_ORIGIN_ICMP, libc::SO_EE_ORIGIN_ICMP6].contains(&err.ee_origin);
+
+            return Ok(Some(icmp.then(|| crate::DestinationError {
+                dest_addr,
+                kind: io::Error::from_raw_os_error(err.ee_errno as i32).kind(),
+            })));
+        }
+
+        // SAFETY: same as above
+        cmsg = unsafe { libc::CMSG_NXTHDR(&raw const msg, cmsg) };
+    }
+
+    Ok(Some(None))
+}
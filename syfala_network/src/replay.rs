@@ -0,0 +1,376 @@
+//! Recording and replaying the datagrams going through a socket.
+//!
+//! Wrap a client's socket in a [`RecordingSock`] to capture a session, then feed the
+//! recording to a [`ReplaySock`] to reproduce it, datagram for datagram, with the same
+//! timing.
+//!
+//! A recording is a sequence of [`Record`]s, each encoded with [`postcard`], back to back.
+
+use core::{
+    cell::{Cell, RefCell},
+    net::SocketAddr,
+    time::Duration,
+};
+use std::{io, time::Instant};
+
+use crate::proto::serde::{Deserialize, Serialize};
+use crate::udp::client::generic::{Clock, MockClock, SystemClock};
+
+/// Something that went through a socket.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(crate = "crate::proto::serde")]
+pub enum Event {
+    /// A datagram was sent to `peer`.
+    Sent { peer: SocketAddr, bytes: Vec<u8> },
+    /// A datagram was received from `peer`.
+    Received { peer: SocketAddr, bytes: Vec<u8> },
+    /// The receive timeout elapsed before any datagram was received.
+    RecvTimeout,
+}
+
+/// A recorded [`Event`], along with when it occured.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(crate = "crate::proto::serde")]
+pub struct Record {
+    /// Time elapsed since the previous record, or since recording started, in
+    /// microseconds.
+    pub delta_micros: u64,
+    pub event: Event,
+}
+
+/// Decodes all the records of a recording.
+pub fn decode_recording(mut bytes: &[u8]) -> postcard::Result<Vec<Record>> {
+    let mut records = Vec::new();
+
+    while !bytes.is_empty() {
+        let (record, rest) = postcard::take_from_bytes(bytes)?;
+        records.push(record);
+        bytes = rest;
+    }
+
+    Ok(records)
+}
+
+/// A socket wrapper, recording every datagram sent or received through it, as well as
+/// receive timeouts, into a [`Write`](io::Write) implementation, e.g. a file.
+///
+/// Records are written as they occur, unbuffered. Wrap the writer in a
+/// [`BufWriter`](io::BufWriter) if need be. Errors writing them don't fail the socket's
+/// operations, the last one is kept instead, see
+/// [`take_record_error`](Self::take_record_error).
+///
+/// Sends and receive timeouts are timestamped through a [`Clock`], the system's one by
+/// default, received datagrams with the timestamps returned by the wrapped socket.
+#[derive(Debug)]
+pub struct RecordingSock<T, W, K = SystemClock> {
+    sock: T,
+    out: RefCell<W>,
+    /// Instant of the previous record, if any.
+    last: Cell<Option<Instant>>,
+    /// Last error writing a record, if any.
+    record_error: RefCell<Option<io::Error>>,
+    clock: K,
+}
+
+impl<T, W> RecordingSock<T, W> {
+    /// Creates a new recording socket, writing records into `out`.
+    #[inline(always)]
+    pub const fn new(sock: T, out: W) -> Self {
+        Self::with_clock(sock, out, SystemClock)
+    }
+}
+
+impl<T, W, K> RecordingSock<T, W, K> {
+    /// Same as [`new`](RecordingSock::new), but reads the current time from the given
+    /// clock.
+    #[inline(always)]
+    pub const fn with_clock(sock: T, out: W, clock: K) -> Self {
+        Self {
+            sock,
+            out: RefCell::new(out),
+            last: Cell::new(None),
+            record_error: RefCell::new(None),
+            clock,
+        }
+    }
+
+    /// Returns the wrapped socket.
+    #[inline(always)]
+    pub const fn get_ref(&self) -> &T {
+        &self.sock
+    }
+
+    /// Returns, and clears, the last error encountered writing a record, if any.
+    ///
+    /// The records following it were still written, unless they failed too, the
+    /// recording is thus incomplete.
+    #[inline(always)]
+    pub fn take_record_error(&self) -> Option<io::Error> {
+        self.record_error.take()
+    }
+
+    /// Returns the wrapped socket and the writer.
+    #[inline(always)]
+    pub fn into_inner(self) -> (T, W) {
+        (self.sock, self.out.into_inner())
+    }
+}
+
+impl<T, W: io::Write, K> RecordingSock<T, W, K> {
+    /// Writes a record of `event`, which occured at `at`, keeping the error encountered,
+    /// if any.
+    fn record(&self, at: Instant, event: Event) {
+        let last = self.last.replace(Some(at)).unwrap_or(at);
+        let delta = at.saturating_duration_since(last);
+
+        let record = Record {
+            delta_micros: delta.as_micros().try_into().unwrap_or(u64::MAX),
+            event,
+        };
+
+        if let Err(e) = postcard::to_io(&record, &mut *self.out.borrow_mut()) {
+            *self.record_error.borrow_mut() = Some(crate::postcard_to_io_err(e));
+        }
+    }
+}
+
+/// Errors writing records are kept, see
+/// [`take_record_error`](RecordingSock::take_record_error), the datagram, if any, is
+/// still sent or received.
+impl<T: crate::SyncUdpSock, W: io::Write, K: Clock> crate::SyncUdpSock for RecordingSock<T, W, K> {
+    fn send(&self, bytes: &[u8], dest_addr: SocketAddr) -> io::Result<()> {
+        self.sock.send(bytes, dest_addr)?;
+
+        let event = Event::Sent {
+            peer: dest_addr,
+            bytes: bytes.to_vec(),
+        };
+
+        self.record(self.clock.now(), event);
+
+        Ok(())
+    }
+
+    fn recv(&self, bytes: &mut [u8]) -> io::Result<(usize, SocketAddr, Instant)> {
+        match self.sock.recv(bytes) {
+            Ok((n, peer, timestamp)) => {
+                let event = Event::Received {
+                    peer,
+                    bytes: bytes[..n].to_vec(),
+                };
+                self.record(timestamp, event);
+
+                Ok((n, peer, timestamp))
+            }
+            Err(e) if crate::io_err_is_timeout(e.kind()) => {
+                self.record(self.clock.now(), Event::RecvTimeout);
+                Err(e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    #[inline(always)]
+    fn set_recv_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.sock.set_recv_timeout(timeout)
+    }
+
+    #[inline(always)]
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.sock.local_addr()
+    }
+
+    #[inline(always)]
+    fn set_broadcast(&self, broadcast: bool) -> io::Result<()> {
+        self.sock.set_broadcast(broadcast)
+    }
+
+    #[inline(always)]
+    fn join_multicast_v4(
+        &self,
+        group: core::net::Ipv4Addr,
+        interface: core::net::Ipv4Addr,
+    ) -> io::Result<()> {
+        self.sock.join_multicast_v4(group, interface)
+    }
+
+    #[inline(always)]
+    fn set_multicast_ttl_v4(&self, ttl: u32) -> io::Result<()> {
+        self.sock.set_multicast_ttl_v4(ttl)
+    }
+
+    #[inline(always)]
+    fn set_recv_errors(&self, enabled: bool) -> io::Result<()> {
+        self.sock.set_recv_errors(enabled)
+    }
+
+    #[inline(always)]
+    fn recv_error(&self) -> io::Result<Option<crate::DestinationError>> {
+        self.sock.recv_error()
+    }
+}
+
+/// How a [`ReplaySock`] paces the events it replays.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pacing {
+    /// Waits the recorded delay before every received datagram, or timeout.
+    #[default]
+    Original,
+    /// Replays events as fast as possible. Time is then only observable through
+    /// [`ReplaySock::clock`].
+    AsFastAsPossible,
+}
+
+/// A socket replaying a recording made with a [`RecordingSock`].
+///
+/// Receiving returns the recorded datagrams and timeouts, in order, until the recording
+/// is exhausted, which is reported as an [`UnexpectedEof`](io::ErrorKind::UnexpectedEof)
+/// error, ending receive loops. Returned timestamps, as well as [`clock`](Self::clock),
+/// follow the recorded timing, whatever the pacing.
+///
+/// Sent datagrams are compared to the recorded ones instead of being sent. Any sent
+/// datagram that doesn't match the next recorded one, or recorded datagram that
+/// hasn't been sent before the next receive, is counted as a
+/// [divergence](Self::n_divergences). A replay without divergences reproduces the
+/// recorded session exactly.
+#[derive(Debug)]
+pub struct ReplaySock {
+    records: Vec<Record>,
+    pacing: Pacing,
+    /// Index of the next record to replay.
+    next: Cell<usize>,
+    clock: MockClock,
+    n_divergences: Cell<u64>,
+    /// Index of the record at which the first divergence occured, if any.
+    first_divergence: Cell<Option<usize>>,
+}
+
+impl ReplaySock {
+    /// Creates a new replay socket from recorded `records`, whose timing starts at the
+    /// current instant.
+    #[inline(always)]
+    pub fn new(records: Vec<Record>, pacing: Pacing) -> Self {
+        Self {
+            records,
+            pacing,
+            next: Cell::new(0),
+            clock: MockClock::new(Instant::now()),
+            n_divergences: Cell::new(0),
+            first_divergence: Cell::new(None),
+        }
+    }
+
+    /// Same as [`new`](Self::new), but decodes the records from a recording first.
+    #[inline(always)]
+    pub fn from_recording(bytes: &[u8], pacing: Pacing) -> postcard::Result<Self> {
+        decode_recording(bytes).map(|records| Self::new(records, pacing))
+    }
+
+    /// Returns a clock following the recorded timing, to be passed to
+    /// [`GenericClient::with_clock`](crate::udp::client::generic::GenericClient::with_clock).
+    #[inline(always)]
+    pub fn clock(&self) -> MockClock {
+        self.clock.clone()
+    }
+
+    /// Returns the number of records replayed, or skipped, so far.
+    #[inline(always)]
+    pub fn position(&self) -> usize {
+        self.next.get()
+    }
+
+    /// Returns `true` if all records have been replayed.
+    #[inline(always)]
+    pub fn is_finished(&self) -> bool {
+        self.next.get() >= self.records.len()
+    }
+
+    /// Returns the number of divergences from the recording so far.
+    #[inline(always)]
+    pub fn n_divergences(&self) -> u64 {
+        self.n_divergences.get()
+    }
+
+    /// Returns the index of the record at which the replay first diverged, if it did.
+    #[inline(always)]
+    pub fn first_divergence(&self) -> Option<usize> {
+        self.first_divergence.get()
+    }
+
+    #[inline(always)]
+    fn diverge(&self, idx: usize) {
+        self.n_divergences
+            .set(self.n_divergences.get().saturating_add(1));
+        self.first_divergence
+            .set(Some(self.first_divergence.get().unwrap_or(idx)));
+    }
+
+    /// Advances to the record at `idx`, returning it.
+    fn advance(&self, idx: usize) -> &Record {
+        let record = &self.records[idx];
+        let delta = Duration::from_micros(record.delta_micros);
+
+        self.next.set(idx.strict_add(1));
+        self.clock.advance(delta);
+
+        record
+    }
+}
+
+impl crate::SyncUdpSock for ReplaySock {
+    fn send(&self, bytes: &[u8], dest_addr: SocketAddr) -> io::Result<()> {
+        let idx = self.next.get();
+
+        let matches = self.records.get(idx).is_some_and(|record| {
+            matches!(&record.event, Event::Sent { peer, bytes: sent }
+                if *peer == dest_addr && sent == bytes)
+        });
+
+        if matches {
+            self.advance(idx);
+        } else {
+            self.diverge(idx);
+        }
+
+        Ok(())
+    }
+
+    fn recv(&self, bytes: &mut [u8]) -> io::Result<(usize, SocketAddr, Instant)> {
+        loop {
+            let idx = self.next.get();
+
+            let Some(record) = self.records.get(idx) else {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            };
+
+            // recorded datagrams not sent in the replay
+            if let Event::Sent { .. } = record.event {
+                self.diverge(idx);
+                self.advance(idx);
+                continue;
+            }
+
+            if self.pacing == Pacing::Original {
+                std::thread::sleep(Duration::from_micros(record.delta_micros));
+            }
+
+            let record = self.advance(idx);
+            let timestamp = self.clock.now();
+
+            return match &record.event {
+                Event::Received { peer, bytes: recv } => {
+                    let n = recv.len().min(bytes.len());
+                    bytes[..n].copy_from_slice(&recv[..n]);
+                    Ok((n, *peer, timestamp))
+                }
+                Event::RecvTimeout => Err(io::ErrorKind::TimedOut.into()),
+                Event::Sent { .. } => unreachable!(),
+            };
+        }
+    }
+
+    #[inline(always)]
+    fn set_recv_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+//! Time sources for the generic client's deadline logic.
+
+use std::{cell::Cell, rc::Rc, time::Instant};
+
+/// A source of the current instant.
+///
+/// Deadline computations of a [`GenericClient`](super::GenericClient) only read the
+/// current time through this trait, allowing to drive them deterministically.
+pub trait Clock {
+    /// Returns the current instant.
+    fn now(&self) -> Instant;
+}
+
+/// The system's monotonic clock, i.e. [`Instant::now`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline(always)]
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A manually advanced clock.
+///
+/// Clones share the same time, so that one can be handed to a client while another
+/// is used to advance it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockClock {
+    now: Rc<Cell<Instant>>,
+}
+
+impl MockClock {
+    /// Creates a new clock, starting at the given instant.
+    #[inline(always)]
+    pub fn new(start: Instant) -> Self {
+        Self {
+            now: Rc::new(Cell::new(start)),
+        }
+    }
+
+    /// Advances the clock by `duration`.
+    #[inline(always)]
+    pub fn advance(&self, duration: core::time::Duration) {
+        self.now.set(self.now.get().checked_add(duration).unwrap());
+    }
+
+    /// Sets the clock to the given instant, which may be in the past.
+    #[inline(always)]
+    pub fn set(&self, now: Instant) {
+        self.now.set(now);
+    }
+}
+
+impl Clock for MockClock {
+    #[inline(always)]
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+}
@@ -0,0 +1,134 @@
+//! Control messages whose sending would have blocked, waiting to be sent again.
+
+use core::net::SocketAddr;
+use std::collections::VecDeque;
+use syfala_proto::message::Client;
+
+use crate::observer::WireObserver;
+
+/// Maximum number of deferred messages, the oldest being dropped first when exceeded.
+const CAPACITY: usize = 32;
+
+/// A bounded queue of control messages whose sending failed with a timeout-kind error,
+/// i.e. [`WouldBlock`](std::io::ErrorKind::WouldBlock) on non-blocking sockets, or when
+/// the OS send buffers are full.
+///
+/// Messages are kept unencoded, they are tiny and encoding them again is cheap.
+#[derive(Debug)]
+pub(crate) struct DeferredSends {
+    queue: VecDeque<(SocketAddr, Client)>,
+    /// Number of messages dropped so far, because the queue was full.
+    n_dropped: u64,
+}
+
+impl DeferredSends {
+    #[inline(always)]
+    pub(crate) const fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            n_dropped: 0,
+        }
+    }
+
+    /// Returns the number of messages waiting to be sent.
+    #[inline(always)]
+    pub(crate) fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns the number of messages dropped so far, because the queue was full.
+    #[inline(always)]
+    pub(crate) fn n_dropped(&self) -> u64 {
+        self.n_dropped
+    }
+
+    /// Handles the result of sending `msg` to `addr`: defers it if it failed with a
+    /// timeout-kind error, which is then swallowed.
+    #[inline(always)]
+    pub(crate) fn on_send_result(
+        &mut self,
+        res: std::io::Result<()>,
+        msg: Client,
+        addr: SocketAddr,
+    ) -> std::io::Result<()> {
+        match res {
+            Err(e) if crate::io_err_is_timeout(e.kind()) => {
+                self.push(addr, msg);
+                Ok(())
+            }
+            res => res,
+        }
+    }
+
+    fn push(&mut self, addr: SocketAddr, msg: Client) {
+        if self.queue.len() >= CAPACITY {
+            self.queue.pop_front();
+            self.n_dropped = self.n_dropped.saturating_add(1);
+        }
+
+        self.queue.push_back((addr, msg));
+    }
+
+    /// Sends deferred messages, oldest first, until one would block again, in which
+    /// case it stays first in line.
+    pub(crate) fn flush(
+        &mut self,
+        sock: &crate::udp::client::ClientSocket<impl crate::SyncUdpSock, impl WireObserver>,
+        encode_buf: &mut [core::mem::MaybeUninit<u8>],
+    ) -> std::io::Result<()> {
+        while let Some(&(addr, msg)) = self.queue.front() {
+            let res = sock.send_msg(msg, addr, encode_buf);
+
+            match res.map_err(std::io::Error::from) {
+                Err(e) if crate::io_err_is_timeout(e.kind()) => break,
+                res => {
+                    self.queue.pop_front();
+                    res?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::new(core::net::Ipv4Addr::LOCALHOST.into(), port)
+    }
+
+    #[test]
+    fn only_timeouts_deferred() {
+        let mut deferred = DeferredSends::new();
+        let would_block = Err(io::ErrorKind::WouldBlock.into());
+        let refused = Err(io::ErrorKind::ConnectionRefused.into());
+        let (msg, addr) = (Client::STOP_IO, addr(1));
+
+        assert!(deferred.on_send_result(Ok(()), msg, addr).is_ok());
+        assert!(deferred.on_send_result(refused, msg, addr).is_err());
+        assert_eq!(deferred.len(), 0);
+
+        assert!(deferred.on_send_result(would_block, msg, addr).is_ok());
+        assert_eq!(deferred.len(), 1);
+    }
+
+    #[test]
+    fn overflow_drops_oldest() {
+        let mut deferred = DeferredSends::new();
+
+        let n = u16::try_from(CAPACITY).unwrap() + 8;
+        for port in 0..n {
+            deferred.push(addr(port), Client::HEARTBEAT);
+        }
+
+        assert_eq!(deferred.len(), CAPACITY);
+        assert_eq!(deferred.n_dropped(), 8);
+
+        let ports: Vec<_> = deferred.queue.iter().map(|(addr, _)| addr.port()).collect();
+        assert_eq!(ports, Vec::from_iter(8..n));
+    }
+}
@@ -0,0 +1,288 @@
+//! Per-connection validation of incoming audio messages.
+//!
+//! All checks performed on incoming audio messages are consolidated in a single, small,
+//! table indexed by stream index, so that the audio fast path only performs one bounds
+//! check, one table lookup and a couple of comparisons. Handling rejected messages is left
+//! to the (cold) caller.
+//!
+//! Audio messages may hold partial samples, see
+//! [`AudioStreamMessageHeader`](syfala_proto::AudioStreamMessageHeader), so checking that
+//! they start and end on sample boundaries is opt-in. When it is disabled, the entries'
+//! modulus is one, and the check always passes, without a branch on the option. The
+//! modulus is checked with a multiplication instead of a (much slower) division.
+
+/// Reason for which an incoming audio message was discarded before reaching the
+/// application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AudioRejection {
+    /// The stream index doesn't correspond to any of the server's input streams.
+    UnknownStream,
+    /// The stream wasn't selected when starting IO, see
+    /// [`StreamSelection`](syfala_proto::message::StreamSelection).
+    Unselected,
+    /// The datagram contains less payload bytes than advertised in the message's header.
+    Truncated,
+    /// The message is a duplicate of the last message accepted on the same stream.
+    Duplicate,
+    /// The message's byte index or payload length isn't a multiple of the stream's sample
+    /// size, only checked if sample alignment is required.
+    Misaligned,
+}
+
+/// Divisibility test by a fixed divisor, without division, see _Hacker's Delight_, 10-17.
+#[derive(Debug, Clone, Copy)]
+struct Modulus {
+    /// Multiplicative inverse of the divisor's odd part, modulo 2^64.
+    inverse: u64,
+    /// Number of trailing zeros of the divisor.
+    shift: u32,
+    /// Largest quotient of a multiple of the divisor.
+    max_quotient: u64,
+}
+
+impl Modulus {
+    #[inline(always)]
+    fn new(divisor: core::num::NonZeroU64) -> Self {
+        let shift = divisor.trailing_zeros();
+        let odd = divisor.get() >> shift;
+
+        // odd numbers are their own inverses modulo 2^3, each Newton iteration doubles
+        // the number of correct bits
+        let mut inverse = odd;
+        for _ in 0..5 {
+            inverse = inverse.wrapping_mul(2u64.wrapping_sub(odd.wrapping_mul(inverse)));
+        }
+
+        Self {
+            inverse,
+            shift,
+            max_quotient: u64::MAX / divisor,
+        }
+    }
+
+    /// Returns whether `n` is a multiple of the divisor.
+    #[inline(always)]
+    fn divides(self, n: u64) -> bool {
+        n.wrapping_mul(self.inverse).rotate_right(self.shift) <= self.max_quotient
+    }
+}
+
+/// Per-stream gate state.
+#[derive(Debug, Clone, Copy)]
+struct StreamGateEntry {
+    /// Whether audio messages are accepted on the stream, i.e. whether it was selected
+    /// when starting IO.
+    enabled: bool,
+    /// Byte indices and payload lengths must be multiples of this, the stream's sample
+    /// size if sample alignment is required, one otherwise.
+    modulus: Modulus,
+    /// Byte index of the last accepted message, if any.
+    last_byte_idx: Option<u64>,
+}
+
+/// Validates the audio messages received from a connected server.
+///
+/// The client validates incoming audio messages with it, it is exposed so that the cost
+/// of validation can be measured on its own.
+#[derive(Debug)]
+pub struct StreamGate {
+    /// One entry per input stream advertised by the server
+    entries: Box<[StreamGateEntry]>,
+}
+
+impl StreamGate {
+    /// Creates a new gate, for a server advertising the given input streams.
+    ///
+    /// If `sample_aligned` is `true`, messages must start and end on sample boundaries.
+    #[inline(always)]
+    pub fn new(streams: &[syfala_proto::format::Format], sample_aligned: bool) -> Self {
+        Self {
+            entries: streams
+                .iter()
+                .map(|format| StreamGateEntry {
+                    enabled: true,
+                    modulus: Modulus::new(if sample_aligned {
+                        format.sample_type.sample_size().into()
+                    } else {
+                        core::num::NonZeroU64::MIN
+                    }),
+                    last_byte_idx: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Only accepts audio messages for the streams in `selection` from now on.
+    ///
+    /// This is called whenever IO starts, streams then start over, so the last accepted
+    /// byte indices are forgotten.
+    #[inline(always)]
+    pub fn select(&mut self, selection: syfala_proto::message::StreamSelection) {
+        for (idx, entry) in (0..).zip(&mut self.entries) {
+            entry.enabled = selection.contains(idx);
+            entry.last_byte_idx = None;
+        }
+    }
+
+    /// Checks an audio message, and returns its payload, without any trailing bytes,
+    /// if it is accepted.
+    #[inline(always)]
+    pub fn check<'a>(
+        &mut self,
+        header: &syfala_proto::AudioMessageHeader,
+        payload: &'a [u8],
+    ) -> Result<&'a [u8], AudioRejection> {
+        let entry = usize::try_from(header.stream_idx)
+            .ok()
+            .and_then(|i| self.entries.get_mut(i))
+            .ok_or(AudioRejection::UnknownStream)?;
+
+        if !entry.enabled {
+            return Err(AudioRejection::Unselected);
+        }
+
+        let msg = header.stream_msg;
+
+        let payload = usize::try_from(msg.n_bytes)
+            .ok()
+            .and_then(|n| payload.get(..n))
+            .ok_or(AudioRejection::Truncated)?;
+
+        if entry.last_byte_idx == Some(msg.byte_idx) {
+            return Err(AudioRejection::Duplicate);
+        }
+
+        if !entry.modulus.divides(msg.byte_idx) || !entry.modulus.divides(msg.n_bytes.into()) {
+            return Err(AudioRejection::Misaligned);
+        }
+
+        entry.last_byte_idx = Some(msg.byte_idx);
+
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syfala_proto::{
+        AudioMessageHeader, AudioStreamMessageHeader,
+        format::{Format, SampleType},
+        message::StreamSelection,
+    };
+
+    fn gate(n_streams: usize) -> StreamGate {
+        StreamGate::new(&vec![Format::standard(); n_streams], false)
+    }
+
+    const fn header(stream_idx: u32, byte_idx: u64, n_bytes: u32) -> AudioMessageHeader {
+        AudioMessageHeader {
+            stream_idx,
+            stream_msg: AudioStreamMessageHeader { byte_idx, n_bytes },
+        }
+    }
+
+    #[test]
+    fn accepts_and_strips_trailing_bytes() {
+        let mut gate = gate(2);
+
+        assert_eq!(
+            gate.check(&header(1, 0, 4), &[1, 2, 3, 4, 5]),
+            Ok(&[1, 2, 3, 4][..])
+        );
+        assert_eq!(gate.check(&header(0, 0, 0), &[]), Ok(&[][..]));
+    }
+
+    #[test]
+    fn rejections() {
+        let mut gate = gate(2);
+        let payload = [0; 8];
+
+        assert_eq!(
+            gate.check(&header(2, 0, 8), &payload),
+            Err(AudioRejection::UnknownStream)
+        );
+        assert_eq!(
+            gate.check(&header(u32::MAX, 0, 8), &payload),
+            Err(AudioRejection::UnknownStream)
+        );
+        assert_eq!(
+            gate.check(&header(0, 0, 9), &payload),
+            Err(AudioRejection::Truncated)
+        );
+
+        assert!(gate.check(&header(0, 0, 8), &payload).is_ok());
+        assert_eq!(
+            gate.check(&header(0, 0, 8), &payload),
+            Err(AudioRejection::Duplicate)
+        );
+        // duplicates are tracked per stream
+        assert!(gate.check(&header(1, 0, 8), &payload).is_ok());
+        assert!(gate.check(&header(0, 8, 8), &payload).is_ok());
+    }
+
+    #[test]
+    fn selection() {
+        let mut gate = gate(3);
+        let payload = [0; 8];
+
+        gate.select(StreamSelection::Mask(0b101));
+        assert!(gate.check(&header(0, 0, 8), &payload).is_ok());
+        assert_eq!(
+            gate.check(&header(1, 0, 8), &payload),
+            Err(AudioRejection::Unselected)
+        );
+        assert!(gate.check(&header(2, 0, 8), &payload).is_ok());
+
+        gate.select(StreamSelection::All);
+        assert!(gate.check(&header(1, 0, 8), &payload).is_ok());
+    }
+
+    #[test]
+    fn modulus() {
+        for divisor in 1..=16 {
+            let modulus = Modulus::new(divisor.try_into().unwrap());
+            for n in (0..1000).chain(u64::MAX - 1000..=u64::MAX) {
+                assert_eq!(modulus.divides(n), n % divisor == 0, "{n} % {divisor}");
+            }
+        }
+    }
+
+    #[test]
+    fn selection_forgets_byte_indices() {
+        let mut gate = gate(1);
+        let payload = [0; 8];
+
+        gate.select(StreamSelection::All);
+        assert!(gate.check(&header(0, 0, 8), &payload).is_ok());
+
+        // IO restarts, the stream starts over from byte zero
+        gate.select(StreamSelection::All);
+        assert!(gate.check(&header(0, 0, 8), &payload).is_ok());
+    }
+
+    #[test]
+    fn sample_alignment() {
+        let i24 = Format::builder()
+            .sample_type(SampleType::I24)
+            .build()
+            .unwrap();
+        let payload = [0; 8];
+
+        let mut gate = StreamGate::new(&[i24], true);
+        assert!(gate.check(&header(0, 0, 6), &payload).is_ok());
+        assert!(gate.check(&header(0, 6, 3), &payload).is_ok());
+        assert_eq!(
+            gate.check(&header(0, 9, 4), &payload),
+            Err(AudioRejection::Misaligned)
+        );
+        assert_eq!(
+            gate.check(&header(0, 10, 3), &payload),
+            Err(AudioRejection::Misaligned)
+        );
+
+        // partial samples are accepted unless alignment is required
+        let mut gate = StreamGate::new(&[i24], false);
+        assert!(gate.check(&header(0, 10, 4), &payload).is_ok());
+    }
+}
@@ -0,0 +1,207 @@
+//! Convenience handle for communicating with a connected server.
+
+use super::SendStats;
+use core::{
+    cell::{Cell, RefCell, RefMut},
+    mem::MaybeUninit,
+    net::SocketAddr,
+};
+use syfala_proto::{format::StreamFormats, message::Client};
+
+/// A connected server, along with the socket used to reach it.
+///
+/// Bundles the server's address and the stream formats it advertised upon
+/// connection, so that they don't have to be tracked separately.
+///
+/// Messages sent through it are recorded in the server's
+/// [send statistics](super::GenericClient::send_stats), and audio in the client's
+/// [`outbound_bps`](super::GenericClient::outbound_bps), as sent at the instant the
+/// handle was created. Handles are thus meant to be short-lived.
+///
+/// See [`GenericClient::server`](super::GenericClient::server).
+#[derive(Debug)]
+pub struct ConnectedServerHandle<'a, T, O = ()> {
+    sock: &'a super::super::ClientSocket<T, O>,
+    addr: SocketAddr,
+    formats: &'a StreamFormats,
+    epoch: u32,
+    send_stats: &'a Cell<SendStats>,
+    audio_rates: &'a super::stats::AudioRates,
+    /// The client's audio encoding buffer.
+    audio_buf: &'a RefCell<Vec<MaybeUninit<u8>>>,
+    /// Instant at which the handle was created.
+    now: std::time::Instant,
+}
+
+impl<'a, T: crate::SyncUdpSock, O: crate::observer::WireObserver> ConnectedServerHandle<'a, T, O> {
+    #[inline(always)]
+    pub(super) fn new<Cx: super::ClientContext + ?Sized>(
+        sock: &'a super::super::ClientSocket<T, O>,
+        addr: SocketAddr,
+        server: &'a super::ConnectedServer<Cx>,
+        audio_buf: &'a RefCell<Vec<MaybeUninit<u8>>>,
+        now: std::time::Instant,
+    ) -> Self {
+        Self {
+            sock,
+            addr,
+            formats: &server.formats,
+            epoch: server.epoch,
+            send_stats: &server.send_stats,
+            audio_rates: &server.audio_rates,
+            audio_buf,
+            now,
+        }
+    }
+
+    /// Returns the server's address.
+    #[inline(always)]
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the stream formats the server advertised upon connection.
+    #[inline(always)]
+    pub fn formats(&self) -> &'a StreamFormats {
+        self.formats
+    }
+
+    /// Returns the server's epoch.
+    #[inline(always)]
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// Records the outcome of a send in the server's send statistics.
+    #[inline(always)]
+    fn record(&self, res: Result<usize, crate::SendError>) -> std::io::Result<usize> {
+        let res = res.map_err(Into::into);
+        let mut stats = self.send_stats.get();
+        stats.record(&res, self.now);
+        self.send_stats.set(stats);
+        res
+    }
+
+    /// Sends a control message to the server, recording the outcome.
+    #[inline(always)]
+    fn send_msg(&self, msg: Client) -> std::io::Result<()> {
+        let mut buf = [MaybeUninit::uninit(); 200];
+
+        self.record(self.sock.send_msg_len(msg, self.addr, &mut buf))
+            .map(drop)
+    }
+
+    /// Borrows the client's audio encoding buffer, grown to at least `len` bytes.
+    #[inline(always)]
+    fn audio_buf(&self, len: usize) -> RefMut<'a, Vec<MaybeUninit<u8>>> {
+        let mut buf = self.audio_buf.borrow_mut();
+
+        if buf.len() < len {
+            buf.resize(len, MaybeUninit::uninit());
+        }
+
+        buf
+    }
+
+    /// Fails with [`InvalidInput`](std::io::ErrorKind::InvalidInput) if the server has no
+    /// output stream `stream_idx`.
+    #[inline(always)]
+    fn check_stream_idx(&self, stream_idx: u32) -> std::io::Result<()> {
+        let n_outputs = self.formats.outputs.len();
+
+        if !usize::try_from(stream_idx).is_ok_and(|idx| idx < n_outputs) {
+            return Err(std::io::ErrorKind::InvalidInput.into());
+        }
+
+        Ok(())
+    }
+
+    /// Sends `payload` to the server's output stream `stream_idx`, starting at byte index
+    /// `byte_idx`, in a single audio message.
+    ///
+    /// Fails with [`InvalidInput`](std::io::ErrorKind::InvalidInput) if the server has no
+    /// such output stream. Other failures wrap a [`SendError`](crate::SendError), recovered
+    /// with `SendError::from`, e.g. [`TooLarge`](crate::SendError::TooLarge) if the
+    /// message exceeds the socket's
+    /// [maximum datagram size](super::super::ClientSocket::max_datagram_size).
+    pub fn send_audio(
+        &self,
+        stream_idx: u32,
+        byte_idx: u64,
+        payload: &[u8],
+    ) -> std::io::Result<()> {
+        self.check_stream_idx(stream_idx)?;
+
+        let size = crate::ENCODED_AUDIO_MESSAGE_OVERHEAD.saturating_add(payload.len());
+        let max = self.sock.max_datagram_size();
+
+        // don't grow the buffer for a message that can't be sent anyway
+        if size > max {
+            return self
+                .record(Err(crate::SendError::TooLarge { size, max }))
+                .map(drop);
+        }
+
+        let mut buf = self.audio_buf(size);
+
+        self.record(
+            self.sock
+                .send_audio_len(self.addr, stream_idx, byte_idx, payload, &mut buf),
+        )?;
+
+        self.audio_rates.record_outbound(self.now, payload.len());
+
+        Ok(())
+    }
+
+    /// Same as [`send_audio`](Self::send_audio), but splits `payload` into as many audio
+    /// messages as needed, see
+    /// [`ClientSocket::send_audio_chunked`](super::super::ClientSocket::send_audio_chunked).
+    pub fn send_audio_chunked(
+        &self,
+        stream_idx: u32,
+        byte_idx: u64,
+        payload: &[u8],
+    ) -> std::io::Result<()> {
+        self.check_stream_idx(stream_idx)?;
+
+        let max_size = self
+            .sock
+            .max_datagram_size()
+            .min(crate::ENCODED_AUDIO_MESSAGE_OVERHEAD.saturating_add(payload.len().max(1)));
+
+        let mut buf = self.audio_buf(max_size);
+
+        for (byte_idx, chunk) in crate::audio_chunks(byte_idx, payload, max_size)? {
+            self.record(
+                self.sock
+                    .send_audio_len(self.addr, stream_idx, byte_idx, chunk, &mut buf),
+            )?;
+
+            self.audio_rates.record_outbound(self.now, chunk.len());
+        }
+
+        Ok(())
+    }
+
+    /// Sends an IO stop request to the server, followed by a
+    /// [status query](Self::query_status).
+    ///
+    /// This doesn't go through the client's state machine, which only
+    /// [`IOActiveContext::poll_stop_io`](super::IOActiveContext::poll_stop_io) drives, so
+    /// the server's acknowledgement is ignored, unless the context also requested IO to
+    /// stop before it arrived. The server's status, reporting inactive IO, then
+    /// reconciles the client's IO state with its own, through
+    /// [`IOActiveContext::io_stopped_by_server`](super::IOActiveContext::io_stopped_by_server).
+    pub fn request_stop_io(&self) -> std::io::Result<()> {
+        self.send_msg(Client::STOP_IO)?;
+        self.query_status()
+    }
+
+    /// Asks the server for its status. The response is passed to
+    /// [`ClientContext::on_status`](super::ClientContext::on_status), and the server's IO
+    /// state is resynchronized with it.
+    pub fn query_status(&self) -> std::io::Result<()> {
+        self.send_msg(Client::QUERY_STATUS)
+    }
+}
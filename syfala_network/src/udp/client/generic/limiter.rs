@@ -0,0 +1,156 @@
+//! Rate limiting of incoming connection requests.
+
+use core::{cmp, net::SocketAddr};
+use std::time::Instant;
+
+/// Limits on the rate of connection requests handled by a
+/// [`GenericClient`](super::GenericClient).
+///
+/// Each rate is also the burst size, i.e. up to one second's worth of requests can be
+/// handled at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectRateLimit {
+    /// Maximum number of connection requests handled per second, from all addresses.
+    pub global_per_sec: u32,
+    /// Maximum number of connection requests handled per second, from any one address.
+    pub per_addr_per_sec: u32,
+    /// Maximum number of addresses whose rate is tracked at once. When exceeded, the
+    /// least recently seen address is forgotten.
+    pub max_tracked_addrs: usize,
+}
+
+impl ConnectRateLimit {
+    /// 10 requests per second globally, 1 per second per address, and at most 256
+    /// tracked addresses.
+    pub const DEFAULT: Self = Self {
+        global_per_sec: 10,
+        per_addr_per_sec: 1,
+        max_tracked_addrs: 256,
+    };
+}
+
+impl Default for ConnectRateLimit {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A token bucket, refilled continuously at `rate` tokens per second, holding at most
+/// `rate` tokens.
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket.
+    #[inline(always)]
+    fn full(rate: u32, now: Instant) -> Self {
+        Self {
+            tokens: rate.into(),
+            last_refill: now,
+        }
+    }
+
+    /// Refills the bucket up to `now`, returns `true` if it holds at least one token.
+    #[inline(always)]
+    fn refill(&mut self, rate: u32, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        let rate = f64::from(rate);
+
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * rate).min(rate);
+        self.last_refill = cmp::max(self.last_refill, now);
+
+        self.tokens >= 1.
+    }
+
+    /// Takes a token, the bucket must hold at least one.
+    #[inline(always)]
+    fn take(&mut self) {
+        self.tokens -= 1.;
+    }
+}
+
+/// Global and per-address token buckets, limiting the rate of connection requests.
+///
+/// Per-address buckets are kept for at most
+/// [`max_tracked_addrs`](ConnectRateLimit::max_tracked_addrs) addresses, the least
+/// recently seen being evicted first.
+pub(crate) struct ConnectRateLimiter {
+    limit: ConnectRateLimit,
+    global: Option<TokenBucket>,
+    buckets: super::ServerMap<TokenBucket>,
+    /// Last time each tracked address was seen, the least recent having the highest
+    /// priority.
+    last_seen: super::ServerPQ<cmp::Reverse<Instant>>,
+    /// Number of requests rejected so far.
+    n_rejected: u64,
+}
+
+impl ConnectRateLimiter {
+    #[inline(always)]
+    pub(crate) const fn new(limit: ConnectRateLimit) -> Self {
+        Self {
+            limit,
+            global: None,
+            buckets: super::ServerMap::with_hasher(rustc_hash::FxBuildHasher),
+            last_seen: super::ServerPQ::with_hasher(rustc_hash::FxBuildHasher),
+            n_rejected: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn limit(&self) -> &ConnectRateLimit {
+        &self.limit
+    }
+
+    /// Returns the number of requests rejected so far.
+    #[inline(always)]
+    pub(crate) fn n_rejected(&self) -> u64 {
+        self.n_rejected
+    }
+
+    /// Returns `true` if a connection request from `addr`, received at `now`, can be
+    /// handled, and accounts for it. Otherwise, counts it as rejected.
+    pub(crate) fn check(&mut self, addr: SocketAddr, now: Instant) -> bool {
+        let ConnectRateLimit {
+            global_per_sec,
+            per_addr_per_sec,
+            max_tracked_addrs,
+        } = self.limit;
+
+        if !self.buckets.contains_key(&addr) {
+            while self.buckets.len() >= max_tracked_addrs.max(1) {
+                let Some((evicted, _)) = self.last_seen.pop() else {
+                    break;
+                };
+                self.buckets.remove(&evicted);
+            }
+        }
+
+        let bucket = self
+            .buckets
+            .entry(addr)
+            .or_insert_with(|| TokenBucket::full(per_addr_per_sec, now));
+        self.last_seen.push(addr, cmp::Reverse(now));
+
+        let global = self
+            .global
+            .get_or_insert_with(|| TokenBucket::full(global_per_sec, now));
+
+        // refill both buckets, without short-circuiting
+        let addr_ok = bucket.refill(per_addr_per_sec, now);
+        let global_ok = global.refill(global_per_sec, now);
+
+        if addr_ok && global_ok {
+            bucket.take();
+            global.take();
+            true
+        } else {
+            self.n_rejected = self.n_rejected.saturating_add(1);
+            false
+        }
+    }
+}
@@ -10,14 +10,32 @@
 //! Socket timeouts are used to periodically poll server deadlines and
 //! disconnect inactive servers.
 
+mod clock;
+mod deferred;
+mod gate;
+mod handle;
+mod limiter;
 mod state;
+mod stats;
+#[cfg(test)]
+mod tests;
+pub use clock::{Clock, MockClock, SystemClock};
 use core::cmp;
+pub use gate::{AudioRejection, StreamGate};
+pub use handle::ConnectedServerHandle;
+pub use limiter::ConnectRateLimit;
 use replace_with::{replace_with_or_abort, replace_with_or_abort_and_return};
 use rustc_hash::FxBuildHasher;
-use state::{
-    ClientContext, IOActiveContext, IOInactiveContext, IOStartPendingContext, IOStopPendingConxtext,
+#[allow(deprecated)]
+pub use state::IOStopPendingConxtext;
+pub use state::{
+    Active, ClientContext, IOActiveContext, IOInactiveContext, IOStartPendingContext,
+    IOStopPendingContext, Inactive, StartPending, StopPending,
+};
+pub use stats::{RequestLatency, SendError, SendStats};
+use syfala_proto::message::{
+    Capabilities, Client, Error, IOState, Server, StreamSelection, server,
 };
-use syfala_proto::message::{Client, Error, IOState, Server, client, server};
 
 /// Hash map storing per-server state, keyed by socket address.
 type ServerMap<V> = rustc_hash::FxHashMap<core::net::SocketAddr, V>;
@@ -28,13 +46,69 @@ type ServerPQ<V> = priority_queue::PriorityQueue<core::net::SocketAddr, V, FxBui
 /// Duration after which a server is considered disconnected if no valid
 /// message is received. Each successfully handled message refreshes the deadline.
 const CONN_TIMEOUT: core::time::Duration = core::time::Duration::from_millis(600);
+/// Shortened deadline of a server whose port the network reported unreachable, leaving
+/// it about a heartbeat to confirm it is still alive.
+const UNREACHABLE_TIMEOUT: core::time::Duration = core::time::Duration::from_millis(100);
 /// the delay between subsequent retries of client request polls
 const REQUEST_POLL_PERIOD: core::time::Duration = core::time::Duration::from_millis(10);
 
+/// Upper bound of the delay between subsequent retries of a failed IO state change
+/// request.
+const MAX_RETRY_PERIOD: core::time::Duration = core::time::Duration::from_secs(1);
+/// Delay after which an unanswered IO state change request is sent again, it, or its
+/// response, having presumably been lost.
+const RESPONSE_TIMEOUT: core::time::Duration = core::time::Duration::from_millis(100);
+/// Default maximum number of retries of a failed IO state change request.
+const DEFAULT_MAX_IO_REQUEST_RETRIES: u32 = 8;
+
+/// Shortest socket receive timeout, zero durations being rejected by most sockets.
+const MIN_WAKEUP_DELAY: core::time::Duration = core::time::Duration::from_micros(100);
+
 /// Temporary stack buffer size used to encode outgoing protocol messages.
 const ENCODE_BUF_LEN: usize = 2000;
 
-// Note: Comments that should be logs are marked with (*)
+/// Stream formats of a server's connection request.
+enum ConnectFormats<'a> {
+    /// Already decoded.
+    Owned(syfala_proto::format::StreamFormats),
+    /// Still encoded, borrowed from the received datagram.
+    Encoded(crate::StreamFormatsRef<'a>),
+}
+
+impl ConnectFormats<'_> {
+    /// Returns the decoded formats, allocating them if needed.
+    #[inline(always)]
+    fn into_owned(self) -> syfala_proto::format::StreamFormats {
+        match self {
+            Self::Owned(formats) => formats,
+            Self::Encoded(formats) => formats.to_stream_formats(),
+        }
+    }
+
+    /// Returns a decoded copy of the formats, leaving them as they are.
+    #[inline(always)]
+    fn to_owned(&self) -> syfala_proto::format::StreamFormats {
+        match self {
+            Self::Owned(formats) => formats.clone(),
+            Self::Encoded(formats) => formats.to_stream_formats(),
+        }
+    }
+}
+
+/// A server's connection request, whose formats may still be encoded.
+struct ConnectRequest<'a> {
+    epoch: u32,
+    capabilities: Capabilities,
+    formats: ConnectFormats<'a>,
+}
+
+/// A received server message.
+enum Received<'a> {
+    /// A connection request.
+    Connect(ConnectRequest<'a>),
+    /// Any other message.
+    Other(Server),
+}
 
 /// Represents the IO state machine for a connected server.
 ///
@@ -45,33 +119,353 @@ const ENCODE_BUF_LEN: usize = 2000;
 /// - Pending stop request
 ///
 /// All state transitions are driven by incoming messages or application requests.
+///
+/// Pending states also hold the retry bookkeeping of their request.
 enum ServerIOState<Cx: ClientContext + ?Sized> {
     Inactive(state::Inactive<Cx>),
-    PendingStart(state::StartPending<Cx>),
+    PendingStart(state::StartPending<Cx>, RequestRetries),
     Active(state::Active<Cx>),
-    PendingStop(state::StopPending<Cx>),
+    PendingStop(state::StopPending<Cx>, RequestRetries),
 }
 
-// TODO: replace all comments marked with (*) with logs
+/// The IO state of a connected server, without the associated state objects.
+///
+/// See [`GenericClient::servers`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IOStateKind {
+    Inactive,
+    PendingStart,
+    Active,
+    PendingStop,
+}
 
 impl<Cx: ClientContext + ?Sized> ServerIOState<Cx> {
+    /// Returns the name of the state, for logging purposes.
+    #[inline(always)]
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Inactive(_) => "inactive",
+            Self::PendingStart(..) => "start pending",
+            Self::Active(_) => "active",
+            Self::PendingStop(..) => "stop pending",
+        }
+    }
+
+    #[inline(always)]
+    fn kind(&self) -> IOStateKind {
+        match self {
+            Self::Inactive(_) => IOStateKind::Inactive,
+            Self::PendingStart(..) => IOStateKind::PendingStart,
+            Self::Active(_) => IOStateKind::Active,
+            Self::PendingStop(..) => IOStateKind::PendingStop,
+        }
+    }
+}
+
+/// Returns how long the client can wait for incoming messages, from `now`, before its
+/// next timeout handling, `None` meaning indefinitely.
+///
+/// That is, until the earliest of:
+/// - `next_deadline`, the earliest connection deadline, if any server is connected.
+/// - `retry_deadline`, the earliest resend of a pending request, if any.
+/// - The next request poll, [`REQUEST_POLL_PERIOD`] from now, if `poll` is `true`,
+///   i.e. if the application could request an IO state change.
+fn next_wakeup(
+    now: std::time::Instant,
+    next_deadline: Option<std::time::Instant>,
+    retry_deadline: Option<std::time::Instant>,
+    poll: bool,
+) -> Option<core::time::Duration> {
+    let next_poll = poll.then(|| now + REQUEST_POLL_PERIOD);
+
+    [next_deadline, retry_deadline, next_poll]
+        .into_iter()
+        .flatten()
+        .min()
+        .map(|wakeup| wakeup.saturating_duration_since(now).max(MIN_WAKEUP_DELAY))
+}
+
+/// Retry bookkeeping of a pending IO state change request.
+///
+/// Failures reported by the server are retried with exponential backoff, starting at
+/// [`REQUEST_POLL_PERIOD`], and up to [`MAX_RETRY_PERIOD`]. Failures reported while a
+/// retry is already scheduled are considered duplicates, and ignored.
+///
+/// Requests left unanswered are sent again, after [`RESPONSE_TIMEOUT`], doubling after
+/// each attempt, up to [`MAX_RETRY_PERIOD`]. Servers treat requests for their current IO
+/// state as immediately successful, so this recovers from lost responses. Such timeouts
+/// count as failed attempts, so that servers that never answer are eventually given up
+/// on too.
+#[derive(Debug, Clone, Copy)]
+struct RequestRetries {
+    /// Number of failures reported by the server, or response timeouts, so far.
+    n_failures: u32,
+    /// When to resend the request, if a failure has been reported since it was last
+    /// sent.
+    next_retry: Option<std::time::Instant>,
+    /// When the request was last sent.
+    sent_at: std::time::Instant,
+}
+
+impl RequestRetries {
+    /// Creates the bookkeeping of a request sent at `sent_at`.
+    #[inline(always)]
+    const fn new(sent_at: std::time::Instant) -> Self {
+        Self {
+            n_failures: 0,
+            next_retry: None,
+            sent_at,
+        }
+    }
+
+    /// Returns the time elapsed between the last sending of the request and `now`.
+    #[inline(always)]
+    fn elapsed(&self, now: std::time::Instant) -> core::time::Duration {
+        now.saturating_duration_since(self.sent_at)
+    }
+
+    /// Returns the delay before the next retry, after `n_failures` failures.
+    #[inline(always)]
+    fn delay(n_failures: u32) -> core::time::Duration {
+        let exp = n_failures.saturating_sub(1).min(16);
+        REQUEST_POLL_PERIOD
+            .saturating_mul(1 << exp)
+            .min(MAX_RETRY_PERIOD)
+    }
+
+    /// Returns how long to wait for a response, after `n_failures` failed attempts.
+    #[inline(always)]
+    fn response_timeout(n_failures: u32) -> core::time::Duration {
+        let exp = n_failures.min(16);
+        RESPONSE_TIMEOUT
+            .saturating_mul(1 << exp)
+            .min(MAX_RETRY_PERIOD)
+    }
+
+    /// Records a failure reported at `now`.
+    ///
+    /// Returns `None` if the failure is a duplicate, `Some(true)` if a retry has been
+    /// scheduled, and `Some(false)` if more than `max_retries` failures have been
+    /// reported, in which case the request should be given up on.
+    fn on_failure(&mut self, now: std::time::Instant, max_retries: u32) -> Option<bool> {
+        if self.next_retry.is_some() {
+            return None;
+        }
+
+        self.n_failures = self.n_failures.saturating_add(1);
+
+        if self.n_failures > max_retries {
+            return Some(false);
+        }
+
+        self.next_retry = Some(now + Self::delay(self.n_failures));
+        Some(true)
+    }
+
+    /// Returns when the request should be sent again: at the scheduled retry, if any, or
+    /// when its response times out.
+    #[inline(always)]
+    fn next_resend(&self) -> std::time::Instant {
+        self.next_retry
+            .unwrap_or(self.sent_at + Self::response_timeout(self.n_failures))
+    }
+
+    /// Returns whether the request should be sent again at `now`, in which case it is
+    /// marked as sent, or given up on, if its response timed out, and more than
+    /// `max_retries` attempts failed.
+    fn poll(&mut self, now: std::time::Instant, max_retries: u32) -> RetryPoll {
+        if self.next_resend() > now {
+            return RetryPoll::Wait;
+        }
+
+        // no failure was reported, the response timed out
+        if self.next_retry.is_none() {
+            self.n_failures = self.n_failures.saturating_add(1);
+
+            if self.n_failures > max_retries {
+                return RetryPoll::GiveUp;
+            }
+        }
+
+        self.next_retry = None;
+        self.sent_at = now;
+
+        RetryPoll::Resend
+    }
+}
+
+/// Outcome of [`RequestRetries::poll`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RetryPoll {
+    /// Nothing to do yet.
+    Wait,
+    /// The request must be sent again.
+    Resend,
+    /// Too many attempts failed, the request must be given up on.
+    GiveUp,
+}
+
+/// Per-server storage: the IO state machine, along with the gate validating incoming
+/// audio messages, and send and traffic statistics.
+struct ConnectedServer<Cx: ClientContext + ?Sized> {
+    io_state: ServerIOState<Cx>,
+    audio_gate: gate::StreamGate,
+    /// Updated through server handles too, which only hold shared references.
+    send_stats: core::cell::Cell<SendStats>,
+    audio_rates: stats::AudioRates,
+    /// Input streams of the last IO start request, or of the active IO.
+    selection: StreamSelection,
+    /// Epoch of the server's connection request.
+    epoch: u32,
+    /// Optional features advertised in the server's connection request.
+    capabilities: Capabilities,
+    /// Stream formats advertised in the server's connection request.
+    formats: syfala_proto::format::StreamFormats,
+}
+
+/// Sends a message to a server and records the outcome in its send statistics.
+///
+/// If sending would block, the message is deferred instead of failing.
+#[inline(always)]
+fn send_msg_tracked(
+    sock: &super::ClientSocket<impl crate::SyncUdpSock, impl crate::observer::WireObserver>,
+    send_stats: &mut SendStats,
+    msg: Client,
+    addr: core::net::SocketAddr,
+    deferred: &mut deferred::DeferredSends,
+    encode_buf: &mut [core::mem::MaybeUninit<u8>],
+    now: std::time::Instant,
+) -> std::io::Result<()> {
+    let res = sock.send_msg_len(msg, addr, encode_buf).map_err(Into::into);
+    send_stats.record(&res, now);
+    deferred.on_send_result(res.map(drop), msg, addr)
+}
+
+/// Inserts a newly connected server, along with its connection deadline.
+///
+/// Both are inserted before anything else can fail, so that a connected server always
+/// has a deadline, even if handling the rest of the message returns early.
+#[inline(always)]
+fn insert_server<'a, Cx: ClientContext + ?Sized>(
+    servers: &'a mut ServerMap<ConnectedServer<Cx>>,
+    deadlines: &mut ServerPQ<cmp::Reverse<std::time::Instant>>,
+    addr: core::net::SocketAddr,
+    server: ConnectedServer<Cx>,
+    now: std::time::Instant,
+) -> &'a mut ConnectedServer<Cx> {
+    deadlines.push(addr, cmp::Reverse(now.checked_add(CONN_TIMEOUT).unwrap()));
+    servers.entry(addr).insert_entry(server).into_mut()
+}
+
+/// Out-of-line slow path of the audio dispatch, keeping the fast path small.
+#[cold]
+#[inline(never)]
+fn on_audio_rejected<Cx: ClientContext + ?Sized>(
+    cx: &mut Cx,
+    addr: core::net::SocketAddr,
+    header: syfala_proto::AudioMessageHeader,
+    reason: AudioRejection,
+) {
+    cx.audio_rejected(addr, header, reason);
+}
+
+impl<Cx: ClientContext + ?Sized> ConnectedServer<Cx> {
+    /// Resynchronizes the server's IO state with the one reported in its status,
+    /// `io_active`.
+    ///
+    /// A pending request whose outcome matches the reported state is considered
+    /// acknowledged, its acknowledgement having been lost. Other pending requests are
+    /// left pending, their response may still be in flight. A stable state disagreeing
+    /// with the server's goes through its pending state, and immediately to the
+    /// server's state, if the context accepts it (see
+    /// [`IOActiveContext::io_stopped_by_server`] and
+    /// [`IOInactiveContext::io_started_by_server`]). All streams are then assumed to be
+    /// active.
+    fn reconcile_io_state(
+        &mut self,
+        cx: &mut Cx,
+        addr: core::net::SocketAddr,
+        io_active: bool,
+        timestamp: std::time::Instant,
+    ) {
+        let Self {
+            io_state,
+            audio_gate,
+            send_stats,
+            selection,
+            ..
+        } = self;
+
+        let send_stats = send_stats.get_mut();
+
+        replace_with_or_abort(io_state, |s| match (s, io_active) {
+            (ServerIOState::PendingStart(s, retries), true) => {
+                crate::log_record!(debug, "{addr}: IO active, assuming start acknowledged");
+                let elapsed = retries.elapsed(timestamp);
+                send_stats.start_io_latency.record(elapsed);
+                audio_gate.select(*selection);
+                ServerIOState::Active(s.start_io_with_latency(cx, *selection, elapsed))
+            }
+            (ServerIOState::PendingStop(s, retries), false) => {
+                crate::log_record!(debug, "{addr}: IO inactive, assuming stop acknowledged");
+                let elapsed = retries.elapsed(timestamp);
+                send_stats.stop_io_latency.record(elapsed);
+                ServerIOState::Inactive(s.stop_io_with_latency(cx, elapsed))
+            }
+            (ServerIOState::Inactive(s), true) => match s.io_started_by_server(cx) {
+                Ok(s) => {
+                    crate::log_record!(info, "{addr}: IO active on the server, starting IO");
+                    *selection = StreamSelection::All;
+                    audio_gate.select(*selection);
+                    ServerIOState::Active(s.start_io(cx, *selection))
+                }
+                Err(s) => {
+                    crate::log_record!(warn, "{addr}: IO active on the server, but not here");
+                    ServerIOState::Inactive(s)
+                }
+            },
+            (ServerIOState::Active(s), false) => match s.io_stopped_by_server(cx) {
+                Ok(s) => {
+                    crate::log_record!(info, "{addr}: IO inactive on the server, stopping IO");
+                    ServerIOState::Inactive(s.stop_io(cx))
+                }
+                Err(s) => {
+                    crate::log_record!(warn, "{addr}: IO inactive on the server, but not here");
+                    ServerIOState::Active(s)
+                }
+            },
+            (s, _) => s,
+        });
+    }
+
     /// Handles an incoming `Server::Connected` message.
     ///
     /// Dispatches control and audio messages to the current state object,
-    /// adn performs state transitions and callbacks where needed.
+    /// and performs state transitions and callbacks where needed.
     ///
     /// Invalid messages for the current state are ignored, but may be logged.
     fn on_msg(
         &mut self,
         addr: core::net::SocketAddr,
         cx: &mut Cx,
-        sock: &super::ClientSocket<impl crate::SyncUdpSock>,
         msg: (server::Connected, &[u8]),
         timestamp: std::time::Instant,
+        max_retries: u32,
     ) -> std::io::Result<()> {
         let (msg, rem_buf) = msg;
 
-        let mut encode_buf = [0; 2000];
+        let Self {
+            io_state,
+            audio_gate,
+            send_stats,
+            audio_rates,
+            selection,
+            epoch: _,
+            capabilities: _,
+            formats: _,
+        } = self;
+
+        let send_stats = send_stats.get_mut();
 
         use server::Connected;
 
@@ -80,36 +474,82 @@ impl<Cx: ClientContext + ?Sized> ServerIOState<Cx> {
             Connected::Control(server::Control::IOStateChangeResult(r)) => match r {
                 // Server acknowledged an IO start request.
                 IOState::Start(r) => match r {
-                    Ok(()) => replace_with_or_abort(self, |s| match s {
-                        Self::PendingStart(s) => Self::Active(s.start_io(cx)),
+                    Ok(()) => replace_with_or_abort(io_state, |s| match s {
+                        ServerIOState::PendingStart(s, retries) => {
+                            let elapsed = retries.elapsed(timestamp);
+                            send_stats.start_io_latency.record(elapsed);
+                            audio_gate.select(*selection);
+                            ServerIOState::Active(s.start_io_with_latency(cx, *selection, elapsed))
+                        }
                         a => {
-                            // (*) not waiting for IO start
+                            crate::log_record!(
+                                debug,
+                                "{addr}: unexpected IO start acknowledgement (IO {})",
+                                a.name()
+                            );
                             a
                         }
                     }),
                     Err(e) => match e {
-                        // Temporary failure: retry start request.
-                        Error::Failure(()) => match self {
-                            Self::PendingStart(s) => {
-                                s.start_io_failed(cx);
-                                sock.send_msg(
-                                    Client::Connected(client::Connected::Control(
-                                        client::Control::RequestIOStateChange(IOState::Start(())),
-                                    )),
-                                    addr,
-                                    &mut encode_buf,
-                                )?;
-                                // (*) io start failed, retrying...
+                        // Temporary failure: schedule a retry of the start request, or give
+                        // up on it, as if it was refused.
+                        Error::Failure(()) => replace_with_or_abort(io_state, |s| match s {
+                            ServerIOState::PendingStart(mut s, mut retries) => {
+                                match retries.on_failure(timestamp, max_retries) {
+                                    None => {
+                                        crate::log_record!(
+                                            trace,
+                                            "{addr}: duplicate IO start failure"
+                                        );
+                                        ServerIOState::PendingStart(s, retries)
+                                    }
+                                    Some(true) => {
+                                        s.start_io_failed(cx);
+                                        crate::log_record!(
+                                            warn,
+                                            "{addr}: IO start failed, retrying ({}/{max_retries})",
+                                            retries.n_failures
+                                        );
+                                        ServerIOState::PendingStart(s, retries)
+                                    }
+                                    Some(false) => {
+                                        s.start_io_failed(cx);
+                                        crate::log_record!(
+                                            warn,
+                                            "{addr}: IO start failed, giving up"
+                                        );
+                                        let elapsed = retries.elapsed(timestamp);
+                                        send_stats.start_io_latency.record(elapsed);
+                                        ServerIOState::Inactive(
+                                            s.start_io_refused_with_latency(cx, elapsed),
+                                        )
+                                    }
+                                }
                             }
-                            _ => {
-                                // (*) not waiting for IO to start
+                            a => {
+                                crate::log_record!(
+                                    debug,
+                                    "{addr}: unexpected IO start failure (IO {})",
+                                    a.name()
+                                );
+                                a
                             }
-                        },
+                        }),
                         // Permanent refusal: notify callbacks and do not retry.
-                        Error::Refusal(()) => replace_with_or_abort(self, |s| match s {
-                            Self::PendingStart(s) => Self::Inactive(s.start_io_refused(cx)),
+                        Error::Refusal(()) => replace_with_or_abort(io_state, |s| match s {
+                            ServerIOState::PendingStart(s, retries) => {
+                                let elapsed = retries.elapsed(timestamp);
+                                send_stats.start_io_latency.record(elapsed);
+                                ServerIOState::Inactive(
+                                    s.start_io_refused_with_latency(cx, elapsed),
+                                )
+                            }
                             a => {
-                                // (*) not waiting for IO start
+                                crate::log_record!(
+                                    debug,
+                                    "{addr}: unexpected IO start refusal (IO {})",
+                                    a.name()
+                                );
                                 a
                             }
                         }),
@@ -118,35 +558,79 @@ impl<Cx: ClientContext + ?Sized> ServerIOState<Cx> {
 
                 // Server acknowledged an IO stop request.
                 IOState::Stop(r) => match r {
-                    Ok(()) => replace_with_or_abort(self, |s| match s {
-                        Self::PendingStop(s) => Self::Inactive(s.stop_io(cx)),
+                    Ok(()) => replace_with_or_abort(io_state, |s| match s {
+                        ServerIOState::PendingStop(s, retries) => {
+                            let elapsed = retries.elapsed(timestamp);
+                            send_stats.stop_io_latency.record(elapsed);
+                            ServerIOState::Inactive(s.stop_io_with_latency(cx, elapsed))
+                        }
                         a => {
-                            // (*) not waiting for io stop
+                            crate::log_record!(
+                                debug,
+                                "{addr}: unexpected IO stop acknowledgement (IO {})",
+                                a.name()
+                            );
                             a
                         }
                     }),
                     Err(e) => match e {
-                        // Temporary failure: retry stop request.
-                        Error::Failure(()) => match self {
-                            Self::PendingStop(s) => {
-                                s.stop_io_failed(cx);
-                                sock.send_msg(
-                                    Client::Connected(client::Connected::Control(
-                                        client::Control::RequestIOStateChange(IOState::Stop(())),
-                                    )),
-                                    addr,
-                                    &mut encode_buf,
-                                )?
+                        // Temporary failure: schedule a retry of the stop request, or give
+                        // up on it, as if it was refused.
+                        Error::Failure(()) => replace_with_or_abort(io_state, |s| match s {
+                            ServerIOState::PendingStop(mut s, mut retries) => {
+                                match retries.on_failure(timestamp, max_retries) {
+                                    None => {
+                                        crate::log_record!(
+                                            trace,
+                                            "{addr}: duplicate IO stop failure"
+                                        );
+                                        ServerIOState::PendingStop(s, retries)
+                                    }
+                                    Some(true) => {
+                                        s.stop_io_failed(cx);
+                                        crate::log_record!(
+                                            warn,
+                                            "{addr}: IO stop failed, retrying ({}/{max_retries})",
+                                            retries.n_failures
+                                        );
+                                        ServerIOState::PendingStop(s, retries)
+                                    }
+                                    Some(false) => {
+                                        s.stop_io_failed(cx);
+                                        crate::log_record!(
+                                            warn,
+                                            "{addr}: IO stop failed, giving up"
+                                        );
+                                        let elapsed = retries.elapsed(timestamp);
+                                        send_stats.stop_io_latency.record(elapsed);
+                                        ServerIOState::Active(
+                                            s.stop_io_refused_with_latency(cx, elapsed),
+                                        )
+                                    }
+                                }
                             }
-                            _ => {
-                                // (*) not waiting for IO stop
+                            a => {
+                                crate::log_record!(
+                                    debug,
+                                    "{addr}: unexpected IO stop failure (IO {})",
+                                    a.name()
+                                );
+                                a
                             }
-                        },
+                        }),
                         // Permanent refusal: notify callbacks.
-                        Error::Refusal(()) => replace_with_or_abort(self, |s| match s {
-                            Self::PendingStop(s) => Self::Active(s.stop_io_refused(cx)),
+                        Error::Refusal(()) => replace_with_or_abort(io_state, |s| match s {
+                            ServerIOState::PendingStop(s, retries) => {
+                                let elapsed = retries.elapsed(timestamp);
+                                send_stats.stop_io_latency.record(elapsed);
+                                ServerIOState::Active(s.stop_io_refused_with_latency(cx, elapsed))
+                            }
                             a => {
-                                // (*) not waiting for IO stop
+                                crate::log_record!(
+                                    debug,
+                                    "{addr}: unexpected IO stop refusal (IO {})",
+                                    a.name()
+                                );
                                 a
                             }
                         }),
@@ -157,10 +641,21 @@ impl<Cx: ClientContext + ?Sized> ServerIOState<Cx> {
             // timeout updating is done outside of this function
             Connected::Control(server::Control::Heartbeat) => (),
 
-            Connected::Audio(header) => match self {
-                ServerIOState::Active(s) => s.on_audio(cx, timestamp, header, rem_buf),
-                _ => {
-                    // (*) audio IO inactive
+            Connected::Control(server::Control::Status(status)) => {
+                cx.on_status(addr, status);
+                self.reconcile_io_state(cx, addr, status.io_active, timestamp);
+            }
+
+            Connected::Audio(header) => match io_state {
+                ServerIOState::Active(s) => match audio_gate.check(&header, rem_buf) {
+                    Ok(payload) => {
+                        audio_rates.record_inbound(timestamp, payload.len());
+                        s.on_audio(cx, timestamp, header, payload)
+                    }
+                    Err(reason) => on_audio_rejected(cx, addr, header, reason),
+                },
+                s => {
+                    crate::log_record!(trace, "{addr}: audio message discarded (IO {})", s.name());
                 }
             },
         }
@@ -176,17 +671,32 @@ impl<Cx: ClientContext + ?Sized> ServerIOState<Cx> {
 /// This also maintains a priority queue of per-server connection timeout deadlines.
 ///
 /// It implements the [`Client`] so that it can be driven by a blocking UDP receive loop.
-pub struct GenericClient<C: ClientContext> {
+///
+/// The current time is read through a [`Clock`], the system's one by default.
+pub struct GenericClient<C: ClientContext, K = SystemClock> {
     /// Priority queue tracking next timeout per server.
     ///
     /// We use [`core::cmp::Reverse`] here to ensure the _earliest_ instant
     /// has the _highest_ priority
     deadlines: ServerPQ<cmp::Reverse<std::time::Instant>>,
     /// Per-server state machine storage.
-    servers: ServerMap<ServerIOState<C>>,
+    servers: ServerMap<ConnectedServer<C>>,
+    /// Earliest resend of a pending IO state change request, across servers.
     retry_deadline: Option<std::time::Instant>,
+    /// Maximum number of retries of a failed IO state change request.
+    max_io_request_retries: u32,
+    /// Whether incoming audio messages must start and end on sample boundaries.
+    sample_aligned_audio: bool,
+    /// Limits the rate of connection requests passed to the context.
+    connect_limiter: limiter::ConnectRateLimiter,
+    /// Control messages whose sending would have blocked.
+    deferred: deferred::DeferredSends,
+    /// Buffer audio messages sent through server handles are encoded into, grown to the
+    /// largest message sent so far, up to the socket's maximum datagram size.
+    audio_buf: core::cell::RefCell<Vec<core::mem::MaybeUninit<u8>>>,
     /// User-provided callbacks defining connection, IO, and audio behavior.
     callbacks: C,
+    clock: K,
 }
 
 impl<C: ClientContext> GenericClient<C> {
@@ -195,40 +705,288 @@ impl<C: ClientContext> GenericClient<C> {
     /// Initially, no servers are connected, and the deadline queue is empty.
     #[inline(always)]
     pub const fn new(callbacks: C) -> Self {
+        Self::with_clock(callbacks, SystemClock)
+    }
+}
+
+impl<C: ClientContext, K: Clock> GenericClient<C, K> {
+    /// Same as [`new`](GenericClient::new), but reads the current time from the given clock.
+    #[inline(always)]
+    pub const fn with_clock(callbacks: C, clock: K) -> Self {
         Self {
             callbacks,
             deadlines: ServerPQ::with_hasher(FxBuildHasher),
             servers: ServerMap::with_hasher(FxBuildHasher),
             retry_deadline: None,
+            max_io_request_retries: DEFAULT_MAX_IO_REQUEST_RETRIES,
+            sample_aligned_audio: false,
+            connect_limiter: limiter::ConnectRateLimiter::new(ConnectRateLimit::DEFAULT),
+            deferred: deferred::DeferredSends::new(),
+            audio_buf: core::cell::RefCell::new(Vec::new()),
+            clock,
         }
     }
 
+    /// Sets the maximum number of retries of an IO state change request the server
+    /// reported a failure for, or didn't answer in time, 8 by default.
+    ///
+    /// Once exceeded, the request is given up on, as if the server refused it.
+    #[inline(always)]
+    pub fn with_max_io_request_retries(mut self, max_retries: u32) -> Self {
+        self.max_io_request_retries = max_retries;
+        self
+    }
+
+    /// Sets whether incoming audio messages must start and end on sample boundaries of
+    /// their stream, `false` by default.
+    ///
+    /// The protocol allows partial samples, only require alignment from servers known to
+    /// send whole samples. Misaligned messages are then rejected with
+    /// [`AudioRejection::Misaligned`]. Applies to servers connecting from now on.
+    #[inline(always)]
+    pub fn with_sample_aligned_audio(mut self, aligned: bool) -> Self {
+        self.sample_aligned_audio = aligned;
+        self
+    }
+
+    /// Sets the limits on the rate of connection requests passed to the context,
+    /// [`ConnectRateLimit::DEFAULT`] by default.
+    ///
+    /// Requests from already connected servers (with the same epoch) aren't limited.
+    #[inline(always)]
+    pub fn with_connect_rate_limit(mut self, limit: ConnectRateLimit) -> Self {
+        self.connect_limiter = limiter::ConnectRateLimiter::new(limit);
+        self
+    }
+
+    /// Returns the limits on the rate of connection requests.
+    #[inline(always)]
+    pub fn connect_rate_limit(&self) -> &ConnectRateLimit {
+        self.connect_limiter.limit()
+    }
+
+    /// Returns the number of connection requests dropped so far, because of the
+    /// connection rate limits.
+    #[inline(always)]
+    pub fn rate_limited_connects(&self) -> u64 {
+        self.connect_limiter.n_rejected()
+    }
+
+    /// Returns the number of control messages waiting to be sent again, because sending
+    /// them would have blocked.
+    ///
+    /// They are sent again upon the next socket receive timeout, at most 32 are kept, the
+    /// oldest being dropped first.
+    #[inline(always)]
+    pub fn deferred_sends(&self) -> usize {
+        self.deferred.len()
+    }
+
+    /// Returns the number of deferred control messages dropped so far, see
+    /// [`deferred_sends`](Self::deferred_sends).
+    #[inline(always)]
+    pub fn dropped_deferred_sends(&self) -> u64 {
+        self.deferred.n_dropped()
+    }
+
+    /// Returns the client's clock.
+    #[inline(always)]
+    pub const fn clock(&self) -> &K {
+        &self.clock
+    }
+
+    /// Returns the client context.
+    #[inline(always)]
+    pub const fn callbacks(&self) -> &C {
+        &self.callbacks
+    }
+
+    /// Returns the client context, mutably.
+    #[inline(always)]
+    pub const fn callbacks_mut(&mut self) -> &mut C {
+        &mut self.callbacks
+    }
+
+    /// Returns the address and IO state of every connected server, in no particular
+    /// order.
+    #[inline(always)]
+    pub fn servers(&self) -> impl Iterator<Item = (&core::net::SocketAddr, IOStateKind)> {
+        self.servers
+            .iter()
+            .map(|(addr, server)| (addr, server.io_state.kind()))
+    }
+
+    /// Returns `true` if the server at `addr` is connected.
+    #[inline(always)]
+    pub fn is_connected(&self, addr: &core::net::SocketAddr) -> bool {
+        self.servers.contains_key(addr)
+    }
+
+    /// Returns the instant after which the server at `addr` will be considered
+    /// disconnected, if no message is received from it in the meantime.
+    #[inline(always)]
+    pub fn deadline(&self, addr: &core::net::SocketAddr) -> Option<std::time::Instant> {
+        self.deadlines
+            .get_priority(addr)
+            .map(|&cmp::Reverse(deadline)| deadline)
+    }
+
+    /// Returns how long until the server at `addr` is considered disconnected, see
+    /// [`deadline`](Self::deadline).
+    #[inline(always)]
+    pub fn remaining(&self, addr: &core::net::SocketAddr) -> Option<core::time::Duration> {
+        let now = self.clock.now();
+        self.deadline(addr)
+            .map(|deadline| deadline.saturating_duration_since(now))
+    }
+
+    /// Returns a handle to the server at `addr`, if connected, to communicate with it
+    /// through `sock`.
+    #[inline(always)]
+    pub fn server<'a, T: crate::SyncUdpSock, O: crate::observer::WireObserver>(
+        &'a self,
+        sock: &'a super::ClientSocket<T, O>,
+        addr: core::net::SocketAddr,
+    ) -> Option<ConnectedServerHandle<'a, T, O>> {
+        let now = self.clock.now();
+        self.servers
+            .get(&addr)
+            .map(|s| ConnectedServerHandle::new(sock, addr, s, &self.audio_buf, now))
+    }
+
+    /// Returns the send statistics of the server at `addr`, if connected.
+    #[inline(always)]
+    pub fn send_stats(&self, addr: &core::net::SocketAddr) -> Option<SendStats> {
+        self.servers.get(addr).map(|s| s.send_stats.get())
+    }
+
+    /// Returns the estimated rate of audio payload received from the server at `addr`,
+    /// if connected, in bits per second.
+    ///
+    /// This is averaged over the last few seconds, and only accounts for audio messages
+    /// accepted while IO is active.
+    #[inline(always)]
+    pub fn inbound_bps(&self, addr: &core::net::SocketAddr) -> Option<u64> {
+        let now = self.clock.now();
+        self.servers
+            .get(addr)
+            .map(|s| s.audio_rates.inbound_bps(now))
+    }
+
+    /// Returns the estimated rate of audio payload successfully sent to the server at
+    /// `addr`, through its [handle](Self::server), if connected, in bits per second.
+    ///
+    /// This is averaged over the last few seconds.
+    #[inline(always)]
+    pub fn outbound_bps(&self, addr: &core::net::SocketAddr) -> Option<u64> {
+        let now = self.clock.now();
+        self.servers
+            .get(addr)
+            .map(|s| s.audio_rates.outbound_bps(now))
+    }
+
+    /// Returns the last error encountered when sending a message to the server at
+    /// `addr`, if connected, and if any error occured.
+    #[inline(always)]
+    pub fn last_send_error(&self, addr: &core::net::SocketAddr) -> Option<SendError> {
+        self.send_stats(addr).and_then(|s| s.last_error)
+    }
+
     /// Handles an incoming server connection request.
     ///
     /// If the server is not already connected, invokes `connect` on the
     /// client context to determine whether the connection is accepted.
     /// Sends a `Client::ConnectionResult` back to the server accordingly.
+    ///
+    /// If the server is connected, but with a different epoch, it has restarted: its
+    /// previous session is torn down first, and the connection is handled as a new one.
+    /// Restarts of servers not advertising [`Capabilities::EPOCH`] can't be detected.
+    ///
+    /// In both cases, the request is dropped if it exceeds the connection rate limits.
+    ///
+    /// Encoded formats are only decoded (and allocated) if `connect` is invoked.
     fn on_server_connect_request(
         &mut self,
-        sock: &super::ClientSocket<impl crate::SyncUdpSock>,
+        sock: &super::ClientSocket<impl crate::SyncUdpSock, impl crate::observer::WireObserver>,
         addr: core::net::SocketAddr,
-        formats: syfala_proto::format::StreamFormats,
-        encode_buf: &mut [u8],
+        req: ConnectRequest<'_>,
+        encode_buf: &mut [core::mem::MaybeUninit<u8>],
+        timestamp: std::time::Instant,
     ) -> std::io::Result<()> {
-        if !self.servers.contains_key(&addr) {
-            match self.callbacks.connect(addr, formats) {
-                Ok(state) => {
-                    self.servers.insert(addr, ServerIOState::Inactive(state));
-                    sock.send_msg(Client::ConnectionResult(Ok(())), addr, encode_buf)?;
-                    // (*) connection success
-                }
-                Err(e) => {
-                    sock.send_msg(Client::ConnectionResult(Err(e)), addr, encode_buf)?;
-                    // (*) connection failed/rejected
-                }
+        let ConnectRequest {
+            epoch,
+            capabilities,
+            formats,
+        } = req;
+
+        // without epochs, a restarted server can't be told apart from its former self
+        let has_epoch = capabilities.contains(Capabilities::EPOCH);
+
+        if self
+            .servers
+            .get(&addr)
+            .is_some_and(|s| !has_epoch || s.epoch == epoch)
+        {
+            crate::log_record!(trace, "{addr}: connection request, already connected");
+            return Ok(());
+        }
+
+        if !self.connect_limiter.check(addr, timestamp) {
+            self.callbacks.connect_rate_limited(addr);
+            crate::log_record!(trace, "{addr}: connection request dropped (rate limited)");
+            return Ok(());
+        }
+
+        if self.servers.contains_key(&addr) {
+            self.servers.remove(&addr).unwrap();
+            self.deadlines.remove(&addr).unwrap();
+            self.callbacks.disconnected(addr);
+            crate::log_record!(debug, "{addr}: restarted (new epoch: {epoch:#010x})");
+        }
+
+        // the context takes its own copy, the server's is only decoded if it is accepted
+        let cx_formats = formats.to_owned();
+
+        // the server sends audio on its input streams
+        let audio_gate = gate::StreamGate::new(&cx_formats.inputs, self.sample_aligned_audio);
+
+        match self.callbacks.connect(addr, cx_formats) {
+            Ok(state) => {
+                let formats = formats.into_owned();
+                let server = ConnectedServer {
+                    io_state: ServerIOState::Inactive(state),
+                    audio_gate,
+                    send_stats: Default::default(),
+                    audio_rates: stats::AudioRates::new(timestamp),
+                    selection: StreamSelection::All,
+                    epoch,
+                    capabilities,
+                    formats,
+                };
+                let server = insert_server(
+                    &mut self.servers,
+                    &mut self.deadlines,
+                    addr,
+                    server,
+                    timestamp,
+                );
+                send_msg_tracked(
+                    sock,
+                    server.send_stats.get_mut(),
+                    Client::ConnectionResult(Ok(())),
+                    addr,
+                    &mut self.deferred,
+                    encode_buf,
+                    timestamp,
+                )?;
+                crate::log_record!(debug, "{addr}: connected");
+            }
+            Err(e) => {
+                let msg = Client::ConnectionResult(Err(e));
+                let res = sock.send_msg(msg, addr, encode_buf).map_err(Into::into);
+                self.deferred.on_send_result(res, msg, addr)?;
+                crate::log_record!(warn, "{addr}: connection rejected ({e:?})");
             }
-        } else {
-            // (*) server already connected
         }
 
         Ok(())
@@ -239,35 +997,68 @@ impl<C: ClientContext> GenericClient<C> {
     /// Also refreshes the server's deadline if it is still connected.
     fn on_decoded_message(
         &mut self,
-        sock: &super::ClientSocket<impl crate::SyncUdpSock>,
+        sock: &super::ClientSocket<impl crate::SyncUdpSock, impl crate::observer::WireObserver>,
         addr: core::net::SocketAddr,
         timestamp: std::time::Instant,
-        msg: (syfala_proto::message::Server, &[u8]),
+        msg: (Received<'_>, &[u8]),
     ) -> std::io::Result<()> {
-        let mut buf = [0; ENCODE_BUF_LEN];
+        let mut buf = [core::mem::MaybeUninit::uninit(); ENCODE_BUF_LEN];
 
         let (msg, rem_buf) = msg;
 
+        // Only audio messages carry a payload, trailing bytes after any other message
+        // are unexpected (some peers pad their datagrams)
+        let is_audio = matches!(
+            msg,
+            Received::Other(Server::Connected(server::Connected::Audio(_)))
+        );
+
+        if !is_audio && !rem_buf.is_empty() {
+            self.callbacks.unexpected_trailing_bytes(addr, rem_buf);
+        }
+
         match msg {
-            Server::Connect(formats) => {
-                self.on_server_connect_request(sock, addr, formats, &mut buf)?;
+            Received::Connect(req) => {
+                self.on_server_connect_request(sock, addr, req, &mut buf, timestamp)?;
+            }
+            Received::Other(Server::Connect {
+                epoch,
+                capabilities,
+                formats,
+            }) => {
+                let req = ConnectRequest {
+                    epoch,
+                    capabilities,
+                    formats: ConnectFormats::Owned(formats),
+                };
+                self.on_server_connect_request(sock, addr, req, &mut buf, timestamp)?;
             }
-            Server::Connected(msg) => {
-                if let Some(state) = self.servers.get_mut(&addr) {
-                    state.on_msg(addr, &mut self.callbacks, sock, (msg, rem_buf), timestamp)?;
+            Received::Other(Server::Connected(msg)) => {
+                if let Some(server) = self.servers.get_mut(&addr) {
+                    let max_retries = self.max_io_request_retries;
+                    server.on_msg(
+                        addr,
+                        &mut self.callbacks,
+                        (msg, rem_buf),
+                        timestamp,
+                        max_retries,
+                    )?;
                 }
             }
-            Server::Disconnect => match self.servers.remove(&addr) {
+            Received::Other(Server::Disconnect) => match self.servers.remove(&addr) {
                 Some(_s) => {
                     self.deadlines.remove(&addr).unwrap();
-                    // (*) successfully disconnected from server
+                    self.callbacks.disconnected(addr);
+                    crate::log_record!(debug, "{addr}: disconnected");
                 }
                 None => {
-                    // (*) no connected server at that address
+                    crate::log_record!(trace, "{addr}: disconnection request, not connected");
                 }
             },
         }
 
+        // Servers are always inserted along with their deadline, and removed along with
+        // it, see `insert_server`. Here, we only refresh it.
         if self.servers.contains_key(&addr) {
             // not that push _replaces_ the corresponding entry if it already exists, so the
             // number of elements in deadlines is always exactly the number of connected servers
@@ -283,29 +1074,124 @@ impl<C: ClientContext> GenericClient<C> {
     /// Handles an incoming UDP message (or lack thereof).
     fn on_message(
         &mut self,
-        sock: &super::ClientSocket<impl crate::SyncUdpSock>,
+        sock: &super::ClientSocket<impl crate::SyncUdpSock, impl crate::observer::WireObserver>,
         addr: core::net::SocketAddr,
         timestamp: std::time::Instant,
         maybe_msg: Option<(syfala_proto::message::Server, &[u8])>,
     ) -> std::io::Result<()> {
         match maybe_msg {
-            Some(msg) => self.on_decoded_message(sock, addr, timestamp, msg)?,
+            Some((msg, rem_buf)) => {
+                self.on_decoded_message(sock, addr, timestamp, (Received::Other(msg), rem_buf))?
+            }
             None => self.callbacks.unknown_message(addr),
         }
 
         Ok(())
     }
 
+    /// Handles an incoming UDP datagram.
+    ///
+    /// Unlike [`on_message`](Self::on_message), the stream formats of connection
+    /// requests are left encoded, and only decoded if the server isn't already
+    /// connected, sparing an allocation for each of a connected server's periodic
+    /// `Connect` messages.
+    fn on_datagram(
+        &mut self,
+        sock: &super::ClientSocket<impl crate::SyncUdpSock, impl crate::observer::WireObserver>,
+        addr: core::net::SocketAddr,
+        timestamp: std::time::Instant,
+        datagram: &[u8],
+    ) -> std::io::Result<()> {
+        use crate::observer::MessageKind;
+
+        let msg = match crate::server_message_decode_ref(datagram) {
+            Ok((
+                crate::ServerMessageRef::Connect {
+                    epoch,
+                    capabilities,
+                    formats,
+                },
+                _n_decoded,
+                rem_buf,
+            )) => {
+                sock.observe_decoded(addr, Some(MessageKind::Connect));
+                let req = ConnectRequest {
+                    epoch,
+                    capabilities,
+                    formats: ConnectFormats::Encoded(formats),
+                };
+                (Received::Connect(req), rem_buf)
+            }
+            Ok((crate::ServerMessageRef::Other(msg), _n_decoded, rem_buf)) => {
+                sock.observe_decoded(addr, Some(MessageKind::of_server(&msg)));
+                (Received::Other(msg), rem_buf)
+            }
+            Err(_) => {
+                sock.observe_decoded(addr, None);
+                self.callbacks.unknown_message(addr);
+                return Ok(());
+            }
+        };
+
+        self.on_decoded_message(sock, addr, timestamp, msg)
+    }
+
+    /// Receives all ICMP errors queued on the socket, recording them in the affected
+    /// servers' send statistics, and returns their number.
+    ///
+    /// The deadlines of servers whose port is unreachable are shortened to
+    /// [`UNREACHABLE_TIMEOUT`].
+    fn on_recv_errors(
+        &mut self,
+        sock: &super::ClientSocket<impl crate::SyncUdpSock, impl crate::observer::WireObserver>,
+    ) -> std::io::Result<usize> {
+        let now = self.clock.now();
+        let mut n_errors = 0;
+
+        while let Some(crate::DestinationError { dest_addr, kind }) = sock.recv_error()? {
+            n_errors += 1;
+
+            let Some(server) = self.servers.get_mut(&dest_addr) else {
+                crate::log_record!(trace, "{dest_addr}: ICMP error ({kind})");
+                continue;
+            };
+
+            server.send_stats.get_mut().record_icmp_error(kind);
+
+            if kind != std::io::ErrorKind::ConnectionRefused {
+                crate::log_record!(debug, "{dest_addr}: ICMP error ({kind})");
+                continue;
+            }
+
+            crate::log_record!(debug, "{dest_addr}: port unreachable, likely dead");
+
+            let deadline = now.checked_add(UNREACHABLE_TIMEOUT).unwrap();
+            // connected servers always have a deadline
+            let cmp::Reverse(current) = *self.deadlines.get_priority(&dest_addr).unwrap();
+
+            if deadline < current {
+                self.deadlines
+                    .change_priority(&dest_addr, cmp::Reverse(deadline));
+            }
+
+            self.callbacks.unreachable(dest_addr);
+        }
+
+        Ok(n_errors)
+    }
+
     /// Handles a socket receive timeout.
     ///
-    /// Expires all servers whose deadlines have elapsed, removes them
-    /// from the map, and resets the socket receive timeout to the next
-    /// earliest deadline if any.
+    /// Receives the queued ICMP errors, if any. Expires all servers whose deadlines have
+    /// elapsed, removes them from the map, and resets the socket receive timeout to the
+    /// next earliest deadline if any.
     fn on_timeout(
         &mut self,
-        sock: &super::ClientSocket<impl crate::SyncUdpSock>,
+        sock: &super::ClientSocket<impl crate::SyncUdpSock, impl crate::observer::WireObserver>,
     ) -> std::io::Result<()> {
-        let now = std::time::Instant::now();
+        self.on_recv_errors(sock)?;
+
+        let now = self.clock.now();
 
         // Expire all overdue servers
         while let Some((addr, _)) = self
@@ -313,47 +1199,199 @@ impl<C: ClientContext> GenericClient<C> {
             .pop_if(|_, cmp::Reverse(deadline)| *deadline <= now)
         {
             self.servers.remove(&addr).unwrap();
+            self.callbacks.disconnected(addr);
+            crate::log_record!(debug, "{addr}: timed out");
         }
 
         // Manage incoming application requests, and retrying pending server requests
-        let mut encode_buf = [0; 200];
+        let mut encode_buf = [core::mem::MaybeUninit::uninit(); 200];
 
-        for (addr, state) in &mut self.servers {
-            replace_with_or_abort_and_return(state, |s| match s {
+        self.deferred.flush(sock, &mut encode_buf)?;
+
+        self.retry_deadline = None;
+        let mut poll = false;
+        let max_retries = self.max_io_request_retries;
+
+        for (addr, server) in &mut self.servers {
+            let send_stats = server.send_stats.get_mut();
+            let selection = &mut server.selection;
+            let selective = server.capabilities.contains(Capabilities::SELECTIVE_START);
+
+            replace_with_or_abort_and_return(&mut server.io_state, |s| match s {
                 ServerIOState::Inactive(s) => match s.poll_start_io(&mut self.callbacks) {
                     Ok(s) => {
-                        // (*) start IO requested by client for the server at addr
+                        crate::log_record!(debug, "{addr}: requesting IO start");
+                        *selection = if selective {
+                            s.requested_streams(&mut self.callbacks)
+                        } else {
+                            StreamSelection::All
+                        };
                         (
-                            sock.send_msg(Client::START_IO, *addr, &mut encode_buf),
-                            ServerIOState::PendingStart(s),
+                            send_msg_tracked(
+                                sock,
+                                send_stats,
+                                Client::start_io(*selection),
+                                *addr,
+                                &mut self.deferred,
+                                &mut encode_buf,
+                                now,
+                            ),
+                            ServerIOState::PendingStart(s, RequestRetries::new(now)),
                         )
                     }
                     Err(s) => (Ok(()), ServerIOState::Inactive(s)),
                 },
                 ServerIOState::Active(s) => match s.poll_stop_io(&mut self.callbacks) {
                     Ok(s) => {
-                        // (*) stop IO requested by client for the server at addr
+                        crate::log_record!(debug, "{addr}: requesting IO stop");
                         (
-                            sock.send_msg(Client::START_IO, *addr, &mut encode_buf),
-                            ServerIOState::PendingStop(s),
+                            send_msg_tracked(
+                                sock,
+                                send_stats,
+                                Client::STOP_IO,
+                                *addr,
+                                &mut self.deferred,
+                                &mut encode_buf,
+                                now,
+                            ),
+                            ServerIOState::PendingStop(s, RequestRetries::new(now)),
                         )
                     }
                     Err(s) => (Ok(()), ServerIOState::Active(s)),
                 },
-                state => (Ok(()), state),
+                ServerIOState::PendingStart(s, mut retries) => {
+                    match retries.poll(now, max_retries) {
+                        RetryPoll::Wait => (Ok(()), ServerIOState::PendingStart(s, retries)),
+                        RetryPoll::Resend => {
+                            crate::log_record!(debug, "{addr}: retrying IO start request");
+                            (
+                                send_msg_tracked(
+                                    sock,
+                                    send_stats,
+                                    Client::start_io(*selection),
+                                    *addr,
+                                    &mut self.deferred,
+                                    &mut encode_buf,
+                                    now,
+                                ),
+                                ServerIOState::PendingStart(s, retries),
+                            )
+                        }
+                        RetryPoll::GiveUp => {
+                            crate::log_record!(warn, "{addr}: IO start unanswered, giving up");
+                            let elapsed = retries.elapsed(now);
+                            send_stats.start_io_latency.record(elapsed);
+                            (
+                                Ok(()),
+                                ServerIOState::Inactive(
+                                    s.start_io_refused_with_latency(&mut self.callbacks, elapsed),
+                                ),
+                            )
+                        }
+                    }
+                }
+                ServerIOState::PendingStop(s, mut retries) => {
+                    match retries.poll(now, max_retries) {
+                        RetryPoll::Wait => (Ok(()), ServerIOState::PendingStop(s, retries)),
+                        RetryPoll::Resend => {
+                            crate::log_record!(debug, "{addr}: retrying IO stop request");
+                            (
+                                send_msg_tracked(
+                                    sock,
+                                    send_stats,
+                                    Client::STOP_IO,
+                                    *addr,
+                                    &mut self.deferred,
+                                    &mut encode_buf,
+                                    now,
+                                ),
+                                ServerIOState::PendingStop(s, retries),
+                            )
+                        }
+                        RetryPoll::GiveUp => {
+                            crate::log_record!(warn, "{addr}: IO stop unanswered, giving up");
+                            let elapsed = retries.elapsed(now);
+                            send_stats.stop_io_latency.record(elapsed);
+                            (
+                                Ok(()),
+                                ServerIOState::Active(
+                                    s.stop_io_refused_with_latency(&mut self.callbacks, elapsed),
+                                ),
+                            )
+                        }
+                    }
+                }
             })?;
+
+            match &server.io_state {
+                // the application can request an IO state change at any time
+                ServerIOState::Inactive(_) | ServerIOState::Active(_) => poll = true,
+                ServerIOState::PendingStart(_, retries)
+                | ServerIOState::PendingStop(_, retries) => {
+                    let next_resend = retries.next_resend();
+                    self.retry_deadline = Some(
+                        self.retry_deadline
+                            .map_or(next_resend, |t| t.min(next_resend)),
+                    );
+                }
+            }
         }
 
-        let min_timeout = REQUEST_POLL_PERIOD.min(CONN_TIMEOUT);
+        // retry deferred sends soon
+        poll |= self.deferred.len() > 0;
 
-        sock.set_recv_timeout(
-            self.deadlines
-                .peek()
-                .map(|(_, cmp::Reverse(next))| next.saturating_duration_since(now))
-                .map(|t| t.min(REQUEST_POLL_PERIOD).min(CONN_TIMEOUT)),
-                
-        )?;
+        let next_deadline = self.deadlines.peek().map(|(_, cmp::Reverse(next))| *next);
+
+        sock.set_recv_timeout(next_wakeup(now, next_deadline, self.retry_deadline, poll))?;
 
         Ok(())
     }
 }
+
+impl<C: ClientContext, K: Clock> super::Client for GenericClient<C, K> {
+    #[inline(always)]
+    fn on_message(
+        &mut self,
+        client: &super::ClientSocket<impl crate::SyncUdpSock, impl crate::observer::WireObserver>,
+        server_addr: core::net::SocketAddr,
+        timestamp: std::time::Instant,
+        message: Option<(syfala_proto::message::Server, &[u8])>,
+    ) -> std::io::Result<()> {
+        // resolves to the inherent method
+        Self::on_message(self, client, server_addr, timestamp, message)
+    }
+
+    #[inline(always)]
+    fn on_datagram(
+        &mut self,
+        client: &super::ClientSocket<impl crate::SyncUdpSock, impl crate::observer::WireObserver>,
+        server_addr: core::net::SocketAddr,
+        timestamp: std::time::Instant,
+        datagram: &[u8],
+    ) -> std::io::Result<()> {
+        Self::on_datagram(self, client, server_addr, timestamp, datagram)
+    }
+
+    /// Receives the queued ICMP errors, failing with `error` if there are none.
+    ///
+    /// Then handles a timeout, rescheduling the next one after the shortened deadlines.
+    fn on_recv_error(
+        &mut self,
+        client: &super::ClientSocket<impl crate::SyncUdpSock, impl crate::observer::WireObserver>,
+        error: std::io::Error,
+    ) -> std::io::Result<()> {
+        if self.on_recv_errors(client)? == 0 {
+            return Err(error);
+        }
+
+        Self::on_timeout(self, client)
+    }
+
+    #[inline(always)]
+    fn on_timeout(
+        &mut self,
+        client: &super::ClientSocket<impl crate::SyncUdpSock, impl crate::observer::WireObserver>,
+    ) -> std::io::Result<()> {
+        Self::on_timeout(self, client)
+    }
+}
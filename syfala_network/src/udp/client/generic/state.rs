@@ -1,4 +1,3 @@
-
 /// Type alias representing the `Inactive` IO state for a given client context.
 ///
 /// Resolves to the associated `IOInactive` type of the `ClientContext`.
@@ -24,7 +23,7 @@ pub type StopPending<Cx> = <Active<Cx> as IOActiveContext>::IOStopPending;
 ///
 /// This trait is implemented by the "application layer" client object, and provides:
 /// - The ability to handle new server connections, and return a nother callback manager
-/// for said connection
+///   for said connection
 pub trait ClientContext {
     /// A newly connected server with inactive IO.
     type IOInactive: IOInactiveContext<Context = Self>;
@@ -40,10 +39,74 @@ pub trait ClientContext {
         stream_formats: syfala_proto::format::StreamFormats,
     ) -> Result<Self::IOInactive, syfala_proto::message::Error>;
 
-    fn unknown_message(
+    fn unknown_message(&mut self, addr: core::net::SocketAddr);
+
+    /// Invoked after the server at `addr` has been disconnected, i.e. when it requested
+    /// it, timed out, or restarted (in which case, `connect` is invoked again right
+    /// after).
+    ///
+    /// Its state has already been dropped. By default, nothing else is done.
+    #[inline(always)]
+    fn disconnected(&mut self, addr: core::net::SocketAddr) {
+        let _ = addr;
+    }
+
+    /// Invoked when the network reports the port of the server at `addr` as unreachable,
+    /// i.e. it likely died, while its host is still up.
+    ///
+    /// Its deadline has been shortened, it is disconnected unless a message is received
+    /// from it in the meantime. By default, nothing else is done.
+    #[inline(always)]
+    fn unreachable(&mut self, addr: core::net::SocketAddr) {
+        let _ = addr;
+    }
+
+    /// Invoked when a connection request from `addr` is dropped, because it exceeds the
+    /// client's connection rate limits.
+    ///
+    /// This can be called at a high rate, by misbehaving or malicious peers, keep it
+    /// cheap. By default, nothing is done.
+    #[inline(always)]
+    fn connect_rate_limited(&mut self, addr: core::net::SocketAddr) {
+        let _ = addr;
+    }
+
+    /// Invoked when the server at `addr` responds to a status query (see
+    /// [`ConnectedServerHandle::query_status`](super::ConnectedServerHandle::query_status)).
+    ///
+    /// Invoked whatever the server's IO state, before it is resynchronized with the
+    /// reported one. By default, nothing is done.
+    #[inline(always)]
+    fn on_status(
         &mut self,
         addr: core::net::SocketAddr,
-    );
+        status: syfala_proto::message::server::Status,
+    ) {
+        let _ = (addr, status);
+    }
+
+    /// Invoked when a successfully decoded, non-audio, message is followed by extra bytes.
+    ///
+    /// The message itself is still handled normally, `bytes` only contains the trailing
+    /// bytes. By default, they are silently ignored.
+    #[inline(always)]
+    fn unexpected_trailing_bytes(&mut self, addr: core::net::SocketAddr, bytes: &[u8]) {
+        let _ = (addr, bytes);
+    }
+
+    /// Invoked when an incoming audio message is discarded before reaching the
+    /// [`IOActiveContext::on_audio`] callback of the server at `addr`.
+    ///
+    /// By default, rejected messages are silently dropped.
+    #[inline(always)]
+    fn audio_rejected(
+        &mut self,
+        addr: core::net::SocketAddr,
+        header: syfala_proto::AudioMessageHeader,
+        reason: super::AudioRejection,
+    ) {
+        let _ = (addr, header, reason);
+    }
 }
 
 /// "Typestate" representing a server with inactive IO.
@@ -62,6 +125,18 @@ pub trait IOInactiveContext: Sized {
     /// - Returns `Ok(IOStartPending)` if a start request was made
     /// - Returns `Err(Self)` if no request was made, leaving the state unchanged
     fn poll_start_io(self, cx: &mut Self::Context) -> Result<Self::IOStartPending, Self>;
+
+    /// Invoked when the server reports active IO, in response to a status query, while
+    /// it is inactive here, e.g. after the client restarted.
+    ///
+    /// Returning `Ok(IOStartPending)` accepts the server's state, IO is then immediately
+    /// started, as if the server acknowledged a start request. By default, `Err(self)`
+    /// is returned, leaving the state unchanged.
+    #[inline(always)]
+    fn io_started_by_server(self, cx: &mut Self::Context) -> Result<Self::IOStartPending, Self> {
+        let _ = cx;
+        Err(self)
+    }
 }
 
 /// "Typestate" representing a server whose IO start request is pending.
@@ -74,15 +149,67 @@ pub trait IOStartPendingContext: Sized {
     /// The typestate representing an active IO session.
     type IOActive: IOActiveContext<Context = Self::Context>;
 
+    /// Returns the server's input streams to request, see
+    /// [`StreamSelection`](syfala_proto::message::StreamSelection). Only called when the
+    /// start request is about to be sent, by default, all streams are requested.
+    ///
+    /// Servers not advertising
+    /// [`SELECTIVE_START`](syfala_proto::message::Capabilities::SELECTIVE_START) are
+    /// always asked for all streams.
+    #[inline(always)]
+    fn requested_streams(&self, cx: &mut Self::Context) -> syfala_proto::message::StreamSelection {
+        let _ = cx;
+        syfala_proto::message::StreamSelection::All
+    }
+
     /// Called when the server acknowledges the start request successfully.
     ///
+    /// `selection` is the set of the server's input streams that are now active, audio
+    /// messages for other streams are discarded.
+    ///
     /// Returns the `Active` IO typestate.
-    fn start_io(self, cx: &mut Self::Context) -> Self::IOActive;
+    fn start_io(
+        self,
+        cx: &mut Self::Context,
+        selection: syfala_proto::message::StreamSelection,
+    ) -> Self::IOActive;
 
     /// Called when the server permanently refuses the start request.
     ///
     /// Returns to the `Inactive` state, allowing the client to retry later.
-    fn start_io_refused(self, cx: &mut Self::Context) -> <Self::Context as ClientContext>::IOInactive;
+    fn start_io_refused(
+        self,
+        cx: &mut Self::Context,
+    ) -> <Self::Context as ClientContext>::IOInactive;
+
+    /// Same as [`start_io`](Self::start_io), `elapsed` being the time since the
+    /// request was (last) sent.
+    ///
+    /// This is the method called by the client. By default, `elapsed` is ignored.
+    #[inline(always)]
+    fn start_io_with_latency(
+        self,
+        cx: &mut Self::Context,
+        selection: syfala_proto::message::StreamSelection,
+        elapsed: core::time::Duration,
+    ) -> Self::IOActive {
+        let _ = elapsed;
+        self.start_io(cx, selection)
+    }
+
+    /// Same as [`start_io_refused`](Self::start_io_refused), `elapsed` being the time
+    /// since the request was (last) sent.
+    ///
+    /// This is the method called by the client. By default, `elapsed` is ignored.
+    #[inline(always)]
+    fn start_io_refused_with_latency(
+        self,
+        cx: &mut Self::Context,
+        elapsed: core::time::Duration,
+    ) -> <Self::Context as ClientContext>::IOInactive {
+        let _ = elapsed;
+        self.start_io_refused(cx)
+    }
 
     /// Called when the server reports a temporary failure to start IO.
     ///
@@ -99,7 +226,7 @@ pub trait IOActiveContext: Sized {
     type Context;
 
     /// The typestate representing a pending IO stop request.
-    type IOStopPending: IOStopPendingConxtext<Context = Self::Context>;
+    type IOStopPending: IOStopPendingContext<Context = Self::Context>;
 
     /// Called when an audio message is received from the server.
     ///
@@ -121,12 +248,24 @@ pub trait IOActiveContext: Sized {
     /// - Returns `Ok(IOStopPending)` if a stop request was made
     /// - Returns `Err(Self)` if no stop request was made, leaving the state unchanged
     fn poll_stop_io(self, cx: &mut Self::Context) -> Result<Self::IOStopPending, Self>;
+
+    /// Invoked when the server reports inactive IO, in response to a status query, while
+    /// it is active here.
+    ///
+    /// Returning `Ok(IOStopPending)` accepts the server's state, IO is then immediately
+    /// stopped, as if the server acknowledged a stop request. By default, `Err(self)` is
+    /// returned, leaving the state unchanged.
+    #[inline(always)]
+    fn io_stopped_by_server(self, cx: &mut Self::Context) -> Result<Self::IOStopPending, Self> {
+        let _ = cx;
+        Err(self)
+    }
 }
 
 /// "Typestate" representing a server whose IO stop request is pending.
 ///
 /// Provides methods to handle the server’s response to the stop request.
-pub trait IOStopPendingConxtext: Sized {
+pub trait IOStopPendingContext: Sized {
     /// The parent client context associated with this state.
     type Context: ClientContext;
 
@@ -140,9 +279,45 @@ pub trait IOStopPendingConxtext: Sized {
     /// Returns to the `Active` state, leaving IO running.
     fn stop_io_refused(self, cx: &mut Self::Context) -> Active<Self::Context>;
 
+    /// Same as [`stop_io`](Self::stop_io), `elapsed` being the time since the request
+    /// was (last) sent.
+    ///
+    /// This is the method called by the client. By default, `elapsed` is ignored.
+    #[inline(always)]
+    fn stop_io_with_latency(
+        self,
+        cx: &mut Self::Context,
+        elapsed: core::time::Duration,
+    ) -> <Self::Context as ClientContext>::IOInactive {
+        let _ = elapsed;
+        self.stop_io(cx)
+    }
+
+    /// Same as [`stop_io_refused`](Self::stop_io_refused), `elapsed` being the time
+    /// since the request was (last) sent.
+    ///
+    /// This is the method called by the client. By default, `elapsed` is ignored.
+    #[inline(always)]
+    fn stop_io_refused_with_latency(
+        self,
+        cx: &mut Self::Context,
+        elapsed: core::time::Duration,
+    ) -> Active<Self::Context> {
+        let _ = elapsed;
+        self.stop_io_refused(cx)
+    }
+
     /// Called when the server reports a temporary failure to stop IO.
     ///
     /// The implementation may perform retries or log diagnostics. The current state
     /// remains in `StopPending`.
     fn stop_io_failed(&mut self, cx: &mut Self::Context);
-}
\ No newline at end of file
+}
+
+/// Former, misspelled, name of [`IOStopPendingContext`], implemented by all of its
+/// implementors.
+#[deprecated(note = "renamed to `IOStopPendingContext`")]
+pub trait IOStopPendingConxtext: IOStopPendingContext {}
+
+#[allow(deprecated)]
+impl<T: IOStopPendingContext> IOStopPendingConxtext for T {}
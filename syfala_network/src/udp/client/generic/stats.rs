@@ -0,0 +1,160 @@
+//! Per-server send and traffic statistics.
+
+/// The last error encountered when sending a message to a server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SendError {
+    /// Kind of the error returned by the socket.
+    pub kind: std::io::ErrorKind,
+    /// Instant at which the error occured.
+    pub at: std::time::Instant,
+}
+
+/// Aggregated round-trip times of IO state change requests, i.e. the time elapsed between
+/// the (last) sending of a request, and the server's response to it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestLatency {
+    /// Number of responses received.
+    pub count: u64,
+    /// Shortest round-trip time, if any.
+    pub min: Option<core::time::Duration>,
+    /// Longest round-trip time, if any.
+    pub max: Option<core::time::Duration>,
+    /// Sum of all round-trip times.
+    pub total: core::time::Duration,
+}
+
+impl RequestLatency {
+    /// Returns the average round-trip time, if any.
+    #[inline(always)]
+    pub fn avg(&self) -> Option<core::time::Duration> {
+        let count = u32::try_from(self.count).unwrap_or(u32::MAX);
+        (count > 0).then(|| self.total / count)
+    }
+
+    /// Records the round-trip time of a request.
+    #[inline(always)]
+    pub(crate) fn record(&mut self, elapsed: core::time::Duration) {
+        self.count = self.count.saturating_add(1);
+        self.min = Some(self.min.map_or(elapsed, |min| min.min(elapsed)));
+        self.max = Some(self.max.map_or(elapsed, |max| max.max(elapsed)));
+        self.total = self.total.saturating_add(elapsed);
+    }
+}
+
+/// Statistics about the messages sent to a connected server.
+///
+/// These help telling apart a client that stopped sending from a server that stopped
+/// receiving.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SendStats {
+    /// Number of messages successfully sent.
+    pub packets_sent: u64,
+    /// Total size of the datagrams successfully sent, in bytes.
+    pub bytes_sent: u64,
+    /// Instant of the last successful send, if any.
+    pub last_send: Option<std::time::Instant>,
+    /// Last send error, if any. Not cleared by subsequent successful sends.
+    pub last_error: Option<SendError>,
+    /// Number of consecutive failed sends, reset by every successful send.
+    pub failure_streak: u32,
+    /// Number of ICMP errors reported about the messages sent, if the socket receives
+    /// them, see [`ClientSocket::set_recv_errors`](super::super::ClientSocket::set_recv_errors).
+    pub icmp_errors: u64,
+    /// Number of those ICMP errors reporting the server's port as unreachable.
+    pub port_unreachable_errors: u64,
+    /// Round-trip times of IO start requests, acknowledged or refused.
+    pub start_io_latency: RequestLatency,
+    /// Round-trip times of IO stop requests, acknowledged or refused.
+    pub stop_io_latency: RequestLatency,
+}
+
+impl SendStats {
+    /// Records the outcome of a send operation that occured at `now`, i.e. the size of
+    /// the sent datagram, or the error encountered.
+    #[inline(always)]
+    pub(crate) fn record(&mut self, res: &std::io::Result<usize>, now: std::time::Instant) {
+        match *res {
+            Ok(n_bytes) => {
+                self.packets_sent = self.packets_sent.saturating_add(1);
+                self.bytes_sent = self
+                    .bytes_sent
+                    .saturating_add(u64::try_from(n_bytes).unwrap());
+                self.last_send = Some(now);
+                self.failure_streak = 0;
+            }
+            Err(ref e) => {
+                self.last_error = Some(SendError {
+                    kind: e.kind(),
+                    at: now,
+                });
+                self.failure_streak = self.failure_streak.saturating_add(1);
+            }
+        }
+    }
+
+    /// Records an ICMP error reported about a sent message.
+    #[inline(always)]
+    pub(crate) fn record_icmp_error(&mut self, kind: std::io::ErrorKind) {
+        self.icmp_errors = self.icmp_errors.saturating_add(1);
+
+        if kind == std::io::ErrorKind::ConnectionRefused {
+            self.port_unreachable_errors = self.port_unreachable_errors.saturating_add(1);
+        }
+    }
+}
+
+/// Span of the audio rate estimation window, in seconds.
+const RATE_WINDOW_SECS: usize = 5;
+
+/// Estimated audio payload rates exchanged with a connected server, see
+/// [`RateEstimator`](syfala_utils::RateEstimator).
+#[derive(Debug)]
+pub(crate) struct AudioRates {
+    /// Origin of the estimators' times.
+    connected_at: std::time::Instant,
+    inbound: syfala_utils::RateEstimator<RATE_WINDOW_SECS>,
+    /// Updated through server handles, which only hold shared references.
+    outbound: core::cell::Cell<syfala_utils::RateEstimator<RATE_WINDOW_SECS>>,
+}
+
+impl AudioRates {
+    #[inline(always)]
+    pub(crate) fn new(connected_at: std::time::Instant) -> Self {
+        Self {
+            connected_at,
+            inbound: Default::default(),
+            outbound: Default::default(),
+        }
+    }
+
+    #[inline(always)]
+    fn elapsed(&self, now: std::time::Instant) -> core::time::Duration {
+        now.saturating_duration_since(self.connected_at)
+    }
+
+    /// Records `n_bytes` of audio payload received at `now`.
+    #[inline(always)]
+    pub(crate) fn record_inbound(&mut self, now: std::time::Instant, n_bytes: usize) {
+        self.inbound.record(self.elapsed(now), n_bytes);
+    }
+
+    /// Records `n_bytes` of audio payload sent at `now`.
+    #[inline(always)]
+    pub(crate) fn record_outbound(&self, now: std::time::Instant, n_bytes: usize) {
+        let mut outbound = self.outbound.get();
+        outbound.record(self.elapsed(now), n_bytes);
+        self.outbound.set(outbound);
+    }
+
+    /// Returns the estimated inbound audio rate at `now`, in bits per second.
+    #[inline(always)]
+    pub(crate) fn inbound_bps(&self, now: std::time::Instant) -> u64 {
+        self.inbound.bits_per_sec(self.elapsed(now))
+    }
+
+    /// Returns the estimated outbound audio rate at `now`, in bits per second.
+    #[inline(always)]
+    pub(crate) fn outbound_bps(&self, now: std::time::Instant) -> u64 {
+        self.outbound.get().bits_per_sec(self.elapsed(now))
+    }
+}
@@ -0,0 +1,1836 @@
+//! Test harness driving a [`GenericClient`] through a mock socket and clock, along with
+//! the tests using it.
+
+use super::*;
+use core::{cell::RefCell, net::SocketAddr, time::Duration};
+use std::{collections::VecDeque, io, time::Instant};
+use syfala_proto::{
+    AudioMessageHeader,
+    format::{Format, StreamFormats},
+};
+
+const SERVER: SocketAddr =
+    SocketAddr::new(core::net::IpAddr::V4(core::net::Ipv4Addr::LOCALHOST), 6910);
+
+/// Socket recording the datagrams sent through it, whose sends can be made to fail.
+#[derive(Default)]
+struct MockSock {
+    sent: RefCell<Vec<(SocketAddr, Vec<u8>)>>,
+    /// Errors returned by the next sends, in order.
+    send_errors: RefCell<VecDeque<io::ErrorKind>>,
+    /// ICMP errors queued on the socket, in order.
+    recv_errors: RefCell<VecDeque<crate::DestinationError>>,
+    recv_timeout: RefCell<Option<Duration>>,
+}
+
+impl crate::SyncUdpSock for MockSock {
+    fn send(&self, bytes: &[u8], dest_addr: SocketAddr) -> io::Result<()> {
+        if let Some(kind) = self.send_errors.borrow_mut().pop_front() {
+            return Err(kind.into());
+        }
+
+        self.sent.borrow_mut().push((dest_addr, bytes.to_vec()));
+        Ok(())
+    }
+
+    fn recv(&self, _bytes: &mut [u8]) -> io::Result<(usize, SocketAddr, Instant)> {
+        Err(io::ErrorKind::WouldBlock.into())
+    }
+
+    fn set_recv_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        *self.recv_timeout.borrow_mut() = timeout;
+        Ok(())
+    }
+
+    fn recv_error(&self) -> io::Result<Option<crate::DestinationError>> {
+        Ok(self.recv_errors.borrow_mut().pop_front())
+    }
+}
+
+/// Callbacks invoked by the client, in order.
+#[derive(Debug, Clone, PartialEq)]
+enum Event {
+    Connected(SocketAddr),
+    Disconnected(SocketAddr),
+    Unreachable(SocketAddr),
+    UnknownMessage,
+    RateLimited,
+    Status(server::Status),
+    TrailingBytes(usize),
+    AudioRejected(AudioRejection),
+    Audio(AudioMessageHeader, Vec<u8>),
+    StartIO(StreamSelection, Duration),
+    StartIORefused(Duration),
+    StartIOFailed,
+    StopIO(Duration),
+    StopIORefused(Duration),
+    StopIOFailed,
+}
+
+/// Scripted context: IO is requested to be active as long as `want_io` is set.
+#[derive(Default)]
+struct Context {
+    events: Vec<Event>,
+    want_io: bool,
+    /// Streams requested when starting IO.
+    selection: StreamSelection,
+    /// Whether IO state changes reported by servers are accepted.
+    follow_server: bool,
+    /// Whether connections are refused.
+    refuse: bool,
+}
+
+/// A server's state, whatever its IO state.
+struct Connection;
+
+impl ClientContext for Context {
+    type IOInactive = Connection;
+
+    fn connect(&mut self, addr: SocketAddr, _formats: StreamFormats) -> Result<Connection, Error> {
+        if self.refuse {
+            return Err(Error::Refusal(()));
+        }
+
+        self.events.push(Event::Connected(addr));
+        Ok(Connection)
+    }
+
+    fn unknown_message(&mut self, _addr: SocketAddr) {
+        self.events.push(Event::UnknownMessage);
+    }
+
+    fn disconnected(&mut self, addr: SocketAddr) {
+        self.events.push(Event::Disconnected(addr));
+    }
+
+    fn unreachable(&mut self, addr: SocketAddr) {
+        self.events.push(Event::Unreachable(addr));
+    }
+
+    fn connect_rate_limited(&mut self, _addr: SocketAddr) {
+        self.events.push(Event::RateLimited);
+    }
+
+    fn on_status(&mut self, _addr: SocketAddr, status: server::Status) {
+        self.events.push(Event::Status(status));
+    }
+
+    fn unexpected_trailing_bytes(&mut self, _addr: SocketAddr, bytes: &[u8]) {
+        self.events.push(Event::TrailingBytes(bytes.len()));
+    }
+
+    fn audio_rejected(
+        &mut self,
+        _addr: SocketAddr,
+        _header: AudioMessageHeader,
+        reason: AudioRejection,
+    ) {
+        self.events.push(Event::AudioRejected(reason));
+    }
+}
+
+impl IOInactiveContext for Connection {
+    type Context = Context;
+    type IOStartPending = Self;
+
+    fn poll_start_io(self, cx: &mut Context) -> Result<Self, Self> {
+        if cx.want_io { Ok(self) } else { Err(self) }
+    }
+
+    fn io_started_by_server(self, cx: &mut Context) -> Result<Self, Self> {
+        if cx.follow_server {
+            cx.want_io = true;
+            Ok(self)
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl IOStartPendingContext for Connection {
+    type Context = Context;
+    type IOActive = Self;
+
+    fn requested_streams(&self, cx: &mut Context) -> StreamSelection {
+        cx.selection
+    }
+
+    fn start_io(self, cx: &mut Context, selection: StreamSelection) -> Self {
+        self.start_io_with_latency(cx, selection, Duration::ZERO)
+    }
+
+    fn start_io_refused(self, cx: &mut Context) -> Self {
+        self.start_io_refused_with_latency(cx, Duration::ZERO)
+    }
+
+    fn start_io_with_latency(
+        self,
+        cx: &mut Context,
+        selection: StreamSelection,
+        elapsed: Duration,
+    ) -> Self {
+        cx.events.push(Event::StartIO(selection, elapsed));
+        self
+    }
+
+    fn start_io_refused_with_latency(self, cx: &mut Context, elapsed: Duration) -> Self {
+        cx.want_io = false;
+        cx.events.push(Event::StartIORefused(elapsed));
+        self
+    }
+
+    fn start_io_failed(&mut self, cx: &mut Context) {
+        cx.events.push(Event::StartIOFailed);
+    }
+}
+
+impl IOActiveContext for Connection {
+    type Context = Context;
+    type IOStopPending = Self;
+
+    fn on_audio(
+        &mut self,
+        cx: &mut Context,
+        _timestamp: Instant,
+        header: AudioMessageHeader,
+        data: &[u8],
+    ) {
+        cx.events.push(Event::Audio(header, data.to_vec()));
+    }
+
+    fn poll_stop_io(self, cx: &mut Context) -> Result<Self, Self> {
+        if cx.want_io { Err(self) } else { Ok(self) }
+    }
+
+    fn io_stopped_by_server(self, cx: &mut Context) -> Result<Self, Self> {
+        if cx.follow_server {
+            cx.want_io = false;
+            Ok(self)
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl IOStopPendingContext for Connection {
+    type Context = Context;
+
+    fn stop_io(self, cx: &mut Context) -> Self {
+        self.stop_io_with_latency(cx, Duration::ZERO)
+    }
+
+    fn stop_io_refused(self, cx: &mut Context) -> Self {
+        self.stop_io_refused_with_latency(cx, Duration::ZERO)
+    }
+
+    fn stop_io_with_latency(self, cx: &mut Context, elapsed: Duration) -> Self {
+        cx.events.push(Event::StopIO(elapsed));
+        self
+    }
+
+    fn stop_io_refused_with_latency(self, cx: &mut Context, elapsed: Duration) -> Self {
+        cx.want_io = true;
+        cx.events.push(Event::StopIORefused(elapsed));
+        self
+    }
+
+    fn stop_io_failed(&mut self, cx: &mut Context) {
+        cx.events.push(Event::StopIOFailed);
+    }
+}
+
+/// A client, the socket it sends through, and the clock it reads.
+struct Harness {
+    client: GenericClient<Context, MockClock>,
+    sock: super::super::ClientSocket<MockSock>,
+    clock: MockClock,
+}
+
+impl Harness {
+    fn new() -> Self {
+        Self::with_connect_rate_limit(ConnectRateLimit::DEFAULT)
+    }
+
+    fn with_connect_rate_limit(limit: ConnectRateLimit) -> Self {
+        let clock = MockClock::new(Instant::now());
+
+        Self {
+            client: GenericClient::with_clock(Context::default(), clock.clone())
+                .with_connect_rate_limit(limit),
+            sock: super::super::ClientSocket::new(MockSock::default()),
+            clock,
+        }
+    }
+
+    fn cx(&mut self) -> &mut Context {
+        self.client.callbacks_mut()
+    }
+
+    /// Returns, and clears, the callbacks invoked so far.
+    fn events(&mut self) -> Vec<Event> {
+        core::mem::take(&mut self.cx().events)
+    }
+
+    /// Returns, and clears, the messages sent so far.
+    fn sent(&self) -> Vec<Client> {
+        self.sock
+            .sock
+            .sent
+            .take()
+            .into_iter()
+            .map(|(addr, bytes)| {
+                assert_eq!(addr, SERVER);
+                let (msg, _, rest) = crate::client_message_decode(&bytes).unwrap();
+                assert!(rest.is_empty());
+                msg
+            })
+            .collect()
+    }
+
+    /// Makes the next sends fail with the given errors, in order.
+    fn fail_sends(&self, kinds: impl IntoIterator<Item = io::ErrorKind>) {
+        self.sock.sock.send_errors.borrow_mut().extend(kinds);
+    }
+
+    /// Queues ICMP errors reported about datagrams sent to `addr`, in order.
+    fn icmp_errors(&self, addr: SocketAddr, kinds: impl IntoIterator<Item = io::ErrorKind>) {
+        self.sock
+            .sock
+            .recv_errors
+            .borrow_mut()
+            .extend(kinds.into_iter().map(|kind| crate::DestinationError {
+                dest_addr: addr,
+                kind,
+            }));
+    }
+
+    fn advance(&self, duration: Duration) {
+        self.clock.advance(duration);
+    }
+
+    /// Receives a datagram from the server, now.
+    fn recv_datagram(&mut self, datagram: &[u8]) -> io::Result<()> {
+        let now = self.clock.now();
+        super::super::Client::on_datagram(&mut self.client, &self.sock, SERVER, now, datagram)
+    }
+
+    /// Receives a message from the server, now.
+    fn try_recv(&mut self, msg: Server) -> io::Result<()> {
+        self.recv_datagram(&crate::server_message_encode(msg, vec![]).unwrap())
+    }
+
+    fn recv(&mut self, msg: Server) {
+        self.try_recv(msg).unwrap();
+    }
+
+    /// Handles a receive timeout, now.
+    fn try_timeout(&mut self) -> io::Result<()> {
+        super::super::Client::on_timeout(&mut self.client, &self.sock)
+    }
+
+    fn timeout(&mut self) {
+        self.try_timeout().unwrap();
+    }
+
+    /// Receives a connection request from a server with `n_inputs` input streams.
+    fn connect(&mut self, epoch: u32, capabilities: Capabilities, n_inputs: usize) {
+        self.recv(connect_request(epoch, capabilities, n_inputs));
+    }
+
+    /// Receives a connection request from `addr`, now, returns `false` if it was
+    /// rejected by the rate limiter.
+    fn connect_from(&mut self, addr: SocketAddr, epoch: u32) -> bool {
+        let now = self.clock.now();
+        let msg =
+            crate::server_message_encode(connect_request(epoch, Capabilities::NONE, 1), vec![])
+                .unwrap();
+        super::super::Client::on_datagram(&mut self.client, &self.sock, addr, now, &msg).unwrap();
+        self.sock.sock.sent.take();
+
+        !self.events().contains(&Event::RateLimited)
+    }
+
+    fn state(&self) -> Option<IOStateKind> {
+        self.client
+            .servers()
+            .find(|&(&addr, _)| addr == SERVER)
+            .map(|(_, state)| state)
+    }
+
+    fn send_stats(&self) -> SendStats {
+        self.client.send_stats(&SERVER).unwrap()
+    }
+}
+
+/// Returns a connection request from a server with `n_inputs` input streams, advertising
+/// its epoch.
+fn connect_request(epoch: u32, capabilities: Capabilities, n_inputs: usize) -> Server {
+    Server::Connect {
+        epoch,
+        capabilities: capabilities.union(Capabilities::EPOCH),
+        formats: StreamFormats {
+            inputs: vec![Format::default(); n_inputs].into(),
+            outputs: Box::new([]),
+        },
+    }
+}
+
+#[test]
+fn send_stats() {
+    let mut h = Harness::new();
+    h.connect(1, Capabilities::NONE, 1);
+
+    let conn_result_len = crate::client_message_encode(Client::CONN_SUCCESS, vec![])
+        .unwrap()
+        .len();
+    let stats = h.send_stats();
+    assert_eq!(stats.packets_sent, 1);
+    assert_eq!(stats.bytes_sent, conn_result_len as u64);
+    assert_eq!(stats.last_send, Some(h.clock.now()));
+    assert_eq!(stats.failure_streak, 0);
+    assert_eq!(stats.last_error, None);
+}
+
+#[test]
+fn send_failures() {
+    let mut h = Harness::new();
+    h.connect(1, Capabilities::NONE, 1);
+    h.sent();
+
+    let connected_at = h.clock.now();
+
+    h.cx().want_io = true;
+    h.advance(Duration::from_millis(1));
+    h.fail_sends([io::ErrorKind::ConnectionRefused]);
+    let failed_at = h.clock.now();
+    assert!(
+        h.try_timeout().is_err(),
+        "non-transient send errors are returned"
+    );
+    assert_eq!(h.state(), Some(IOStateKind::PendingStart));
+
+    let stats = h.send_stats();
+    assert_eq!(stats.packets_sent, 1);
+    assert_eq!(stats.last_send, Some(connected_at));
+    assert_eq!(stats.failure_streak, 1);
+    assert_eq!(
+        stats.last_error,
+        Some(SendError {
+            kind: io::ErrorKind::ConnectionRefused,
+            at: failed_at,
+        })
+    );
+
+    // the request is resent once its response times out
+    h.advance(RequestRetries::response_timeout(0));
+    h.fail_sends([io::ErrorKind::ConnectionRefused]);
+    assert!(h.try_timeout().is_err());
+    assert_eq!(h.send_stats().failure_streak, 2);
+
+    // backing off between resends
+    h.advance(RequestRetries::response_timeout(1));
+    h.timeout();
+    assert_eq!(h.sent(), [Client::START_IO]);
+
+    let stats = h.send_stats();
+    assert_eq!(stats.packets_sent, 2);
+    assert_eq!(stats.last_send, Some(h.clock.now()));
+    assert_eq!(stats.failure_streak, 0, "reset by the successful send");
+    assert_eq!(
+        stats.last_error.map(|e| e.kind),
+        Some(io::ErrorKind::ConnectionRefused),
+        "kept after the successful send"
+    );
+    assert_eq!(h.client.last_send_error(&SERVER), stats.last_error);
+}
+
+#[test]
+fn handle_send_stats() {
+    let mut h = Harness::new();
+    h.recv(Server::Connect {
+        epoch: 1,
+        capabilities: Capabilities::NONE,
+        formats: StreamFormats {
+            inputs: Box::new([]),
+            outputs: Box::new([Format::default()]),
+        },
+    });
+    h.sent();
+    h.advance(Duration::from_millis(1));
+
+    let before = h.send_stats();
+    let server = h.client.server(&h.sock, SERVER).unwrap();
+
+    h.fail_sends([io::ErrorKind::ConnectionRefused]);
+    assert!(server.send_audio(0, 0, &[0; 16]).is_err());
+    server.send_audio(0, 16, &[0; 16]).unwrap();
+    h.fail_sends([io::ErrorKind::ConnectionRefused]);
+    assert!(server.query_status().is_err());
+
+    let sent = h.sock.sock.sent.take();
+    assert_eq!(sent.len(), 1);
+
+    let stats = h.send_stats();
+    assert_eq!(stats.packets_sent, before.packets_sent + 1);
+    assert_eq!(stats.bytes_sent, before.bytes_sent + sent[0].1.len() as u64);
+    assert_eq!(stats.failure_streak, 1);
+    assert_eq!(
+        stats.last_error,
+        Some(SendError {
+            kind: io::ErrorKind::ConnectionRefused,
+            at: h.clock.now(),
+        })
+    );
+}
+
+#[test]
+fn handle_large_audio() {
+    const MAX_SIZE: usize = 9000;
+
+    let mut h = Harness::new();
+    h.sock = super::super::ClientSocket::new(MockSock::default()).with_max_datagram_size(MAX_SIZE);
+    h.recv(Server::Connect {
+        epoch: 1,
+        capabilities: Capabilities::NONE,
+        formats: StreamFormats {
+            inputs: Box::new([]),
+            outputs: Box::new([Format::default()]),
+        },
+    });
+    h.sent();
+
+    let server = h.client.server(&h.sock, SERVER).unwrap();
+    let max_payload = MAX_SIZE - crate::ENCODED_AUDIO_MESSAGE_OVERHEAD;
+
+    // not limited by any intermediate buffer, only by the maximum datagram size
+    server.send_audio(0, 0, &vec![1; max_payload]).unwrap();
+    let err = server
+        .send_audio(0, 0, &vec![1; max_payload + 1])
+        .unwrap_err();
+    assert!(matches!(
+        crate::SendError::from(err),
+        crate::SendError::TooLarge { size, max: MAX_SIZE } if size == MAX_SIZE + 1
+    ));
+
+    server
+        .send_audio_chunked(0, 0, &vec![1; 2 * max_payload])
+        .unwrap();
+
+    let sizes: Vec<_> = h
+        .sock
+        .sock
+        .sent
+        .take()
+        .iter()
+        .map(|(_, d)| d.len())
+        .collect();
+    assert_eq!(sizes, [MAX_SIZE; 3]);
+    assert_eq!(
+        h.send_stats().last_error.unwrap().kind,
+        io::ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn handle_stop_request_reconciled() {
+    let mut h = Harness::new();
+    h.connect(1, Capabilities::NONE, 1);
+    h.cx().want_io = true;
+    h.timeout();
+    h.recv(Server::START_IO_OK);
+    h.sent();
+    h.events();
+
+    h.cx().follow_server = true;
+    let server = h.client.server(&h.sock, SERVER).unwrap();
+    server.request_stop_io().unwrap();
+    assert_eq!(h.sent(), [Client::STOP_IO, Client::QUERY_STATUS]);
+
+    // the acknowledgement doesn't match any pending request, but the status does
+    h.recv(Server::STOP_IO_OK);
+    assert_eq!(h.state(), Some(IOStateKind::Active));
+
+    let status = server::Status {
+        io_active: false,
+        ..Default::default()
+    };
+    h.recv(Server::status(status));
+    assert_eq!(
+        h.events(),
+        [Event::Status(status), Event::StopIO(Duration::ZERO)]
+    );
+    assert_eq!(h.state(), Some(IOStateKind::Inactive));
+}
+
+#[test]
+fn deferred_sends() {
+    let mut h = Harness::new();
+    h.connect(1, Capabilities::NONE, 1);
+    h.sent();
+
+    h.cx().want_io = true;
+    h.fail_sends([io::ErrorKind::WouldBlock]);
+    h.timeout();
+    assert_eq!(h.client.deferred_sends(), 1);
+    assert_eq!(h.send_stats().failure_streak, 1);
+    assert!(h.sent().is_empty());
+
+    // sent again upon the next timeout
+    h.timeout();
+    assert_eq!(h.client.deferred_sends(), 0);
+    assert_eq!(h.sent(), [Client::START_IO]);
+}
+
+#[test]
+fn connect_send_failure_keeps_deadline() {
+    for kind in [io::ErrorKind::ConnectionRefused, io::ErrorKind::WouldBlock] {
+        let mut h = Harness::new();
+        let connected_at = h.clock.now();
+
+        h.fail_sends([kind]);
+        let res = h.try_recv(connect_request(1, Capabilities::NONE, 1));
+        assert_eq!(res.is_err(), kind == io::ErrorKind::ConnectionRefused);
+
+        assert!(h.client.is_connected(&SERVER));
+        assert_eq!(
+            h.client.deadline(&SERVER),
+            Some(connected_at + CONN_TIMEOUT)
+        );
+        assert_eq!(h.events(), [Event::Connected(SERVER)]);
+
+        h.advance(CONN_TIMEOUT);
+        h.timeout();
+        assert!(!h.client.is_connected(&SERVER));
+        assert_eq!(h.client.deadline(&SERVER), None);
+        assert_eq!(h.events(), [Event::Disconnected(SERVER)]);
+    }
+}
+
+#[test]
+fn deadline_and_remaining() {
+    let mut h = Harness::new();
+    assert_eq!(h.client.deadline(&SERVER), None);
+    assert_eq!(h.client.remaining(&SERVER), None);
+
+    let connected_at = h.clock.now();
+    h.connect(1, Capabilities::NONE, 1);
+    assert_eq!(
+        h.client.deadline(&SERVER),
+        Some(connected_at + CONN_TIMEOUT)
+    );
+    assert_eq!(h.client.remaining(&SERVER), Some(CONN_TIMEOUT));
+
+    h.advance(Duration::from_millis(200));
+    assert_eq!(
+        h.client.deadline(&SERVER),
+        Some(connected_at + CONN_TIMEOUT)
+    );
+    assert_eq!(
+        h.client.remaining(&SERVER),
+        Some(CONN_TIMEOUT - Duration::from_millis(200))
+    );
+
+    // any message from the server pushes the deadline back
+    h.recv(Server::HEARTBEAT);
+    assert_eq!(
+        h.client.deadline(&SERVER),
+        Some(h.clock.now() + CONN_TIMEOUT)
+    );
+    assert_eq!(h.client.remaining(&SERVER), Some(CONN_TIMEOUT));
+
+    // past the deadline, but not expired yet, as no timeout was handled
+    h.advance(CONN_TIMEOUT * 2);
+    assert_eq!(h.client.remaining(&SERVER), Some(Duration::ZERO));
+    assert!(h.client.is_connected(&SERVER));
+}
+
+#[test]
+fn timeout_expiry() {
+    let mut h = Harness::new();
+    h.connect(1, Capabilities::NONE, 1);
+    h.events();
+
+    h.advance(CONN_TIMEOUT - Duration::from_micros(1));
+    h.timeout();
+    assert!(h.client.is_connected(&SERVER));
+    assert_eq!(h.client.remaining(&SERVER), Some(Duration::from_micros(1)));
+    assert_eq!(h.events(), []);
+
+    // expires exactly at the deadline
+    h.advance(Duration::from_micros(1));
+    h.timeout();
+    assert!(!h.client.is_connected(&SERVER));
+    assert_eq!(h.client.deadline(&SERVER), None);
+    assert_eq!(h.client.remaining(&SERVER), None);
+    assert_eq!(h.events(), [Event::Disconnected(SERVER)]);
+
+    // messages from disconnected servers don't bring their deadline back
+    h.recv(Server::HEARTBEAT);
+    assert_eq!(h.client.deadline(&SERVER), None);
+}
+
+#[test]
+fn port_unreachable() {
+    let mut h = Harness::new();
+    h.connect(1, Capabilities::NONE, 1);
+    h.events();
+
+    let other = SocketAddr::new(SERVER.ip(), SERVER.port() + 1);
+
+    // only port unreachable errors, for connected servers, shorten deadlines
+    h.advance(Duration::from_millis(10));
+    h.icmp_errors(SERVER, [io::ErrorKind::HostUnreachable]);
+    h.icmp_errors(other, [io::ErrorKind::ConnectionRefused]);
+    h.timeout();
+    assert_eq!(h.events(), []);
+    assert_eq!(
+        h.client.remaining(&SERVER),
+        Some(CONN_TIMEOUT - Duration::from_millis(10))
+    );
+
+    h.icmp_errors(SERVER, [io::ErrorKind::ConnectionRefused; 2]);
+    h.timeout();
+    assert_eq!(
+        h.events(),
+        [Event::Unreachable(SERVER), Event::Unreachable(SERVER)]
+    );
+    assert_eq!(h.client.remaining(&SERVER), Some(UNREACHABLE_TIMEOUT));
+
+    let stats = h.send_stats();
+    assert_eq!(stats.icmp_errors, 3);
+    assert_eq!(stats.port_unreachable_errors, 2);
+
+    // a message from the server confirms it is alive
+    h.recv(Server::HEARTBEAT);
+    assert_eq!(h.client.remaining(&SERVER), Some(CONN_TIMEOUT));
+
+    // otherwise, it expires
+    h.icmp_errors(SERVER, [io::ErrorKind::ConnectionRefused]);
+    h.timeout();
+    h.advance(UNREACHABLE_TIMEOUT);
+    h.timeout();
+    assert!(!h.client.is_connected(&SERVER));
+    assert_eq!(
+        h.events(),
+        [Event::Unreachable(SERVER), Event::Disconnected(SERVER)]
+    );
+}
+
+#[test]
+fn recv_errors() {
+    let mut h = Harness::new();
+    h.connect(1, Capabilities::NONE, 1);
+    h.events();
+
+    // receive failures with no queued ICMP errors are returned
+    let e = super::super::Client::on_recv_error(
+        &mut h.client,
+        &h.sock,
+        io::ErrorKind::ConnectionReset.into(),
+    )
+    .unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionReset);
+
+    h.icmp_errors(SERVER, [io::ErrorKind::ConnectionRefused]);
+    super::super::Client::on_recv_error(
+        &mut h.client,
+        &h.sock,
+        io::ErrorKind::ConnectionRefused.into(),
+    )
+    .unwrap();
+    assert_eq!(h.events(), [Event::Unreachable(SERVER)]);
+    assert_eq!(h.client.remaining(&SERVER), Some(UNREACHABLE_TIMEOUT));
+
+    // the next timeout is rescheduled
+    assert!(h.sock.sock.recv_timeout.borrow().unwrap() <= UNREACHABLE_TIMEOUT);
+}
+
+/// Sending to a closed port on loopback elicits a real ICMP port unreachable error.
+#[cfg(all(target_os = "linux", feature = "recv_errors"))]
+#[test]
+fn port_unreachable_loopback() {
+    let bind = || std::net::UdpSocket::bind((core::net::Ipv4Addr::LOCALHOST, 0)).unwrap();
+    // nothing listens on it once the socket is dropped
+    let server_addr = bind().local_addr().unwrap();
+
+    let sock = super::super::ClientSocket::new(bind());
+    sock.set_recv_errors(true).unwrap();
+
+    let clock = MockClock::new(Instant::now());
+    let mut client = GenericClient::with_clock(Context::default(), clock.clone());
+
+    // the connection result is sent to the closed port
+    let request =
+        crate::server_message_encode(connect_request(1, Capabilities::NONE, 1), vec![]).unwrap();
+    super::super::Client::on_datagram(&mut client, &sock, server_addr, clock.now(), &request)
+        .unwrap();
+    assert_eq!(client.send_stats(&server_addr).unwrap().packets_sent, 1);
+
+    sock.set_recv_timeout(Some(Duration::from_secs(1))).unwrap();
+    let e = sock.recv_raw(&mut [0; 64]).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionRefused);
+
+    super::super::Client::on_recv_error(&mut client, &sock, e).unwrap();
+    assert_eq!(
+        client.callbacks().events,
+        [
+            Event::Connected(server_addr),
+            Event::Unreachable(server_addr)
+        ]
+    );
+    assert_eq!(client.remaining(&server_addr), Some(UNREACHABLE_TIMEOUT));
+
+    let stats = client.send_stats(&server_addr).unwrap();
+    assert_eq!(stats.icmp_errors, 1);
+    assert_eq!(stats.port_unreachable_errors, 1);
+
+    clock.advance(UNREACHABLE_TIMEOUT);
+    super::super::Client::on_timeout(&mut client, &sock).unwrap();
+    assert!(!client.is_connected(&server_addr));
+}
+
+#[test]
+fn server_restart() {
+    let mut h = Harness::new();
+    h.connect(1, Capabilities::NONE, 1);
+
+    h.cx().want_io = true;
+    h.timeout();
+    h.recv(Server::START_IO_OK);
+    assert_eq!(h.state(), Some(IOStateKind::Active));
+    assert_eq!(h.sent(), [Client::CONN_SUCCESS, Client::START_IO]);
+    h.events();
+
+    // same epoch, e.g. a periodic connection request, the session is kept
+    h.advance(Duration::from_millis(100));
+    h.connect(1, Capabilities::NONE, 1);
+    assert_eq!(h.state(), Some(IOStateKind::Active));
+    assert_eq!(h.events(), []);
+    assert_eq!(h.sent(), []);
+
+    // past the per-address connection rate limit, without timing out
+    for _ in 0..2 {
+        h.advance(Duration::from_millis(500));
+        h.recv(Server::HEARTBEAT);
+    }
+
+    // the server rebooted, with different formats
+    h.connect(2, Capabilities::NONE, 2);
+    assert_eq!(h.state(), Some(IOStateKind::Inactive));
+    assert_eq!(
+        h.events(),
+        [Event::Disconnected(SERVER), Event::Connected(SERVER)]
+    );
+    assert_eq!(h.sent(), [Client::CONN_SUCCESS]);
+
+    let server = h.client.server(&h.sock, SERVER).unwrap();
+    assert_eq!(server.epoch(), 2);
+    assert_eq!(server.formats().inputs.len(), 2);
+    assert_eq!(
+        h.client.deadline(&SERVER),
+        Some(h.clock.now() + CONN_TIMEOUT)
+    );
+
+    // the new session starts IO from scratch
+    h.timeout();
+    assert_eq!(h.sent(), [Client::START_IO]);
+    assert_eq!(h.state(), Some(IOStateKind::PendingStart));
+}
+
+#[test]
+fn server_restart_without_epochs() {
+    let legacy_connect = |epoch| Server::Connect {
+        epoch,
+        capabilities: Capabilities::NONE,
+        formats: StreamFormats {
+            inputs: Box::new([Format::default()]),
+            outputs: Box::new([]),
+        },
+    };
+
+    let mut h = Harness::new();
+    h.recv(legacy_connect(1));
+    assert_eq!(h.events(), [Event::Connected(SERVER)]);
+    assert_eq!(h.sent(), [Client::CONN_SUCCESS]);
+
+    // the epoch isn't sent without capabilities
+    let server = h.client.server(&h.sock, SERVER).unwrap();
+    assert_eq!(server.epoch(), 0);
+
+    // past the per-address connection rate limit, a restart can't be detected
+    h.advance(Duration::from_secs(1));
+    h.recv(legacy_connect(2));
+    assert_eq!(h.events(), []);
+    assert_eq!(h.sent(), []);
+}
+
+/// Returns a distinct server address for each `port`.
+fn server_addr(port: u16) -> SocketAddr {
+    SocketAddr::new(core::net::IpAddr::V4(core::net::Ipv4Addr::LOCALHOST), port)
+}
+
+#[test]
+fn connect_rate_limit_refill() {
+    let mut h = Harness::with_connect_rate_limit(ConnectRateLimit {
+        global_per_sec: 100,
+        per_addr_per_sec: 2,
+        max_tracked_addrs: 16,
+    });
+
+    // a new epoch every time, so that each request is handled
+    let mut epoch = 0;
+    let mut connect = |h: &mut Harness| {
+        epoch += 1;
+        h.connect_from(SERVER, epoch)
+    };
+
+    // the burst
+    assert!(connect(&mut h));
+    assert!(connect(&mut h));
+    assert!(!connect(&mut h));
+    assert_eq!(h.client.rate_limited_connects(), 1);
+
+    // one token is refilled every 500ms
+    h.advance(Duration::from_millis(250));
+    assert!(!connect(&mut h));
+    h.advance(Duration::from_millis(250));
+    assert!(connect(&mut h));
+    assert!(!connect(&mut h));
+    assert_eq!(h.client.rate_limited_connects(), 3);
+
+    // never refilled past the burst size
+    h.advance(Duration::from_secs(10));
+    assert!(connect(&mut h));
+    assert!(connect(&mut h));
+    assert!(!connect(&mut h));
+    assert_eq!(h.client.rate_limited_connects(), 4);
+
+    // time going backwards refills nothing
+    let now = h.clock.now();
+    h.clock.set(now - Duration::from_secs(1));
+    assert!(!connect(&mut h));
+    h.clock.set(now + Duration::from_millis(500));
+    assert!(connect(&mut h));
+}
+
+#[test]
+fn connect_rate_limit_global_burst() {
+    let mut h = Harness::with_connect_rate_limit(ConnectRateLimit {
+        global_per_sec: 3,
+        per_addr_per_sec: 10,
+        max_tracked_addrs: 16,
+    });
+
+    // the global burst is shared by all addresses
+    assert!(h.connect_from(server_addr(1), 1));
+    assert!(h.connect_from(server_addr(2), 1));
+    assert!(h.connect_from(server_addr(3), 1));
+    assert!(!h.connect_from(server_addr(4), 1));
+    assert!(!h.connect_from(server_addr(1), 2));
+    assert_eq!(h.client.rate_limited_connects(), 2);
+    assert!(!h.client.is_connected(&server_addr(4)));
+
+    h.advance(Duration::from_millis(334));
+    assert!(h.connect_from(server_addr(4), 1));
+    assert!(!h.connect_from(server_addr(5), 1));
+    assert!(h.client.is_connected(&server_addr(4)));
+    assert_eq!(h.client.rate_limited_connects(), 3);
+}
+
+#[test]
+fn connect_rate_limit_eviction() {
+    let mut h = Harness::with_connect_rate_limit(ConnectRateLimit {
+        global_per_sec: 100,
+        per_addr_per_sec: 1,
+        max_tracked_addrs: 2,
+    });
+
+    let (a, b, c) = (server_addr(1), server_addr(2), server_addr(3));
+    let ms = Duration::from_millis(1);
+
+    assert!(h.connect_from(a, 1));
+    h.advance(ms);
+    assert!(h.connect_from(b, 1));
+
+    // a's bucket is empty, seeing it again makes b the least recently seen address
+    h.advance(ms);
+    assert!(!h.connect_from(a, 2));
+
+    // the table is full, b is forgotten to make room for c
+    h.advance(ms);
+    assert!(h.connect_from(c, 1));
+
+    // a is still tracked, with its bucket still empty
+    h.advance(ms);
+    assert!(!h.connect_from(a, 3));
+
+    // b starts over with a full bucket, c (now the least recent) is forgotten
+    h.advance(ms);
+    assert!(h.connect_from(b, 2));
+    h.advance(ms);
+    assert!(h.connect_from(c, 2));
+
+    // a is forgotten in turn
+    h.advance(ms);
+    assert!(h.connect_from(a, 4));
+    assert_eq!(h.client.rate_limited_connects(), 2);
+}
+
+#[test]
+fn next_wakeup_cases() {
+    let now = Instant::now();
+    let ms = Duration::from_millis;
+
+    // no servers
+    assert_eq!(next_wakeup(now, None, None, false), None);
+
+    // one server, the application could request an IO state change
+    let deadline = Some(now + CONN_TIMEOUT);
+    assert_eq!(
+        next_wakeup(now, deadline, None, true),
+        Some(REQUEST_POLL_PERIOD)
+    );
+    assert_eq!(next_wakeup(now, Some(now + ms(3)), None, true), Some(ms(3)));
+
+    // one server, with a pending request
+    let retry = Some(now + RESPONSE_TIMEOUT);
+    assert_eq!(
+        next_wakeup(now, deadline, retry, false),
+        Some(RESPONSE_TIMEOUT)
+    );
+    assert_eq!(
+        next_wakeup(now, deadline, retry, true),
+        Some(REQUEST_POLL_PERIOD)
+    );
+
+    // overdue, but never zero, which would mean waiting indefinitely
+    assert_eq!(
+        next_wakeup(now + ms(5), deadline, Some(now), false),
+        Some(MIN_WAKEUP_DELAY)
+    );
+}
+
+#[test]
+fn socket_timeout() {
+    let mut h = Harness::new();
+    let recv_timeout = |h: &Harness| *h.sock.sock.recv_timeout.borrow();
+
+    h.timeout();
+    assert_eq!(recv_timeout(&h), None);
+
+    h.connect(1, Capabilities::NONE, 1);
+    h.timeout();
+    assert_eq!(recv_timeout(&h), Some(REQUEST_POLL_PERIOD));
+
+    // the request is resent if unanswered
+    h.cx().want_io = true;
+    h.timeout();
+    assert_eq!(h.state(), Some(IOStateKind::PendingStart));
+    assert_eq!(recv_timeout(&h), Some(RESPONSE_TIMEOUT));
+
+    h.recv(Server::START_IO_FAILED);
+    h.timeout();
+    assert_eq!(recv_timeout(&h), Some(RequestRetries::delay(1)));
+
+    // nothing to wait for once the server is gone
+    h.recv(Server::Disconnect);
+    h.timeout();
+    assert_eq!(recv_timeout(&h), None);
+}
+
+#[test]
+fn retry_delays() {
+    let ms = Duration::from_millis;
+
+    assert_eq!(RequestRetries::delay(0), REQUEST_POLL_PERIOD);
+    assert_eq!(RequestRetries::delay(1), REQUEST_POLL_PERIOD);
+    assert_eq!(RequestRetries::delay(2), ms(20));
+    assert_eq!(RequestRetries::delay(7), ms(640));
+    assert_eq!(RequestRetries::delay(8), MAX_RETRY_PERIOD);
+    assert_eq!(RequestRetries::delay(u32::MAX), MAX_RETRY_PERIOD);
+}
+
+#[test]
+fn retry_backoff_and_cap() {
+    let mut h = Harness::new();
+    h.connect(1, Capabilities::NONE, 1);
+    h.cx().want_io = true;
+    h.timeout();
+    assert_eq!(h.sent(), [Client::CONN_SUCCESS, Client::START_IO]);
+    h.events();
+
+    // a scripted server, always failing to start IO
+    for n_failures in 1..=DEFAULT_MAX_IO_REQUEST_RETRIES {
+        h.recv(Server::START_IO_FAILED);
+        // duplicate failures, e.g. responses to resent requests, aren't counted
+        h.recv(Server::START_IO_FAILED);
+        assert_eq!(h.events(), [Event::StartIOFailed]);
+
+        let delay = RequestRetries::delay(n_failures);
+        assert_eq!(
+            delay,
+            (REQUEST_POLL_PERIOD * 2u32.pow(n_failures - 1)).min(MAX_RETRY_PERIOD)
+        );
+
+        // heartbeats keep the server from timing out during long delays
+        h.advance(delay - Duration::from_micros(1));
+        h.recv(Server::HEARTBEAT);
+        h.timeout();
+        assert_eq!(h.sent(), [], "retried early after {n_failures} failures");
+
+        h.advance(Duration::from_micros(1));
+        h.timeout();
+        assert_eq!(h.sent(), [Client::START_IO]);
+        assert_eq!(h.state(), Some(IOStateKind::PendingStart));
+    }
+
+    // one failure too many, the request is given up on, as if it was refused
+    h.recv(Server::START_IO_FAILED);
+    assert_eq!(
+        h.events(),
+        [Event::StartIOFailed, Event::StartIORefused(Duration::ZERO)]
+    );
+    assert_eq!(h.state(), Some(IOStateKind::Inactive));
+
+    h.advance(MAX_RETRY_PERIOD);
+    h.recv(Server::HEARTBEAT);
+    h.timeout();
+    assert_eq!(h.sent(), []);
+}
+
+#[test]
+fn response_timeout_backoff_and_cap() {
+    let mut h = Harness::new();
+    h.connect(1, Capabilities::NONE, 1);
+    h.cx().want_io = true;
+    h.timeout();
+    assert_eq!(h.sent(), [Client::CONN_SUCCESS, Client::START_IO]);
+    h.events();
+
+    // a scripted server, alive, but never answering IO requests
+    for n_timeouts in 0..DEFAULT_MAX_IO_REQUEST_RETRIES {
+        let timeout = RequestRetries::response_timeout(n_timeouts);
+        assert_eq!(
+            timeout,
+            (RESPONSE_TIMEOUT * 2u32.pow(n_timeouts)).min(MAX_RETRY_PERIOD)
+        );
+
+        h.advance(timeout - Duration::from_micros(1));
+        h.recv(Server::HEARTBEAT);
+        h.timeout();
+        assert_eq!(h.sent(), [], "resent early after {n_timeouts} timeouts");
+
+        h.advance(Duration::from_micros(1));
+        h.timeout();
+        assert_eq!(h.sent(), [Client::START_IO]);
+        assert_eq!(h.state(), Some(IOStateKind::PendingStart));
+    }
+
+    // one timeout too many, the request is given up on, as if it was refused
+    h.advance(MAX_RETRY_PERIOD);
+    h.recv(Server::HEARTBEAT);
+    h.timeout();
+    assert_eq!(h.sent(), []);
+    assert_eq!(h.events(), [Event::StartIORefused(MAX_RETRY_PERIOD)]);
+    assert_eq!(h.state(), Some(IOStateKind::Inactive));
+}
+
+/// Sample source yielding one scripted block of samples per poll.
+#[cfg(feature = "audio")]
+struct Blocks(VecDeque<Vec<f32>>);
+
+#[cfg(feature = "audio")]
+impl syfala_utils::SampleSource for Blocks {
+    type Sample = f32;
+
+    fn get_samples(&mut self) -> impl IntoIterator<Item = f32> {
+        self.0.pop_front().unwrap_or_default()
+    }
+}
+
+#[test]
+#[cfg(feature = "audio")]
+fn sender_round_trip() {
+    use crate::audio::AudioStreamSender;
+    use core::num::NonZeroUsize;
+    use syfala_proto::message::client;
+    use syfala_utils::{
+        AudioPacketSamplePadder, IndexedAudioSampleStreamReceiver, SampleByteStream,
+    };
+
+    const MAX_PAYLOAD: usize = 100;
+    // stereo frames
+    const CHUNK_LEN: usize = 8;
+
+    let mut h = Harness::new();
+    h.recv(Server::Connect {
+        epoch: 1,
+        capabilities: Capabilities::NONE,
+        formats: StreamFormats {
+            inputs: Box::new([]),
+            outputs: Box::new([Format::default()]),
+        },
+    });
+    h.events();
+    h.sent();
+
+    // arbitrary bit patterns, including NaNs, must survive unchanged
+    let mut samples = (0u32..).map(|i| f32::from_bits(i.wrapping_mul(0x9E37_79B9)));
+    let blocks: Vec<Vec<f32>> = [3, 50, 1, 0, 17, 64, 7, 25, 2]
+        .map(|n| samples.by_ref().take(n).collect())
+        .into();
+    let expected = blocks.concat();
+
+    let framer = SampleByteStream::new();
+    let producer = IndexedAudioSampleStreamReceiver::new(Blocks(blocks.into()), framer);
+    let mut sender = AudioStreamSender::new(
+        producer,
+        0,
+        NonZeroUsize::new(MAX_PAYLOAD).unwrap(),
+        NonZeroUsize::new(CHUNK_LEN).unwrap(),
+    );
+
+    let server = h.client.server(&h.sock, SERVER).unwrap();
+    let mut n_pulled = 0;
+    while !sender.producer().source().0.is_empty() {
+        n_pulled += sender.poll(&server).unwrap();
+    }
+    sender.flush(&server).unwrap();
+    assert_eq!(n_pulled, expected.len() * 4);
+    assert_eq!(sender.n_pending(), 0);
+
+    let sent = h.sock.sock.sent.take();
+    let n_messages = sent.len();
+    let mut padder = AudioPacketSamplePadder::<f32>::new();
+    let mut received = vec![];
+
+    for (i, (addr, datagram)) in sent.into_iter().enumerate() {
+        assert_eq!(addr, SERVER);
+
+        let (msg, _, payload) = crate::client_message_decode(&datagram).unwrap();
+        let Client::Connected(client::Connected::Audio(header)) = msg else {
+            panic!("expected an audio message, got {msg:?}");
+        };
+        assert_eq!(header.stream_idx, 0);
+
+        let stream_msg = header.stream_msg;
+        assert_eq!(usize::try_from(stream_msg.n_bytes).unwrap(), payload.len());
+        assert!(!payload.is_empty() && payload.len() <= MAX_PAYLOAD);
+
+        // every message but the flushed one is either full, or ends on a chunk boundary
+        let aligned = stream_msg.next_byte_idx() % CHUNK_LEN as u64 == 0;
+        assert!(aligned || payload.len() == MAX_PAYLOAD || i == n_messages - 1);
+
+        let pad = || panic!("padding requested");
+        received.extend(padder.feed_bytes(stream_msg.byte_idx, payload.iter().copied(), pad));
+    }
+
+    assert_eq!(
+        received.iter().map(|s| s.to_bits()).collect::<Vec<_>>(),
+        expected.iter().map(|s| s.to_bits()).collect::<Vec<_>>(),
+    );
+    assert_eq!(
+        padder.stats(),
+        syfala_utils::PadderStats {
+            bytes_consumed: u64::try_from(n_pulled).unwrap(),
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn request_latencies() {
+    let ms = Duration::from_millis;
+    let mut h = Harness::new();
+    h.connect(1, Capabilities::NONE, 1);
+    h.sent();
+    h.events();
+
+    h.cx().want_io = true;
+    h.timeout();
+    assert_eq!(h.sent(), [Client::START_IO]);
+    h.advance(ms(30));
+    h.recv(Server::START_IO_OK);
+    assert_eq!(h.events(), [Event::StartIO(StreamSelection::All, ms(30))]);
+
+    // measured from the last time the request was sent
+    h.cx().want_io = false;
+    h.timeout();
+    assert_eq!(h.sent(), [Client::STOP_IO]);
+    h.advance(RESPONSE_TIMEOUT);
+    h.timeout();
+    assert_eq!(h.sent(), [Client::STOP_IO]);
+    h.advance(ms(20));
+    h.recv(Server::STOP_IO_OK);
+    assert_eq!(h.events(), [Event::StopIO(ms(20))]);
+
+    h.cx().want_io = true;
+    h.timeout();
+    assert_eq!(h.sent(), [Client::START_IO]);
+    h.advance(ms(10));
+    h.recv(Server::START_IO_REFUSED);
+    assert_eq!(h.events(), [Event::StartIORefused(ms(10))]);
+
+    let stats = h.send_stats();
+    let latency = stats.start_io_latency;
+    assert_eq!(latency.count, 2);
+    assert_eq!((latency.min, latency.max), (Some(ms(10)), Some(ms(30))));
+    assert_eq!((latency.total, latency.avg()), (ms(40), Some(ms(20))));
+
+    let latency = stats.stop_io_latency;
+    assert_eq!(latency.count, 1);
+    assert_eq!((latency.min, latency.max), (Some(ms(20)), Some(ms(20))));
+    assert_eq!((latency.total, latency.avg()), (ms(20), Some(ms(20))));
+    assert_eq!(RequestLatency::default().avg(), None);
+}
+
+#[test]
+fn status_resync() {
+    let status = |io_active| {
+        Server::status(server::Status {
+            io_active,
+            ..Default::default()
+        })
+    };
+    let reported = |io_active| {
+        Event::Status(server::Status {
+            io_active,
+            ..Default::default()
+        })
+    };
+
+    let mut h = Harness::new();
+    h.connect(1, Capabilities::NONE, 1);
+    h.sent();
+    h.events();
+
+    let server = h.client.server(&h.sock, SERVER).unwrap();
+    server.query_status().unwrap();
+    assert_eq!(h.sent(), [Client::QUERY_STATUS]);
+
+    // stable states only follow the server if the context accepts it
+    h.recv(status(true));
+    assert_eq!(h.events(), [reported(true)]);
+    assert_eq!(h.state(), Some(IOStateKind::Inactive));
+
+    h.cx().follow_server = true;
+    h.recv(status(true));
+    let start = Event::StartIO(StreamSelection::All, Duration::ZERO);
+    assert_eq!(h.events(), [reported(true), start]);
+    assert_eq!(h.state(), Some(IOStateKind::Active));
+    assert!(h.cx().want_io);
+
+    h.cx().follow_server = false;
+    h.recv(status(false));
+    assert_eq!(h.events(), [reported(false)]);
+    assert_eq!(h.state(), Some(IOStateKind::Active));
+
+    h.cx().follow_server = true;
+    h.recv(status(false));
+    assert_eq!(h.events(), [reported(false), Event::StopIO(Duration::ZERO)]);
+    assert_eq!(h.state(), Some(IOStateKind::Inactive));
+    assert!(!h.cx().want_io);
+
+    // a pending request is acknowledged by a matching status, the ack having been lost
+    h.cx().want_io = true;
+    h.timeout();
+    assert_eq!(h.sent(), [Client::START_IO]);
+    h.advance(Duration::from_millis(5));
+
+    h.recv(status(false));
+    assert_eq!(h.events(), [reported(false)]);
+    assert_eq!(h.state(), Some(IOStateKind::PendingStart));
+
+    h.recv(status(true));
+    let start = Event::StartIO(StreamSelection::All, Duration::from_millis(5));
+    assert_eq!(h.events(), [reported(true), start]);
+    assert_eq!(h.state(), Some(IOStateKind::Active));
+
+    h.cx().want_io = false;
+    h.timeout();
+    assert_eq!(h.sent(), [Client::STOP_IO]);
+
+    h.recv(status(true));
+    assert_eq!(h.events(), [reported(true)]);
+    assert_eq!(h.state(), Some(IOStateKind::PendingStop));
+
+    h.recv(status(false));
+    assert_eq!(h.events(), [reported(false), Event::StopIO(Duration::ZERO)]);
+    assert_eq!(h.state(), Some(IOStateKind::Inactive));
+
+    // only requests count towards latencies, not transitions following the server
+    let stats = h.send_stats();
+    assert_eq!(stats.start_io_latency.count, 1);
+    assert_eq!(stats.stop_io_latency.count, 1);
+}
+
+#[test]
+fn resend_on_response_timeout() {
+    let mut h = Harness::new();
+    h.connect(1, Capabilities::NONE, 1);
+    h.sent();
+
+    for (request, ack, state) in [
+        (Client::START_IO, Server::START_IO_OK, IOStateKind::Active),
+        (Client::STOP_IO, Server::STOP_IO_OK, IOStateKind::Inactive),
+    ] {
+        h.cx().want_io = state == IOStateKind::Active;
+        h.timeout();
+        assert_eq!(h.sent(), [request]);
+        h.events();
+
+        // the server's acknowledgement is lost
+        h.advance(RESPONSE_TIMEOUT - Duration::from_micros(1));
+        h.timeout();
+        assert_eq!(h.sent(), []);
+
+        h.advance(Duration::from_micros(1));
+        h.timeout();
+        assert_eq!(h.sent(), [request]);
+
+        // the server, already in the requested state, acknowledges it again
+        h.recv(ack.clone());
+        assert_eq!(h.state(), Some(state));
+        assert_eq!(h.events().len(), 1);
+
+        // late duplicates are ignored, and nothing is resent anymore
+        h.recv(ack);
+        assert_eq!(h.state(), Some(state));
+        assert_eq!(h.events(), []);
+
+        h.advance(RESPONSE_TIMEOUT);
+        h.timeout();
+        assert_eq!(h.sent(), []);
+    }
+}
+
+#[test]
+fn selective_start() {
+    let audio = |stream_idx| {
+        let header = AudioMessageHeader {
+            stream_idx,
+            stream_msg: syfala_proto::AudioStreamMessageHeader {
+                byte_idx: 0,
+                n_bytes: 2,
+            },
+        };
+        let msg = crate::server_message_encode(Server::audio(header), vec![]).unwrap();
+        ([msg, vec![1, 2]].concat(), Event::Audio(header, vec![1, 2]))
+    };
+    let mask = StreamSelection::Mask(0b10);
+
+    // servers not advertising the capability are asked for all streams
+    for (capabilities, selection) in [
+        (Capabilities::NONE, StreamSelection::All),
+        (Capabilities::SELECTIVE_START, mask),
+    ] {
+        let mut h = Harness::new();
+        h.connect(1, capabilities, 2);
+        h.sent();
+        h.events();
+
+        h.cx().selection = mask;
+        h.cx().want_io = true;
+        h.timeout();
+        assert_eq!(h.sent(), [Client::start_io(selection)]);
+
+        h.recv(Server::START_IO_OK);
+        assert_eq!(h.events(), [Event::StartIO(selection, Duration::ZERO)]);
+
+        let (datagram, event) = audio(0);
+        h.recv_datagram(&datagram).unwrap();
+        if selection == mask {
+            let rejected = Event::AudioRejected(AudioRejection::Unselected);
+            assert_eq!(h.events(), [rejected]);
+        } else {
+            assert_eq!(h.events(), [event]);
+        }
+
+        let (datagram, event) = audio(1);
+        h.recv_datagram(&datagram).unwrap();
+        assert_eq!(h.events(), [event]);
+    }
+}
+
+#[test]
+fn restarted_streams() {
+    let header = AudioMessageHeader {
+        stream_idx: 0,
+        stream_msg: syfala_proto::AudioStreamMessageHeader {
+            byte_idx: 0,
+            n_bytes: 2,
+        },
+    };
+    let msg = crate::server_message_encode(Server::audio(header), vec![]).unwrap();
+    let datagram = [msg, vec![1, 2]].concat();
+    let event = Event::Audio(header, vec![1, 2]);
+
+    let mut h = Harness::new();
+    h.connect(1, Capabilities::NONE, 1);
+    h.cx().want_io = true;
+    h.timeout();
+    h.recv(Server::START_IO_OK);
+    h.events();
+
+    h.recv_datagram(&datagram).unwrap();
+    assert_eq!(h.events(), core::slice::from_ref(&event));
+
+    h.cx().want_io = false;
+    h.timeout();
+    h.recv(Server::STOP_IO_OK);
+    h.cx().want_io = true;
+    h.timeout();
+    h.recv(Server::START_IO_OK);
+    h.events();
+
+    // the stream starts over from byte zero, which isn't a duplicate
+    h.recv_datagram(&datagram).unwrap();
+    assert_eq!(h.events(), [event]);
+}
+
+#[test]
+fn audio_rates() {
+    let mut h = Harness::new();
+    h.recv(Server::Connect {
+        epoch: 1,
+        capabilities: Capabilities::NONE,
+        formats: StreamFormats {
+            inputs: Box::new([Format::default()]),
+            outputs: Box::new([Format::default()]),
+        },
+    });
+    h.cx().want_io = true;
+    h.timeout();
+    h.recv(Server::START_IO_OK);
+    h.sent();
+
+    let header = AudioMessageHeader {
+        stream_idx: 0,
+        stream_msg: syfala_proto::AudioStreamMessageHeader {
+            byte_idx: 0,
+            n_bytes: 100,
+        },
+    };
+    let mut datagram = crate::server_message_encode(Server::audio(header), vec![]).unwrap();
+    datagram.extend_from_slice(&[0; 100]);
+
+    // 100 bytes received, and 50 sent, every 10ms, for 3 seconds
+    for i in 0..300 {
+        let mut header = header;
+        header.stream_msg.byte_idx = i * 100;
+        let prefix = crate::server_message_encode(Server::audio(header), vec![]).unwrap();
+        datagram[..prefix.len()].copy_from_slice(&prefix);
+        h.recv_datagram(&datagram).unwrap();
+
+        let server = h.client.server(&h.sock, SERVER).unwrap();
+        server.send_audio(0, i * 50, &[0; 50]).unwrap();
+
+        h.advance(Duration::from_millis(10));
+        if i % 20 == 0 {
+            h.recv(Server::HEARTBEAT);
+        }
+    }
+
+    assert_eq!(h.client.inbound_bps(&SERVER), Some(80_000));
+    assert_eq!(h.client.outbound_bps(&SERVER), Some(40_000));
+
+    // rejected audio doesn't count
+    h.recv_datagram(&datagram).unwrap();
+    assert_eq!(
+        h.events().last(),
+        Some(&Event::AudioRejected(AudioRejection::Duplicate))
+    );
+    // one silent second later
+    h.advance(Duration::from_secs(1));
+    assert_eq!(h.client.inbound_bps(&SERVER), Some(60_000));
+    assert_eq!(h.client.inbound_bps(&"127.0.0.1:1".parse().unwrap()), None);
+}
+
+/// Socket receiving scripted datagrams, or timeouts (`None`), each after a delay on a
+/// mock clock, until the script is exhausted.
+struct ScriptSock {
+    clock: MockClock,
+    script: RefCell<VecDeque<(Duration, Option<Vec<u8>>)>>,
+}
+
+impl crate::SyncUdpSock for ScriptSock {
+    fn send(&self, _bytes: &[u8], _dest_addr: SocketAddr) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn recv(&self, bytes: &mut [u8]) -> io::Result<(usize, SocketAddr, Instant)> {
+        let Some((delay, datagram)) = self.script.borrow_mut().pop_front() else {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        };
+
+        self.clock.advance(delay);
+
+        let datagram = datagram.ok_or(io::ErrorKind::TimedOut)?;
+        bytes[..datagram.len()].copy_from_slice(&datagram);
+        Ok((datagram.len(), SERVER, self.clock.now()))
+    }
+
+    fn set_recv_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn replay_round_trip() {
+    use crate::replay::{Pacing, RecordingSock, ReplaySock};
+
+    let ms = Duration::from_millis;
+    let header = AudioMessageHeader {
+        stream_idx: 0,
+        stream_msg: syfala_proto::AudioStreamMessageHeader {
+            byte_idx: 0,
+            n_bytes: 4,
+        },
+    };
+    let mut audio = crate::server_message_encode(Server::audio(header), vec![]).unwrap();
+    audio.extend_from_slice(&[1, 2, 3, 4]);
+
+    let encode = |msg| Some(crate::server_message_encode(msg, vec![]).unwrap());
+    let script = [
+        (ms(0), encode(connect_request(1, Capabilities::NONE, 1))),
+        (ms(5), None),
+        (ms(20), encode(Server::START_IO_OK)),
+        (ms(1), Some(audio)),
+        (ms(300), encode(Server::HEARTBEAT)),
+        // the connection times out
+        (ms(700), None),
+    ];
+
+    let want_io = || Context {
+        want_io: true,
+        ..Default::default()
+    };
+
+    // record a session
+    let clock = MockClock::new(Instant::now());
+    let script_sock = ScriptSock {
+        clock: clock.clone(),
+        script: RefCell::new(script.into()),
+    };
+    let sock = RecordingSock::with_clock(script_sock, vec![], clock.clone());
+    let sock = super::super::ClientSocket::new(sock);
+    let mut client = GenericClient::with_clock(want_io(), clock);
+
+    let e = super::super::Client::start(&mut client, &sock).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+
+    let recorded = core::mem::take(&mut client.callbacks_mut().events);
+    assert!(matches!(
+        recorded[..],
+        [
+            Event::Connected(SERVER),
+            Event::StartIO(StreamSelection::All, _),
+            Event::Audio(..),
+            Event::Disconnected(SERVER),
+        ],
+    ));
+
+    let (_, recording) = sock.sock.into_inner();
+
+    // replay it to the same client, it behaves identically, at the same instants
+    let replay = || {
+        let sock = ReplaySock::from_recording(&recording, Pacing::AsFastAsPossible).unwrap();
+        super::super::ClientSocket::new(sock)
+    };
+
+    let sock = replay();
+    let mut client = GenericClient::with_clock(want_io(), sock.sock.clock());
+    let e = super::super::Client::start(&mut client, &sock).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+
+    assert_eq!(client.callbacks_mut().events, recorded);
+    assert!(sock.sock.is_finished());
+    assert_eq!(sock.sock.n_divergences(), 0);
+
+    // a client not requesting IO doesn't send the recorded requests
+    let sock = replay();
+    let clock = sock.sock.clock();
+    let mut client = GenericClient::with_clock(Context::default(), clock);
+    let _ = super::super::Client::start(&mut client, &sock);
+
+    assert!(sock.sock.n_divergences() > 0);
+}
+
+#[test]
+fn recording_write_errors() {
+    use crate::{SyncUdpSock, replay::RecordingSock};
+
+    let datagram = crate::server_message_encode(Server::HEARTBEAT, vec![]).unwrap();
+    let clock = MockClock::new(Instant::now());
+    let script_sock = ScriptSock {
+        clock: clock.clone(),
+        script: RefCell::new([(Duration::ZERO, Some(datagram.clone()))].into()),
+    };
+
+    // no room for any record
+    let mut out = [0; 0];
+    let sock = RecordingSock::with_clock(script_sock, &mut out[..], clock);
+    assert!(sock.take_record_error().is_none());
+
+    // the datagram is still received
+    let mut buf = [0; 64];
+    let (n, peer, _) = sock.recv(&mut buf).unwrap();
+    assert_eq!((&buf[..n], peer), (&datagram[..], SERVER));
+    assert!(sock.take_record_error().is_some());
+    assert!(sock.take_record_error().is_none(), "cleared");
+
+    // and sent
+    sock.send(&datagram, SERVER).unwrap();
+    assert!(sock.take_record_error().is_some());
+}
+
+#[test]
+fn counting_observer_session() {
+    use crate::observer::{CountingObserver, MessageKind};
+
+    let ms = Duration::from_millis;
+    let header = AudioMessageHeader {
+        stream_idx: 0,
+        stream_msg: syfala_proto::AudioStreamMessageHeader {
+            byte_idx: 0,
+            n_bytes: 8,
+        },
+    };
+
+    let encode = |msg| crate::server_message_encode(msg, vec![]).unwrap();
+    let connect = encode(connect_request(1, Capabilities::NONE, 1));
+    let start_ok = encode(Server::START_IO_OK);
+    let audio = [encode(Server::audio(header)), vec![0; 8]].concat();
+    let heartbeat = encode(Server::HEARTBEAT);
+    let garbage = vec![0xff; 3];
+
+    let received = [&connect, &start_ok, &audio, &heartbeat, &garbage];
+    let bytes_received = received.iter().map(|d| d.len()).sum::<usize>();
+
+    let clock = MockClock::new(Instant::now());
+    let script = [
+        (ms(0), Some(connect)),
+        (ms(1), None),
+        (ms(10), Some(start_ok)),
+        (ms(1), Some(audio)),
+        (ms(1), Some(heartbeat)),
+        (ms(1), Some(garbage)),
+    ];
+    let sock = ScriptSock {
+        clock: clock.clone(),
+        script: RefCell::new(script.into()),
+    };
+
+    let observer = CountingObserver::new();
+    let sock = super::super::ClientSocket::new(sock).with_observer(&observer);
+    let cx = Context {
+        want_io: true,
+        ..Default::default()
+    };
+    let mut client = GenericClient::with_clock(cx, clock);
+
+    let e = super::super::Client::start(&mut client, &sock).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+
+    let sent = [Client::CONN_SUCCESS, Client::START_IO];
+    let bytes_sent = sent
+        .iter()
+        .map(|&msg| crate::client_message_encode(msg, vec![]).unwrap().len())
+        .sum::<usize>();
+
+    assert_eq!(observer.datagrams_sent(), 2);
+    assert_eq!(observer.bytes_sent(), bytes_sent as u64);
+    assert_eq!(observer.datagrams_received(), 5);
+    assert_eq!(observer.bytes_received(), bytes_received as u64);
+    assert_eq!(observer.decode_failures(), 1);
+
+    for kind in MessageKind::ALL {
+        let n_sent = match kind {
+            MessageKind::ConnectionResult | MessageKind::IOStateChange => 1,
+            _ => 0,
+        };
+        let n_received = match kind {
+            MessageKind::Connect
+            | MessageKind::IOStateChangeResult
+            | MessageKind::Audio
+            | MessageKind::Heartbeat => 1,
+            _ => 0,
+        };
+
+        assert_eq!(observer.messages_sent(kind), n_sent, "{kind:?}");
+        assert_eq!(observer.messages_received(kind), n_received, "{kind:?}");
+    }
+}
+
+/// Logger capturing the records emitted by the current thread, so that tests running
+/// concurrently don't see each other's records.
+#[cfg(feature = "log")]
+mod capture {
+    use std::{cell::RefCell, sync::Once, vec::Vec};
+
+    struct CapturingLogger;
+
+    std::thread_local! {
+        static RECORDS: RefCell<Vec<(log::Level, String)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            let msg = (record.level(), record.args().to_string());
+            RECORDS.with_borrow_mut(|records| records.push(msg));
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Installs the capturing logger, if not done already.
+    pub fn install() {
+        static INSTALL: Once = Once::new();
+
+        INSTALL.call_once(|| {
+            log::set_logger(&CapturingLogger).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+    }
+
+    /// Returns, and clears, the records emitted by the current thread so far.
+    pub fn records() -> Vec<(log::Level, String)> {
+        RECORDS.take()
+    }
+}
+
+#[test]
+#[cfg(feature = "log")]
+fn connect_timeout_records() {
+    use log::Level;
+
+    capture::install();
+    let mut h = Harness::new();
+    capture::records();
+
+    h.connect(1, Capabilities::NONE, 1);
+    h.connect(1, Capabilities::NONE, 1);
+    h.advance(CONN_TIMEOUT);
+    h.timeout();
+    h.recv(Server::Disconnect);
+
+    let records = capture::records();
+    let records: Vec<_> = records
+        .iter()
+        .map(|(level, msg)| (*level, msg.strip_prefix("127.0.0.1:6910: ").unwrap()))
+        .collect();
+
+    assert_eq!(
+        records,
+        [
+            (Level::Debug, "connected"),
+            (Level::Trace, "connection request, already connected"),
+            (Level::Debug, "timed out"),
+            (Level::Trace, "disconnection request, not connected"),
+        ]
+    );
+}
@@ -7,9 +7,14 @@
 
 use core::{convert::Infallible, net::SocketAddr};
 
+use crate::observer::{MessageKind, WireObserver};
+
 #[cfg(feature = "generic")]
 pub mod generic;
 
+mod writer;
+pub use writer::AudioWriter;
+
 /// A UDP server.
 ///
 /// This type encapsulates a UDP socket, used to communicate with one or more servers.
@@ -22,22 +27,109 @@ pub mod generic;
 /// The client itself is agnostic to whether messages are sent via unicast,
 /// multicast, or broadcast addresses.
 #[derive(Debug)]
-pub struct ClientSocket<T> {
+pub struct ClientSocket<T, O = ()> {
     sock: T,
+    max_datagram_size: usize,
+    observer: O,
 }
 
 impl<T> ClientSocket<T> {
     /// Creates a new server backed by the given UDP socket.
+    ///
+    /// The maximum datagram size defaults to [`MAX_UDP_PAYLOAD_SIZE`](crate::MAX_UDP_PAYLOAD_SIZE).
     #[inline(always)]
     pub fn new(sock: T) -> Self {
-        Self { sock }
+        Self {
+            sock,
+            max_datagram_size: crate::MAX_UDP_PAYLOAD_SIZE,
+            observer: (),
+        }
+    }
+}
+
+impl<T, O> ClientSocket<T, O> {
+    /// Replaces the socket's [observer](crate::observer) of wire-level events.
+    #[inline(always)]
+    pub fn with_observer<P: WireObserver>(self, observer: P) -> ClientSocket<T, P> {
+        ClientSocket {
+            sock: self.sock,
+            max_datagram_size: self.max_datagram_size,
+            observer,
+        }
+    }
+
+    /// Returns the socket's observer of wire-level events.
+    #[inline(always)]
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Sets the maximum size of sent datagrams, typically the path MTU minus the IP and
+    /// UDP header sizes.
+    ///
+    /// Larger datagrams fail to send with [`SendError::TooLarge`](crate::SendError::TooLarge),
+    /// before reaching the socket. This is also the size of the receive buffer allocated by
+    /// [`Client::start`], larger received datagrams are truncated.
+    #[inline(always)]
+    pub fn with_max_datagram_size(mut self, max_datagram_size: usize) -> Self {
+        self.max_datagram_size = max_datagram_size;
+        self
+    }
+
+    /// Returns the maximum size of sent, and received, datagrams.
+    #[inline(always)]
+    pub fn max_datagram_size(&self) -> usize {
+        self.max_datagram_size
     }
 }
 
-impl<T: crate::SyncUdpSock> ClientSocket<T> {
+impl<T: crate::SyncUdpSock, O: WireObserver> super::AudioSocket for ClientSocket<T, O> {
+    #[inline(always)]
+    fn max_datagram_size(&self) -> usize {
+        self.max_datagram_size
+    }
+
+    #[inline(always)]
+    fn encode_audio_header(
+        header: syfala_proto::AudioMessageHeader,
+        cursor: &mut syfala_utils::UninitCursor<'_>,
+    ) -> postcard::Result<()> {
+        crate::client_message_encode(syfala_proto::message::Client::audio(header), cursor).map(drop)
+    }
+
+    #[inline(always)]
+    fn send_audio_datagram(
+        &self,
+        datagram: &[u8],
+        server_addr: SocketAddr,
+    ) -> Result<(), crate::SendError> {
+        self.send_raw_packet(datagram, server_addr)?;
+        self.observer.message_sent(MessageKind::Audio);
+        Ok(())
+    }
+}
+
+impl<T: crate::SyncUdpSock, O: WireObserver> ClientSocket<T, O> {
+    /// Sends `bytes` as a single datagram to `dest_addr`.
+    ///
+    /// Fails, without sending anything, if it exceeds the
+    /// [maximum datagram size](Self::max_datagram_size).
     #[inline]
-    pub fn send_raw_packet(&self, bytes: &[u8], dest_addr: SocketAddr) -> std::io::Result<()> {
-        self.sock.send(bytes, dest_addr)
+    pub fn send_raw_packet(
+        &self,
+        bytes: &[u8],
+        dest_addr: SocketAddr,
+    ) -> Result<(), crate::SendError> {
+        let (size, max) = (bytes.len(), self.max_datagram_size);
+
+        if size > max {
+            return Err(crate::SendError::TooLarge { size, max });
+        }
+
+        self.sock.send(bytes, dest_addr)?;
+        self.observer.datagram_sent(dest_addr, size);
+
+        Ok(())
     }
 
     /// Serializes and sends a client message to the specified destination address.
@@ -51,38 +143,169 @@ impl<T: crate::SyncUdpSock> ClientSocket<T> {
         &self,
         message: syfala_proto::message::Client,
         server_addr: SocketAddr,
-        buf: &mut [u8],
-    ) -> std::io::Result<()> {
-        crate::client_message_encode(message, buf)
-            .map_err(crate::postcard_to_io_err)
-            .and_then(|s| self.send_raw_packet(s, server_addr))
+        buf: &mut [core::mem::MaybeUninit<u8>],
+    ) -> Result<(), crate::SendError> {
+        self.send_msg_len(message, server_addr, buf).map(drop)
+    }
+
+    /// Same as [`send_msg`](Self::send_msg), but returns the size of the sent datagram.
+    #[inline(always)]
+    pub(crate) fn send_msg_len(
+        &self,
+        message: syfala_proto::message::Client,
+        server_addr: SocketAddr,
+        buf: &mut [core::mem::MaybeUninit<u8>],
+    ) -> Result<usize, crate::SendError> {
+        let s = crate::client_message_encode_uninit(message, buf)?;
+        let len = s.len();
+        self.send_raw_packet(s, server_addr)?;
+        self.observer.message_sent(MessageKind::of_client(&message));
+
+        Ok(len)
+    }
+
+    /// Returns an [`io::Write`](std::io::Write) adapter sending the bytes written to it as
+    /// audio messages, of at most `mtu` bytes, for the server's output stream `stream_idx`,
+    /// starting at byte index `byte_idx`.
+    ///
+    /// # Panics
+    ///
+    /// If `mtu` is too small to hold an audio message header and at least one byte of
+    /// payload.
+    #[inline(always)]
+    pub fn audio_writer(
+        &self,
+        server_addr: SocketAddr,
+        stream_idx: u32,
+        byte_idx: u64,
+        mtu: usize,
+    ) -> AudioWriter<'_, T, O> {
+        AudioWriter::new(self, server_addr, stream_idx, byte_idx, mtu)
+    }
+
+    /// Sends a single audio message, for the server's output stream `stream_idx`,
+    /// carrying `payload`, starting at byte index `byte_idx`.
+    ///
+    /// The header is encoded into `buf`, which needn't be initialized, and directly
+    /// followed by the payload, which must thus fit in it too. See
+    /// [`audio_writer`](Self::audio_writer) to packetize a continuous stream instead.
+    pub fn send_audio(
+        &self,
+        server_addr: SocketAddr,
+        stream_idx: u32,
+        byte_idx: u64,
+        payload: &[u8],
+        buf: &mut [core::mem::MaybeUninit<u8>],
+    ) -> Result<(), crate::SendError> {
+        self.send_audio_len(server_addr, stream_idx, byte_idx, payload, buf)
+            .map(drop)
+    }
+
+    /// Same as [`send_audio`](Self::send_audio), but returns the size of the sent
+    /// datagram.
+    pub(crate) fn send_audio_len(
+        &self,
+        server_addr: SocketAddr,
+        stream_idx: u32,
+        byte_idx: u64,
+        payload: &[u8],
+        buf: &mut [core::mem::MaybeUninit<u8>],
+    ) -> Result<usize, crate::SendError> {
+        super::send_audio(self, server_addr, stream_idx, byte_idx, payload, buf, false)
+    }
+
+    /// Same as [`send_audio`](Self::send_audio), but splits `payload` into as many
+    /// consecutive audio messages as needed for each to fit in both `buf` and the
+    /// [maximum datagram size](Self::max_datagram_size), with advancing byte indices.
+    ///
+    /// This avoids relying on IP fragmentation for large payloads. Nothing is sent if
+    /// `payload` is empty. Stops at the first failure, previous messages having been
+    /// sent.
+    pub fn send_audio_chunked(
+        &self,
+        server_addr: SocketAddr,
+        stream_idx: u32,
+        byte_idx: u64,
+        payload: &[u8],
+        buf: &mut [core::mem::MaybeUninit<u8>],
+    ) -> Result<(), crate::SendError> {
+        super::send_audio(self, server_addr, stream_idx, byte_idx, payload, buf, true).map(drop)
     }
 
     pub fn set_recv_timeout(&self, timeout: Option<core::time::Duration>) -> std::io::Result<()> {
         self.sock.set_recv_timeout(timeout)
     }
 
-    /// Receives and deserializes a server message from the underlying socket.
+    /// Returns the local address the underlying socket is bound to.
+    #[inline(always)]
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.sock.local_addr()
+    }
+
+    /// Allows, or disallows, sending to broadcast addresses, e.g. for discovery.
+    #[inline(always)]
+    pub fn set_broadcast(&self, broadcast: bool) -> std::io::Result<()> {
+        self.sock.set_broadcast(broadcast)
+    }
+
+    /// Joins the IPv4 multicast group `group`, on the interface whose address is
+    /// `interface` ([`Ipv4Addr::UNSPECIFIED`](core::net::Ipv4Addr::UNSPECIFIED) letting
+    /// the system choose).
+    #[inline(always)]
+    pub fn join_multicast_v4(
+        &self,
+        group: core::net::Ipv4Addr,
+        interface: core::net::Ipv4Addr,
+    ) -> std::io::Result<()> {
+        self.sock.join_multicast_v4(group, interface)
+    }
+
+    /// Sets the time-to-live of outgoing IPv4 multicast datagrams.
+    #[inline(always)]
+    pub fn set_multicast_ttl_v4(&self, ttl: u32) -> std::io::Result<()> {
+        self.sock.set_multicast_ttl_v4(ttl)
+    }
+
+    /// Enables, or disables, queueing the ICMP errors reported about sent datagrams, e.g.
+    /// when a server's port is unreachable, see
+    /// [`SyncUdpSock::set_recv_errors`](crate::SyncUdpSock::set_recv_errors).
     ///
-    /// On success, returns the sender’s socket address and an optional decoded
-    /// protocol message.
+    /// Only supported by standard library sockets on Linux, with the `recv_errors`
+    /// feature enabled.
+    #[inline(always)]
+    pub fn set_recv_errors(&self, enabled: bool) -> std::io::Result<()> {
+        self.sock.set_recv_errors(enabled)
+    }
+
+    /// Receives the oldest queued ICMP error, without blocking, see
+    /// [`set_recv_errors`](Self::set_recv_errors).
+    #[inline(always)]
+    pub fn recv_error(&self) -> std::io::Result<Option<crate::DestinationError>> {
+        self.sock.recv_error()
+    }
+
+    /// Receives a datagram from the underlying socket, without decoding it.
     ///
-    /// If a datagram is received but cannot be parsed as a valid protocol message,
-    /// the `Option` will be `None`.
+    /// On success, returns the sender’s socket address, and the received bytes.
     #[inline(always)]
-    fn recv<'a>(
+    fn recv_raw<'a>(
         &self,
         buf: &'a mut [u8],
-    ) -> std::io::Result<(
-        SocketAddr,
-        std::time::Instant,
-        Option<(syfala_proto::message::Server, &'a [u8])>,
-    )> {
-        self.sock.recv(buf).map(|(n, server, timestamp)| {
-            let buf = &buf[..n];
+    ) -> std::io::Result<(SocketAddr, std::time::Instant, &'a [u8])> {
+        let (n, server, timestamp) = self.sock.recv(buf)?;
+        self.observer.datagram_received(server, n);
 
-            (server, timestamp, crate::server_message_decode(buf).ok())
-        })
+        Ok((server, timestamp, &buf[..n]))
+    }
+
+    /// Notifies the observer of the outcome of decoding a datagram received from `addr`,
+    /// `None` meaning that decoding failed.
+    #[inline(always)]
+    pub(crate) fn observe_decoded(&self, addr: SocketAddr, kind: Option<MessageKind>) {
+        match kind {
+            Some(kind) => self.observer.message_received(kind),
+            None => self.observer.decode_failed(addr),
+        }
     }
 
     #[inline]
@@ -107,7 +330,9 @@ impl<T: crate::SyncUdpSock> ClientSocket<T> {
         let buf = &disc_packet_buf.get_ref()[..payload_size];
 
         loop {
-            let res = self.send_raw_packet(buf, dest_addr);
+            let res = self
+                .send_raw_packet(buf, dest_addr)
+                .map_err(std::io::Error::from);
 
             match res {
                 Err(e) if crate::io_err_is_timeout(e.kind()) => continue,
@@ -135,34 +360,180 @@ pub trait Client {
     /// valid protocol message.
     fn on_message(
         &mut self,
-        client: &ClientSocket<impl crate::SyncUdpSock>,
+        client: &ClientSocket<impl crate::SyncUdpSock, impl WireObserver>,
         server_addr: core::net::SocketAddr,
         timestamp: std::time::Instant,
         message: Option<(syfala_proto::message::Server, &[u8])>,
     ) -> std::io::Result<()>;
 
-    fn on_timeout(&mut self, client: &ClientSocket<impl crate::SyncUdpSock>) -> std::io::Result<()>;
+    fn on_timeout(
+        &mut self,
+        client: &ClientSocket<impl crate::SyncUdpSock, impl WireObserver>,
+    ) -> std::io::Result<()>;
+
+    /// Called when receiving a datagram fails, with anything other than a timeout.
+    ///
+    /// Sockets reporting ICMP errors (see
+    /// [`ClientSocket::set_recv_errors`]) fail to receive when they get one. The default
+    /// implementation returns the error, stopping the receive loop.
+    #[inline(always)]
+    fn on_recv_error(
+        &mut self,
+        client: &ClientSocket<impl crate::SyncUdpSock, impl WireObserver>,
+        error: std::io::Error,
+    ) -> std::io::Result<()> {
+        let _ = client;
+        Err(error)
+    }
+
+    /// Called on every received datagram, before it is decoded.
+    ///
+    /// The default implementation decodes it, and calls
+    /// [`on_message`](Self::on_message). Implementors can override it to decode
+    /// datagrams differently, e.g. using
+    /// [`server_message_decode_ref`](crate::server_message_decode_ref) to avoid
+    /// allocating.
+    #[inline(always)]
+    fn on_datagram(
+        &mut self,
+        client: &ClientSocket<impl crate::SyncUdpSock, impl WireObserver>,
+        server_addr: core::net::SocketAddr,
+        timestamp: std::time::Instant,
+        datagram: &[u8],
+    ) -> std::io::Result<()> {
+        let maybe_msg = crate::server_message_decode(datagram)
+            .ok()
+            .map(|(msg, _n_decoded, rem_buf)| (msg, rem_buf));
+
+        let kind = maybe_msg
+            .as_ref()
+            .map(|(msg, _)| MessageKind::of_server(msg));
+        client.observe_decoded(server_addr, kind);
+
+        self.on_message(client, server_addr, timestamp, maybe_msg)
+    }
 
     /// Starts the client receive loop
     ///
     /// This function blocks indefinitely, receiving datagrams and invoking
-    /// [`on_message`](ClientState::on_message) for each one.
+    /// [`on_datagram`](Self::on_datagram) for each one.
     ///
-    /// The function only returns if a non-recoverable I/O error occurs.
-    fn start(&mut self, client: &ClientSocket<impl crate::SyncUdpSock>) -> std::io::Result<Infallible> {
-        let mut buf = [0; 5000];
+    /// The receive buffer is [`max_datagram_size`](ClientSocket::max_datagram_size) bytes
+    /// long, larger datagrams are truncated.
+    ///
+    /// The function only returns if a non-recoverable I/O error occurs, see
+    /// [`on_recv_error`](Self::on_recv_error).
+    fn start(
+        &mut self,
+        client: &ClientSocket<impl crate::SyncUdpSock, impl WireObserver>,
+    ) -> std::io::Result<Infallible> {
+        let mut buf = vec![0; client.max_datagram_size()].into_boxed_slice();
 
         loop {
-            let res = client.recv(&mut buf);
+            let res = client.recv_raw(&mut buf);
 
             // don't return on timeout errors...
             match res {
-                Ok((addr, timestamp, maybe_msg)) => {
-                    self.on_message(client, addr, timestamp, maybe_msg)?
+                Ok((addr, timestamp, datagram)) => {
+                    self.on_datagram(client, addr, timestamp, datagram)?
                 }
                 Err(e) if crate::io_err_is_timeout(e.kind()) => self.on_timeout(client)?,
-                Err(e) => return Err(e),
+                Err(e) => self.on_recv_error(client, e)?,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::mem::MaybeUninit;
+    use syfala_proto::message::{Client, StreamSelection, client};
+
+    fn loopback() -> (ClientSocket<std::net::UdpSocket>, std::net::UdpSocket) {
+        let bind = || std::net::UdpSocket::bind((core::net::Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let (client, rx) = (ClientSocket::new(bind()), bind());
+        rx.set_read_timeout(Some(core::time::Duration::from_secs(1)))
+            .unwrap();
+        (client, rx)
+    }
+
+    #[test]
+    fn send_msg_loopback() {
+        let (client, rx) = loopback();
+        let rx_addr = rx.local_addr().unwrap();
+
+        let header = syfala_proto::AudioMessageHeader {
+            stream_idx: 1,
+            stream_msg: syfala_proto::AudioStreamMessageHeader {
+                byte_idx: 96,
+                n_bytes: 4,
+            },
+        };
+
+        let msgs = [
+            Client::HEARTBEAT,
+            Client::start_io(StreamSelection::Mask(0b101)),
+            Client::audio(header),
+        ];
+
+        for msg in msgs {
+            // filled with garbage, only the encoded message may be sent
+            let mut buf = [MaybeUninit::new(0xa5); 64];
+            client.send_msg(msg, rx_addr, &mut buf).unwrap();
+
+            let mut datagram = [0; 128];
+            let (n, _) = rx.recv_from(&mut datagram).unwrap();
+            let datagram = &datagram[..n];
+
+            let encoded = crate::client_message_encode(msg, vec![]).unwrap();
+            assert_eq!(datagram, encoded);
+            let decoded = crate::client_message_decode(datagram).unwrap();
+            assert_eq!(decoded, (msg, n, &[][..]));
+        }
+    }
+
+    #[test]
+    fn send_audio_chunked_loopback() {
+        const MAX_SIZE: usize = 1400;
+        const START_IDX: u64 = 1000;
+
+        let (client, rx) = loopback();
+        let client = client.with_max_datagram_size(MAX_SIZE);
+        let rx_addr = rx.local_addr().unwrap();
+
+        let payload: Vec<u8> = (0..1 << 16).map(|i: u32| (i ^ (i >> 8)) as u8).collect();
+        let mut buf = [MaybeUninit::uninit(); 2000];
+        client
+            .send_audio_chunked(rx_addr, 2, START_IDX, &payload, &mut buf)
+            .unwrap();
+
+        let chunk_len = MAX_SIZE - crate::ENCODED_AUDIO_MESSAGE_OVERHEAD;
+        let n_datagrams = payload.len().div_ceil(chunk_len);
+        let mut padder = syfala_utils::AudioPacketSamplePadder::<u8>::new();
+        let mut received = vec![];
+        let mut next_idx = START_IDX;
+
+        for i in 0..n_datagrams {
+            let mut datagram = [0; 2 * MAX_SIZE];
+            let (n, _) = rx.recv_from(&mut datagram).unwrap();
+            assert!(n == MAX_SIZE || i == n_datagrams - 1);
+
+            let (msg, _, bytes) = crate::client_message_decode(&datagram[..n]).unwrap();
+            let Client::Connected(client::Connected::Audio(header)) = msg else {
+                panic!("expected an audio message, got {msg:?}");
             };
+            let byte_idx = header.stream_msg.byte_idx;
+            assert_eq!(header.stream_idx, 2);
+            assert_eq!(byte_idx, next_idx);
+            assert_eq!(header.stream_msg.n_bytes as usize, bytes.len());
+            next_idx = header.stream_msg.next_byte_idx();
+
+            let pad = || panic!("padding requested");
+            received.extend(padder.feed_bytes(byte_idx, bytes.iter().copied(), pad));
         }
+
+        assert_eq!(received, payload);
+        assert_eq!(padder.stats().padding_samples, 0);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,275 @@
+//! [`std::io::Write`] adapter sending audio messages to a server.
+
+use core::net::SocketAddr;
+use std::io;
+use syfala_proto::{AudioMessageHeader, AudioStreamMessageHeader, message::Client};
+
+use crate::observer::WireObserver;
+
+/// Encodes the header of an audio message at the start of `buf`, returning its length.
+#[inline(always)]
+fn encode_audio_header(header: AudioMessageHeader, buf: &mut [u8]) -> postcard::Result<usize> {
+    let mut cursor = io::Cursor::new(buf);
+
+    crate::client_message_encode(Client::audio(header), &mut cursor)?;
+
+    Ok(usize::try_from(cursor.position()).unwrap())
+}
+
+/// An [`io::Write`] implementation packetizing the bytes written to it into audio messages
+/// for a single stream, and sending them to a server.
+///
+/// Bytes are buffered until a full datagram's worth of payload is available, or until
+/// [`flush`](io::Write::flush) is called. The byte index of every message is tracked
+/// automatically, starting at the one provided upon creation.
+///
+/// Pending bytes are flushed when the writer is dropped, errors are ignored in that case.
+/// Call `flush` explicitly to handle them.
+///
+/// See [`ClientSocket::audio_writer`](super::ClientSocket::audio_writer).
+#[derive(Debug)]
+pub struct AudioWriter<'a, T: crate::SyncUdpSock, O: WireObserver = ()> {
+    sock: &'a super::ClientSocket<T, O>,
+    server_addr: SocketAddr,
+    stream_idx: u32,
+    /// Byte index of the first pending byte.
+    byte_idx: u64,
+    /// Length of the (fixed-size) encoded audio message header.
+    header_len: usize,
+    /// Datagram buffer, room for the header, followed by the pending payload bytes.
+    buf: Box<[u8]>,
+    /// Number of pending payload bytes.
+    n_pending: usize,
+}
+
+impl<'a, T: crate::SyncUdpSock, O: WireObserver> AudioWriter<'a, T, O> {
+    /// # Panics
+    ///
+    /// If `mtu` is too small to hold at least one byte of payload.
+    pub(super) fn new(
+        sock: &'a super::ClientSocket<T, O>,
+        server_addr: SocketAddr,
+        stream_idx: u32,
+        byte_idx: u64,
+        mtu: usize,
+    ) -> Self {
+        let mut buf: Box<[u8]> = core::iter::repeat_n(0, mtu).collect();
+
+        // all header fields are fixed-size, so any header will do
+        let header_len = encode_audio_header(
+            AudioMessageHeader {
+                stream_idx,
+                stream_msg: AudioStreamMessageHeader {
+                    byte_idx,
+                    n_bytes: 0,
+                },
+            },
+            &mut buf,
+        )
+        .ok()
+        .filter(|&n| n < mtu)
+        .expect("MTU too small to fit an audio message");
+
+        Self {
+            sock,
+            server_addr,
+            stream_idx,
+            byte_idx,
+            header_len,
+            buf,
+            n_pending: 0,
+        }
+    }
+
+    /// Returns the byte index of the next byte to be written.
+    #[inline(always)]
+    pub fn byte_idx(&self) -> u64 {
+        self.byte_idx
+            .strict_add(u64::try_from(self.n_pending).unwrap())
+    }
+
+    /// Returns the maximum number of payload bytes sent in a single message.
+    #[inline(always)]
+    pub fn payload_capacity(&self) -> usize {
+        self.buf.len().strict_sub(self.header_len)
+    }
+
+    /// Sends all pending bytes in a single message. On error, they are kept pending.
+    fn send_pending(&mut self) -> io::Result<()> {
+        if self.n_pending == 0 {
+            return Ok(());
+        }
+
+        let header = AudioMessageHeader {
+            stream_idx: self.stream_idx,
+            stream_msg: AudioStreamMessageHeader {
+                byte_idx: self.byte_idx,
+                n_bytes: self.n_pending.try_into().unwrap(),
+            },
+        };
+
+        encode_audio_header(header, &mut self.buf[..self.header_len])
+            .map_err(crate::postcard_to_io_err)?;
+
+        let datagram_len = self.header_len.strict_add(self.n_pending);
+        self.sock
+            .send_raw_packet(&self.buf[..datagram_len], self.server_addr)?;
+        self.sock
+            .observer
+            .message_sent(crate::observer::MessageKind::Audio);
+
+        self.byte_idx = header.stream_msg.next_byte_idx();
+        self.n_pending = 0;
+
+        Ok(())
+    }
+}
+
+impl<T: crate::SyncUdpSock, O: WireObserver> io::Write for AudioWriter<'_, T, O> {
+    /// Buffers as many bytes as fit in the current message, sending it if it is full.
+    ///
+    /// If sending fails, the bytes are not buffered, and the error is returned.
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let start = self.header_len.strict_add(self.n_pending);
+        let free = &mut self.buf[start..];
+        let n = free.len().min(data.len());
+        free[..n].copy_from_slice(&data[..n]);
+        self.n_pending = self.n_pending.strict_add(n);
+
+        if self.n_pending == self.payload_capacity() {
+            self.send_pending().inspect_err(|_| {
+                self.n_pending = self.n_pending.strict_sub(n);
+            })?;
+        }
+
+        Ok(n)
+    }
+
+    /// Sends all pending bytes, if any.
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_pending()
+    }
+}
+
+impl<T: crate::SyncUdpSock, O: WireObserver> Drop for AudioWriter<'_, T, O> {
+    fn drop(&mut self) {
+        let _ = self.send_pending();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::{Cell, RefCell};
+    use io::Write;
+
+    const SERVER: SocketAddr =
+        SocketAddr::new(core::net::IpAddr::V4(core::net::Ipv4Addr::LOCALHOST), 6910);
+
+    /// Socket recording the datagrams sent through it.
+    #[derive(Default)]
+    struct MockSock {
+        sent: RefCell<Vec<Vec<u8>>>,
+        /// Number of the next sends to fail.
+        n_failures: Cell<usize>,
+    }
+
+    impl crate::SyncUdpSock for MockSock {
+        fn send(&self, bytes: &[u8], dest_addr: SocketAddr) -> io::Result<()> {
+            assert_eq!(dest_addr, SERVER);
+
+            if let Some(n) = self.n_failures.get().checked_sub(1) {
+                self.n_failures.set(n);
+                return Err(io::ErrorKind::ConnectionRefused.into());
+            }
+
+            self.sent.borrow_mut().push(bytes.to_vec());
+            Ok(())
+        }
+
+        fn recv(&self, _bytes: &mut [u8]) -> io::Result<(usize, SocketAddr, std::time::Instant)> {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+
+        fn set_recv_timeout(&self, _timeout: Option<core::time::Duration>) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Decodes the audio messages sent so far, checking that they are contiguous and
+    /// start at `byte_idx`, and returns their concatenated payloads.
+    fn reassemble(sock: &super::super::ClientSocket<MockSock>, mut byte_idx: u64) -> Vec<u8> {
+        let mut stream = vec![];
+
+        for datagram in sock.sock.sent.take() {
+            let (msg, _, payload) = crate::client_message_decode(&datagram).unwrap();
+
+            let Client::Connected(syfala_proto::message::client::Connected::Audio(header)) = msg
+            else {
+                panic!("not an audio message: {msg:?}");
+            };
+
+            assert_eq!(header.stream_idx, 3);
+            assert_eq!(header.stream_msg.byte_idx, byte_idx);
+            assert_eq!(header.stream_msg.n_bytes as usize, payload.len());
+
+            byte_idx = header.stream_msg.next_byte_idx();
+            stream.extend_from_slice(payload);
+        }
+
+        stream
+    }
+
+    #[test]
+    fn stream_100k() {
+        let sock = super::super::ClientSocket::new(MockSock::default());
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let mtu = 1400;
+
+        let mut writer = sock.audio_writer(SERVER, 3, 1 << 40, mtu);
+        let capacity = writer.payload_capacity();
+
+        for chunk in data.chunks(333) {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.flush().unwrap();
+        assert_eq!(writer.byte_idx(), (1 << 40) + 100_000);
+        drop(writer);
+
+        let n_datagrams = sock.sock.sent.borrow().len();
+        assert_eq!(n_datagrams, 100_000usize.div_ceil(capacity));
+        assert!(sock.sock.sent.borrow().iter().all(|d| d.len() <= mtu));
+
+        assert_eq!(reassemble(&sock, 1 << 40), data);
+    }
+
+    #[test]
+    fn flush_on_drop() {
+        let sock = super::super::ClientSocket::new(MockSock::default());
+
+        let mut writer = sock.audio_writer(SERVER, 3, 0, 1400);
+        writer.write_all(&[1, 2, 3]).unwrap();
+        assert!(sock.sock.sent.borrow().is_empty());
+        drop(writer);
+
+        assert_eq!(reassemble(&sock, 0), [1, 2, 3]);
+    }
+
+    #[test]
+    fn send_failure_keeps_bytes() {
+        let sock = super::super::ClientSocket::new(MockSock::default());
+
+        let mut writer = sock.audio_writer(SERVER, 3, 0, 64);
+        let capacity = writer.payload_capacity();
+        let data: Vec<u8> = (0..capacity as u8 + 1).collect();
+
+        sock.sock.n_failures.set(1);
+        assert!(writer.write(&data).is_err());
+        assert_eq!(writer.byte_idx(), 0);
+
+        writer.write_all(&data).unwrap();
+        drop(writer);
+
+        assert_eq!(reassemble(&sock, 0), data);
+    }
+}
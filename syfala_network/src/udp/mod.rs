@@ -1,2 +1,83 @@
 pub mod client;
 pub mod server;
+
+use core::{mem::MaybeUninit, net::SocketAddr};
+use syfala_proto::{AudioMessageHeader, AudioStreamMessageHeader};
+
+/// Either socket, sending audio messages in its own direction, see [`send_audio`].
+pub(crate) trait AudioSocket {
+    /// Returns the maximum size of the datagrams sent.
+    fn max_datagram_size(&self) -> usize;
+
+    /// Encodes an audio message with `header`, in the socket's direction.
+    fn encode_audio_header(
+        header: AudioMessageHeader,
+        cursor: &mut syfala_utils::UninitCursor<'_>,
+    ) -> postcard::Result<()>;
+
+    /// Sends an encoded audio message to `dest_addr`.
+    fn send_audio_datagram(
+        &self,
+        datagram: &[u8],
+        dest_addr: SocketAddr,
+    ) -> Result<(), crate::SendError>;
+}
+
+/// Sends `payload` to `dest_addr`, for the stream `stream_idx`, starting at byte index
+/// `byte_idx`, through `sock`, encoding audio messages into `buf`.
+///
+/// If `chunked` is `true`, `payload` is split into as many consecutive messages as
+/// needed for each to fit in both `buf` and the socket's maximum datagram size, see
+/// [`audio_chunks`](crate::audio_chunks), and nothing is sent if it's empty.
+/// Otherwise, it's sent in a single message, which must fit in both.
+///
+/// Stops at the first failure, previous messages having been sent. Returns the size of
+/// the last datagram sent.
+pub(crate) fn send_audio<S: AudioSocket>(
+    sock: &S,
+    dest_addr: SocketAddr,
+    stream_idx: u32,
+    byte_idx: u64,
+    payload: &[u8],
+    buf: &mut [MaybeUninit<u8>],
+    chunked: bool,
+) -> Result<usize, crate::SendError> {
+    let max_size = sock.max_datagram_size().min(buf.len());
+
+    let mut send_one = |byte_idx, payload: &[u8]| {
+        let n_bytes = u32::try_from(payload.len()).map_err(|_| crate::SendError::TooLarge {
+            size: payload.len(),
+            max: sock.max_datagram_size(),
+        })?;
+
+        let header = AudioMessageHeader {
+            stream_idx,
+            stream_msg: AudioStreamMessageHeader { byte_idx, n_bytes },
+        };
+
+        let mut cursor = syfala_utils::UninitCursor::new(buf);
+
+        S::encode_audio_header(header, &mut cursor)?;
+
+        if cursor.write_bytes(payload) != payload.len() {
+            return Err(postcard::Error::SerializeBufferFull.into());
+        }
+
+        let datagram = cursor.written();
+        sock.send_audio_datagram(datagram, dest_addr)?;
+
+        Ok(datagram.len())
+    };
+
+    if !chunked {
+        return send_one(byte_idx, payload);
+    }
+
+    let mut len = 0;
+
+    for (byte_idx, chunk) in crate::audio_chunks(byte_idx, payload, max_size)? {
+        len = send_one(byte_idx, chunk)?;
+    }
+
+    Ok(len)
+}
@@ -7,6 +7,26 @@
 
 use core::{convert::Infallible, net::SocketAddr};
 
+use crate::observer::{MessageKind, WireObserver};
+
+/// A decoded client message, along with the bytes following it in the datagram.
+type DecodedClientMessage<'a> = (syfala_proto::message::Client, &'a [u8]);
+
+/// Generates a new server epoch, to be sent in all of the server's
+/// [`Connect`](syfala_proto::message::Server::Connect) messages.
+///
+/// Call this once when the server starts, so that clients can tell its restarts apart.
+/// Clients only look at it if the server advertises
+/// [`Capabilities::EPOCH`](syfala_proto::message::Capabilities::EPOCH).
+pub fn new_epoch() -> u32 {
+    use std::hash::{BuildHasher, Hasher};
+
+    // RandomState is seeded randomly, this spares us a dependency on an RNG
+    let hash = std::hash::RandomState::new().build_hasher().finish();
+    // fold the hash, to make use of all of its bits
+    (hash ^ (hash >> 32)) as u32
+}
+
 /// A UDP server socket
 ///
 /// This type encapsulates a UDP socket, used to communicate with one or more clients,
@@ -19,8 +39,10 @@ use core::{convert::Infallible, net::SocketAddr};
 /// The server itself is agnostic to whether messages are sent via unicast,
 /// multicast, or broadcast addresses.
 #[derive(Debug)]
-pub struct ServerSocket {
+pub struct ServerSocket<O = ()> {
     sock: std::net::UdpSocket,
+    max_datagram_size: usize,
+    observer: O,
 }
 
 impl ServerSocket {
@@ -32,18 +54,92 @@ impl ServerSocket {
     /// establishment and remain fixed for the lifetime of the server.
     #[inline(always)]
     pub const fn new(sock: std::net::UdpSocket) -> Self {
-        Self { sock }
+        Self {
+            sock,
+            max_datagram_size: crate::MAX_UDP_PAYLOAD_SIZE,
+            observer: (),
+        }
+    }
+}
+
+impl<O> ServerSocket<O> {
+    /// Replaces the socket's [observer](crate::observer) of wire-level events.
+    #[inline(always)]
+    pub fn with_observer<P: WireObserver>(self, observer: P) -> ServerSocket<P> {
+        ServerSocket {
+            sock: self.sock,
+            max_datagram_size: self.max_datagram_size,
+            observer,
+        }
+    }
+
+    /// Returns the socket's observer of wire-level events.
+    #[inline(always)]
+    pub const fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Sets the maximum size of sent datagrams, typically the path MTU minus the IP and
+    /// UDP header sizes. Defaults to [`MAX_UDP_PAYLOAD_SIZE`](crate::MAX_UDP_PAYLOAD_SIZE).
+    ///
+    /// Larger datagrams fail to send with [`SendError::TooLarge`](crate::SendError::TooLarge),
+    /// before reaching the socket.
+    #[inline(always)]
+    pub const fn with_max_datagram_size(mut self, max_datagram_size: usize) -> Self {
+        self.max_datagram_size = max_datagram_size;
+        self
+    }
+
+    /// Returns the maximum size of sent datagrams.
+    #[inline(always)]
+    pub const fn max_datagram_size(&self) -> usize {
+        self.max_datagram_size
+    }
+}
+
+impl<O: WireObserver> super::AudioSocket for ServerSocket<O> {
+    #[inline(always)]
+    fn max_datagram_size(&self) -> usize {
+        self.max_datagram_size
+    }
+
+    #[inline(always)]
+    fn encode_audio_header(
+        header: syfala_proto::AudioMessageHeader,
+        cursor: &mut syfala_utils::UninitCursor<'_>,
+    ) -> postcard::Result<()> {
+        crate::server_message_encode(syfala_proto::message::Server::audio(header), cursor).map(drop)
+    }
+
+    #[inline(always)]
+    fn send_audio_datagram(
+        &self,
+        datagram: &[u8],
+        client_addr: SocketAddr,
+    ) -> Result<(), crate::SendError> {
+        self.send_packet(datagram, client_addr)?;
+        self.observer.message_sent(MessageKind::Audio);
+        Ok(())
     }
+}
 
+impl<O: WireObserver> ServerSocket<O> {
+    /// Sends `bytes` as a single datagram to `dest_addr`.
+    ///
+    /// Fails, without sending anything, if it exceeds the
+    /// [maximum datagram size](Self::max_datagram_size).
     #[inline]
-    pub fn send_packet(&self, bytes: &[u8], dest_addr: SocketAddr) -> std::io::Result<()> {
-        let res = self.sock.send_to(bytes, dest_addr);
+    pub fn send_packet(&self, bytes: &[u8], dest_addr: SocketAddr) -> Result<(), crate::SendError> {
+        let (size, max) = (bytes.len(), self.max_datagram_size);
 
-        res.and_then(|n| {
-            (n == bytes.len())
-                .then_some(())
-                .ok_or(std::io::ErrorKind::FileTooLarge.into())
-        })
+        if size > max {
+            return Err(crate::SendError::TooLarge { size, max });
+        }
+
+        crate::send_all_to(&self.sock, bytes, dest_addr)?;
+        self.observer.datagram_sent(dest_addr, size);
+
+        Ok(())
     }
 
     /// Serializes and sends a server message to the specified destination address.
@@ -57,11 +153,88 @@ impl ServerSocket {
         &self,
         message: syfala_proto::message::Server,
         client_addr: SocketAddr,
-        buf: &mut [u8],
+        buf: &mut [core::mem::MaybeUninit<u8>],
+    ) -> Result<(), crate::SendError> {
+        let kind = MessageKind::of_server(&message);
+        let s = crate::server_message_encode_uninit(message, buf)?;
+        self.send_packet(s, client_addr)?;
+        self.observer.message_sent(kind);
+
+        Ok(())
+    }
+
+    /// Sends an audio message, for the client's input stream `stream_idx`, carrying
+    /// `payload`, starting at byte index `byte_idx`.
+    ///
+    /// The header is encoded into `buf`, which needn't be initialized, and directly
+    /// followed by the payload, which must thus fit in it too, see the
+    /// [wire format](crate#wire-format).
+    pub fn send_audio(
+        &self,
+        client_addr: SocketAddr,
+        stream_idx: u32,
+        byte_idx: u64,
+        payload: &[u8],
+        buf: &mut [core::mem::MaybeUninit<u8>],
+    ) -> Result<(), crate::SendError> {
+        super::send_audio(self, client_addr, stream_idx, byte_idx, payload, buf, false).map(drop)
+    }
+
+    /// Same as [`send_audio`](Self::send_audio), but splits `payload` into as many
+    /// consecutive audio messages as needed for each to fit in both `buf` and the
+    /// [maximum datagram size](Self::max_datagram_size), with advancing byte indices.
+    ///
+    /// This avoids relying on IP fragmentation for large payloads. Nothing is sent if
+    /// `payload` is empty. Stops at the first failure, previous messages having been
+    /// sent.
+    pub fn send_audio_chunked(
+        &self,
+        client_addr: SocketAddr,
+        stream_idx: u32,
+        byte_idx: u64,
+        payload: &[u8],
+        buf: &mut [core::mem::MaybeUninit<u8>],
+    ) -> Result<(), crate::SendError> {
+        super::send_audio(self, client_addr, stream_idx, byte_idx, payload, buf, true).map(drop)
+    }
+
+    /// Sets the receive timeout of the underlying socket, `None` meaning that receiving
+    /// blocks indefinitely.
+    ///
+    /// When it elapses, [`ServerState::on_timeout`] is called.
+    #[inline(always)]
+    pub fn set_recv_timeout(&self, timeout: Option<core::time::Duration>) -> std::io::Result<()> {
+        self.sock.set_read_timeout(timeout)
+    }
+
+    /// Returns the local address the underlying socket is bound to.
+    #[inline(always)]
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.sock.local_addr()
+    }
+
+    /// Allows, or disallows, sending to broadcast addresses, e.g. for discovery.
+    #[inline(always)]
+    pub fn set_broadcast(&self, broadcast: bool) -> std::io::Result<()> {
+        self.sock.set_broadcast(broadcast)
+    }
+
+    /// Joins the IPv4 multicast group `group`, on the interface whose address is
+    /// `interface` ([`Ipv4Addr::UNSPECIFIED`](core::net::Ipv4Addr::UNSPECIFIED) letting
+    /// the system choose).
+    #[inline(always)]
+    pub fn join_multicast_v4(
+        &self,
+        group: core::net::Ipv4Addr,
+        interface: core::net::Ipv4Addr,
     ) -> std::io::Result<()> {
-        crate::server_message_encode(message, buf)
-            .map_err(crate::postcard_to_io_err)
-            .and_then(|s| self.send_packet(s, client_addr))
+        self.sock.join_multicast_v4(&group, &interface)
+    }
+
+    /// Sets the time-to-live of outgoing IPv4 multicast datagrams.
+    #[inline(always)]
+    pub fn set_multicast_ttl_v4(&self, ttl: u32) -> std::io::Result<()> {
+        self.sock.set_multicast_ttl_v4(ttl)
     }
 
     /// Receives and deserializes a client message from the underlying socket.
@@ -75,53 +248,95 @@ impl ServerSocket {
     fn recv<'a>(
         &self,
         buf: &'a mut [u8],
-    ) -> std::io::Result<(
-        SocketAddr,
-        Option<(syfala_proto::message::Client, &'a [u8])>,
-    )> {
+    ) -> std::io::Result<(SocketAddr, Option<DecodedClientMessage<'a>>)> {
         self.sock.recv_from(buf).map(|(n, client_addr)| {
             let buf = &buf[..n];
+            self.observer.datagram_received(client_addr, n);
 
-            (client_addr, crate::client_message_decode(buf).ok())
+            let maybe_msg = crate::client_message_decode(buf)
+                .ok()
+                .map(|(msg, _n_decoded, rem_buf)| (msg, rem_buf));
+
+            match &maybe_msg {
+                Some((msg, _)) => self.observer.message_received(MessageKind::of_client(msg)),
+                None => self.observer.decode_failed(client_addr),
+            }
+
+            (client_addr, maybe_msg)
         })
     }
 }
 
 /// Encapsulates server-side protocol state and message handling.
-/// 
+///
 /// Implementors of this trait define how the server reacts to incoming client
 /// messages. The provided [`start`](ServerState::start) method runs a blocking receive
 /// loop and dispatches messages to [`on_message`](ServerState::on_message).
-/// 
+///
 /// This design allows applications to cleanly separate networking concerns from
 /// higher-level protocol logic.
+///
+/// IO state change requests must be handled idempotently, see
+/// [`RequestIOStateChange`](syfala_proto::message::client::Control::RequestIOStateChange).
 pub trait ServerState {
+    /// Size of the receive buffer allocated by [`start`](ServerState::start), datagrams
+    /// larger than this are truncated.
+    const RECV_BUF_LEN: usize = 5000;
+
     /// Called on every received datagram.
     ///
     /// The `message` parameter is `None` if the datagram could not be decoded as a
     /// valid protocol message.
     fn on_message(
         &mut self,
-        server: &ServerSocket,
+        server: &ServerSocket<impl WireObserver>,
         client_addr: core::net::SocketAddr,
         message: Option<(syfala_proto::message::Client, &[u8])>,
     ) -> std::io::Result<()>;
 
+    /// Called when the socket's receive timeout (see
+    /// [`ServerSocket::set_recv_timeout`]) elapses without any datagram being received.
+    ///
+    /// Can be used to expire clients, or for any periodic housekeeping. Does nothing by
+    /// default.
+    #[inline(always)]
+    fn on_timeout(&mut self, server: &ServerSocket<impl WireObserver>) -> std::io::Result<()> {
+        let _ = server;
+        Ok(())
+    }
+
     /// Starts the server receive loop.
-    /// 
+    ///
     /// This function blocks indefinitely, receiving datagrams and invoking
-    /// [`on_message`](ServerState::on_message) for each one.
-    /// 
+    /// [`on_message`](ServerState::on_message) for each one, or
+    /// [`on_timeout`](ServerState::on_timeout) when the receive timeout elapses.
+    ///
+    /// Receives into a buffer of [`RECV_BUF_LEN`](ServerState::RECV_BUF_LEN) bytes,
+    /// allocated once, see [`start_with_buf`](ServerState::start_with_buf) to provide
+    /// your own.
+    ///
     /// The function only returns if a non-recoverable I/O error occurs.
-    fn start(&mut self, server: &ServerSocket) -> std::io::Result<Infallible> {
-        let mut buf = [0; 5000];
+    fn start(&mut self, server: &ServerSocket<impl WireObserver>) -> std::io::Result<Infallible> {
+        let mut buf = vec![0; Self::RECV_BUF_LEN];
+
+        self.start_with_buf(server, &mut buf)
+    }
 
+    /// Same as [`start`](ServerState::start), but receives into `buf`.
+    fn start_with_buf(
+        &mut self,
+        server: &ServerSocket<impl WireObserver>,
+        buf: &mut [u8],
+    ) -> std::io::Result<Infallible> {
         loop {
-            let res = server.recv(&mut buf);
+            let res = server.recv(buf);
 
             let (peer_addr, maybe_msg) = match res {
                 Ok(r) => r,
-                Err(e) if crate::io_err_is_timeout(e.kind()) => continue,
+                Err(e) if crate::io_err_is_timeout(e.kind()) => {
+                    self.on_timeout(server)?;
+                    continue;
+                }
                 Err(e) => return Err(e),
             };
 
@@ -129,3 +344,49 @@ pub trait ServerState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::mem::MaybeUninit;
+    use syfala_proto::message::{Capabilities, Server};
+
+    #[test]
+    fn send_msg_loopback() {
+        let bind = || std::net::UdpSocket::bind((core::net::Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let (server, rx) = (ServerSocket::new(bind()), bind());
+        rx.set_read_timeout(Some(core::time::Duration::from_secs(1)))
+            .unwrap();
+        let rx_addr = rx.local_addr().unwrap();
+
+        let formats = syfala_proto::format::StreamFormats {
+            inputs: vec![Default::default(); 2].into(),
+            outputs: vec![Default::default()].into(),
+        };
+
+        let msgs = [
+            Server::HEARTBEAT,
+            Server::STOP_IO_REFUSED,
+            Server::Connect {
+                epoch: 42,
+                capabilities: Capabilities::SELECTIVE_START,
+                formats,
+            },
+        ];
+
+        for msg in msgs {
+            // filled with garbage, only the encoded message may be sent
+            let mut buf = [MaybeUninit::new(0xa5); 128];
+            server.send_msg(msg.clone(), rx_addr, &mut buf).unwrap();
+
+            let mut datagram = [0; 256];
+            let (n, _) = rx.recv_from(&mut datagram).unwrap();
+            let datagram = &datagram[..n];
+
+            let encoded = crate::server_message_encode(msg.clone(), vec![]).unwrap();
+            assert_eq!(datagram, encoded);
+            let decoded = crate::server_message_decode(datagram).unwrap();
+            assert_eq!(decoded, (msg, n, &[][..]));
+        }
+    }
+}
@@ -159,6 +159,196 @@ impl Format {
                 .unwrap()
         })
     }
+
+    /// Lowest sample rate accepted by [`FormatBuilder::build`], in Hz.
+    pub const MIN_SAMPLE_RATE: f64 = 1e3;
+
+    /// Highest sample rate accepted by [`FormatBuilder::build`], in Hz.
+    pub const MAX_SAMPLE_RATE: f64 = 1.536e6;
+
+    /// Highest channel count accepted by [`FormatBuilder::build`].
+    pub const MAX_CHANNEL_COUNT: u32 = 4096;
+
+    /// Returns a new [`FormatBuilder`], initialized with the [standard](Self::standard)
+    /// format.
+    #[inline(always)]
+    pub const fn builder() -> FormatBuilder {
+        FormatBuilder::new()
+    }
+
+    /// Returns a validated, [`IEEF32`](SampleType::IEEF32), stereo format with the given
+    /// sample rate, and no buffer size hint.
+    #[inline(always)]
+    pub fn f32_stereo(sample_rate: f64) -> Result<Self, FormatError> {
+        Self::builder()
+            .sample_rate(sample_rate)
+            .channel_count(2)
+            .buffer_size(0)
+            .sample_type(SampleType::IEEF32)
+            .build()
+    }
+
+    /// Returns a validated, integer, format with the given sample type, channel count
+    /// and sample rate, and no buffer size hint.
+    ///
+    /// Fails with [`FormatError::SampleType`] if `sample_type` is floating-point.
+    #[inline(always)]
+    pub fn integer(
+        sample_type: SampleType,
+        channel_count: u32,
+        sample_rate: f64,
+    ) -> Result<Self, FormatError> {
+        if sample_type.is_float() {
+            return Err(FormatError::SampleType);
+        }
+
+        Self::builder()
+            .sample_rate(sample_rate)
+            .channel_count(channel_count)
+            .buffer_size(0)
+            .sample_type(sample_type)
+            .build()
+    }
+
+    /// Returns the number of bytes per second of audio in this format.
+    #[inline(always)]
+    pub fn bytes_per_second(&self) -> f64 {
+        let frame_size =
+            u64::from(self.channel_count.0.get()) * u64::from(self.sample_type.sample_size().get());
+
+        // frame_size is at most 2^35, exactly representable in an f64
+        *self.sample_rate.get() * frame_size as f64
+    }
+
+    /// Returns whether this format requires more than `threshold` bytes per second.
+    #[inline(always)]
+    pub fn is_high_bandwidth(&self, threshold: f64) -> bool {
+        self.bytes_per_second() > threshold
+    }
+}
+
+/// Error returned when building an invalid [`Format`].
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub enum FormatError {
+    /// The sample rate isn't normal and positive, or is outside of
+    /// [`Format::MIN_SAMPLE_RATE`]`..=`[`Format::MAX_SAMPLE_RATE`].
+    SampleRate,
+    /// The channel count is zero, or greater than [`Format::MAX_CHANNEL_COUNT`].
+    ChannelCount,
+    /// The size of a buffer, in bytes, doesn't fit in a `u32`.
+    BufferSizeOverflow,
+    /// The sample type isn't supported by the constructor used.
+    SampleType,
+}
+
+impl fmt::Display for FormatError {
+    #[inline(always)]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::SampleRate => write!(
+                f,
+                "Sample rate must be between {} and {} Hz",
+                Format::MIN_SAMPLE_RATE,
+                Format::MAX_SAMPLE_RATE,
+            ),
+            Self::ChannelCount => write!(
+                f,
+                "Channel count must be between 1 and {}",
+                Format::MAX_CHANNEL_COUNT,
+            ),
+            Self::BufferSizeOverflow => write!(f, "Buffer size in bytes overflows a u32"),
+            Self::SampleType => write!(f, "Unsupported sample type"),
+        }
+    }
+}
+
+/// Builder for validated [`Format`]s.
+///
+/// See [`Format::builder`].
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct FormatBuilder {
+    sample_rate: f64,
+    channel_count: u32,
+    buffer_size: u32,
+    sample_type: SampleType,
+}
+
+impl Default for FormatBuilder {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FormatBuilder {
+    /// Returns a new builder, initialized with the [standard](Format::standard) format.
+    #[inline(always)]
+    pub const fn new() -> Self {
+        let Format {
+            sample_rate,
+            channel_count,
+            buffer_size,
+            sample_type,
+        } = Format::standard();
+
+        Self {
+            sample_rate: *sample_rate.get(),
+            channel_count: channel_count.0.get(),
+            buffer_size: buffer_size.0,
+            sample_type,
+        }
+    }
+
+    /// Sets the sample rate, in Hz.
+    #[inline(always)]
+    pub const fn sample_rate(mut self, sample_rate: f64) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// Sets the channel count.
+    #[inline(always)]
+    pub const fn channel_count(mut self, channel_count: u32) -> Self {
+        self.channel_count = channel_count;
+        self
+    }
+
+    /// Sets the buffer size hint, in frames. Zero means no hint.
+    #[inline(always)]
+    pub const fn buffer_size(mut self, buffer_size: u32) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Sets the sample type.
+    #[inline(always)]
+    pub const fn sample_type(mut self, sample_type: SampleType) -> Self {
+        self.sample_type = sample_type;
+        self
+    }
+
+    /// Validates the parameters, and builds the corresponding [`Format`].
+    pub fn build(self) -> Result<Format, FormatError> {
+        let sample_rate = SampleRate::new(self.sample_rate)
+            .filter(|r| (Format::MIN_SAMPLE_RATE..=Format::MAX_SAMPLE_RATE).contains(r.get()))
+            .ok_or(FormatError::SampleRate)?;
+
+        let channel_count = num::NonZeroU32::new(self.channel_count)
+            .filter(|&n| n.get() <= Format::MAX_CHANNEL_COUNT)
+            .ok_or(FormatError::ChannelCount)?;
+
+        self.buffer_size
+            .checked_mul(channel_count.get())
+            .and_then(|n| n.checked_mul(self.sample_type.sample_size().get().into()))
+            .ok_or(FormatError::BufferSizeOverflow)?;
+
+        Ok(Format {
+            sample_rate,
+            channel_count: ChannelCount(channel_count),
+            buffer_size: BufferSize(self.buffer_size),
+            sample_type: self.sample_type,
+        })
+    }
 }
 
 /// Describes all input and output stream formats of a server.
@@ -179,3 +369,94 @@ impl AsRef<StreamFormats> for StreamFormats {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_defaults_to_standard() {
+        assert_eq!(Format::builder().build(), Ok(Format::standard()));
+    }
+
+    #[test]
+    fn sample_rate_bounds() {
+        let build = |rate: f64| Format::builder().sample_rate(rate).build();
+
+        assert!(build(Format::MIN_SAMPLE_RATE).is_ok());
+        assert!(build(Format::MAX_SAMPLE_RATE).is_ok());
+
+        for rate in [
+            Format::MIN_SAMPLE_RATE.next_down(),
+            Format::MAX_SAMPLE_RATE.next_up(),
+            0.,
+            -48e3,
+            f64::NAN,
+            f64::INFINITY,
+            f64::MIN_POSITIVE / 2.,
+        ] {
+            assert_eq!(build(rate), Err(FormatError::SampleRate), "{rate}");
+        }
+    }
+
+    #[test]
+    fn channel_count_bounds() {
+        let build = |n: u32| Format::builder().channel_count(n).build();
+
+        assert!(build(1).is_ok());
+        assert!(build(Format::MAX_CHANNEL_COUNT).is_ok());
+        assert_eq!(build(0), Err(FormatError::ChannelCount));
+        assert_eq!(
+            build(Format::MAX_CHANNEL_COUNT + 1),
+            Err(FormatError::ChannelCount)
+        );
+    }
+
+    #[test]
+    fn buffer_size_overflow_boundary() {
+        // 4096 channels of 8 byte samples: 2^15 bytes per frame
+        let build = |buffer_size: u32| {
+            Format::builder()
+                .channel_count(Format::MAX_CHANNEL_COUNT)
+                .sample_type(SampleType::I64)
+                .buffer_size(buffer_size)
+                .build()
+        };
+
+        let max = u32::MAX >> 15;
+        let format = build(max).unwrap();
+        assert_eq!(format.chunk_size_bytes().unwrap().get(), max << 15);
+        assert_eq!(build(max + 1), Err(FormatError::BufferSizeOverflow));
+        assert_eq!(build(u32::MAX), Err(FormatError::BufferSizeOverflow));
+    }
+
+    #[test]
+    fn constructors() {
+        let format = Format::f32_stereo(44.1e3).unwrap();
+        assert_eq!(*format.sample_rate.get(), 44.1e3);
+        assert_eq!(format.channel_count.0.get(), 2);
+        assert_eq!(format.buffer_size, BufferSize(0));
+        assert_eq!(format.sample_type, SampleType::IEEF32);
+        assert_eq!(Format::f32_stereo(0.), Err(FormatError::SampleRate));
+
+        let format = Format::integer(SampleType::I24, 8, 96e3).unwrap();
+        assert_eq!(format.channel_count.0.get(), 8);
+        assert_eq!(format.sample_type, SampleType::I24);
+        assert_eq!(
+            Format::integer(SampleType::IEEF64, 2, 48e3),
+            Err(FormatError::SampleType)
+        );
+        assert_eq!(
+            Format::integer(SampleType::U8, 0, 48e3),
+            Err(FormatError::ChannelCount)
+        );
+    }
+
+    #[test]
+    fn bandwidth() {
+        let format = Format::standard();
+        assert_eq!(format.bytes_per_second(), 384e3);
+        assert!(format.is_high_bandwidth(383e3));
+        assert!(!format.is_high_bandwidth(384e3));
+    }
+}
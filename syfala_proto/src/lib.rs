@@ -126,9 +126,9 @@ impl AudioStreamMessageHeader {
 /// Stream indices are interpreted differently depending on the sender:
 ///
 /// - **Clients** specify the index of the server’s **output** stream. Servers must
-/// associate it, in incoming audio messages, with one of their **output** streams.
+///   associate it, in incoming audio messages, with one of their **output** streams.
 /// - **Servers** specify the index of on of their **input** streams. Clients must
-/// associate it, in incoming audio messages, with one the server's **output** streams
+///   associate it, in incoming audio messages, with one the server's **output** streams
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct AudioMessageHeader {
     pub stream_idx: u32,
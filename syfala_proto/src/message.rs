@@ -16,6 +16,61 @@ pub enum IOState<T = (), U = ()> {
     Stop(U),
 }
 
+/// The input streams of a server (i.e. those it sends audio on) an IO start request asks
+/// for.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum StreamSelection {
+    /// All streams.
+    #[default]
+    All,
+    /// Only the streams whose bit is set, the least significant bit being stream `0`.
+    /// Streams with an index of 64 or more are never selected.
+    Mask(u64),
+}
+
+impl StreamSelection {
+    /// Returns `true` if the stream at `stream_idx` is selected.
+    #[inline(always)]
+    pub const fn contains(&self, stream_idx: u32) -> bool {
+        match *self {
+            Self::All => true,
+            Self::Mask(mask) => stream_idx < u64::BITS && mask & (1 << stream_idx) != 0,
+        }
+    }
+}
+
+/// Optional protocol features supported by a server, advertised in its connection
+/// requests, as a set of bit flags.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct Capabilities(pub u32);
+
+impl Capabilities {
+    /// No optional features.
+    pub const NONE: Self = Self(0);
+    /// IO start requests may select a subset of the server's streams, see
+    /// [`StreamSelection`]. Otherwise, clients must request all streams.
+    pub const SELECTIVE_START: Self = Self(1);
+    /// Connection requests carry the server's epoch, clients can thus tell its restarts
+    /// apart. Otherwise, the epoch is meaningless.
+    pub const EPOCH: Self = Self(2);
+
+    /// Returns `true` if all the features in `other` are supported.
+    #[inline(always)]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns the features supported by either `self` or `other`.
+    #[inline(always)]
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
 /// A generic error message used during connection or control handling.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum Error<Fail = (), Refuse = ()> {
@@ -35,11 +90,21 @@ pub mod client {
         ///
         /// # Note
         ///
-        /// When starting IO, both sides must expect **all** advertised input
-        /// and output streams to become active _simultaneously_, and for as long as IO is active.
-        RequestIOStateChange(IOState),
+        /// When starting IO, both sides must expect **all** advertised output streams,
+        /// and all _selected_ input streams, to become active _simultaneously_, and for
+        /// as long as IO is active. Clients may only select a subset of the server's
+        /// input streams if it advertised [`Capabilities::SELECTIVE_START`].
+        ///
+        /// Requests are idempotent: servers must respond to a request for their current
+        /// IO state (e.g. a start request while IO is already active) with an immediate
+        /// success. Clients resend unanswered requests, and accept a success whenever
+        /// their request is pending, this recovers from lost responses.
+        RequestIOStateChange(IOState<StreamSelection>),
         /// Sent periodically to notify the server that our connection to them is still active.
         Heartbeat,
+        /// Asks the server for its [`Status`](super::server::Status), e.g. to
+        /// resynchronize after the client restarted.
+        QueryStatus,
     }
 
     /// Messages sent by a client after a connection is established.
@@ -71,16 +136,22 @@ pub enum Client {
 impl Client {
     pub const HEARTBEAT: Self =
         Self::Connected(client::Connected::Control(client::Control::Heartbeat));
-    pub const START_IO: Self = Self::Connected(client::Connected::Control(
-        client::Control::RequestIOStateChange(IOState::Start(())),
-    ));
+    pub const START_IO: Self = Self::start_io(StreamSelection::All);
     pub const STOP_IO: Self = Self::Connected(client::Connected::Control(
         client::Control::RequestIOStateChange(IOState::Stop(())),
     ));
+    pub const QUERY_STATUS: Self =
+        Self::Connected(client::Connected::Control(client::Control::QueryStatus));
     pub const CONN_SUCCESS: Self = Self::ConnectionResult(Ok(()));
     pub const CONN_FAILED: Self = Self::ConnectionResult(Err(Error::Failure(())));
     pub const CONN_REFUSED: Self = Self::ConnectionResult(Err(Error::Refusal(())));
 
+    pub const fn start_io(selection: StreamSelection) -> Self {
+        Self::Connected(client::Connected::Control(
+            client::Control::RequestIOStateChange(IOState::Start(selection)),
+        ))
+    }
+
     pub const fn audio(header: crate::AudioMessageHeader) -> Self {
         Self::Connected(client::Connected::Audio(header))
     }
@@ -89,6 +160,19 @@ impl Client {
 pub mod server {
     use super::*;
 
+    /// A server's current state, sent in response to a status query.
+    #[derive(
+        Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+    )]
+    pub struct Status {
+        /// Whether IO is currently active with the querying client.
+        pub io_active: bool,
+        /// Time elapsed since the server started, in milliseconds.
+        pub uptime_ms: u64,
+        /// Number of streams currently active with the querying client.
+        pub active_streams: u32,
+    }
+
     /// Control messages sent from a server to a connected client.
     #[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
     pub enum Control {
@@ -96,6 +180,8 @@ pub mod server {
         IOStateChangeResult(IOState<Result<(), Error>, Result<(), Error>>),
         /// Sent periodically to notify the server that our connection to them is still active.
         Heartbeat,
+        /// Response to a client status query.
+        Status(Status),
     }
 
     /// Messages sent by a server after a connection is established.
@@ -116,7 +202,24 @@ pub enum Server {
     /// Requests to connect to a (assumed to be known) client.
     ///
     /// Do not send this over broadcast addresses.
-    Connect(crate::format::StreamFormats),
+    Connect {
+        /// Random value generated by the server when it starts, and sent in all of its
+        /// connection requests.
+        ///
+        /// A client receiving a different epoch from an already connected server knows
+        /// that it has restarted, and that the previous session is gone.
+        ///
+        /// Only meaningful if `capabilities` contain [`Capabilities::EPOCH`]. Connection
+        /// requests without capabilities don't carry it at all, for clients predating
+        /// epochs to decode them, it is then decoded as `0`.
+        epoch: u32,
+        /// Optional features supported by the server.
+        ///
+        /// Clients predating capabilities can't decode connection requests advertising
+        /// any.
+        capabilities: Capabilities,
+        formats: crate::format::StreamFormats,
+    },
     /// Messages sent after a connection is established.
     Connected(server::Connected),
     /// Sent to indicate that a connection has been terminated.
@@ -145,7 +248,47 @@ impl Server {
         server::Control::IOStateChangeResult(IOState::Stop(Err(Error::Refusal(())))),
     ));
 
+    pub const fn status(status: server::Status) -> Self {
+        Self::Connected(server::Connected::Control(server::Control::Status(status)))
+    }
+
     pub const fn audio(header: crate::AudioMessageHeader) -> Self {
         Server::Connected(server::Connected::Audio(header))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_selection_contains() {
+        let mask = StreamSelection::Mask(1 | 1 << 5 | 1 << 63);
+
+        for idx in [0, 5, 63] {
+            assert!(mask.contains(idx), "{idx}");
+        }
+        for idx in [1, 4, 6, 62, 64, u32::MAX] {
+            assert!(!mask.contains(idx), "{idx}");
+        }
+
+        // streams past the mask's width are never selected, even by a full mask
+        assert!(!StreamSelection::Mask(u64::MAX).contains(64));
+        assert!(!StreamSelection::Mask(0).contains(0));
+        assert!(StreamSelection::All.contains(u32::MAX));
+    }
+
+    #[test]
+    fn capabilities() {
+        let selective = Capabilities::SELECTIVE_START;
+        let other = Capabilities(1 << 7);
+
+        assert!(Capabilities::NONE.contains(Capabilities::NONE));
+        assert!(!Capabilities::NONE.contains(selective));
+        assert!(selective.contains(Capabilities::NONE));
+
+        let both = selective.union(other);
+        assert!(both.contains(selective) && both.contains(other));
+        assert!(!selective.contains(both));
+    }
+}
@@ -0,0 +1,99 @@
+//! Sending and receiving blocks of samples through the indexed ring buffer adapters, as
+//! done between the network and audio threads.
+//!
+//! Each block is transferred both sample by sample, through iterators, and in bulk,
+//! through slices, to compare both paths.
+
+use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
+use syfala_utils::queue::{GenericCounter, IndexedRx, IndexedTx, rtrb};
+
+const BLOCK_LENS: [usize; 2] = [64, 1024];
+
+/// Ring buffer capacity, a few blocks of the largest size.
+const CAPACITY: usize = 4 * 1024;
+
+fn bench_send(c: &mut Criterion) {
+    let mut group = c.benchmark_group("indexed_tx_send");
+
+    for block_len in BLOCK_LENS {
+        let block = vec![0f32; block_len];
+        group.throughput(Throughput::Elements(block_len as u64));
+
+        group.bench_function(BenchmarkId::new("per_sample", block_len), |b| {
+            let (tx, mut rx) = rtrb::RingBuffer::new(CAPACITY);
+            let mut tx = IndexedTx::new(tx, GenericCounter::new());
+
+            b.iter(|| {
+                let idx = tx.current();
+                tx.send(idx, black_box(block.iter().copied()), || 0.)
+                    .unwrap();
+
+                // drain, without going through an adapter
+                rx.read_chunk(rx.slots()).unwrap().commit_all();
+            })
+        });
+
+        group.bench_function(BenchmarkId::new("slice", block_len), |b| {
+            let (tx, mut rx) = rtrb::RingBuffer::new(CAPACITY);
+            let mut tx = IndexedTx::new(tx, GenericCounter::new());
+
+            b.iter(|| {
+                let idx = tx.current();
+                tx.send_slice(idx, black_box(&block), 0.).unwrap();
+
+                // same as above
+                rx.read_chunk(rx.slots()).unwrap().commit_all();
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_recv(c: &mut Criterion) {
+    let mut group = c.benchmark_group("indexed_rx_recv");
+
+    for block_len in BLOCK_LENS {
+        let block = vec![0f32; block_len];
+        group.throughput(Throughput::Elements(block_len as u64));
+
+        group.bench_function(BenchmarkId::new("per_sample", block_len), |b| {
+            let (mut tx, rx) = rtrb::RingBuffer::new(CAPACITY);
+            let mut rx = IndexedRx::new(rx, GenericCounter::new());
+
+            b.iter(|| {
+                // fill, without going through an adapter
+                tx.write_chunk_uninit(block_len)
+                    .unwrap()
+                    .fill_from_iter(block.iter().copied());
+
+                let idx = rx.current();
+                rx.recv(idx, || 0.).unwrap().into_iter().for_each(|s| {
+                    black_box(s);
+                });
+            })
+        });
+
+        group.bench_function(BenchmarkId::new("slice", block_len), |b| {
+            let (mut tx, rx) = rtrb::RingBuffer::new(CAPACITY);
+            let mut rx = IndexedRx::new(rx, GenericCounter::new());
+            let mut out = vec![0f32; block_len];
+
+            b.iter(|| {
+                // same as above
+                tx.write_chunk_uninit(block_len)
+                    .unwrap()
+                    .fill_from_iter(block.iter().copied());
+
+                let idx = rx.current();
+                rx.recv_into(idx, &mut out, 0.).unwrap();
+                black_box(&out);
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_send, bench_recv);
+criterion_main!(benches);
@@ -0,0 +1,74 @@
+//! Conversion between streams of samples and streams of bytes, as done on both ends of
+//! every audio stream.
+
+use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
+use syfala_utils::{
+    AudioPacketSamplePadder, SampleByteStream, SampleFromBytes, SampleToBytes, SampleTypeSilence,
+};
+
+/// Number of samples fed to a [`SampleByteStream`] at once.
+const N_SAMPLES: usize = 1024;
+
+/// Size of the packets fed to an [`AudioPacketSamplePadder`], that of a typical audio
+/// message's payload.
+const PACKET_LEN: usize = 1400;
+
+fn bench_byte_stream<T: SampleToBytes + SampleTypeSilence + Copy>(c: &mut Criterion, name: &str) {
+    let samples = [T::SILENCE; N_SAMPLES];
+    let n_bytes = N_SAMPLES * usize::from(T::SIZE.get());
+
+    let mut group = c.benchmark_group("sample_byte_stream");
+    group.throughput(Throughput::Bytes(n_bytes as u64));
+
+    group.bench_function(BenchmarkId::from_parameter(name), |b| {
+        let mut stream = SampleByteStream::<T>::new();
+        let mut out = vec![0; n_bytes];
+
+        b.iter(|| {
+            let bytes = stream.feed_samples(black_box(samples));
+            for (dst, src) in out.iter_mut().zip(bytes) {
+                *dst = src;
+            }
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_padder<T: SampleFromBytes + SampleTypeSilence>(c: &mut Criterion, name: &str) {
+    let packet = [0x55; PACKET_LEN];
+    // an integer number of samples, so that consecutive packets are contiguous
+    let packet_len = PACKET_LEN - PACKET_LEN % usize::from(T::SIZE.get());
+
+    let mut group = c.benchmark_group("audio_packet_sample_padder");
+    group.throughput(Throughput::Bytes(packet_len as u64));
+
+    group.bench_function(BenchmarkId::from_parameter(name), |b| {
+        let mut padder = AudioPacketSamplePadder::<T>::new();
+        let mut byte_idx = 0u64;
+
+        b.iter(|| {
+            let samples = padder.feed_bytes(
+                byte_idx,
+                black_box(&packet[..packet_len]).iter().copied(),
+                || T::SILENCE,
+            );
+            samples.into_iter().for_each(|s| {
+                black_box(s);
+            });
+            byte_idx += packet_len as u64;
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_samples(c: &mut Criterion) {
+    bench_byte_stream::<f32>(c, "f32");
+    bench_byte_stream::<i16>(c, "i16");
+    bench_padder::<f32>(c, "f32");
+    bench_padder::<i16>(c, "i16");
+}
+
+criterion_group!(benches, bench_samples);
+criterion_main!(benches);
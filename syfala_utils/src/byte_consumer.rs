@@ -49,6 +49,34 @@ impl<T> SampleSink for rtrb::Producer<T> {
 // without NIGHTLY: #[feature(min_generic_const_args)]
 // So, yes, the following feels a bit hacky
 
+/// Determines how a padder handles the first packet it receives.
+///
+/// A receiver joining an already running stream receives a first packet with an arbitrarily
+/// large byte index. Padding everything before it is rarely what is wanted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StartPolicy {
+    /// The byte index of the first packet, aligned down to a sample boundary, becomes
+    /// the start of the stream. Nothing is padded before it.
+    #[default]
+    AnchorToFirst,
+    /// The stream is assumed to start at byte index `0`, any missing data before the
+    /// first packet is padded.
+    PadFromZero,
+}
+
+/// Counters describing how much of a stream a padder reconstructed, versus padded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PadderStats {
+    /// Bytes that made it into reconstructed samples (or frames).
+    pub bytes_consumed: u64,
+    /// Bytes of packets arriving before the expected byte index, which were discarded.
+    pub reordered_bytes: u64,
+    /// Padding samples inserted in place of missing data.
+    pub padding_samples: u64,
+    /// Number of gaps in the byte stream, i.e. the number of times padding was inserted.
+    pub discontinuities: u64,
+}
+
 /// Stateful adapter that reconstructs samples from indexed byte streams.
 ///
 /// The padder tracks the global byte index and inserts padding samples
@@ -57,6 +85,15 @@ impl<T> SampleSink for rtrb::Producer<T> {
 pub struct AudioPacketSamplePadder<T: SampleFromBytes> { // name bikeshedding welcome
     /// Current global byte index expected by the stream.
     current_byte_idx: u64,
+    /// Byte index of the start of the stream, set when the first packet is received.
+    anchor_byte_idx: Option<u64>,
+    /// How the first packet's byte index is handled.
+    start_policy: StartPolicy,
+    /// Maximum number of padding samples inserted for a single gap, `None` if unlimited.
+    max_pad_samples: Option<u64>,
+    /// Number of gaps for which padding was clamped to `max_pad_samples`.
+    n_clamped_gaps: u64,
+    stats: PadderStats,
     /// Buffer holding the bytes of the partially reconstructed sample.
     ///
     /// Invariant: its length is always equal to `T::SIZE`.
@@ -72,18 +109,119 @@ impl<T: SampleFromBytes> Default for AudioPacketSamplePadder<T> {
 }
 
 impl<T: SampleFromBytes> AudioPacketSamplePadder<T> {
-    /// Create a new `AudioPacketSamplePadder`.
+    /// Create a new `AudioPacketSamplePadder`, with the default [`StartPolicy`].
     ///
-    /// The padder starts at byte index `0` with an empty sample buffer.
+    /// The padder starts with an empty sample buffer.
     #[inline(always)]
     pub fn new() -> Self {
+        Self::with_start_policy(StartPolicy::default())
+    }
+
+    /// Create a new `AudioPacketSamplePadder` with the given [`StartPolicy`].
+    ///
+    /// The padder starts with an empty sample buffer.
+    #[inline(always)]
+    pub fn with_start_policy(start_policy: StartPolicy) -> Self {
         Self {
             current_byte_idx: 0,
+            anchor_byte_idx: None,
+            start_policy,
+            max_pad_samples: None,
+            n_clamped_gaps: 0,
+            stats: PadderStats::default(),
             current_sample_bytes: iter::repeat_n(0, usize::from(T::SIZE.get())).collect(),
             _marker: marker::PhantomData,
         }
     }
 
+    /// Limits the number of padding samples inserted for a single gap to `max`.
+    ///
+    /// When a packet arrives after a longer gap (e.g. the sender paused for a while),
+    /// exactly `max` padding samples are inserted, and the stream resynchronizes to the
+    /// packet's byte index. Such events are counted, see
+    /// [`n_clamped_gaps`](Self::n_clamped_gaps).
+    ///
+    /// By default, the amount of padding is unlimited.
+    #[inline(always)]
+    pub fn with_max_pad_samples(mut self, max: u64) -> Self {
+        self.max_pad_samples = Some(max);
+        self
+    }
+
+    /// Returns the [`StartPolicy`] of this padder.
+    #[inline(always)]
+    pub fn start_policy(&self) -> StartPolicy {
+        self.start_policy
+    }
+
+    /// Returns the maximum number of padding samples inserted for a single gap, if
+    /// any.
+    #[inline(always)]
+    pub fn max_pad_samples(&self) -> Option<u64> {
+        self.max_pad_samples
+    }
+
+    /// Returns the number of gaps for which padding was clamped to
+    /// [`max_pad_samples`](Self::max_pad_samples), i.e. the number of times the stream
+    /// was resynchronized.
+    #[inline(always)]
+    pub fn n_clamped_gaps(&self) -> u64 {
+        self.n_clamped_gaps
+    }
+
+    /// Returns the counters accumulated since creation, or since the last call to
+    /// [`reset_stats`](Self::reset_stats).
+    ///
+    /// Padding is accounted for when a packet is fed, bytes only once the returned
+    /// iterator reaches them.
+    #[inline(always)]
+    pub fn stats(&self) -> PadderStats {
+        self.stats
+    }
+
+    /// Resets all counters returned by [`stats`](Self::stats) to zero.
+    #[inline(always)]
+    pub fn reset_stats(&mut self) {
+        self.stats = PadderStats::default();
+    }
+
+    /// Returns the global byte index expected by the next packet.
+    #[inline(always)]
+    pub fn current_byte_idx(&self) -> u64 {
+        self.current_byte_idx
+    }
+
+    /// Returns the byte index the stream has been anchored to, i.e. the byte index of
+    /// the first sample produced by this padder.
+    ///
+    /// Returns `None` if no packet has been fed yet.
+    #[inline(always)]
+    pub fn anchor_byte_idx(&self) -> Option<u64> {
+        self.anchor_byte_idx
+    }
+
+    /// Same as [`anchor_byte_idx`](Self::anchor_byte_idx), but expressed in samples.
+    #[inline(always)]
+    pub fn anchor_sample_idx(&self) -> Option<u64> {
+        self.anchor_byte_idx
+            .map(|idx| idx / num::NonZeroU64::from(T::SIZE))
+    }
+
+    /// Resynchronizes the padder to `byte_idx`, e.g. after the stream was restarted.
+    ///
+    /// Any partially reconstructed sample is discarded, and the stream is re-anchored
+    /// to `byte_idx`, aligned down to a sample boundary. The next packet is then
+    /// expected at that index, packets before it are discarded, and gaps after it
+    /// are padded as usual.
+    #[inline(always)]
+    pub fn resync_to(&mut self, byte_idx: u64) {
+        let anchor = byte_idx.strict_sub(byte_idx % num::NonZeroU64::from(T::SIZE));
+
+        self.anchor_byte_idx = Some(anchor);
+        self.current_byte_idx = anchor;
+        self.current_sample_bytes.fill(0);
+    }
+
     /// Feed a packet of bytes into the padder and obtain reconstructed samples.
     ///
     /// The provided `byte_idx` indicates the starting position of the byte
@@ -92,6 +230,11 @@ impl<T: SampleFromBytes> AudioPacketSamplePadder<T> {
     ///
     /// Bytes that belong to incomplete samples are buffered internally until
     /// enough data is available to reconstruct a full sample.
+    ///
+    /// Packets arriving before the expected index (e.g. reordered packets) are
+    /// discarded, yielding nothing, and leaving the padder's state untouched.
+    ///
+    /// The first packet ever fed is handled according to the padder's [`StartPolicy`].
     #[inline(always)]
     pub fn feed_bytes(
         &mut self,
@@ -102,29 +245,59 @@ impl<T: SampleFromBytes> AudioPacketSamplePadder<T> {
         let sample_size = num::NonZeroUsize::from(T::SIZE).get();
         assert_eq!(sample_size, self.current_sample_bytes.len());
 
+        let bps = num::NonZeroU64::from(T::SIZE);
+
+        if self.anchor_byte_idx.is_none() {
+            let anchor = match self.start_policy {
+                StartPolicy::AnchorToFirst => byte_idx.strict_sub(byte_idx % bps),
+                StartPolicy::PadFromZero => 0,
+            };
+
+            self.anchor_byte_idx = Some(anchor);
+            self.current_byte_idx = anchor;
+        }
+
+        let mut bytes = bytes.into_iter();
+
+        // `None` if the packet must be discarded entirely
         let (n_padding_spls, n_skipped_bytes) = match byte_idx.cmp(&self.current_byte_idx) {
-            // reordered packet, skip all bytes
-            core::cmp::Ordering::Less => (0usize, usize::MAX),
+            // reordered packet, discard it without touching any state, besides counters
+            core::cmp::Ordering::Less => {
+                let n_bytes = u64::try_from(bytes.by_ref().count()).unwrap();
+                self.stats.reordered_bytes = self.stats.reordered_bytes.strict_add(n_bytes);
+                (0usize, None)
+            }
             // correct packet index, don't pad or skip
-            core::cmp::Ordering::Equal => (0, 0),
+            core::cmp::Ordering::Equal => (0, Some(0)),
             core::cmp::Ordering::Greater => {
-                let bps = num::NonZeroU64::from(T::SIZE);
-
                 // previous valid sample index
                 let prev_spl_idx = self.current_byte_idx / bps;
                 // next valid sample index
                 let next_spl_idx = byte_idx.strict_add(bps.get().strict_sub(1)) / bps;
 
-                let n_padding_samples = next_spl_idx.strict_sub(prev_spl_idx);
+                let mut n_padding_samples = next_spl_idx.strict_sub(prev_spl_idx);
+
+                if let Some(max) = self.max_pad_samples
+                    && n_padding_samples > max
+                {
+                    n_padding_samples = max;
+                    self.n_clamped_gaps = self.n_clamped_gaps.strict_add(1);
+                }
 
                 let next_spl_byte_idx = next_spl_idx.strict_mul(bps.get());
 
-                let n_skipped_bytes = next_spl_byte_idx.strict_sub(self.current_byte_idx);
+                // the bytes of this packet preceding the next sample boundary belong to
+                // a sample that is replaced by padding
+                let n_skipped_bytes = next_spl_byte_idx.strict_sub(byte_idx);
                 self.current_byte_idx = next_spl_byte_idx;
 
+                self.stats.padding_samples =
+                    self.stats.padding_samples.strict_add(n_padding_samples);
+                self.stats.discontinuities = self.stats.discontinuities.strict_add(1);
+
                 (
                     n_padding_samples.try_into().unwrap(),
-                    n_skipped_bytes.try_into().unwrap(),
+                    Some(n_skipped_bytes.try_into().unwrap()),
                 )
             }
         };
@@ -135,40 +308,287 @@ impl<T: SampleFromBytes> AudioPacketSamplePadder<T> {
         // also a bit hacky
         // i don't see any way to make this cleaner
         // without using NIGHTLY: #[feature(iter_array_chunks)]
-        let sample_iter = bytes
+        let sample_iter = n_skipped_bytes
+            .map(|n| bytes.skip(n))
             .into_iter()
-            .skip(n_skipped_bytes)
+            .flatten()
             .filter_map(move |byte| {
-                let curr = usize::try_from(self.current_byte_idx % num::NonZeroU64::from(T::SIZE))
-                    .unwrap();
+                let curr = usize::try_from(self.current_byte_idx % bps).unwrap();
 
                 self.current_sample_bytes[curr] = byte;
                 self.current_byte_idx = self.current_byte_idx.strict_add(1);
+                self.stats.bytes_consumed = self.stats.bytes_consumed.strict_add(1);
 
-                if self.current_byte_idx % num::NonZeroU64::from(T::SIZE) != 0 {
+                if self.current_byte_idx % bps != 0 {
                     return None;
                 }
 
-                let this_sample_bytes =
-                    &self.current_sample_bytes[curr.strict_sub(sample_size)..curr];
-
-                Some(T::from_bytes(this_sample_bytes))
+                Some(T::from_bytes(&self.current_sample_bytes))
             });
 
         iter::chain(padding_iter, sample_iter)
     }
 }
 
-// We can also do something like this for frames, if you wish to discard
-// whole frames on byte loss, (as is recommended in the documentation
-// for AudioMessageHeader)
-// 
-// pub struct AudioPacketFramePadder<T: SampleType> {
-//     current_byte_idx: u64,
-//     current_frame_bytes: Box<[[u8 ; T::SIZE]]>,
-// }
-// 
-// but we just keep it simple with samples for now.
+/// Stateful adapter that reconstructs interleaved frames from indexed byte streams.
+///
+/// Like [`AudioPacketSamplePadder`], but operating on whole frames: bytes are buffered
+/// until a full frame is available, and, on byte loss, the entire frame containing the
+/// loss is replaced by padding, as recommended in the documentation for
+/// `syfala_proto`'s `AudioMessageHeader`. Channels thus never get rotated, even after
+/// losses of arbitrary size.
+#[derive(Debug)]
+pub struct AudioPacketFramePadder<T: SampleFromBytes> {
+    /// Current global byte index expected by the stream.
+    current_byte_idx: u64,
+    /// Bytes before this index belong to a frame that has been replaced by padding, and
+    /// are discarded. Always on a frame boundary.
+    ///
+    /// Tracked separately from `current_byte_idx` so that a packet ending before the
+    /// next frame boundary doesn't cause the following, in-order, one to be considered
+    /// reordered.
+    discard_until: u64,
+    /// Byte index of the start of the stream, set when the first packet is received.
+    anchor_byte_idx: Option<u64>,
+    /// How the first packet's byte index is handled.
+    start_policy: StartPolicy,
+    /// Number of interleaved channels per frame.
+    n_channels: num::NonZeroUsize,
+    stats: PadderStats,
+    /// Buffer holding the bytes of the partially reconstructed frame.
+    ///
+    /// Invariant: its length is always equal to `T::SIZE * n_channels`.
+    current_frame_bytes: Box<[u8]>,
+    /// Marker tying the padder to its sample type.
+    _marker: marker::PhantomData<T>,
+}
+
+impl<T: SampleFromBytes> AudioPacketFramePadder<T> {
+    /// Create a new `AudioPacketFramePadder` for frames of `n_channels` interleaved
+    /// samples, with the default [`StartPolicy`].
+    #[inline(always)]
+    pub fn new(n_channels: num::NonZeroUsize) -> Self {
+        Self::with_start_policy(n_channels, StartPolicy::default())
+    }
+
+    /// Create a new `AudioPacketFramePadder` for frames of `n_channels` interleaved
+    /// samples, with the given [`StartPolicy`].
+    #[inline(always)]
+    pub fn with_start_policy(n_channels: num::NonZeroUsize, start_policy: StartPolicy) -> Self {
+        let frame_size = n_channels.get().strict_mul(usize::from(T::SIZE.get()));
+
+        Self {
+            current_byte_idx: 0,
+            discard_until: 0,
+            anchor_byte_idx: None,
+            start_policy,
+            n_channels,
+            stats: PadderStats::default(),
+            current_frame_bytes: iter::repeat_n(0, frame_size).collect(),
+            _marker: marker::PhantomData,
+        }
+    }
+
+    /// Returns the number of interleaved channels per frame.
+    #[inline(always)]
+    pub fn n_channels(&self) -> num::NonZeroUsize {
+        self.n_channels
+    }
+
+    /// Returns the size of a frame, in bytes.
+    #[inline(always)]
+    pub fn frame_size(&self) -> num::NonZeroUsize {
+        num::NonZeroUsize::new(self.current_frame_bytes.len()).unwrap()
+    }
+
+    /// Returns the [`StartPolicy`] of this padder.
+    #[inline(always)]
+    pub fn start_policy(&self) -> StartPolicy {
+        self.start_policy
+    }
+
+    /// Returns the global byte index expected by the next packet.
+    #[inline(always)]
+    pub fn current_byte_idx(&self) -> u64 {
+        self.current_byte_idx
+    }
+
+    /// Returns the byte index the stream has been anchored to, i.e. the byte index of
+    /// the first frame produced by this padder.
+    ///
+    /// Returns `None` if no packet has been fed yet.
+    #[inline(always)]
+    pub fn anchor_byte_idx(&self) -> Option<u64> {
+        self.anchor_byte_idx
+    }
+
+    /// Returns the counters accumulated since creation, or since the last call to
+    /// [`reset_stats`](Self::reset_stats).
+    ///
+    /// Padding is accounted for when a packet is fed, bytes only once the returned
+    /// iterator reaches them.
+    #[inline(always)]
+    pub fn stats(&self) -> PadderStats {
+        self.stats
+    }
+
+    /// Resets all counters returned by [`stats`](Self::stats) to zero.
+    #[inline(always)]
+    pub fn reset_stats(&mut self) {
+        self.stats = PadderStats::default();
+    }
+
+    /// Feed a packet of bytes into the padder and obtain reconstructed, interleaved,
+    /// samples.
+    ///
+    /// The provided `byte_idx` indicates the starting position of the byte
+    /// iterator in the global byte stream. If bytes are missing relative to
+    /// the expected index, every frame they (even partially) belong to is replaced
+    /// by padding. `pad_frame` is called once per channel of each padded frame, with
+    /// the channel's index. The remaining bytes of a padded frame are discarded, even
+    /// when they arrive in subsequent packets.
+    ///
+    /// Samples are only yielded once their whole frame has been received. Packets
+    /// arriving before the expected index (e.g. reordered packets) are discarded.
+    ///
+    /// The first packet ever fed is handled according to the padder's [`StartPolicy`].
+    #[inline(always)]
+    pub fn feed_bytes<I: IntoIterator<Item = u8>, P: FnMut(usize) -> T>(
+        &mut self,
+        byte_idx: u64,
+        bytes: I,
+        pad_frame: P,
+    ) -> FramePadderIter<'_, T, I::IntoIter, P> {
+        let frame_size = num::NonZeroU64::try_from(self.frame_size()).unwrap();
+
+        if self.anchor_byte_idx.is_none() {
+            let anchor = match self.start_policy {
+                StartPolicy::AnchorToFirst => byte_idx.strict_sub(byte_idx % frame_size),
+                StartPolicy::PadFromZero => 0,
+            };
+
+            self.anchor_byte_idx = Some(anchor);
+            self.current_byte_idx = anchor;
+            self.discard_until = anchor;
+        }
+
+        let mut bytes = bytes.into_iter();
+
+        let (n_padding_frames, discard) = match byte_idx.cmp(&self.current_byte_idx) {
+            // reordered packet, discard it entirely
+            core::cmp::Ordering::Less => {
+                let n_bytes = u64::try_from(bytes.by_ref().count()).unwrap();
+                self.stats.reordered_bytes = self.stats.reordered_bytes.strict_add(n_bytes);
+                (0, true)
+            }
+            // correct packet index, don't pad or skip
+            core::cmp::Ordering::Equal => (0, false),
+            core::cmp::Ordering::Greater => {
+                // the (possibly partial) frame the loss starts in, unless it has already
+                // been padded by a previous loss
+                let prev_frame_idx = self.current_byte_idx.max(self.discard_until) / frame_size;
+                // the first frame fully contained in this packet
+                let next_frame_idx = byte_idx.div_ceil(frame_size.get());
+
+                // the bytes of this packet, and possibly of the following ones, preceding
+                // the next frame boundary belong to a frame that is replaced by padding
+                self.discard_until = next_frame_idx.strict_mul(frame_size.get());
+                self.current_byte_idx = byte_idx;
+
+                let n_padding_frames = next_frame_idx.strict_sub(prev_frame_idx);
+                let n_channels = u64::try_from(self.n_channels.get()).unwrap();
+
+                self.stats.padding_samples = self
+                    .stats
+                    .padding_samples
+                    .strict_add(n_padding_frames.strict_mul(n_channels));
+                self.stats.discontinuities = self.stats.discontinuities.strict_add(1);
+
+                (n_padding_frames, false)
+            }
+        };
+
+        FramePadderIter {
+            n_padding_samples: usize::try_from(n_padding_frames)
+                .unwrap()
+                .strict_mul(self.n_channels.get()),
+            n_padded: 0,
+            // nothing to emit until a frame is complete
+            next_sample: self.n_channels.get(),
+            padder: self,
+            bytes: (!discard).then_some(bytes),
+            pad_frame,
+        }
+    }
+}
+
+/// Iterator returned by [`AudioPacketFramePadder::feed_bytes`].
+///
+/// Yields padding samples first, then the samples of each frame completed by the
+/// packet's bytes. Dropping it before exhaustion discards any remaining bytes of the
+/// packet, along with any frame it has only partially yielded.
+pub struct FramePadderIter<'a, T: SampleFromBytes, I, P> {
+    padder: &'a mut AudioPacketFramePadder<T>,
+    /// Remaining bytes of the packet, `None` if it is discarded.
+    bytes: Option<I>,
+    pad_frame: P,
+    /// Total number of padding samples to yield.
+    n_padding_samples: usize,
+    /// Number of padding samples yielded so far.
+    n_padded: usize,
+    /// Index of the next sample to yield from the completed frame buffer.
+    next_sample: usize,
+}
+
+impl<T: SampleFromBytes, I: Iterator<Item = u8>, P: FnMut(usize) -> T> Iterator
+    for FramePadderIter<'_, T, I, P>
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let n_channels = self.padder.n_channels.get();
+
+        if self.n_padded < self.n_padding_samples {
+            let channel = self.n_padded % n_channels;
+            self.n_padded += 1;
+            return Some((self.pad_frame)(channel));
+        }
+
+        if self.next_sample == n_channels {
+            let bytes = self.bytes.as_mut()?;
+            let frame_size = num::NonZeroU64::try_from(self.padder.frame_size()).unwrap();
+
+            loop {
+                let byte = bytes.next()?;
+                let idx = self.padder.current_byte_idx;
+                self.padder.current_byte_idx = idx.strict_add(1);
+
+                if idx < self.padder.discard_until {
+                    continue;
+                }
+
+                let curr = usize::try_from(idx % frame_size).unwrap();
+
+                self.padder.current_frame_bytes[curr] = byte;
+                self.padder.stats.bytes_consumed = self.padder.stats.bytes_consumed.strict_add(1);
+
+                if self.padder.current_byte_idx % frame_size == 0 {
+                    break;
+                }
+            }
+
+            self.next_sample = 0;
+        }
+
+        let sample_size = usize::from(T::SIZE.get());
+        let start = self.next_sample.strict_mul(sample_size);
+        self.next_sample += 1;
+
+        Some(T::from_bytes(
+            &self.padder.current_frame_bytes[start..start.strict_add(sample_size)],
+        ))
+    }
+}
 
 /// Framing abstraction that converts indexed byte streams into samples.
 ///
@@ -205,10 +625,68 @@ impl<T: SampleFromBytes + SampleTypeSilence> ByteStreamFramer for AudioPacketSam
     }
 }
 
+/// [`ByteStreamFramer`] implementation for [`AudioPacketFramePadder`].
+///
+/// Frames containing missing bytes are padded using the sample type's
+/// silence value.
+impl<T: SampleFromBytes + SampleTypeSilence> ByteStreamFramer for AudioPacketFramePadder<T> {
+    type Sample = T;
+
+    fn frame_bytes(
+        &mut self,
+        byte_idx: u64,
+        bytes: impl IntoIterator<Item = u8>,
+    ) -> impl IntoIterator<Item = Self::Sample> {
+        self.feed_bytes(byte_idx, bytes, |_| T::SILENCE)
+    }
+}
+
 /// Adapter combining a byte stream framer and a sample sink.
 /// 
 /// Incoming byte packets are framed into samples and immediately
 /// forwarded to the sink.
+///
+/// # Example
+///
+/// Packetizing samples with an [`IndexedAudioSampleStreamReceiver`], losing a packet, and
+/// reconstructing the stream on the other side:
+///
+/// ```
+/// use syfala_utils::{
+///     AudioPacketConsumer, AudioPacketProducer, IndexedAudioByteStreamSender,
+///     IndexedAudioSampleStreamReceiver, queue::rtrb::RingBuffer,
+/// };
+///
+/// // sending side, packetizing the samples written by the audio thread
+/// let (mut audio_tx, audio_rx) = RingBuffer::<i16>::new(64);
+/// let mut packetizer = IndexedAudioSampleStreamReceiver::for_rtrb_consumer(audio_rx);
+///
+/// // receiving side, handing the reconstructed samples to the audio thread
+/// let (net_tx, mut net_rx) = RingBuffer::<i16>::new(64);
+/// let mut depacketizer = IndexedAudioByteStreamSender::for_rtrb_producer(net_tx);
+///
+/// let mut packets = vec![];
+///
+/// for period in [[1, 2], [3, 4], [5, 6]] {
+///     for sample in period {
+///         audio_tx.push(sample).unwrap();
+///     }
+///
+///     let (byte_idx, bytes) = packetizer.produce_packet();
+///     packets.push((byte_idx, bytes.into_iter().collect::<Vec<u8>>()));
+/// }
+///
+/// // the second packet is lost
+/// packets.remove(1);
+///
+/// for (byte_idx, bytes) in packets {
+///     depacketizer.consume_packet(byte_idx, bytes);
+/// }
+///
+/// let received: Vec<i16> = std::iter::from_fn(|| net_rx.pop().ok()).collect();
+/// assert_eq!(received, [1, 2, 0, 0, 5, 6]);
+/// assert_eq!(depacketizer.stats().padding_samples, 2);
+/// ```
 pub struct IndexedAudioByteStreamSender<S, F> {
     /// Sink that consumes reconstructed samples.
     sink: S,
@@ -216,6 +694,84 @@ pub struct IndexedAudioByteStreamSender<S, F> {
     framer: F,
 }
 
+impl<S, F> IndexedAudioByteStreamSender<S, F> {
+    /// Creates a new sender, framing bytes with `framer` and forwarding the resulting
+    /// samples to `sink`.
+    #[inline(always)]
+    pub const fn new(sink: S, framer: F) -> Self {
+        Self { sink, framer }
+    }
+
+    /// Returns a reference to the underlying sink.
+    #[inline(always)]
+    pub const fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Returns a mutable reference to the underlying sink.
+    #[inline(always)]
+    pub const fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Returns a reference to the underlying framer.
+    #[inline(always)]
+    pub const fn framer(&self) -> &F {
+        &self.framer
+    }
+
+    /// Returns a mutable reference to the underlying framer.
+    #[inline(always)]
+    pub const fn framer_mut(&mut self) -> &mut F {
+        &mut self.framer
+    }
+
+    /// Returns the underlying sink and framer.
+    #[inline(always)]
+    pub fn into_parts(self) -> (S, F) {
+        (self.sink, self.framer)
+    }
+}
+
+impl<S, T: SampleFromBytes> IndexedAudioByteStreamSender<S, AudioPacketSamplePadder<T>> {
+    /// Returns the padder's counters, see [`AudioPacketSamplePadder::stats`].
+    #[inline(always)]
+    pub fn stats(&self) -> PadderStats {
+        self.framer.stats()
+    }
+
+    /// Resets the padder's counters, see [`AudioPacketSamplePadder::reset_stats`].
+    #[inline(always)]
+    pub fn reset_stats(&mut self) {
+        self.framer.reset_stats();
+    }
+}
+
+impl<S, T: SampleFromBytes> IndexedAudioByteStreamSender<S, AudioPacketFramePadder<T>> {
+    /// Returns the padder's counters, see [`AudioPacketFramePadder::stats`].
+    #[inline(always)]
+    pub fn stats(&self) -> PadderStats {
+        self.framer.stats()
+    }
+
+    /// Resets the padder's counters, see [`AudioPacketFramePadder::reset_stats`].
+    #[inline(always)]
+    pub fn reset_stats(&mut self) {
+        self.framer.reset_stats();
+    }
+}
+
+impl<T: SampleFromBytes + SampleTypeSilence>
+    IndexedAudioByteStreamSender<rtrb::Producer<T>, AudioPacketSamplePadder<T>>
+{
+    /// Creates a new sender writing reconstructed samples into `tx`, padding missing
+    /// samples with silence, using a default [`AudioPacketSamplePadder`].
+    #[inline(always)]
+    pub fn for_rtrb_producer(tx: rtrb::Producer<T>) -> Self {
+        Self::new(tx, AudioPacketSamplePadder::new())
+    }
+}
+
 /// Consumer of indexed audio packets.
 /// 
 /// Each packet consists of a starting byte index and an iterator of bytes.
@@ -236,3 +792,299 @@ impl<S: SampleSink, F: ByteStreamFramer<Sample = S::Sample>> AudioPacketConsumer
             .consume_samples(self.framer.frame_bytes(byte_idx, bytes));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// Padding value, distinguishable from the test streams' samples.
+    const PAD: u16 = u16::MAX;
+
+    /// Encodes the samples `range` as little-endian bytes.
+    fn samples(range: core::ops::Range<u16>) -> Vec<u8> {
+        range.flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn feed(padder: &mut AudioPacketSamplePadder<u16>, byte_idx: u64, bytes: &[u8]) -> Vec<u16> {
+        padder
+            .feed_bytes(byte_idx, bytes.iter().copied(), || PAD)
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn anchor_to_first() {
+        let mut padder = AudioPacketSamplePadder::<u16>::new();
+
+        assert_eq!(feed(&mut padder, 1_000_000, &samples(0..4)), [0, 1, 2, 3]);
+        assert_eq!(padder.anchor_byte_idx(), Some(1_000_000));
+        assert_eq!(padder.anchor_sample_idx(), Some(500_000));
+        assert_eq!(padder.stats().padding_samples, 0);
+
+        // a genuine gap of two samples
+        assert_eq!(
+            feed(&mut padder, 1_000_012, &samples(6..8)),
+            [PAD, PAD, 6, 7]
+        );
+        assert_eq!(padder.stats().padding_samples, 2);
+        assert_eq!(padder.stats().discontinuities, 1);
+    }
+
+    #[test]
+    fn anchor_to_first_unaligned() {
+        let mut padder = AudioPacketSamplePadder::<u16>::new();
+
+        // the first packet starts in the middle of a sample, which is padded
+        let out = feed(&mut padder, 1_000_001, &samples(0..4)[1..]);
+        assert_eq!(out, [PAD, 1, 2, 3]);
+        assert_eq!(padder.anchor_byte_idx(), Some(1_000_000));
+    }
+
+    #[test]
+    fn pad_from_zero() {
+        let mut padder =
+            AudioPacketSamplePadder::<u16>::with_start_policy(StartPolicy::PadFromZero);
+
+        let out = feed(&mut padder, 1_000_000, &samples(0..4));
+        assert_eq!(out.len(), 500_004);
+        assert!(out[..500_000].iter().all(|&s| s == PAD));
+        assert_eq!(out[500_000..], [0, 1, 2, 3]);
+        assert_eq!(padder.anchor_byte_idx(), Some(0));
+        assert_eq!(padder.stats().padding_samples, 500_000);
+
+        // a genuine gap of two samples
+        assert_eq!(
+            feed(&mut padder, 1_000_012, &samples(6..8)),
+            [PAD, PAD, 6, 7]
+        );
+        assert_eq!(padder.stats().padding_samples, 500_002);
+        assert_eq!(padder.stats().discontinuities, 2);
+    }
+
+    #[test]
+    fn frame_padder_anchor_to_first() {
+        let n_channels = num::NonZeroUsize::new(2).unwrap();
+        let mut padder = AudioPacketFramePadder::<u16>::new(n_channels);
+
+        let out: Vec<u16> = padder
+            .feed_bytes(1_000_000, samples(0..4), |_| PAD)
+            .collect();
+        assert_eq!(out, [0, 1, 2, 3]);
+        assert_eq!(padder.anchor_byte_idx(), Some(1_000_000));
+
+        let mut padder =
+            AudioPacketFramePadder::<u16>::with_start_policy(n_channels, StartPolicy::PadFromZero);
+
+        let out: Vec<u16> = padder
+            .feed_bytes(1_000_000, samples(0..4), |_| PAD)
+            .collect();
+        assert_eq!(out.len(), 500_004);
+        assert!(out[..500_000].iter().all(|&s| s == PAD));
+        assert_eq!(out[500_000..], [0, 1, 2, 3]);
+        assert_eq!(padder.stats().padding_samples, 500_000);
+    }
+
+    #[test]
+    fn resync_with_partial_sample() {
+        let mut padder = AudioPacketSamplePadder::<u16>::new();
+
+        // one and a half samples
+        assert_eq!(feed(&mut padder, 0, &[1, 0, 2]), [1]);
+        assert_eq!(padder.current_byte_idx(), 3);
+
+        // aligned down, the pending byte is discarded
+        padder.resync_to(1001);
+        assert_eq!(padder.anchor_byte_idx(), Some(1000));
+        assert_eq!(padder.current_byte_idx(), 1000);
+        assert_eq!(feed(&mut padder, 1000, &[3, 0]), [3]);
+
+        // packets before the new index are discarded, gaps after it are padded
+        assert_eq!(feed(&mut padder, 998, &[4, 0]), []);
+        assert_eq!(padder.stats().reordered_bytes, 2);
+        assert_eq!(feed(&mut padder, 1004, &[5, 0]), [PAD, 5]);
+    }
+
+    #[test]
+    fn max_pad_samples() {
+        let mut padder = AudioPacketSamplePadder::<u16>::new().with_max_pad_samples(1000);
+        assert_eq!(feed(&mut padder, 0, &samples(0..4)), [0, 1, 2, 3]);
+
+        // a gap of exactly the maximum isn't clamped
+        let out = feed(&mut padder, 2008, &samples(1004..1006));
+        assert_eq!(out.len(), 1002);
+        assert_eq!(padder.n_clamped_gaps(), 0);
+
+        // a gap of several megabytes
+        let out = feed(&mut padder, 8_000_000, &samples(0..4));
+        assert_eq!(out.len(), 1004);
+        assert!(out[..1000].iter().all(|&s| s == PAD));
+        assert_eq!(out[1000..], [0, 1, 2, 3]);
+        assert_eq!(padder.n_clamped_gaps(), 1);
+        assert_eq!(padder.stats().padding_samples, 2000);
+
+        // resynchronized to the packet's index
+        assert_eq!(padder.current_byte_idx(), 8_000_008);
+        assert_eq!(feed(&mut padder, 8_000_008, &samples(4..6)), [4, 5]);
+    }
+
+    #[test]
+    fn reordered_packets() {
+        let mut padder = AudioPacketSamplePadder::<u16>::new();
+        let packet = |i: u16| (u64::from(i) * 4, samples(i * 2..i * 2 + 2));
+
+        let mut out = Vec::new();
+        for i in [0, 2, 1, 3] {
+            let (byte_idx, bytes) = packet(i);
+            out.extend(feed(&mut padder, byte_idx, &bytes));
+
+            if i == 1 {
+                // discarded without touching the padder's state
+                assert_eq!(padder.current_byte_idx(), 12);
+            }
+        }
+
+        // the in-order stream, with the late packet padded
+        assert_eq!(out, [0, 1, PAD, PAD, 4, 5, 6, 7]);
+
+        let stats = padder.stats();
+        assert_eq!(stats.reordered_bytes, 4);
+        assert_eq!(stats.padding_samples, 2);
+        assert_eq!(stats.discontinuities, 1);
+        assert_eq!(stats.bytes_consumed, 12);
+    }
+
+    /// Padding values of the left and right channels of a stereo frame.
+    const PAD_FRAME: [u16; 2] = [PAD, PAD - 1];
+
+    /// Returns a stereo frame padder, fed the stream of samples `0..`, whose `i`-th byte
+    /// has byte index `i`.
+    fn stereo_padder() -> AudioPacketFramePadder<u16> {
+        AudioPacketFramePadder::new(num::NonZeroUsize::new(2).unwrap())
+    }
+
+    /// Feeds the bytes `range` of the sample stream `0..`.
+    fn feed_frames(
+        padder: &mut AudioPacketFramePadder<u16>,
+        range: core::ops::Range<usize>,
+    ) -> Vec<u16> {
+        let bytes = samples(0..100);
+        padder
+            .feed_bytes(range.start as u64, bytes[range].iter().copied(), |ch| {
+                PAD_FRAME[ch]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn frame_loss_on_frame_boundary() {
+        let mut padder = stereo_padder();
+
+        assert_eq!(feed_frames(&mut padder, 0..8), [0, 1, 2, 3]);
+        // frame 2 lost
+        assert_eq!(feed_frames(&mut padder, 12..16), [PAD, PAD - 1, 6, 7]);
+        assert_eq!(padder.stats().padding_samples, 2);
+        assert_eq!(padder.stats().discontinuities, 1);
+    }
+
+    #[test]
+    fn frame_loss_mid_frame() {
+        let mut padder = stereo_padder();
+
+        // ends in the middle of frame 1, between samples
+        assert_eq!(feed_frames(&mut padder, 0..6), [0, 1]);
+        // frames 1 and 2 are incomplete
+        assert_eq!(
+            feed_frames(&mut padder, 10..16),
+            [PAD, PAD - 1, PAD, PAD - 1, 6, 7]
+        );
+        assert_eq!(padder.stats().padding_samples, 4);
+        assert_eq!(padder.stats().bytes_consumed, 10);
+    }
+
+    #[test]
+    fn frame_loss_mid_sample() {
+        let mut padder = stereo_padder();
+
+        // both ends in the middle of a sample of frame 1
+        assert_eq!(feed_frames(&mut padder, 0..5), [0, 1]);
+        assert_eq!(feed_frames(&mut padder, 7..16), [PAD, PAD - 1, 4, 5, 6, 7]);
+        assert_eq!(padder.stats().padding_samples, 2);
+        assert_eq!(padder.stats().bytes_consumed, 13);
+    }
+
+    #[test]
+    fn frame_loss_short_packet() {
+        let mut padder = stereo_padder();
+
+        assert_eq!(feed_frames(&mut padder, 0..4), [0, 1]);
+
+        // a loss, followed by a packet ending before the next frame boundary
+        assert_eq!(
+            feed_frames(&mut padder, 9..10),
+            [PAD, PAD - 1, PAD, PAD - 1]
+        );
+        assert_eq!(padder.current_byte_idx(), 10);
+
+        // the next, in-order, packet isn't considered reordered, only the bytes of the
+        // padded frame are discarded
+        assert_eq!(feed_frames(&mut padder, 10..16), [6, 7]);
+        assert_eq!(padder.stats().reordered_bytes, 0);
+        assert_eq!(padder.stats().padding_samples, 4);
+
+        assert_eq!(feed_frames(&mut padder, 17..18), [PAD, PAD - 1]);
+        // a second loss, within the already padded frame, doesn't pad it again
+        assert_eq!(feed_frames(&mut padder, 19..20), []);
+        assert_eq!(padder.stats().padding_samples, 6);
+        assert_eq!(padder.stats().discontinuities, 3);
+        assert_eq!(feed_frames(&mut padder, 20..24), [10, 11]);
+
+        // genuinely reordered packets are still discarded
+        assert_eq!(feed_frames(&mut padder, 4..8), []);
+        assert_eq!(padder.stats().reordered_bytes, 4);
+    }
+
+    #[test]
+    fn scripted_loss_stats() {
+        let (net_tx, mut net_rx) = rtrb::RingBuffer::new(64);
+        let mut depacketizer = IndexedAudioByteStreamSender::for_rtrb_producer(net_tx);
+
+        // packets of 4 samples, packet 2 and 9 are lost, 5 arrives after 6, and 8 is
+        // truncated in the middle of its third sample
+        for i in [0, 1, 3, 4, 6, 5, 7, 8, 10, 11] {
+            let bytes = samples(i * 4..i * 4 + 4);
+            let len = if i == 8 { 5 } else { 8 };
+            depacketizer.consume_packet(u64::from(i) * 8, bytes[..len].iter().copied());
+        }
+
+        let stats = depacketizer.stats();
+        assert_eq!(stats, depacketizer.framer().stats());
+        assert_eq!(
+            stats,
+            PadderStats {
+                bytes_consumed: 69,
+                reordered_bytes: 8,
+                padding_samples: 14,
+                discontinuities: 3,
+            },
+        );
+
+        let pad = |n| iter::repeat_n(u16::SILENCE, n);
+        let expected: Vec<u16> = iter::empty()
+            .chain(0..8)
+            .chain(pad(4))
+            .chain(12..20)
+            .chain(pad(4))
+            .chain(24..34)
+            .chain(pad(6))
+            .chain(40..48)
+            .collect();
+        let received: Vec<u16> = iter::from_fn(|| net_rx.pop().ok()).collect();
+        assert_eq!(received, expected);
+
+        depacketizer.reset_stats();
+        assert_eq!(depacketizer.stats(), PadderStats::default());
+        depacketizer.consume_packet(96, samples(48..52));
+        assert_eq!(depacketizer.stats().bytes_consumed, 8);
+    }
+}
@@ -46,7 +46,9 @@ impl<T> SampleSource for rtrb::Consumer<T> {
 /// This iterator keeps track of the current byte index globally and
 /// converts samples to bytes lazily, only when a new sample boundary
 /// is reached.
-struct SampleByteStreamIter<'a, I> {
+///
+/// Returned by [`SampleByteStream::feed_samples`].
+pub struct SampleByteStreamIter<'a, I> {
     /// Iterator yielding samples to be converted.
     iter: I,
     /// Global byte index into the logical byte stream.
@@ -75,7 +77,80 @@ impl<'a, I: Iterator<Item: SampleToBytes>> Iterator for SampleByteStreamIter<'a,
         Some(self.current_sample_bytes[usize::try_from(current_spl_byte_idx).unwrap()])
     }
 
-    // TODO: implement nth and size_hint
+    #[inline(always)]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let sample_size = usize::from(I::Item::SIZE.get());
+
+        // bytes of the current, partially yielded, sample
+        let partial = match self.partial_spl_byte_idx() {
+            0 => 0,
+            pos => sample_size.strict_sub(pos),
+        };
+
+        let (lower, upper) = self.iter.size_hint();
+
+        (
+            lower.saturating_mul(sample_size).saturating_add(partial),
+            upper.and_then(|u| u.checked_mul(sample_size)?.checked_add(partial)),
+        )
+    }
+
+    /// Skips `n` bytes, and yields the next one.
+    ///
+    /// Samples that are skipped entirely are pulled from the inner iterator, but never
+    /// converted to bytes.
+    #[inline]
+    fn nth(&mut self, mut n: usize) -> Option<Self::Item> {
+        let sample_size = usize::from(I::Item::SIZE.get());
+
+        match self.partial_spl_byte_idx() {
+            0 => {}
+            pos => {
+                let rem = sample_size.strict_sub(pos);
+
+                if n < rem {
+                    self.advance_byte_idx(n);
+                    return self.next();
+                }
+
+                n = n.strict_sub(rem);
+                self.advance_byte_idx(rem);
+            }
+        }
+
+        // we are now on a sample boundary
+        for _ in 0..n / sample_size {
+            self.iter.next()?;
+            self.advance_byte_idx(sample_size);
+        }
+
+        match n % sample_size {
+            0 => self.next(),
+            offset => {
+                self.iter.next()?.to_bytes(self.current_sample_bytes);
+                self.advance_byte_idx(offset);
+                self.next()
+            }
+        }
+    }
+}
+
+impl<'a, I: ExactSizeIterator<Item: SampleToBytes>> ExactSizeIterator
+    for SampleByteStreamIter<'a, I>
+{
+}
+
+impl<'a, I: Iterator<Item: SampleToBytes>> SampleByteStreamIter<'a, I> {
+    /// Returns the position of the next byte in its sample.
+    #[inline(always)]
+    fn partial_spl_byte_idx(&self) -> usize {
+        usize::try_from(*self.current_byte_idx % num::NonZeroU64::from(I::Item::SIZE)).unwrap()
+    }
+
+    #[inline(always)]
+    fn advance_byte_idx(&mut self, n: usize) {
+        *self.current_byte_idx = self.current_byte_idx.strict_add(n.try_into().unwrap());
+    }
 }
 
 // the same setback mentioned in byte_consumer occurs here
@@ -94,6 +169,12 @@ pub struct SampleByteStream<T: SampleToBytes> {
     _marker: marker::PhantomData<T>,
 }
 
+impl<T: SampleToBytes> Default for SampleByteStream<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: SampleToBytes> SampleByteStream<T> {
     /// Create a new `SampleByteStream`.
     ///
@@ -116,15 +197,53 @@ impl<T: SampleToBytes> SampleByteStream<T> {
         self.current_byte_idx
     }
 
+    /// Returns the number of bytes of the current sample that have not been yielded
+    /// yet, i.e. that the next call to [`feed_samples`](Self::feed_samples) will yield
+    /// before pulling any new sample.
+    #[inline(always)]
+    pub fn pending_partial_bytes(&self) -> usize {
+        let sample_size = num::NonZeroU64::from(T::SIZE);
+
+        match self.current_byte_idx % sample_size {
+            0 => 0,
+            pos => usize::try_from(sample_size.get().strict_sub(pos)).unwrap(),
+        }
+    }
+
+    /// Repositions the stream at byte index `idx`, discarding any pending bytes of a
+    /// partially yielded sample.
+    ///
+    /// # Panics
+    ///
+    /// If `idx` isn't a multiple of the sample size.
+    #[inline(always)]
+    pub fn set_byte_idx(&mut self, idx: u64) {
+        assert_eq!(
+            idx % num::NonZeroU64::from(T::SIZE),
+            0,
+            "byte index must be on a sample boundary",
+        );
+
+        self.current_byte_idx = idx;
+        self.current_sample_bytes.fill(0);
+    }
+
+    /// Restarts the stream at byte index `0`, discarding any pending bytes of a
+    /// partially yielded sample.
+    #[inline(always)]
+    pub fn reset(&mut self) {
+        self.set_byte_idx(0);
+    }
+
     /// Feed a sequence of samples into the stream and obtain an iterator of bytes.
     ///
     /// The returned iterator may be partially consumed; any remaining bytes
     /// are preserved internally and will be yielded first on the next call.
     #[inline(always)]
-    pub fn feed_samples(
+    pub fn feed_samples<I: IntoIterator<Item = T>>(
         &mut self,
-        samples: impl IntoIterator<Item = T>,
-    ) -> impl IntoIterator<Item = u8> {
+        samples: I,
+    ) -> SampleByteStreamIter<'_, I::IntoIter> {
         SampleByteStreamIter {
             iter: samples.into_iter(),
             current_byte_idx: &mut self.current_byte_idx,
@@ -183,6 +302,29 @@ pub trait AudioPacketProducer {
 ///
 /// Samples are pulled from the source and immediately framed into
 /// indexed byte packets.
+///
+/// # Example
+///
+/// ```
+/// use syfala_utils::{AudioPacketProducer, IndexedAudioSampleStreamReceiver, queue::rtrb};
+///
+/// let (mut tx, rx) = rtrb::RingBuffer::<i16>::new(64);
+/// let mut packetizer = IndexedAudioSampleStreamReceiver::for_rtrb_consumer(rx);
+///
+/// tx.push(1).unwrap();
+/// tx.push(-2).unwrap();
+///
+/// let (byte_idx, bytes) = packetizer.produce_packet();
+/// assert_eq!(byte_idx, 0);
+/// assert!(bytes.into_iter().eq([0x01, 0x00, 0xfe, 0xff]));
+///
+/// // byte indices carry on from one packet to the next
+/// tx.push(3).unwrap();
+///
+/// let (byte_idx, bytes) = packetizer.produce_packet();
+/// assert_eq!(byte_idx, 4);
+/// assert!(bytes.into_iter().eq([0x03, 0x00]));
+/// ```
 pub struct IndexedAudioSampleStreamReceiver<S, F> {
     /// Underlying sample source.
     source: S,
@@ -190,6 +332,54 @@ pub struct IndexedAudioSampleStreamReceiver<S, F> {
     framer: F,
 }
 
+impl<S, F> IndexedAudioSampleStreamReceiver<S, F> {
+    /// Creates a new receiver, pulling samples from `source` and framing them with
+    /// `framer`.
+    #[inline(always)]
+    pub const fn new(source: S, framer: F) -> Self {
+        Self { source, framer }
+    }
+
+    /// Returns a reference to the underlying source.
+    #[inline(always)]
+    pub const fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// Returns a mutable reference to the underlying source.
+    #[inline(always)]
+    pub const fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// Returns a reference to the underlying framer.
+    #[inline(always)]
+    pub const fn framer(&self) -> &F {
+        &self.framer
+    }
+
+    /// Returns a mutable reference to the underlying framer.
+    #[inline(always)]
+    pub const fn framer_mut(&mut self) -> &mut F {
+        &mut self.framer
+    }
+
+    /// Returns the underlying source and framer.
+    #[inline(always)]
+    pub fn into_parts(self) -> (S, F) {
+        (self.source, self.framer)
+    }
+}
+
+impl<T: SampleToBytes> IndexedAudioSampleStreamReceiver<rtrb::Consumer<T>, SampleByteStream<T>> {
+    /// Creates a new receiver draining samples from `rx`, framing them with a new
+    /// [`SampleByteStream`], starting at byte index `0`.
+    #[inline(always)]
+    pub fn for_rtrb_consumer(rx: rtrb::Consumer<T>) -> Self {
+        Self::new(rx, SampleByteStream::new())
+    }
+}
+
 impl<S: SampleSource, F: SampleStreamFramer<Sample = S::Sample>> AudioPacketProducer
     for IndexedAudioSampleStreamReceiver<S, F>
 {
@@ -198,4 +388,127 @@ impl<S: SampleSource, F: SampleStreamFramer<Sample = S::Sample>> AudioPacketProd
     fn produce_packet(&mut self) -> (u64, impl IntoIterator<Item = u8>) {
         self.framer.frame_samples(self.source.get_samples())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// Compares the bytes yielded by a [`SampleByteStream`] to `naive`, the concatenation
+    /// of the little-endian bytes of `values`, for various consumption patterns.
+    fn check<T: SampleToBytes + Copy>(values: &[T], naive: &[u8]) {
+        let sample_size = usize::from(T::SIZE.get());
+        let samples = || values.iter().copied();
+
+        let mut stream = SampleByteStream::new();
+        assert_eq!(stream.feed_samples(samples()).collect::<Vec<u8>>(), naive);
+
+        for split in 0..=naive.len() {
+            // stopping in the middle of a sample, then resuming with the next ones
+            let mut stream = SampleByteStream::new();
+            let mut bytes: Vec<u8> = stream.feed_samples(samples()).take(split).collect();
+
+            let n_pulled = split.div_ceil(sample_size);
+            let partial = n_pulled * sample_size - split;
+            assert_eq!(stream.pending_partial_bytes(), partial);
+
+            let remaining = naive.len() - split;
+            let mut iter = stream.feed_samples(samples().skip(n_pulled));
+            assert_eq!(iter.len(), remaining);
+            assert_eq!(iter.size_hint(), (remaining, Some(remaining)));
+
+            while let Some(byte) = iter.next() {
+                bytes.push(byte);
+                assert_eq!(iter.len(), naive.len() - bytes.len());
+            }
+
+            assert_eq!(bytes, naive, "split: {split}");
+        }
+
+        for start in 0..=sample_size.min(naive.len()) {
+            for n in 0..=naive.len() - start {
+                // skipping from a sample boundary, or from within a sample
+                let mut stream = SampleByteStream::new();
+                let mut iter = stream.feed_samples(samples());
+                iter.by_ref().take(start).for_each(drop);
+
+                assert_eq!(iter.nth(n), naive.get(start + n).copied());
+                let rest: Vec<u8> = iter.collect();
+                assert_eq!(rest, naive.get(start + n + 1..).unwrap_or(&[]));
+                assert_eq!(stream.current_byte_idx(), naive.len() as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn u8_stream() {
+        let values = [0, 1, 0x7f, 0x80, 0xff];
+        check(&values, &values);
+    }
+
+    #[test]
+    fn i16_stream() {
+        let values = [i16::MIN, -1, 0, 0x1234, i16::MAX];
+        let naive: Vec<u8> = values.into_iter().flat_map(i16::to_le_bytes).collect();
+        check(&values, &naive);
+    }
+
+    #[test]
+    fn u32_stream() {
+        let values = [0xdead_beef, 0, u32::MAX];
+        let naive: Vec<u8> = values.into_iter().flat_map(u32::to_le_bytes).collect();
+        check(&values, &naive);
+    }
+
+    #[test]
+    fn f64_stream() {
+        let values = [-0., 1.5, f64::NAN, f64::MIN_POSITIVE];
+        let naive: Vec<u8> = values.into_iter().flat_map(f64::to_le_bytes).collect();
+        check(&values, &naive);
+    }
+
+    #[test]
+    fn reposition_with_partial_sample() {
+        let mut stream = SampleByteStream::<i16>::new();
+        assert_eq!(stream.pending_partial_bytes(), 0);
+
+        // one and a half samples
+        let bytes: Vec<u8> = stream.feed_samples([0x0201, 0x0403]).take(3).collect();
+        assert_eq!(bytes, [1, 2, 3]);
+        assert_eq!(stream.pending_partial_bytes(), 1);
+
+        // the pending byte is discarded
+        stream.set_byte_idx(100);
+        assert_eq!(stream.pending_partial_bytes(), 0);
+        assert_eq!(stream.current_byte_idx(), 100);
+
+        let bytes: Vec<u8> = stream.feed_samples([0x0605]).collect();
+        assert_eq!(bytes, [5, 6]);
+        assert_eq!(stream.current_byte_idx(), 102);
+
+        stream.feed_samples([0x0807]).next();
+        stream.reset();
+        assert_eq!(stream.pending_partial_bytes(), 0);
+        assert_eq!(stream.current_byte_idx(), 0);
+
+        let bytes: Vec<u8> = stream.feed_samples([0x0a09]).collect();
+        assert_eq!(bytes, [9, 10]);
+    }
+
+    #[test]
+    #[should_panic = "byte index must be on a sample boundary"]
+    fn reposition_unaligned() {
+        SampleByteStream::<i16>::new().set_byte_idx(101);
+    }
+
+    #[test]
+    fn unbounded_size_hint() {
+        let mut stream = SampleByteStream::<u16>::new();
+        let mut iter = stream.feed_samples(core::iter::repeat(0).filter(|_| true));
+        assert_eq!(iter.size_hint(), (0, None));
+
+        // the rest of the current sample is always known
+        iter.next();
+        assert_eq!(iter.size_hint(), (1, None));
+    }
+}
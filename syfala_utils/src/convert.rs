@@ -0,0 +1,420 @@
+//! Sample format conversion.
+//!
+//! Streams are received in whatever sample type the remote end chose,
+//! but audio APIs usually want a fixed one (e.g. `f32` for JACK). This module provides
+//! scaling conversions between all supported sample types, and sink/source adapters
+//! applying them on the fly, optionally with TPDF dithering when reducing bit depth.
+
+use crate::{SampleSink, SampleSource};
+
+use core::{marker, num};
+
+/// Sample types that can be converted to and from a normalized `f64` representation.
+///
+/// Integers are mapped to `[-1, 1)`, by dividing them by `2^(BITS - 1)` (after removing
+/// the offset of unsigned types, which are offset binary). Floats are passed through.
+pub trait SampleConvert: Copy {
+    /// Effective resolution of the type, in bits.
+    const BITS: u32;
+    /// Whether the type is an integer, i.e. whether it benefits from dithering.
+    const INTEGER: bool;
+
+    /// Converts the sample to a normalized `f64`.
+    fn to_normalized(self) -> f64;
+
+    /// Converts a normalized `f64` to a sample, rounding to the nearest value, and
+    /// saturating if out of range.
+    fn from_normalized(x: f64) -> Self;
+}
+
+/// Rounds to the nearest integer, half away from zero, without relying on `std`.
+///
+/// The returned value is still a float, but is meant to be cast to an integer type
+/// (saturating out of range values).
+#[inline(always)]
+fn round_half_away(x: f64) -> f64 {
+    if x.is_sign_negative() {
+        x - 0.5
+    } else {
+        x + 0.5
+    }
+}
+
+macro_rules! impl_sample_convert_int {
+    ($($s:ty, $u:ty);* $(;)?) => {$(
+        impl SampleConvert for $s {
+            const BITS: u32 = <$s>::BITS;
+            const INTEGER: bool = true;
+
+            #[inline(always)]
+            fn to_normalized(self) -> f64 {
+                // precision is only lost for 64-bit integers, where it is inaudible
+                self as f64 / (1u64 << (Self::BITS - 1)) as f64
+            }
+
+            #[inline(always)]
+            fn from_normalized(x: f64) -> Self {
+                // float to int casts saturate, and map NaN to 0
+                round_half_away(x * (1u64 << (Self::BITS - 1)) as f64) as $s
+            }
+        }
+
+        impl SampleConvert for $u {
+            const BITS: u32 = <$u>::BITS;
+            const INTEGER: bool = true;
+
+            #[inline(always)]
+            fn to_normalized(self) -> f64 {
+                // flipping the most significant bit turns offset binary into two's complement
+                ((self ^ (1 << (Self::BITS - 1))) as $s).to_normalized()
+            }
+
+            #[inline(always)]
+            fn from_normalized(x: f64) -> Self {
+                (<$s>::from_normalized(x) as $u) ^ (1 << (Self::BITS - 1))
+            }
+        }
+    )*};
+}
+
+impl_sample_convert_int! {
+    i8, u8;
+    i16, u16;
+    i32, u32;
+    i64, u64;
+}
+
+impl SampleConvert for f32 {
+    const BITS: u32 = f32::MANTISSA_DIGITS;
+    const INTEGER: bool = false;
+
+    #[inline(always)]
+    fn to_normalized(self) -> f64 {
+        self.into()
+    }
+
+    #[inline(always)]
+    fn from_normalized(x: f64) -> Self {
+        x as f32
+    }
+}
+
+impl SampleConvert for f64 {
+    const BITS: u32 = f64::MANTISSA_DIGITS;
+    const INTEGER: bool = false;
+
+    #[inline(always)]
+    fn to_normalized(self) -> f64 {
+        self
+    }
+
+    #[inline(always)]
+    fn from_normalized(x: f64) -> Self {
+        x
+    }
+}
+
+/// Triangular probability density function (TPDF) dither generator.
+///
+/// Uses a small xorshift PRNG, it is allocation-free, and suitable for real-time use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Dither {
+    state: num::NonZeroU32,
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Self::new(num::NonZeroU32::new(0x9E37_79B9).unwrap())
+    }
+}
+
+impl Dither {
+    /// Creates a new dither generator, from the given PRNG seed.
+    #[inline(always)]
+    pub const fn new(seed: num::NonZeroU32) -> Self {
+        Self { state: seed }
+    }
+
+    /// Returns a uniformly distributed value in `[0, 1)`.
+    #[inline(always)]
+    fn next_unit(&mut self) -> f64 {
+        let mut x = self.state.get();
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        // xorshift never maps a non-zero state to zero
+        self.state = num::NonZeroU32::new(x).unwrap();
+
+        f64::from(x) / (f64::from(u32::MAX) + 1.)
+    }
+
+    /// Returns TPDF noise, in `(-lsb, lsb)`.
+    #[inline(always)]
+    pub fn tpdf(&mut self, lsb: f64) -> f64 {
+        (self.next_unit() + self.next_unit() - 1.) * lsb
+    }
+}
+
+/// Converts a sample from one type to another, scaling it accordingly.
+///
+/// If `dither` is provided, and the conversion reduces the bit depth of an integer
+/// target type, TPDF noise of one target LSB is added before rounding.
+#[inline(always)]
+pub fn convert_sample<From: SampleConvert, To: SampleConvert>(
+    sample: From,
+    dither: Option<&mut Dither>,
+) -> To {
+    let mut x = sample.to_normalized();
+
+    if To::INTEGER
+        && To::BITS < From::BITS
+        && let Some(dither) = dither
+    {
+        x += dither.tpdf(1. / (1u64 << (To::BITS - 1)) as f64);
+    }
+
+    To::from_normalized(x)
+}
+
+/// [`SampleSink`] adapter, converting samples of type `From` before forwarding them to
+/// a sink accepting samples of type `To`.
+#[derive(Debug, Clone)]
+pub struct ConvertingSink<S, From, To> {
+    sink: S,
+    dither: Option<Dither>,
+    _marker: marker::PhantomData<fn(From) -> To>,
+}
+
+impl<S, From, To> ConvertingSink<S, From, To> {
+    /// Wraps `sink`, without dithering.
+    #[inline(always)]
+    pub const fn new(sink: S) -> Self {
+        Self {
+            sink,
+            dither: None,
+            _marker: marker::PhantomData,
+        }
+    }
+
+    /// Wraps `sink`, dithering samples with `dither` when the conversion reduces
+    /// bit depth.
+    #[inline(always)]
+    pub const fn with_dither(sink: S, dither: Dither) -> Self {
+        Self {
+            sink,
+            dither: Some(dither),
+            _marker: marker::PhantomData,
+        }
+    }
+
+    /// Returns a reference to the underlying sink.
+    #[inline(always)]
+    pub const fn inner(&self) -> &S {
+        &self.sink
+    }
+
+    /// Returns a mutable reference to the underlying sink.
+    #[inline(always)]
+    pub const fn inner_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Returns the underlying sink.
+    #[inline(always)]
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<S: SampleSink<Sample = To>, From: SampleConvert, To: SampleConvert> SampleSink
+    for ConvertingSink<S, From, To>
+{
+    type Sample = From;
+
+    fn consume_samples(&mut self, spls: impl IntoIterator<Item = Self::Sample>) {
+        let dither = &mut self.dither;
+
+        self.sink
+            .consume_samples(spls.into_iter().map(|s| convert_sample(s, dither.as_mut())));
+    }
+}
+
+/// [`SampleSource`] adapter, converting samples of type `From`, produced by a source,
+/// to samples of type `To`.
+#[derive(Debug, Clone)]
+pub struct ConvertingSource<S, From, To> {
+    source: S,
+    dither: Option<Dither>,
+    _marker: marker::PhantomData<fn(From) -> To>,
+}
+
+impl<S, From, To> ConvertingSource<S, From, To> {
+    /// Wraps `source`, without dithering.
+    #[inline(always)]
+    pub const fn new(source: S) -> Self {
+        Self {
+            source,
+            dither: None,
+            _marker: marker::PhantomData,
+        }
+    }
+
+    /// Wraps `source`, dithering samples with `dither` when the conversion reduces
+    /// bit depth.
+    #[inline(always)]
+    pub const fn with_dither(source: S, dither: Dither) -> Self {
+        Self {
+            source,
+            dither: Some(dither),
+            _marker: marker::PhantomData,
+        }
+    }
+
+    /// Returns a reference to the underlying source.
+    #[inline(always)]
+    pub const fn inner(&self) -> &S {
+        &self.source
+    }
+
+    /// Returns a mutable reference to the underlying source.
+    #[inline(always)]
+    pub const fn inner_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// Returns the underlying source.
+    #[inline(always)]
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+impl<S: SampleSource<Sample = From>, From: SampleConvert, To: SampleConvert> SampleSource
+    for ConvertingSource<S, From, To>
+{
+    type Sample = To;
+
+    fn get_samples(&mut self) -> impl IntoIterator<Item = Self::Sample> {
+        let dither = &mut self.dither;
+
+        self.source
+            .get_samples()
+            .into_iter()
+            .map(move |s| convert_sample(s, dither.as_mut()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn convert<From: SampleConvert, To: SampleConvert>(sample: From) -> To {
+        convert_sample(sample, None)
+    }
+
+    #[test]
+    fn reference_values() {
+        assert_eq!(convert::<i16, f32>(i16::MAX), 32767. / 32768.);
+        assert!(convert::<i16, f32>(i16::MAX) < 1.);
+        assert_eq!(convert::<i16, f32>(i16::MIN), -1.);
+        assert_eq!(convert::<i16, f32>(0), 0.);
+
+        // offset binary
+        assert_eq!(convert::<u8, f32>(0x80), 0.);
+        assert_eq!(convert::<u8, f32>(0), -1.);
+        assert_eq!(convert::<u8, f32>(0xff), 127. / 128.);
+        assert_eq!(convert::<u16, i16>(0x8001), 1);
+        assert_eq!(convert::<i32, u32>(-1), 0x7fff_ffff);
+
+        // widening is exact
+        assert_eq!(convert::<i16, i32>(-3), -3 << 16);
+        assert_eq!(convert::<u8, u64>(0x81), 0x81 << 56);
+    }
+
+    #[test]
+    fn rounding_and_saturation() {
+        // half an LSB, rounded away from zero
+        assert_eq!(convert::<i32, i16>(0x8000), 1);
+        assert_eq!(convert::<i32, i16>(-0x8000), -1);
+        assert_eq!(convert::<i32, i16>(0x7fff), 0);
+        // rounds up to 32768, out of range
+        assert_eq!(convert::<i32, i16>(i32::MAX), i16::MAX);
+
+        assert_eq!(convert::<f32, i16>(1.), i16::MAX);
+        assert_eq!(convert::<f32, i16>(-1.), i16::MIN);
+        assert_eq!(convert::<f32, i16>(2.), i16::MAX);
+        assert_eq!(convert::<f32, i16>(f32::NEG_INFINITY), i16::MIN);
+        assert_eq!(convert::<f32, i16>(f32::NAN), 0);
+        assert_eq!(convert::<f32, u8>(f32::NAN), 0x80);
+        assert_eq!(convert::<f64, u16>(1.), u16::MAX);
+    }
+
+    #[test]
+    fn round_trips() {
+        for s in i16::MIN..=i16::MAX {
+            assert_eq!(convert::<f32, i16>(convert::<i16, f32>(s)), s);
+        }
+
+        for s in u16::MIN..=u16::MAX {
+            assert_eq!(convert::<f64, u16>(convert::<u16, f64>(s)), s);
+        }
+
+        // f32 only has 24 bits of precision
+        for s in (i32::MIN..=i32::MAX).step_by(65_537) {
+            let err = convert::<f32, i32>(convert::<i32, f32>(s)).abs_diff(s);
+            assert!(err <= 1 << 7, "{s}: {err}");
+        }
+
+        // i32 fits exactly in an f64
+        for s in (i32::MIN..=i32::MAX).step_by(65_537) {
+            assert_eq!(convert::<f64, i32>(convert::<i32, f64>(s)), s);
+        }
+    }
+
+    #[test]
+    fn dither() {
+        // a quarter of an i16 LSB
+        let sample: i32 = 1 << 14;
+        let n = 100_000;
+
+        let undithered: Vec<i16> = (0..n).map(|_| convert(sample)).collect();
+        assert!(undithered.iter().all(|&s| s == 0));
+
+        let mut dither = Dither::default();
+        let dithered: Vec<i16> = (0..n)
+            .map(|_| convert_sample(sample, Some(&mut dither)))
+            .collect();
+
+        // TPDF noise spans two LSBs in each direction
+        assert!(dithered.iter().all(|s| (-1..=2).contains(s)));
+        let mean = dithered.iter().map(|&s| f64::from(s)).sum::<f64>() / f64::from(n);
+        assert!((mean - 0.25).abs() < 0.02, "mean: {mean}");
+
+        // not applied when the bit depth isn't reduced, or for float targets
+        let mut dither = Dither::default();
+        assert_eq!(convert_sample::<i16, i32>(3, Some(&mut dither)), 3 << 16);
+        let converted: f32 = convert_sample(1i32 << 14, Some(&mut dither));
+        assert_eq!(converted, 2f32.powi(-17));
+        assert_eq!(dither, Dither::default());
+    }
+
+    #[test]
+    fn adapters() {
+        let (tx, mut rx) = rtrb::RingBuffer::<f32>::new(8);
+        let mut sink = ConvertingSink::<_, i16, f32>::new(tx);
+        sink.consume_samples([i16::MIN, 0, 1 << 14]);
+
+        let received: Vec<f32> = core::iter::from_fn(|| rx.pop().ok()).collect();
+        assert_eq!(received, [-1., 0., 0.5]);
+
+        let (mut tx, rx) = rtrb::RingBuffer::<f32>::new(8);
+        for s in [-1., 0., 0.5, 1.] {
+            tx.push(s).unwrap();
+        }
+
+        let mut source = ConvertingSource::<_, f32, u8>::new(rx);
+        let samples: Vec<u8> = source.get_samples().into_iter().collect();
+        assert_eq!(samples, [0, 0x80, 0xc0, 0xff]);
+    }
+}
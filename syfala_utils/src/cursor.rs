@@ -0,0 +1,75 @@
+//! Writing bytes into uninitialized buffers.
+
+use core::mem::MaybeUninit;
+
+/// A writer over an uninitialized byte buffer, keeping track of the initialized prefix.
+///
+/// Unlike writing into a `&mut [u8]`, this spares zero-initializing the buffer
+/// beforehand, e.g. when encoding messages into large scratch buffers.
+///
+/// With the `std` feature, it also implements `std::io::Write`.
+#[derive(Debug)]
+pub struct UninitCursor<'a> {
+    buf: &'a mut [MaybeUninit<u8>],
+    /// Invariant: the first `n_written` bytes of `buf` are initialized.
+    n_written: usize,
+}
+
+impl<'a> UninitCursor<'a> {
+    /// Creates a new cursor at the start of `buf`.
+    #[inline(always)]
+    pub const fn new(buf: &'a mut [MaybeUninit<u8>]) -> Self {
+        Self { buf, n_written: 0 }
+    }
+
+    /// Returns the number of bytes written so far.
+    #[inline(always)]
+    pub const fn n_written(&self) -> usize {
+        self.n_written
+    }
+
+    /// Returns the number of bytes that can still be written.
+    #[inline(always)]
+    pub const fn remaining(&self) -> usize {
+        self.buf.len().strict_sub(self.n_written)
+    }
+
+    /// Writes as many bytes of `bytes` as fit in the buffer, and returns their count.
+    #[inline]
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> usize {
+        let n = self.remaining().min(bytes.len());
+        let end = self.n_written.strict_add(n);
+
+        self.buf[self.n_written..end].write_copy_of_slice(&bytes[..n]);
+        self.n_written = end;
+
+        n
+    }
+
+    /// Returns the bytes written so far.
+    #[inline(always)]
+    pub fn written(&self) -> &[u8] {
+        // SAFETY: the first n_written bytes are initialized, see the field's invariant
+        unsafe { self.buf[..self.n_written].assume_init_ref() }
+    }
+
+    /// Returns the bytes written, consuming the cursor.
+    #[inline(always)]
+    pub fn into_written(self) -> &'a mut [u8] {
+        // SAFETY: the first n_written bytes are initialized, see the field's invariant
+        unsafe { self.buf[..self.n_written].assume_init_mut() }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for UninitCursor<'_> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Ok(self.write_bytes(buf))
+    }
+
+    #[inline(always)]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
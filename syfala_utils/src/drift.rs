@@ -0,0 +1,291 @@
+//! Fractional-rate drift compensation.
+//!
+//! The clocks driving the producer and consumer sides of a ring buffer (e.g. a remote
+//! DAC and the local audio callback) always differ by a few tens of ppm. Left alone,
+//! the buffer slowly fills up or drains, until a hard skip or pad is required.
+//!
+//! A [`DriftCompensator`] slightly resamples the consumed stream instead, steering the
+//! buffer's fill level towards a target.
+
+use alloc::boxed::Box;
+use core::{iter, num};
+
+/// Linear-interpolation resampler, adjusting its ratio to keep a ring buffer's fill level
+/// centered around a target.
+///
+/// It operates on interleaved `f32` frames, pulled one sample at a time from the consumer
+/// side of the buffer, and is allocation-free once created.
+///
+/// The resampling ratio never deviates from `1` by more than
+/// [`MAX_PPM`](Self::MAX_PPM). Larger discontinuities (e.g. packet loss bursts, audio cycle
+/// skips) should still be handled by skipping or padding, after which the compensator
+/// should be [`reset`](Self::reset).
+#[derive(Debug, Clone)]
+pub struct DriftCompensator {
+    /// Target fill level, in samples.
+    target_fill: f64,
+    /// Smoothed fill level, in samples, `None` until the first call to `process`.
+    fill_avg: Option<f64>,
+    /// Integral term of the controller.
+    integral: f64,
+    /// Number of input frames consumed per output frame.
+    ratio: f64,
+    /// Position of the next output frame between `prev` and `next`, in `[0, 1)`.
+    phase: f64,
+    /// Whether `prev` and `next` hold frames pulled from the input.
+    primed: bool,
+    prev: Box<[f32]>,
+    next: Box<[f32]>,
+}
+
+impl DriftCompensator {
+    /// Maximum deviation of the resampling ratio from `1`, in ppm.
+    pub const MAX_PPM: f64 = 200.;
+
+    const MAX_DEVIATION: f64 = Self::MAX_PPM * 1e-6;
+
+    /// Proportional gain, ratio deviation per unit of relative fill level error.
+    const KP: f64 = Self::MAX_DEVIATION * 20.;
+
+    /// Integral gain, per call to `process`.
+    const KI: f64 = Self::MAX_DEVIATION * 1e-3;
+
+    /// Smoothing factor of the fill level's moving average, per call to `process`.
+    const SMOOTHING: f64 = 0.05;
+
+    /// Creates a new compensator for streams of `n_channels` interleaved channels,
+    /// targeting a fill level of `target_fill` samples.
+    pub fn new(n_channels: num::NonZeroUsize, target_fill: num::NonZeroUsize) -> Self {
+        let frame = || iter::repeat_n(0., n_channels.get()).collect();
+
+        Self {
+            // usize to f64 conversions are exact for any realistic buffer size
+            target_fill: target_fill.get() as f64,
+            fill_avg: None,
+            integral: 0.,
+            ratio: 1.,
+            phase: 0.,
+            primed: false,
+            prev: frame(),
+            next: frame(),
+        }
+    }
+
+    /// Returns the number of interleaved channels.
+    #[inline(always)]
+    pub fn n_channels(&self) -> num::NonZeroUsize {
+        num::NonZeroUsize::new(self.prev.len()).unwrap()
+    }
+
+    /// Returns the current resampling ratio, i.e. the number of input frames consumed per
+    /// output frame.
+    #[inline(always)]
+    pub fn ratio(&self) -> f64 {
+        self.ratio
+    }
+
+    /// Resets the compensator's state, to be called after any hard discontinuity in
+    /// the input stream.
+    pub fn reset(&mut self) {
+        self.fill_avg = None;
+        self.integral = 0.;
+        self.ratio = 1.;
+        self.phase = 0.;
+        self.primed = false;
+    }
+
+    /// Fills `out` with resampled frames, pulling input samples from `pull`.
+    ///
+    /// `fill_level` is the number of samples available in the buffer `pull` reads from,
+    /// observed before this call. It is used to update the resampling ratio.
+    ///
+    /// When `pull` returns `None`, the previous sample of the same channel is repeated.
+    /// Returns the number of samples that couldn't be pulled.
+    ///
+    /// # Panics
+    ///
+    /// If `out.len()` isn't a multiple of the number of channels.
+    pub fn process(
+        &mut self,
+        fill_level: usize,
+        mut pull: impl FnMut() -> Option<f32>,
+        out: &mut [f32],
+    ) -> usize {
+        let n_channels = self.n_channels().get();
+        assert_eq!(out.len() % n_channels, 0);
+
+        self.update_ratio(fill_level);
+
+        let mut n_missing = 0;
+
+        let mut pull_frame = |frame: &mut [f32]| {
+            for s in frame {
+                match pull() {
+                    Some(v) => *s = v,
+                    None => n_missing += 1,
+                }
+            }
+        };
+
+        if !self.primed {
+            pull_frame(&mut self.prev);
+            self.next.copy_from_slice(&self.prev);
+            pull_frame(&mut self.next);
+            self.primed = true;
+        }
+
+        for out_frame in out.chunks_exact_mut(n_channels) {
+            // f64 -> f32 precision is plenty for an interpolation weight
+            let t = self.phase as f32;
+
+            for ((o, &a), &b) in out_frame.iter_mut().zip(&self.prev).zip(&self.next) {
+                *o = a + t * (b - a);
+            }
+
+            self.phase += self.ratio;
+
+            while self.phase >= 1. {
+                self.phase -= 1.;
+                self.prev.copy_from_slice(&self.next);
+                pull_frame(&mut self.next);
+            }
+        }
+
+        n_missing
+    }
+
+    /// Updates the resampling ratio, using a PI controller on the smoothed, relative,
+    /// fill level error.
+    #[inline]
+    fn update_ratio(&mut self, fill_level: usize) {
+        let fill_level = fill_level as f64;
+
+        let fill_avg = self
+            .fill_avg
+            .map_or(fill_level, |avg| avg + Self::SMOOTHING * (fill_level - avg));
+
+        self.fill_avg = Some(fill_avg);
+
+        // a fuller buffer than targeted means the input is running fast: consume faster
+        let error = (fill_avg - self.target_fill) / self.target_fill;
+
+        let max = Self::MAX_DEVIATION;
+
+        self.integral = (self.integral + Self::KI * error).clamp(-max, max);
+        self.ratio = 1. + (Self::KP * error + self.integral).clamp(-max, max);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::VecDeque;
+
+    const SAMPLE_RATE: f64 = 48000.;
+    const PERIOD: usize = 256;
+    const CAPACITY: usize = 768;
+    const TARGET: usize = 512;
+    /// Value at which the test ramp signal wraps back to `0`.
+    const RAMP_LEN: u32 = 1 << 16;
+
+    /// Simulates one minute of a mono stream, produced at `SAMPLE_RATE * (1 + ppm * 1e-6)`
+    /// and consumed at `SAMPLE_RATE`, through a ring buffer of `CAPACITY` samples.
+    ///
+    /// The input is a ramp, so any hard skip or pad shows up as an irregular step in the
+    /// output. Returns the final resampling ratio.
+    ///
+    /// Uncompensated, a 150 ppm drift accumulates 432 samples in a minute, overflowing or
+    /// draining the buffer.
+    fn simulate_minute(ppm: f64) -> f64 {
+        let mut compensator = DriftCompensator::new(
+            num::NonZeroUsize::new(1).unwrap(),
+            num::NonZeroUsize::new(TARGET).unwrap(),
+        );
+
+        let producer_rate = 1. + ppm * 1e-6;
+        let n_periods = (60. * SAMPLE_RATE) as usize / PERIOD;
+
+        let mut buf = VecDeque::with_capacity(CAPACITY);
+        let mut next_sample = 0u32;
+        let mut owed = 0.;
+        // last output lying on the ramp, and the number of outputs since
+        let mut prev_out: Option<(f32, u32)> = None;
+        let mut out = [0.; PERIOD];
+        let mut fill_level = 0;
+
+        let mut push = |buf: &mut VecDeque<f32>, n: usize| {
+            for _ in 0..n {
+                buf.push_back(next_sample as f32);
+                next_sample = (next_sample + 1) % RAMP_LEN;
+            }
+            assert!(buf.len() <= CAPACITY, "overflow");
+        };
+
+        // the compensator observes the fill level right after the producer's push
+        push(&mut buf, TARGET - PERIOD);
+
+        for _ in 0..n_periods {
+            owed += PERIOD as f64 * producer_rate;
+            let n = owed as usize;
+            owed -= n as f64;
+            push(&mut buf, n);
+
+            fill_level = buf.len();
+            let n_missing = compensator.process(fill_level, || buf.pop_front(), &mut out);
+            assert_eq!(n_missing, 0, "underrun");
+
+            for &s in &out {
+                let Some((prev, n_steps)) = prev_out else {
+                    prev_out = Some((s, 1));
+                    continue;
+                };
+
+                // the ramp wrapping around is the only expected decreasing step, by
+                // exactly its length
+                let step = if s < prev {
+                    s + RAMP_LEN as f32 - prev
+                } else {
+                    s - prev
+                };
+                let n = n_steps as f32;
+
+                if (0.99 * n..1.01 * n).contains(&step) {
+                    prev_out = Some((s, 1));
+                } else {
+                    // only the output interpolated between the ramp's last sample and
+                    // the next ramp's first one is off it
+                    assert!(
+                        n_steps == 1 && prev >= (RAMP_LEN - 2) as f32,
+                        "step: {step}, after {prev}"
+                    );
+                    prev_out = Some((prev, 2));
+                }
+            }
+        }
+
+        assert!(
+            fill_level.abs_diff(TARGET) < PERIOD / 4,
+            "fill level: {fill_level}"
+        );
+
+        compensator.ratio()
+    }
+
+    #[test]
+    fn producer_faster() {
+        let ratio = simulate_minute(150.);
+        assert!((ratio - 1.00015).abs() < 20e-6, "ratio: {ratio}");
+    }
+
+    #[test]
+    fn producer_slower() {
+        let ratio = simulate_minute(-150.);
+        assert!((ratio - 0.99985).abs() < 20e-6, "ratio: {ratio}");
+    }
+
+    #[test]
+    fn same_rate() {
+        let ratio = simulate_minute(0.);
+        assert!((ratio - 1.).abs() < 20e-6, "ratio: {ratio}");
+    }
+}
@@ -0,0 +1,441 @@
+//! Reordering window for indexed audio packets.
+//!
+//! UDP datagrams may arrive out of order. Feeding them as-is to a padder discards the
+//! late packet's samples, even if it was only late by a fraction of a millisecond, and
+//! replaces them with padding. A [`JitterBuffer`] holds packets arriving ahead of the
+//! expected byte index for a short while, giving late packets a chance to fill the gap.
+
+use crate::AudioPacketConsumer;
+
+use alloc::boxed::Box;
+use core::iter;
+
+/// Counters describing how packets went through a [`JitterBuffer`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JitterBufferStats {
+    /// Packets that were released as soon as they arrived, at the expected byte index,
+    /// or ahead of it if they couldn't be held.
+    pub on_time: u64,
+    /// Packets that arrived after packets following them, but still in time to be
+    /// released in order.
+    pub reordered: u64,
+    /// Packets that arrived ahead of the expected byte index, and were held until the
+    /// gap preceding them was filled, or until they had to be released anyway.
+    pub held: u64,
+    /// Packets that arrived after their bytes had already been released (or padded),
+    /// and were discarded.
+    pub late_dropped: u64,
+}
+
+/// Metadata of a packet held in a [`JitterBuffer`] slot.
+#[derive(Debug, Clone, Copy)]
+struct HeldPacket<T> {
+    /// Global byte index of the first byte of the packet.
+    byte_idx: u64,
+    /// Number of bytes of the packet.
+    len: usize,
+    /// Instant after which the packet is released, regardless of any preceding gap.
+    deadline: T,
+}
+
+/// Fixed-capacity reordering window placed in front of an [`AudioPacketConsumer`].
+///
+/// Packets arriving at the expected byte index are forwarded immediately, along with
+/// any held packets they make contiguous. Packets arriving ahead of it are held until
+/// either the gap before them is filled, or their release deadline expires, in which
+/// case they are released in order and the consumer is left to pad the gap.
+///
+/// Deadlines are of any ordered type `T` (e.g. `std::time::Instant`), and are computed
+/// by the caller, typically as the packet's arrival time plus a hold time.
+///
+/// All storage is allocated upon creation, no allocation happens afterwards.
+#[derive(Debug)]
+pub struct JitterBuffer<T> {
+    /// Metadata of held packets, `None` for free slots.
+    slots: Box<[Option<HeldPacket<T>>]>,
+    /// Packet bytes, `max_packet_len` bytes per slot.
+    data: Box<[u8]>,
+    /// Maximum number of bytes a slot can hold.
+    max_packet_len: usize,
+    /// Byte index expected by the next packet, set by the first packet received.
+    next_byte_idx: Option<u64>,
+    stats: JitterBufferStats,
+}
+
+impl<T: Copy + Ord> JitterBuffer<T> {
+    /// Creates a new `JitterBuffer` able to hold up to `capacity` packets of at most
+    /// `max_packet_len` bytes each.
+    pub fn new(capacity: usize, max_packet_len: usize) -> Self {
+        Self {
+            slots: iter::repeat_n(None, capacity).collect(),
+            data: iter::repeat_n(0, capacity.strict_mul(max_packet_len)).collect(),
+            max_packet_len,
+            next_byte_idx: None,
+            stats: JitterBufferStats::default(),
+        }
+    }
+
+    /// Returns the maximum number of packets this buffer can hold.
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns the number of packets currently held.
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.slots.iter().flatten().count()
+    }
+
+    /// Returns `true` if no packets are currently held.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(Option::is_none)
+    }
+
+    /// Returns the byte index expected by the next packet, if any packet has been
+    /// received yet.
+    #[inline(always)]
+    pub fn next_byte_idx(&self) -> Option<u64> {
+        self.next_byte_idx
+    }
+
+    /// Returns the counters accumulated so far.
+    #[inline(always)]
+    pub fn stats(&self) -> JitterBufferStats {
+        self.stats
+    }
+
+    /// Handles an incoming packet, starting at `byte_idx`.
+    ///
+    /// `deadline` is the instant after which the packet must be released, even if the
+    /// gap preceding it isn't filled. See [`poll`](Self::poll).
+    ///
+    /// If the buffer is full, the earliest held packet is released to make room. Packets
+    /// longer than `max_packet_len` bytes can't be held, and are forwarded immediately.
+    pub fn push(
+        &mut self,
+        byte_idx: u64,
+        bytes: &[u8],
+        deadline: T,
+        consumer: &mut impl AudioPacketConsumer,
+    ) {
+        let next = *self.next_byte_idx.get_or_insert(byte_idx);
+
+        if byte_idx == next {
+            if self.is_empty() {
+                self.stats.on_time += 1;
+            } else {
+                self.stats.reordered += 1;
+            }
+
+            self.release(byte_idx, bytes, consumer);
+            self.drain_contiguous(consumer);
+            return;
+        }
+
+        if byte_idx < next {
+            // (part of) this packet's bytes have already been released, or padded
+            self.stats.late_dropped += 1;
+            return;
+        }
+
+        if bytes.len() > self.max_packet_len || self.capacity() == 0 {
+            // held packets preceding this one must go first, to stay in order
+            while self
+                .earliest()
+                .is_some_and(|(_, held_idx)| held_idx < byte_idx)
+            {
+                self.release_earliest(consumer);
+            }
+
+            self.stats.on_time += 1;
+            self.release(byte_idx, bytes, consumer);
+            self.drain_contiguous(consumer);
+            return;
+        }
+
+        if self.is_full() {
+            self.release_earliest(consumer);
+            self.drain_contiguous(consumer);
+
+            // releasing may have moved the expected byte index past this packet
+            if self.next_byte_idx.is_some_and(|next| byte_idx < next) {
+                self.stats.late_dropped += 1;
+                return;
+            }
+        }
+
+        let slot_idx = self.slots.iter().position(Option::is_none).unwrap();
+        let start = slot_idx.strict_mul(self.max_packet_len);
+
+        self.data[start..start.strict_add(bytes.len())].copy_from_slice(bytes);
+        self.slots[slot_idx] = Some(HeldPacket {
+            byte_idx,
+            len: bytes.len(),
+            deadline,
+        });
+    }
+
+    /// Releases, in order, all held packets whose deadline is before or at `now`,
+    /// along with any held packets they make contiguous.
+    ///
+    /// Should be called periodically, e.g. once per audio callback.
+    pub fn poll(&mut self, now: T, consumer: &mut impl AudioPacketConsumer) {
+        while self.slots.iter().flatten().any(|p| p.deadline <= now) {
+            self.release_earliest(consumer);
+            self.drain_contiguous(consumer);
+        }
+    }
+
+    /// Releases all held packets, in order, and forgets the expected byte index.
+    pub fn flush(&mut self, consumer: &mut impl AudioPacketConsumer) {
+        while !self.is_empty() {
+            self.release_earliest(consumer);
+        }
+
+        self.next_byte_idx = None;
+    }
+
+    #[inline(always)]
+    fn is_full(&self) -> bool {
+        self.slots.iter().all(Option::is_some)
+    }
+
+    /// Forwards a packet to the consumer, and advances the expected byte index past it.
+    #[inline(always)]
+    fn release(&mut self, byte_idx: u64, bytes: &[u8], consumer: &mut impl AudioPacketConsumer) {
+        consumer.consume_packet(byte_idx, bytes.iter().copied());
+        self.next_byte_idx = Some(byte_idx.strict_add(u64::try_from(bytes.len()).unwrap()));
+    }
+
+    /// Releases the held packet at the given slot.
+    fn release_slot(&mut self, slot_idx: usize, consumer: &mut impl AudioPacketConsumer) {
+        let packet = self.slots[slot_idx].take().unwrap();
+        self.stats.held += 1;
+
+        let start = slot_idx.strict_mul(self.max_packet_len);
+
+        consumer.consume_packet(
+            packet.byte_idx,
+            self.data[start..start.strict_add(packet.len)]
+                .iter()
+                .copied(),
+        );
+
+        self.next_byte_idx = Some(
+            packet
+                .byte_idx
+                .strict_add(u64::try_from(packet.len).unwrap()),
+        );
+    }
+
+    /// Returns the slot, and byte index, of the held packet with the lowest byte index,
+    /// if any.
+    #[inline(always)]
+    fn earliest(&self) -> Option<(usize, u64)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| p.map(|p| (i, p.byte_idx)))
+            .min_by_key(|&(_, byte_idx)| byte_idx)
+    }
+
+    /// Releases the held packet with the lowest byte index, if any.
+    fn release_earliest(&mut self, consumer: &mut impl AudioPacketConsumer) {
+        if let Some((slot_idx, _)) = self.earliest() {
+            self.release_slot(slot_idx, consumer);
+        }
+    }
+
+    /// Releases held packets starting exactly at the expected byte index, and discards
+    /// those that now lie entirely before it.
+    fn drain_contiguous(&mut self, consumer: &mut impl AudioPacketConsumer) {
+        loop {
+            let Some(next) = self.next_byte_idx else {
+                return;
+            };
+
+            let mut released = false;
+
+            for slot_idx in 0..self.slots.len() {
+                let Some(packet) = self.slots[slot_idx] else {
+                    continue;
+                };
+
+                if packet.byte_idx == next {
+                    self.release_slot(slot_idx, consumer);
+                    released = true;
+                    break;
+                }
+
+                if packet.byte_idx < next {
+                    self.slots[slot_idx] = None;
+                    self.stats.late_dropped += 1;
+                }
+            }
+
+            if !released {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// Records the packets it consumes.
+    #[derive(Default)]
+    struct Recorder(Vec<(u64, Vec<u8>)>);
+
+    impl Recorder {
+        fn byte_indices(&self) -> Vec<u64> {
+            self.0.iter().map(|&(byte_idx, _)| byte_idx).collect()
+        }
+    }
+
+    impl AudioPacketConsumer for Recorder {
+        fn consume_packet(&mut self, byte_idx: u64, bytes: impl IntoIterator<Item = u8>) {
+            self.0.push((byte_idx, bytes.into_iter().collect()));
+        }
+    }
+
+    /// Returns a 4 byte packet, starting at `byte_idx`.
+    fn packet(byte_idx: u64) -> [u8; 4] {
+        [byte_idx as u8; 4]
+    }
+
+    #[test]
+    fn in_order() {
+        let mut buf = JitterBuffer::<u32>::new(4, 4);
+        let mut rec = Recorder::default();
+
+        for byte_idx in [100, 104, 108] {
+            buf.push(byte_idx, &packet(byte_idx), 0, &mut rec);
+        }
+
+        assert_eq!(rec.byte_indices(), [100, 104, 108]);
+        assert_eq!(rec.0[1].1, packet(104));
+        assert!(buf.is_empty());
+        assert_eq!(buf.next_byte_idx(), Some(112));
+        assert_eq!(
+            buf.stats(),
+            JitterBufferStats {
+                on_time: 3,
+                reordered: 0,
+                held: 0,
+                late_dropped: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn reordered() {
+        let mut buf = JitterBuffer::<u32>::new(4, 4);
+        let mut rec = Recorder::default();
+
+        buf.push(0, &packet(0), 10, &mut rec);
+        buf.push(8, &packet(8), 10, &mut rec);
+        buf.push(12, &packet(12), 10, &mut rec);
+        assert_eq!(rec.byte_indices(), [0]);
+        assert_eq!(buf.len(), 2);
+
+        buf.push(4, &packet(4), 10, &mut rec);
+        assert_eq!(rec.byte_indices(), [0, 4, 8, 12]);
+        assert!(buf.is_empty());
+        assert_eq!(
+            buf.stats(),
+            JitterBufferStats {
+                on_time: 1,
+                reordered: 1,
+                held: 2,
+                late_dropped: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn deadline() {
+        let mut buf = JitterBuffer::<u32>::new(4, 4);
+        let mut rec = Recorder::default();
+
+        buf.push(0, &packet(0), 5, &mut rec);
+        buf.push(8, &packet(8), 10, &mut rec);
+
+        buf.poll(9, &mut rec);
+        assert_eq!(rec.byte_indices(), [0]);
+
+        buf.poll(10, &mut rec);
+        assert_eq!(rec.byte_indices(), [0, 8]);
+        assert_eq!(buf.next_byte_idx(), Some(12));
+
+        // too late, the consumer has already padded it
+        buf.push(4, &packet(4), 15, &mut rec);
+        assert_eq!(rec.byte_indices(), [0, 8]);
+        assert_eq!(buf.stats().late_dropped, 1);
+    }
+
+    #[test]
+    fn full() {
+        let mut buf = JitterBuffer::<u32>::new(2, 4);
+        let mut rec = Recorder::default();
+
+        buf.push(0, &packet(0), 0, &mut rec);
+        buf.push(8, &packet(8), 0, &mut rec);
+        buf.push(16, &packet(16), 0, &mut rec);
+        assert_eq!(buf.len(), 2);
+
+        // releases the earliest held packet to make room
+        buf.push(24, &packet(24), 0, &mut rec);
+        assert_eq!(rec.byte_indices(), [0, 8]);
+        assert_eq!(buf.len(), 2);
+
+        buf.flush(&mut rec);
+        assert_eq!(rec.byte_indices(), [0, 8, 16, 24]);
+        assert!(buf.is_empty());
+        assert_eq!(buf.next_byte_idx(), None);
+    }
+
+    #[test]
+    fn oversized() {
+        let mut buf = JitterBuffer::<u32>::new(2, 4);
+        let mut rec = Recorder::default();
+
+        buf.push(0, &packet(0), 0, &mut rec);
+        buf.push(8, &[0; 8], 0, &mut rec);
+        assert_eq!(rec.byte_indices(), [0, 8]);
+        assert!(buf.is_empty());
+        assert_eq!(buf.next_byte_idx(), Some(16));
+    }
+
+    #[test]
+    fn oversized_after_held() {
+        let mut buf = JitterBuffer::<u32>::new(4, 4);
+        let mut rec = Recorder::default();
+
+        buf.push(0, &packet(0), 10, &mut rec);
+        buf.push(8, &packet(8), 10, &mut rec);
+        buf.push(12, &packet(12), 10, &mut rec);
+        buf.push(24, &packet(24), 10, &mut rec);
+        assert_eq!(buf.len(), 3);
+
+        // held packets preceding the oversized one are released first, in order, and
+        // those it makes contiguous right after it
+        buf.push(16, &[0; 8], 10, &mut rec);
+        assert_eq!(rec.byte_indices(), [0, 8, 12, 16, 24]);
+        assert!(buf.is_empty());
+        assert_eq!(buf.next_byte_idx(), Some(28));
+        assert_eq!(
+            buf.stats(),
+            JitterBufferStats {
+                on_time: 2,
+                reordered: 0,
+                held: 3,
+                late_dropped: 0,
+            }
+        );
+    }
+}
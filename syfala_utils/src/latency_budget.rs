@@ -0,0 +1,294 @@
+//! Planning of the buffering stages of an audio stream's path from a latency target.
+//!
+//! The latency added by the network path of a stream is spread across three stages:
+//!
+//! - The sender accumulates a chunk of frames before sending it.
+//! - The receiver's [`JitterBuffer`](crate::JitterBuffer) holds packets arriving early,
+//!   for at most the jitter allowance, waiting for the ones before them.
+//! - The receiver's queue is filled up to a prefill threshold before playback starts,
+//!   playback then trails the last received frame by that much. Frames arrive a chunk at
+//!   a time, so at least one chunk must be prefilled.
+//!
+//! The minimum achievable latency is thus two chunks, plus the jitter allowance, any
+//! remaining budget goes to the prefill, absorbing more of the network's jitter.
+
+use core::{num, time::Duration};
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Returns the number of frames spanning `duration`, at `rate` Hz, rounded down.
+#[inline(always)]
+fn frames_floor(duration: Duration, rate: num::NonZeroU32) -> usize {
+    let frames = duration.as_nanos().saturating_mul(rate.get().into()) / NANOS_PER_SEC;
+    usize::try_from(frames).unwrap_or(usize::MAX)
+}
+
+/// Returns the number of frames spanning `duration`, at `rate` Hz, rounded up.
+#[inline(always)]
+fn frames_ceil(duration: Duration, rate: num::NonZeroU32) -> usize {
+    let frames = duration
+        .as_nanos()
+        .saturating_mul(rate.get().into())
+        .div_ceil(NANOS_PER_SEC);
+    usize::try_from(frames).unwrap_or(usize::MAX)
+}
+
+/// Returns the duration of `frames` frames, at `rate` Hz, rounded up to the nanosecond.
+#[inline(always)]
+fn duration_of(frames: usize, rate: num::NonZeroU32) -> Duration {
+    let nanos = (frames as u128)
+        .saturating_mul(NANOS_PER_SEC)
+        .div_ceil(rate.get().into());
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
+
+/// Latency target of an audio stream's network path, from which the sizes of the
+/// buffering stages along it are planned, see [`plan`](Self::plan).
+///
+/// The stream's format is given by its sample rate, channel count and sample size, as
+/// advertised in the protocol's stream formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LatencyBudget {
+    /// Maximum latency added by the network path, from the sender's capture to the
+    /// receiver's playback.
+    pub target: Duration,
+    /// Sample rate of the stream, in Hz.
+    pub sample_rate: num::NonZeroU32,
+    /// Number of channels of the stream.
+    pub n_channels: num::NonZeroUsize,
+    /// Size of a sample, in bytes.
+    pub sample_size: num::NonZeroUsize,
+    /// Number of frames sent in each network packet.
+    pub chunk_frames: num::NonZeroUsize,
+    /// Maximum delay of a packet's arrival, relative to the other packets', absorbed by
+    /// the jitter buffer.
+    pub jitter: Duration,
+}
+
+/// Sizes of the buffering stages of an audio stream's network path, planned from a
+/// [`LatencyBudget`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LatencyPlan {
+    /// Longest a sender holds frames before sending them, even if they don't fill a
+    /// whole chunk: the duration of one chunk.
+    pub flush_max_age: Duration,
+    /// Number of packets the jitter buffer holds: those sent during the jitter
+    /// allowance, plus one.
+    pub jitter_buffer_capacity: num::NonZeroUsize,
+    /// Maximum length of the packets the jitter buffer holds, in bytes: one chunk.
+    pub jitter_buffer_packet_len: num::NonZeroUsize,
+    /// How long the jitter buffer holds packets, waiting for the ones before them: the
+    /// jitter allowance.
+    pub hold_time: Duration,
+    /// Number of frames the receiver's queue is filled with before playback starts.
+    pub prefill_frames: num::NonZeroUsize,
+    /// Capacity of the receiver's queue, in frames: the prefill, the frames of the
+    /// packets the jitter buffer may release at once, and one chunk of headroom.
+    pub ring_frames: num::NonZeroUsize,
+    /// Capacity of the receiver's queue, in samples, i.e. of the underlying ring buffer.
+    pub ring_capacity: num::NonZeroUsize,
+    /// Latency of the planned path, at most the target.
+    pub latency: Duration,
+}
+
+impl core::fmt::Display for LatencyPlan {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{:?} latency: senders flush after {:?}, jitter buffers hold {} packets of {} \
+            bytes for {:?}, queues of {} frames are prefilled with {} frames",
+            self.latency,
+            self.flush_max_age,
+            self.jitter_buffer_capacity,
+            self.jitter_buffer_packet_len,
+            self.hold_time,
+            self.ring_frames,
+            self.prefill_frames,
+        )
+    }
+}
+
+/// Error returned when planning a [`LatencyBudget`] whose target can't be met.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InfeasibleLatency {
+    /// The minimum achievable latency, given the stream's sample rate, chunk size and
+    /// jitter allowance.
+    pub min_latency: Duration,
+}
+
+impl core::fmt::Display for InfeasibleLatency {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "latency target infeasible, at least {:?} needed",
+            self.min_latency
+        )
+    }
+}
+
+impl core::error::Error for InfeasibleLatency {}
+
+impl LatencyBudget {
+    /// Returns the minimum achievable latency: two chunks, plus the jitter allowance.
+    #[inline(always)]
+    pub fn min_latency(&self) -> Duration {
+        let jitter_frames = frames_ceil(self.jitter, self.sample_rate);
+        let min_frames = self
+            .chunk_frames
+            .get()
+            .saturating_mul(2)
+            .saturating_add(jitter_frames);
+
+        duration_of(min_frames, self.sample_rate)
+    }
+
+    /// Plans the sizes of the buffering stages so that the path's latency is as close
+    /// as possible to the target, without exceeding it.
+    ///
+    /// Fails if the target is below the [minimum achievable latency](Self::min_latency).
+    ///
+    /// # Panics
+    ///
+    /// If the capacity of the receiver's queue, in samples, or the length of a chunk, in
+    /// bytes, overflows a `usize`.
+    pub fn plan(&self) -> Result<LatencyPlan, InfeasibleLatency> {
+        let rate = self.sample_rate;
+        let chunk = self.chunk_frames.get();
+        let jitter_frames = frames_ceil(self.jitter, rate);
+        let target_frames = frames_floor(self.target, rate);
+
+        let infeasible = || InfeasibleLatency {
+            min_latency: self.min_latency(),
+        };
+
+        let prefill_frames = target_frames
+            .checked_sub(chunk)
+            .and_then(|n| n.checked_sub(jitter_frames))
+            .filter(|&n| n >= chunk)
+            .ok_or_else(infeasible)?;
+
+        // the prefill, jitter and chunk frames add up to the target's
+        let ring_frames = num::NonZeroUsize::new(target_frames).unwrap();
+
+        Ok(LatencyPlan {
+            flush_max_age: duration_of(chunk, rate),
+            jitter_buffer_capacity: num::NonZeroUsize::MIN
+                .saturating_add(jitter_frames.div_ceil(chunk)),
+            jitter_buffer_packet_len: self
+                .chunk_frames
+                .checked_mul(self.n_channels)
+                .and_then(|n| n.checked_mul(self.sample_size))
+                .unwrap(),
+            hold_time: self.jitter,
+            // at least one chunk
+            prefill_frames: num::NonZeroUsize::new(prefill_frames).unwrap(),
+            ring_frames,
+            ring_capacity: ring_frames.checked_mul(self.n_channels).unwrap(),
+            latency: duration_of(target_frames, rate),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn budget(target: Duration, sample_rate: u32, jitter: Duration) -> LatencyBudget {
+        LatencyBudget {
+            target,
+            sample_rate: num::NonZeroU32::new(sample_rate).unwrap(),
+            n_channels: num::NonZeroUsize::new(2).unwrap(),
+            sample_size: num::NonZeroUsize::new(4).unwrap(),
+            chunk_frames: num::NonZeroUsize::new(48).unwrap(),
+            jitter,
+        }
+    }
+
+    const fn nz(n: usize) -> num::NonZeroUsize {
+        num::NonZeroUsize::new(n).unwrap()
+    }
+
+    #[test]
+    fn feasible() {
+        let budget = budget(Duration::from_millis(10), 48000, Duration::from_millis(2));
+
+        assert_eq!(
+            budget.plan(),
+            Ok(LatencyPlan {
+                flush_max_age: Duration::from_millis(1),
+                jitter_buffer_capacity: nz(3),
+                jitter_buffer_packet_len: nz(48 * 2 * 4),
+                hold_time: Duration::from_millis(2),
+                // 10ms, minus one chunk, minus the jitter allowance
+                prefill_frames: nz(480 - 48 - 96),
+                ring_frames: nz(480),
+                ring_capacity: nz(960),
+                latency: Duration::from_millis(10),
+            })
+        );
+    }
+
+    #[test]
+    fn infeasible() {
+        let min_latency = Duration::from_millis(4);
+
+        for target in [
+            Duration::ZERO,
+            Duration::from_millis(1),
+            min_latency - Duration::from_nanos(1),
+        ] {
+            let budget = budget(target, 48000, Duration::from_millis(2));
+            assert_eq!(budget.min_latency(), min_latency);
+            assert_eq!(budget.plan(), Err(InfeasibleLatency { min_latency }));
+        }
+
+        // the minimum is achievable, with a single chunk of prefill
+        let plan = budget(min_latency, 48000, Duration::from_millis(2))
+            .plan()
+            .unwrap();
+        assert_eq!(plan.prefill_frames, nz(48));
+        assert_eq!(plan.latency, min_latency);
+    }
+
+    #[test]
+    fn no_jitter() {
+        let plan = budget(Duration::from_millis(2), 48000, Duration::ZERO)
+            .plan()
+            .unwrap();
+
+        assert_eq!(plan.jitter_buffer_capacity, nz(1));
+        assert_eq!(plan.prefill_frames, nz(48));
+        assert_eq!(plan.ring_frames, nz(96));
+    }
+
+    #[test]
+    fn rounding() {
+        let jitter = Duration::from_micros(1500);
+
+        for millis in 5..50 {
+            let target = Duration::from_millis(millis);
+            let budget = budget(target, 44100, jitter);
+            let plan = budget.plan().unwrap();
+
+            // never above the target, and less than a frame below it
+            assert!(plan.latency <= target);
+            assert!(target - plan.latency < duration_of(1, budget.sample_rate));
+
+            // 1.5ms is 66.15 frames, rounded up, along with 2 chunks of 48
+            assert_eq!(budget.min_latency(), duration_of(163, budget.sample_rate));
+            assert_eq!(plan.jitter_buffer_capacity, nz(3));
+            assert_eq!(plan.ring_capacity.get(), plan.ring_frames.get() * 2);
+        }
+
+        // the reported minimum is itself feasible
+        let budget = budget(Duration::ZERO, 44100, jitter);
+        let min_latency = budget.plan().unwrap_err().min_latency;
+        let plan = LatencyBudget {
+            target: min_latency,
+            ..budget
+        }
+        .plan()
+        .unwrap();
+        assert_eq!(plan.latency, min_latency);
+    }
+}
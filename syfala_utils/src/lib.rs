@@ -13,6 +13,29 @@ pub use byte_consumer::*;
 mod byte_producer;
 pub use byte_producer::*;
 
+mod jitter_buffer;
+pub use jitter_buffer::*;
+
+mod drift;
+pub use drift::*;
+
+mod convert;
+pub use convert::*;
+
+mod cursor;
+pub use cursor::*;
+
+mod rate;
+pub use rate::*;
+
+mod latency_budget;
+pub use latency_budget::*;
+
+#[cfg(feature = "wav")]
+mod wav;
+#[cfg(feature = "wav")]
+pub use wav::*;
+
 // TODO: This crate is in desperate need of tests
 
 extern crate alloc;
\ No newline at end of file
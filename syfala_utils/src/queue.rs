@@ -4,7 +4,8 @@
 //! buffers and periodic wake-up logic. It also provides ring buffer adapters
 //! that track and automatically react (by padding/skipping samples) to data misalignment
 //! (audio cycle skips, packet loss, packet reordering, jitter...)
-use core::{num, iter};
+use alloc::boxed::Box;
+use core::{iter, mem, num};
 
 pub use rtrb;
 /// A minimal abstraction for a monotonically increasing logical counter.
@@ -30,7 +31,7 @@ pub trait Counter {
 ///
 /// This allows `&mut T` to be passed wherever a [`Counter`] is expected,
 /// without forcing callers to manually dereference.
-impl<'a, T: Counter> Counter for &'a mut T {
+impl<T: Counter> Counter for &mut T {
     #[inline(always)]
     fn advance(&mut self, delta: usize) {
         (**self).advance(delta);
@@ -101,6 +102,260 @@ impl Waker for std::thread::Thread {
     }
 }
 
+/// `Waker` implementation based on a mutex-protected counter and a condition variable.
+///
+/// Unlike unparking a thread, this doesn't require the woken thread to be the one
+/// that parks itself, any thread holding a clone can [`wait`](Self::wait) on it,
+/// and wakeups are counted, never lost, even if they occur while no-one is waiting.
+///
+/// # Real-time safety
+///
+/// Waking locks a mutex, and may block if another thread holds it, so this waker is
+/// **not** real-time safe: it must not be woken from an audio callback, or any other
+/// thread with hard deadlines. Those should use the `std::thread::Thread` implementation,
+/// which never blocks, instead.
+#[cfg(feature = "std")]
+#[derive(Debug, Default, Clone)]
+pub struct CondvarWaker {
+    inner: alloc::sync::Arc<(std::sync::Mutex<usize>, std::sync::Condvar)>,
+}
+
+#[cfg(feature = "std")]
+impl CondvarWaker {
+    /// Creates a new waker, with no pending wakeups.
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of pending wakeups, and resets it, without blocking
+    /// (besides locking the mutex).
+    #[inline]
+    pub fn take_pending(&self) -> usize {
+        let (count, _) = &*self.inner;
+        core::mem::take(&mut *count.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Blocks until at least one wakeup is pending, then returns the number of pending
+    /// wakeups, and resets it.
+    #[inline]
+    pub fn wait(&self) -> num::NonZeroUsize {
+        let (count, condvar) = &*self.inner;
+
+        let mut count = condvar
+            .wait_while(count.lock().unwrap_or_else(|e| e.into_inner()), |c| *c == 0)
+            .unwrap_or_else(|e| e.into_inner());
+
+        num::NonZeroUsize::new(core::mem::take(&mut *count)).unwrap()
+    }
+
+    /// Same as [`wait`](Self::wait), but gives up after `timeout`, returning `None`
+    /// if no wakeup occurred in the meantime.
+    #[inline]
+    pub fn wait_timeout(&self, timeout: core::time::Duration) -> Option<num::NonZeroUsize> {
+        let (count, condvar) = &*self.inner;
+
+        let (mut count, _) = condvar
+            .wait_timeout_while(
+                count.lock().unwrap_or_else(|e| e.into_inner()),
+                timeout,
+                |c| *c == 0,
+            )
+            .unwrap_or_else(|e| e.into_inner());
+
+        num::NonZeroUsize::new(core::mem::take(&mut *count))
+    }
+}
+
+#[cfg(feature = "std")]
+impl Waker for CondvarWaker {
+    /// Adds `times` pending wakeups, and wakes all waiting threads.
+    ///
+    /// Not real-time safe, as this locks the internal mutex.
+    #[inline]
+    fn wake(&mut self, times: num::NonZeroUsize) {
+        let (count, condvar) = &*self.inner;
+
+        {
+            let mut count = count.lock().unwrap_or_else(|e| e.into_inner());
+            *count = count.saturating_add(times.get());
+        }
+
+        condvar.notify_all();
+    }
+}
+
+/// `Waker` implementation based on a Linux `eventfd`.
+///
+/// Wakeups are counted by the kernel, and the file descriptor, exposed through
+/// [`AsFd`](std::os::fd::AsFd), becomes readable as long as some are pending. It can thus
+/// be registered in the same `poll` (or `epoll`) set as a socket, letting a thread sleep
+/// until either the socket is readable, or new audio is produced, whichever comes first.
+///
+/// # Real-time safety
+///
+/// Waking writes to the (non-blocking) file descriptor, a single system call that never
+/// blocks, and takes no lock shared with the woken thread.
+#[cfg(all(target_os = "linux", feature = "eventfd"))]
+#[derive(Debug, Clone)]
+pub struct EventFdWaker {
+    fd: alloc::sync::Arc<std::os::fd::OwnedFd>,
+}
+
+#[cfg(all(target_os = "linux", feature = "eventfd"))]
+impl EventFdWaker {
+    /// Creates a new waker, with no pending wakeups.
+    pub fn new() -> std::io::Result<Self> {
+        // SAFETY: no pointers are involved
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // SAFETY: fd was just created, and is owned by no-one else
+        let fd = unsafe { <std::os::fd::OwnedFd as std::os::fd::FromRawFd>::from_raw_fd(fd) };
+
+        Ok(Self {
+            fd: alloc::sync::Arc::new(fd),
+        })
+    }
+
+    #[inline(always)]
+    fn raw_fd(&self) -> std::os::fd::RawFd {
+        std::os::fd::AsRawFd::as_raw_fd(&*self.fd)
+    }
+
+    /// Returns the number of pending wakeups, and resets it, without blocking.
+    #[inline]
+    pub fn take_pending(&self) -> usize {
+        let mut count = 0u64;
+
+        // SAFETY: count is valid for writes of its size.
+        // Fails (with EAGAIN) if no wakeups are pending.
+        let res = unsafe {
+            libc::read(
+                self.raw_fd(),
+                (&raw mut count).cast(),
+                mem::size_of::<u64>(),
+            )
+        };
+
+        if res == mem::size_of::<u64>() as isize {
+            usize::try_from(count).unwrap_or(usize::MAX)
+        } else {
+            0
+        }
+    }
+
+    /// Waits for the file descriptor to become readable, for at most `timeout_ms`
+    /// milliseconds, or indefinitely if negative.
+    fn poll(&self, timeout_ms: libc::c_int) {
+        let mut pollfd = libc::pollfd {
+            fd: self.raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        // SAFETY: pollfd is a valid array of one pollfd.
+        // Errors (EINTR) are handled by the callers, as spurious wakeups.
+        unsafe { libc::poll(&raw mut pollfd, 1, timeout_ms) };
+    }
+
+    /// Blocks until at least one wakeup is pending, then returns the number of pending
+    /// wakeups, and resets it.
+    #[inline]
+    pub fn wait(&self) -> num::NonZeroUsize {
+        loop {
+            if let Some(count) = num::NonZeroUsize::new(self.take_pending()) {
+                return count;
+            }
+
+            self.poll(-1);
+        }
+    }
+
+    /// Same as [`wait`](Self::wait), but gives up after `timeout`, returning `None`
+    /// if no wakeup occurred in the meantime.
+    #[inline]
+    pub fn wait_timeout(&self, timeout: core::time::Duration) -> Option<num::NonZeroUsize> {
+        let start = std::time::Instant::now();
+
+        loop {
+            if let Some(count) = num::NonZeroUsize::new(self.take_pending()) {
+                return Some(count);
+            }
+
+            let remaining = timeout.checked_sub(start.elapsed())?;
+
+            // round up, to not spin on sub-millisecond remainders
+            let ms = remaining.as_nanos().div_ceil(1_000_000);
+            self.poll(ms.try_into().unwrap_or(libc::c_int::MAX));
+
+            if remaining.is_zero() {
+                return num::NonZeroUsize::new(self.take_pending());
+            }
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "eventfd"))]
+impl std::os::fd::AsFd for EventFdWaker {
+    #[inline(always)]
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "eventfd"))]
+impl Waker for EventFdWaker {
+    /// Adds `times` pending wakeups, making the file descriptor readable.
+    #[inline]
+    fn wake(&mut self, times: num::NonZeroUsize) {
+        let count = times.get() as u64;
+
+        // SAFETY: count is valid for reads of its size.
+        // Only fails (with EAGAIN) if the kernel's counter would overflow, in which case
+        // enough wakeups are already pending.
+        unsafe {
+            libc::write(
+                self.raw_fd(),
+                (&raw const count).cast(),
+                mem::size_of::<u64>(),
+            )
+        };
+    }
+}
+
+/// How a consumer thread is woken when work becomes available, selected at runtime.
+///
+/// Each variant forwards to the corresponding [`Waker`] implementation.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub enum WakeStrategy {
+    /// Unparks a thread, which must park itself, and wait on nothing else.
+    Unpark(std::thread::Thread),
+    /// Counts wakeups any thread can wait on, see [`CondvarWaker`].
+    Condvar(CondvarWaker),
+    /// Counts wakeups in a file descriptor, that can be polled alongside sockets,
+    /// see [`EventFdWaker`].
+    #[cfg(all(target_os = "linux", feature = "eventfd"))]
+    EventFd(EventFdWaker),
+}
+
+#[cfg(feature = "std")]
+impl Waker for WakeStrategy {
+    #[inline]
+    fn wake(&mut self, times: num::NonZeroUsize) {
+        match self {
+            Self::Unpark(thread) => thread.wake(times),
+            Self::Condvar(waker) => waker.wake(times),
+            #[cfg(all(target_os = "linux", feature = "eventfd"))]
+            Self::EventFd(waker) => waker.wake(times),
+        }
+    }
+}
+
 /// A counter adapter that tracks progress through fixed-size periods.
 ///
 /// `PeriodicCounter` wraps another [`Counter`] and observes how many
@@ -114,6 +369,8 @@ pub struct PeriodicCounter<C, W> {
     counter: C,
     waker: W,
     period: num::NonZeroUsize,
+    /// Value of the underlying counter from which periods are measured.
+    origin: u64,
 }
 
 impl<C, W> PeriodicCounter<C, W> {
@@ -128,6 +385,7 @@ impl<C, W> PeriodicCounter<C, W> {
             period,
             waker,
             counter,
+            origin: 0,
         }
     }
 
@@ -139,13 +397,43 @@ impl<C, W> PeriodicCounter<C, W> {
 }
 
 impl<C: Counter, W> PeriodicCounter<C, W> {
+    /// Returns the number of steps taken since the last [`reset`](Self::reset), or
+    /// since creation.
+    #[inline(always)]
+    fn elapsed(&self) -> u64 {
+        self.counter.current().strict_sub(self.origin)
+    }
+
     /// Returns the total number of period boundaries crossed so far.
     ///
     /// This value is derived from the underlying counter and does not
     /// depend on how many times `advance` was called.
     #[inline(always)]
     pub fn boundaries_crossed(&self) -> u64 {
-        self.counter.current() / num::NonZeroU64::try_from(self.period()).unwrap()
+        self.elapsed() / num::NonZeroU64::try_from(self.period()).unwrap()
+    }
+
+    /// Returns the position of the counter within the current period, in `[0, period)`.
+    #[inline(always)]
+    pub fn position(&self) -> usize {
+        (self.elapsed() % num::NonZeroU64::try_from(self.period()).unwrap())
+            .try_into()
+            .unwrap()
+    }
+
+    /// Returns the number of steps remaining until the next period boundary.
+    #[inline(always)]
+    pub fn remaining(&self) -> num::NonZeroUsize {
+        num::NonZeroUsize::new(self.period().get().strict_sub(self.position())).unwrap()
+    }
+
+    /// Restarts period tracking from the underlying counter's current value.
+    ///
+    /// The underlying counter itself is left untouched, only subsequent boundaries are
+    /// measured relative to its current value. No wake-up is triggered.
+    #[inline(always)]
+    pub fn reset(&mut self) {
+        self.origin = self.counter.current();
     }
 }
 
@@ -175,6 +463,165 @@ impl<C: Counter, W: Waker> Counter for PeriodicCounter<C, W> {
     }
 }
 
+/// A counter adapter that tracks progress through periods of non-integer length.
+///
+/// Like [`PeriodicCounter`], but the period is stored as a fixed-point number, with
+/// [`FRAC_BITS`](Self::FRAC_BITS) fractional bits. Boundaries are then crossed
+/// at irregular (integer) positions, such that, on average, they are exactly one period
+/// apart, without accumulating any rounding error.
+///
+/// This is useful when the wake-up period is expressed in time, and doesn't
+/// correspond to a whole number of samples (e.g. 5ms at 44.1kHz, `220.5` samples).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FractionalPeriodicCounter<C, W> {
+    counter: C,
+    waker: W,
+    /// Fixed-point period, with `FRAC_BITS` fractional bits.
+    period: num::NonZeroU64,
+    /// Value of the underlying counter from which periods are measured.
+    origin: u64,
+}
+
+impl<C, W> FractionalPeriodicCounter<C, W> {
+    /// Number of fractional bits of the fixed-point period.
+    pub const FRAC_BITS: u32 = 32;
+
+    /// Creates a new fractional periodic counter, from a fixed-point `period`, with
+    /// [`FRAC_BITS`](Self::FRAC_BITS) fractional bits.
+    ///
+    /// # Panics
+    ///
+    /// If `period` is less than one step.
+    #[inline(always)]
+    pub const fn from_fixed(period: num::NonZeroU64, counter: C, waker: W) -> Self {
+        assert!(
+            period.get() >> Self::FRAC_BITS != 0,
+            "period must be at least one step"
+        );
+
+        Self {
+            counter,
+            waker,
+            period,
+            origin: 0,
+        }
+    }
+
+    /// Creates a new fractional periodic counter, with a period of
+    /// `numerator / denominator` steps, rounded to the nearest representable value.
+    ///
+    /// # Panics
+    ///
+    /// If the resulting period is less than one step, or not representable.
+    #[inline(always)]
+    pub fn from_ratio(
+        numerator: num::NonZeroU64,
+        denominator: num::NonZeroU64,
+        counter: C,
+        waker: W,
+    ) -> Self {
+        let num = u128::from(numerator.get()) << Self::FRAC_BITS;
+        let den = u128::from(denominator.get());
+
+        let period = num.strict_add(den / 2) / den;
+
+        Self::from_fixed(
+            num::NonZeroU64::new(period.try_into().unwrap()).unwrap(),
+            counter,
+            waker,
+        )
+    }
+
+    /// Returns the configured fixed-point period, with [`FRAC_BITS`](Self::FRAC_BITS)
+    /// fractional bits.
+    #[inline(always)]
+    pub const fn period_fixed(&self) -> num::NonZeroU64 {
+        self.period
+    }
+
+    /// Returns the configured period, as a floating-point number.
+    #[inline(always)]
+    pub fn period(&self) -> f64 {
+        // u64 -> f64 precision loss is irrelevant for reasonable periods
+        self.period.get() as f64 / (1u64 << Self::FRAC_BITS) as f64
+    }
+
+    /// Returns the position (in steps, rounded up) of the `k`-th boundary, relative
+    /// to the origin.
+    #[inline(always)]
+    fn boundary_position(&self, k: u64) -> u64 {
+        (u128::from(k) * u128::from(self.period.get()))
+            .div_ceil(1 << Self::FRAC_BITS)
+            .try_into()
+            .unwrap()
+    }
+}
+
+impl<C: Counter, W> FractionalPeriodicCounter<C, W> {
+    /// Returns the number of steps taken since the last [`reset`](Self::reset), or
+    /// since creation.
+    #[inline(always)]
+    fn elapsed(&self) -> u64 {
+        self.counter.current().strict_sub(self.origin)
+    }
+
+    /// Returns the total number of period boundaries crossed so far.
+    #[inline(always)]
+    pub fn boundaries_crossed(&self) -> u64 {
+        ((u128::from(self.elapsed()) << Self::FRAC_BITS) / u128::from(self.period.get()))
+            .try_into()
+            .unwrap()
+    }
+
+    /// Returns the number of steps taken since the last boundary crossed.
+    #[inline(always)]
+    pub fn position(&self) -> usize {
+        let last = self.boundary_position(self.boundaries_crossed());
+        self.elapsed().strict_sub(last).try_into().unwrap()
+    }
+
+    /// Returns the number of steps remaining until the next period boundary.
+    #[inline(always)]
+    pub fn remaining(&self) -> num::NonZeroUsize {
+        let next = self.boundary_position(self.boundaries_crossed().strict_add(1));
+        num::NonZeroUsize::new(next.strict_sub(self.elapsed()).try_into().unwrap()).unwrap()
+    }
+
+    /// Restarts period tracking from the underlying counter's current value.
+    ///
+    /// The underlying counter itself is left untouched. No wake-up is triggered.
+    #[inline(always)]
+    pub fn reset(&mut self) {
+        self.origin = self.counter.current();
+    }
+}
+
+impl<C: Counter, W: Waker> Counter for FractionalPeriodicCounter<C, W> {
+    /// Advances the counter by `n` steps.
+    ///
+    /// If advancing causes one or more new period boundaries to be crossed,
+    /// the associated [`Waker`] is notified with the number of newly crossed
+    /// boundaries.
+    #[inline(always)]
+    fn advance(&mut self, n: usize) {
+        let b = self.boundaries_crossed();
+
+        self.counter.advance(n);
+
+        if let Some(n) =
+            num::NonZeroUsize::new(self.boundaries_crossed().strict_sub(b).try_into().unwrap())
+        {
+            self.waker.wake(n);
+        }
+    }
+
+    /// Returns the current value of the underlying counter.
+    #[inline(always)]
+    fn current(&self) -> u64 {
+        self.counter.current()
+    }
+}
+
 /// Shifts an iterator forward or backward by a signed deviation.
 ///
 /// Let `n = |deviation|`.
@@ -287,83 +734,553 @@ pub fn consumer_get_all<T>(rx: &mut rtrb::Consumer<T>) -> rtrb::chunks::ReadChun
     rx.read_chunk(rx.slots()).unwrap()
 }
 
-/// A receive-side adapter that associates values pulled from a ring buffer
-/// with a monotonically increasing external counter.
-/// 
-/// This allows consumers of a ring buffer to request data aligned to a specific
-/// index, transparently handling gaps and overruns, lazily, and without allocations.
-/// 
-/// Useful for decoupling data production and consumption, handling packet loss, audio cycle
-/// skips, and jitter. All while receiving data according to a stable, logical timeline.
-pub struct IndexedRx<Counter, Elem> {
-    rx: rtrb::Consumer<Elem>,
-    counter: Counter,
-}
+/// Splits the `n` elements starting at `offset`, in the concatenation of two slices, the
+/// first of which has length `first_len`, into ranges of each slice.
+///
+/// The second slice is assumed to be long enough to hold the remaining elements.
+#[inline(always)]
+fn split_range(
+    first_len: usize,
+    offset: usize,
+    n: usize,
+) -> (core::ops::Range<usize>, core::ops::Range<usize>) {
+    let first_start = offset.min(first_len);
+    let first_end = offset.strict_add(n).min(first_len);
+    let second_start = offset.strict_sub(first_start);
+    let second_end = second_start.strict_add(n.strict_sub(first_end.strict_sub(first_start)));
 
-impl<Counter, Elem> IndexedRx<Counter, Elem> {
-    /// Creates a new `IndexedRx` from a ring buffer consumer and a counter.
-    /// 
-    /// The counter is assumed to represent the logical index corresponding
-    /// to the next value expected from the consumer.
-    /// 
-    /// Ideally, at the time of calling this function `counter.current()` should
-    /// return 0. 
-    #[inline(always)]
-    pub fn new(rx: rtrb::Consumer<Elem>, counter: Counter) -> Self {
-        Self { rx, counter }
-    }
+    (first_start..first_end, second_start..second_end)
 }
 
-impl<Ctr: Counter, Elem> IndexedRx<Ctr, Elem> {
-    /// Attempts to receive all currently available values from the ring buffer
-    /// and align them to the requested logical index.
-    ///
-    /// The `idx` parameter represents the desired logical index of the first
-    /// returned element. If the underlying data stream is ahead or behind
-    /// this index, the returned iterator is adjusted accordingly:
-    ///
-    /// - If data is *ahead*, excess elements are skipped.
-    /// - If data is *behind*, missing elements are synthesized using `pad_fn`.
-    ///
-    /// This method never blocks and performs no allocation. All adjustments
-    /// are applied lazily via iterator composition.
-    // TODO: we cannot implement ExactSizeIterator for this, because Chain doesn't
-    // implement it for some reason, even though it's size is known.
-    #[inline]
-    pub fn recv(&mut self, idx: u64, pad_fn: impl FnMut() -> Elem) -> impl IntoIterator<Item = Elem> {
-        let deviation: isize = idx
-            .checked_signed_diff(self.counter.current())
-            .unwrap()
-            .try_into()
-            .unwrap();
-
-        let in_samples = consumer_get_all(&mut self.rx);
-        let iter = ReadChunksIterCounter::new(in_samples, &mut self.counter);
+/// A writer over a byte ring buffer's write chunk, that commits exactly the number of
+/// bytes written to it, when dropped.
+///
+/// Use [`commit`](Self::commit) to commit explicitly, and learn how many bytes were.
+///
+/// With the `std` feature, it also implements `std::io::Write`.
+#[derive(Debug)]
+pub struct ChunkWriteGuard<'a> {
+    /// Always `Some`, until committed.
+    chunk: Option<rtrb::chunks::WriteChunkUninit<'a, u8>>,
+    n_written: usize,
+}
 
-        shift_iter(iter, deviation, pad_fn)
+impl<'a> ChunkWriteGuard<'a> {
+    /// Creates a new guard over the given chunk, with no bytes written.
+    #[inline(always)]
+    pub fn new(chunk: rtrb::chunks::WriteChunkUninit<'a, u8>) -> Self {
+        Self {
+            chunk: Some(chunk),
+            n_written: 0,
+        }
     }
 
-    /// Returns the current value of the internal conter.
+    /// Creates a new guard over all available slots of `tx`.
     #[inline(always)]
-    pub fn current(&self) -> u64 {
-        self.counter.current()
+    pub fn all(tx: &'a mut rtrb::Producer<u8>) -> Self {
+        Self::new(producer_get_all(tx))
     }
 
-    /// Returns the number of elements that can be read at the moment this function was called
+    /// Returns the number of bytes written so far.
     #[inline(always)]
-    pub fn available_slots(&self) -> usize {
-        self.rx.slots()
+    pub fn n_written(&self) -> usize {
+        self.n_written
     }
 
-    /// Returns whether the producer side of the ring buffer has been destroyed.
+    /// Returns the number of bytes that can still be written.
     #[inline(always)]
-    pub fn is_abandoned(&self) -> bool {
-        self.rx.is_abandoned()
+    pub fn remaining(&self) -> usize {
+        self.chunk
+            .as_ref()
+            .unwrap()
+            .len()
+            .strict_sub(self.n_written)
     }
-}
 
-/// A sender-side adapter that associates values written to a ring buffer
-/// with a monotonically increasing external counter.
+    /// Writes as many bytes of `buf` as fit in the chunk, and returns their count.
+    #[inline]
+    pub fn write_bytes(&mut self, buf: &[u8]) -> usize {
+        let n = self.remaining().min(buf.len());
+        let (first, second) = self.chunk.as_mut().unwrap().as_mut_slices();
+        let (first_range, second_range) = split_range(first.len(), self.n_written, n);
+
+        let (buf_first, buf_second) = buf[..n].split_at(first_range.len());
+        first[first_range].write_copy_of_slice(buf_first);
+        second[second_range].write_copy_of_slice(buf_second);
+
+        self.n_written = self.n_written.strict_add(n);
+
+        n
+    }
+
+    /// Commits all bytes written so far, and returns their count.
+    #[inline(always)]
+    pub fn commit(mut self) -> usize {
+        self.commit_inner();
+        self.n_written
+    }
+
+    #[inline(always)]
+    fn commit_inner(&mut self) {
+        if let Some(chunk) = self.chunk.take() {
+            // SAFETY: exactly the first n_written slots have been initialized in write
+            unsafe { chunk.commit(self.n_written) };
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for ChunkWriteGuard<'_> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Ok(self.write_bytes(buf))
+    }
+
+    #[inline(always)]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for ChunkWriteGuard<'_> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.commit_inner();
+    }
+}
+
+/// A reader over a byte ring buffer's read chunk, that commits (frees) exactly the
+/// number of bytes read from it, when dropped.
+///
+/// Use [`commit`](Self::commit) to commit explicitly, and learn how many bytes were.
+///
+/// With the `std` feature, it also implements `std::io::Read`.
+#[derive(Debug)]
+pub struct ChunkReadGuard<'a> {
+    /// Always `Some`, until committed.
+    chunk: Option<rtrb::chunks::ReadChunk<'a, u8>>,
+    n_read: usize,
+}
+
+impl<'a> ChunkReadGuard<'a> {
+    /// Creates a new guard over the given chunk, with no bytes read.
+    #[inline(always)]
+    pub fn new(chunk: rtrb::chunks::ReadChunk<'a, u8>) -> Self {
+        Self {
+            chunk: Some(chunk),
+            n_read: 0,
+        }
+    }
+
+    /// Creates a new guard over all available slots of `rx`.
+    #[inline(always)]
+    pub fn all(rx: &'a mut rtrb::Consumer<u8>) -> Self {
+        Self::new(consumer_get_all(rx))
+    }
+
+    /// Returns the number of bytes read so far.
+    #[inline(always)]
+    pub fn n_read(&self) -> usize {
+        self.n_read
+    }
+
+    /// Returns the number of bytes that can still be read.
+    #[inline(always)]
+    pub fn remaining(&self) -> usize {
+        self.chunk.as_ref().unwrap().len().strict_sub(self.n_read)
+    }
+
+    /// Reads as many bytes as are available into `buf`, and returns their count.
+    #[inline]
+    pub fn read_bytes(&mut self, buf: &mut [u8]) -> usize {
+        let n = self.remaining().min(buf.len());
+        let (first, second) = self.chunk.as_ref().unwrap().as_slices();
+        let (first_range, second_range) = split_range(first.len(), self.n_read, n);
+
+        let (buf_first, buf_second) = buf[..n].split_at_mut(first_range.len());
+        buf_first.copy_from_slice(&first[first_range]);
+        buf_second.copy_from_slice(&second[second_range]);
+
+        self.n_read = self.n_read.strict_add(n);
+
+        n
+    }
+
+    /// Commits all bytes read so far, and returns their count.
+    #[inline(always)]
+    pub fn commit(mut self) -> usize {
+        self.commit_inner();
+        self.n_read
+    }
+
+    #[inline(always)]
+    fn commit_inner(&mut self) {
+        if let Some(chunk) = self.chunk.take() {
+            chunk.commit(self.n_read);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Read for ChunkReadGuard<'_> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Ok(self.read_bytes(buf))
+    }
+}
+
+impl Drop for ChunkReadGuard<'_> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.commit_inner();
+    }
+}
+
+/// Direction of a [`DriftError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DriftDirection {
+    /// The requested index is ahead of the expected one.
+    Ahead,
+    /// The requested index is behind the expected one.
+    Behind,
+}
+
+/// Error returned when the index requested from an [`IndexedTx`] or [`IndexedRx`] is too
+/// far from the one expected by its internal counter to be compensated for by padding
+/// or skipping elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DriftError {
+    /// The index expected by the internal counter.
+    pub expected: u64,
+    /// The requested index.
+    pub got: u64,
+    pub direction: DriftDirection,
+}
+
+impl DriftError {
+    #[inline(always)]
+    fn new(expected: u64, got: u64) -> Self {
+        Self {
+            expected,
+            got,
+            direction: if got > expected {
+                DriftDirection::Ahead
+            } else {
+                DriftDirection::Behind
+            },
+        }
+    }
+
+    /// Returns the absolute distance between the expected and requested indices.
+    #[inline(always)]
+    pub fn distance(&self) -> u64 {
+        self.expected.abs_diff(self.got)
+    }
+}
+
+impl core::fmt::Display for DriftError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let direction = match self.direction {
+            DriftDirection::Ahead => "ahead of",
+            DriftDirection::Behind => "behind",
+        };
+
+        write!(
+            f,
+            "requested index {} is {} elements {direction} the expected index {}, too far to \
+            compensate for",
+            self.got,
+            self.distance(),
+            self.expected,
+        )
+    }
+}
+
+impl core::error::Error for DriftError {}
+
+/// Number of elements written by [`IndexedRx::recv_into`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RecvCount {
+    /// Number of elements read from the ring buffer.
+    pub copied: usize,
+    /// Number of padding elements, written either because the requested index was behind
+    /// the expected one, or because not enough elements were available (underrun).
+    pub padded: usize,
+}
+
+/// Splits a deviation into a number of elements to pad (if negative) or to skip
+/// (if positive), in that order.
+#[inline(always)]
+fn split_deviation(deviation: isize) -> (usize, usize) {
+    if deviation.is_negative() {
+        (deviation.unsigned_abs(), 0)
+    } else {
+        (0, deviation.unsigned_abs())
+    }
+}
+
+/// Returns `got - expected`, or a [`DriftError`] if it doesn't fit in an `isize`.
+#[inline(always)]
+fn deviation(expected: u64, got: u64) -> Result<isize, DriftError> {
+    got.checked_signed_diff(expected)
+        .and_then(|d| d.try_into().ok())
+        .ok_or(DriftError::new(expected, got))
+}
+
+/// Telemetry gathered by an [`IndexedTx`] or [`IndexedRx`], useful for tuning ring buffer
+/// sizes.
+///
+/// See [`IndexedTx::take_stats`] and [`IndexedRx::take_stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IndexedQueueStats {
+    /// Number of elements pushed into (for senders) or popped from (for receivers) the
+    /// ring buffer, including padding, for senders, and skipped elements, for receivers.
+    pub transferred: u64,
+    /// Number of elements skipped to compensate for drift.
+    ///
+    /// For [`IndexedTx::send`], this is the number of elements requested to be skipped,
+    /// which may exceed the number of elements actually provided.
+    pub skipped: u64,
+    /// Number of padding elements inserted to compensate for drift.
+    ///
+    /// For [`IndexedRx::recv`], this is the number of padding elements scheduled at the
+    /// start of the returned iterator, whether it is consumed entirely or not.
+    pub padded: u64,
+    /// Lowest number of available slots observed.
+    pub min_slots: Option<usize>,
+    /// Highest number of available slots observed.
+    pub max_slots: Option<usize>,
+}
+
+impl IndexedQueueStats {
+    #[inline(always)]
+    fn record(&mut self, n_skipped: usize, n_padded: usize, n_slots: usize) {
+        self.skipped = self.skipped.saturating_add(n_skipped.try_into().unwrap());
+        self.padded = self.padded.saturating_add(n_padded.try_into().unwrap());
+        self.min_slots = Some(self.min_slots.map_or(n_slots, |n| n.min(n_slots)));
+        self.max_slots = Some(self.max_slots.map_or(n_slots, |n| n.max(n_slots)));
+    }
+
+    /// Returns the gathered stats, and resets them. `transferred` is derived from the
+    /// progress of the counter since the previous call.
+    #[inline(always)]
+    fn take(&mut self, counter_start: &mut Option<u64>, counter_current: u64) -> Self {
+        let start = counter_start
+            .replace(counter_current)
+            .unwrap_or(counter_current);
+
+        Self {
+            transferred: counter_current.strict_sub(start),
+            ..mem::take(self)
+        }
+    }
+}
+
+/// Writes `*n_padding` copies of `pad_value`, then the elements of `*values`, into `dst`,
+/// until it is full, and updates `n_padding` and `values` to what remains to be written.
+///
+/// # Panics
+///
+/// if `dst` is longer than the padding and values combined.
+#[inline(always)]
+fn pad_and_copy_uninit<T: Copy>(
+    dst: &mut [mem::MaybeUninit<T>],
+    n_padding: &mut usize,
+    pad_value: T,
+    values: &mut &[T],
+) {
+    let (padding, dst) = dst.split_at_mut((*n_padding).min(dst.len()));
+
+    for slot in padding.iter_mut() {
+        slot.write(pad_value);
+    }
+
+    *n_padding = n_padding.strict_sub(padding.len());
+
+    let (copied, rest) = values.split_at(dst.len());
+    dst.write_copy_of_slice(copied);
+    *values = rest;
+}
+
+/// Copies elements of the concatenation of `src.0` and `src.1`, starting at `offset`,
+/// into `dst`, until it is full.
+///
+/// # Panics
+///
+/// if there aren't enough elements after `offset` to fill `dst`.
+#[inline(always)]
+fn copy_from_split<T: Copy>(src: (&[T], &[T]), offset: usize, dst: &mut [T]) {
+    let (first, second) = src;
+
+    let first_offset = offset.min(first.len());
+    let first = &first[first_offset..];
+    let second = &second[offset.strict_sub(first_offset)..];
+
+    let (dst_first, dst_second) = dst.split_at_mut(first.len().min(dst.len()));
+    dst_first.copy_from_slice(&first[..dst_first.len()]);
+    dst_second.copy_from_slice(&second[..dst_second.len()]);
+}
+
+/// A receive-side adapter that associates values pulled from a ring buffer
+/// with a monotonically increasing external counter.
+/// 
+/// This allows consumers of a ring buffer to request data aligned to a specific
+/// index, transparently handling gaps and overruns, lazily, and without allocations.
+/// 
+/// Useful for decoupling data production and consumption, handling packet loss, audio cycle
+/// skips, and jitter. All while receiving data according to a stable, logical timeline.
+pub struct IndexedRx<Counter, Elem> {
+    rx: rtrb::Consumer<Elem>,
+    counter: Counter,
+    stats: IndexedQueueStats,
+    /// Counter value at the start of the current stats gathering period.
+    stats_counter_start: Option<u64>,
+}
+
+impl<Counter, Elem> IndexedRx<Counter, Elem> {
+    /// Creates a new `IndexedRx` from a ring buffer consumer and a counter.
+    /// 
+    /// The counter is assumed to represent the logical index corresponding
+    /// to the next value expected from the consumer.
+    /// 
+    /// Ideally, at the time of calling this function `counter.current()` should
+    /// return 0. 
+    #[inline(always)]
+    pub fn new(rx: rtrb::Consumer<Elem>, counter: Counter) -> Self {
+        Self {
+            rx,
+            counter,
+            stats: IndexedQueueStats {
+                transferred: 0,
+                skipped: 0,
+                padded: 0,
+                min_slots: None,
+                max_slots: None,
+            },
+            stats_counter_start: None,
+        }
+    }
+}
+
+impl<Ctr: Counter, Elem> IndexedRx<Ctr, Elem> {
+    /// Attempts to receive all currently available values from the ring buffer
+    /// and align them to the requested logical index.
+    ///
+    /// The `idx` parameter represents the desired logical index of the first
+    /// returned element. If the underlying data stream is ahead or behind
+    /// this index, the returned iterator is adjusted accordingly:
+    ///
+    /// - If data is *ahead*, excess elements are skipped.
+    /// - If data is *behind*, missing elements are synthesized using `pad_fn`.
+    ///
+    /// This method never blocks and performs no allocation. All adjustments
+    /// are applied lazily via iterator composition.
+    ///
+    /// Returns an error, and receives nothing, if `idx` is too far from the internal
+    /// counter's value.
+    // TODO: we cannot implement ExactSizeIterator for this, because Chain doesn't
+    // implement it for some reason, even though its size is known.
+    #[inline]
+    pub fn recv(
+        &mut self,
+        idx: u64,
+        pad_fn: impl FnMut() -> Elem,
+    ) -> Result<impl IntoIterator<Item = Elem>, DriftError> {
+        let current = self.counter.current();
+        let deviation = deviation(current, idx)?;
+        self.stats_counter_start.get_or_insert(current);
+
+        let in_samples = consumer_get_all(&mut self.rx);
+
+        let (n_padding, n_skipped) = split_deviation(deviation);
+        self.stats
+            .record(n_skipped.min(in_samples.len()), n_padding, in_samples.len());
+
+        let iter = ReadChunksIterCounter::new(in_samples, &mut self.counter);
+
+        Ok(shift_iter(iter, deviation, pad_fn))
+    }
+
+    /// Same as [`recv`](Self::recv), but copies the values into `out`, in bulk, instead of
+    /// returning an iterator.
+    ///
+    /// Unlike `recv`, only as many values as fit in `out` are read from the ring buffer,
+    /// the remaining ones are left for subsequent calls.
+    ///
+    /// `out` is always filled entirely: if not enough values are available (underrun), the
+    /// rest of it is filled with `pad_value` too, instead of being left untouched. The
+    /// internal counter isn't advanced past the values actually read.
+    #[inline]
+    pub fn recv_into(
+        &mut self,
+        idx: u64,
+        out: &mut [Elem],
+        pad_value: Elem,
+    ) -> Result<RecvCount, DriftError>
+    where
+        Elem: Copy,
+    {
+        let current = self.counter.current();
+        let deviation = deviation(current, idx)?;
+        self.stats_counter_start.get_or_insert(current);
+
+        let (n_padding, n_skipped) = split_deviation(deviation);
+
+        let (padding, out) = out.split_at_mut(n_padding.min(out.len()));
+        padding.fill(pad_value);
+
+        let n_available = self.rx.slots();
+        let n_skipped = n_skipped.min(n_available);
+        let n_copied = n_available.strict_sub(n_skipped).min(out.len());
+        let n_read = n_skipped.strict_add(n_copied);
+
+        self.stats.record(n_skipped, padding.len(), n_available);
+
+        let (out, underrun) = out.split_at_mut(n_copied);
+
+        let chunk = self.rx.read_chunk(n_read).unwrap();
+        copy_from_split(chunk.as_slices(), n_skipped, out);
+        chunk.commit_all();
+
+        self.counter.advance(n_read);
+
+        underrun.fill(pad_value);
+
+        Ok(RecvCount {
+            copied: n_copied,
+            padded: padding.len().strict_add(underrun.len()),
+        })
+    }
+
+    /// Returns the current value of the internal counter.
+    #[inline(always)]
+    pub fn current(&self) -> u64 {
+        self.counter.current()
+    }
+
+    /// Returns the number of elements that can be read at the moment this function was called
+    #[inline(always)]
+    pub fn available_slots(&self) -> usize {
+        self.rx.slots()
+    }
+
+    /// Returns whether the producer side of the ring buffer has been destroyed.
+    #[inline(always)]
+    pub fn is_abandoned(&self) -> bool {
+        self.rx.is_abandoned()
+    }
+
+    /// Returns the stats gathered since the previous call (or since creation), and
+    /// resets them.
+    #[inline(always)]
+    pub fn take_stats(&mut self) -> IndexedQueueStats {
+        self.stats
+            .take(&mut self.stats_counter_start, self.counter.current())
+    }
+}
+
+/// A sender-side adapter that associates values written to a ring buffer
+/// with a monotonically increasing external counter.
 ///
 /// This allows callers to submit values intended for a specific logical position
 /// transparently handling gaps and overlaps.
@@ -373,6 +1290,9 @@ impl<Ctr: Counter, Elem> IndexedRx<Ctr, Elem> {
 pub struct IndexedTx<Counter, Elem> {
     counter: Counter,
     tx: rtrb::Producer<Elem>,
+    stats: IndexedQueueStats,
+    /// Counter value at the start of the current stats gathering period.
+    stats_counter_start: Option<u64>,
 }
 
 impl<Counter, Elem> IndexedTx<Counter, Elem> {
@@ -382,7 +1302,18 @@ impl<Counter, Elem> IndexedTx<Counter, Elem> {
     /// to the next element that will be written into the producer.
     #[inline(always)]
     pub const fn new(tx: rtrb::Producer<Elem>, counter: Counter) -> Self {
-        Self { counter, tx }
+        Self {
+            counter,
+            tx,
+            stats: IndexedQueueStats {
+                transferred: 0,
+                skipped: 0,
+                padded: 0,
+                min_slots: None,
+                max_slots: None,
+            },
+            stats_counter_start: None,
+        }
     }
 }
 
@@ -403,26 +1334,88 @@ impl<Ctr: Counter, Elem> IndexedTx<Ctr, Elem> {
     /// This method will silently not write the remaining elements
     /// if the ring buffer's capacity is too small. The internal counter will
     /// have kept track of the number of elements written.
+    ///
+    /// Returns an error, and sends nothing, if `idx` is too far from the internal
+    /// counter's value.
     #[inline]
     pub fn send(
         &mut self,
         idx: u64,
         values: impl IntoIterator<Item = Elem>,
         pad_fn: impl FnMut() -> Elem,
-    ) {
-        let deviation: isize = self.counter.current()
-            .checked_signed_diff(idx)
-            .unwrap()
-            .try_into()
-            .unwrap();
+    ) -> Result<(), DriftError> {
+        let deviation = self.deviation(idx)?;
+        self.stats_counter_start
+            .get_or_insert(self.counter.current());
+
+        let out_chunk = producer_get_all(&mut self.tx);
+        let n_slots = out_chunk.len();
 
         let out_iter = shift_iter(values, deviation, pad_fn);
-        let n_pushed_samples = producer_get_all(&mut self.tx).fill_from_iter(out_iter);
+        let n_pushed_samples = out_chunk.fill_from_iter(out_iter);
+
+        let (n_padding, n_skipped) = split_deviation(deviation);
+        self.stats
+            .record(n_skipped, n_padding.min(n_pushed_samples), n_slots);
 
         self.counter.advance(n_pushed_samples);
+
+        Ok(())
     }
-    
-    /// Returns the current value of the internal conter.
+
+    /// Returns by how many elements the internal counter is ahead of `idx`.
+    #[inline(always)]
+    fn deviation(&self, idx: u64) -> Result<isize, DriftError> {
+        let expected = self.counter.current();
+
+        deviation(expected, idx)?
+            .checked_neg()
+            .ok_or(DriftError::new(expected, idx))
+    }
+
+    /// Same as [`send`](Self::send), but copies the values from a contiguous slice, in bulk,
+    /// instead of writing them one by one.
+    ///
+    /// Returns the number of elements written into the ring buffer, padding included.
+    #[inline]
+    pub fn send_slice(
+        &mut self,
+        idx: u64,
+        values: &[Elem],
+        pad_value: Elem,
+    ) -> Result<usize, DriftError>
+    where
+        Elem: Copy,
+    {
+        let deviation = self.deviation(idx)?;
+        self.stats_counter_start
+            .get_or_insert(self.counter.current());
+
+        let (mut n_padding, n_skipped) = split_deviation(deviation);
+        let n_skipped = n_skipped.min(values.len());
+        let mut values = &values[n_skipped..];
+
+        let n_slots = self.tx.slots();
+        let n_pushed_samples = n_slots.min(n_padding.saturating_add(values.len()));
+
+        self.stats
+            .record(n_skipped, n_padding.min(n_pushed_samples), n_slots);
+
+        let mut chunk = self.tx.write_chunk_uninit(n_pushed_samples).unwrap();
+        let (first, second) = chunk.as_mut_slices();
+
+        pad_and_copy_uninit(first, &mut n_padding, pad_value, &mut values);
+        pad_and_copy_uninit(second, &mut n_padding, pad_value, &mut values);
+
+        // SAFETY: both slices of the chunk have been fully initialized above
+        unsafe { chunk.commit_all() };
+
+        self.counter.advance(n_pushed_samples);
+
+        Ok(n_pushed_samples)
+    }
+
+    /// Returns the current value of the internal counter.
     #[inline(always)]
     pub fn current(&self) -> u64 {
         self.counter.current()
@@ -434,9 +1427,853 @@ impl<Ctr: Counter, Elem> IndexedTx<Ctr, Elem> {
         self.tx.slots()
     }
 
+    /// Returns the total number of slots of the underlying ring buffer.
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        self.tx.buffer().capacity()
+    }
+
     /// Returns whether the consumer side of the ring buffer has been destroyed.
     #[inline(always)]
     pub fn is_abandoned(&self) -> bool {
         self.tx.is_abandoned()
     }
+
+    /// Returns the stats gathered since the previous call (or since creation), and
+    /// resets them.
+    #[inline(always)]
+    pub fn take_stats(&mut self) -> IndexedQueueStats {
+        self.stats
+            .take(&mut self.stats_counter_start, self.counter.current())
+    }
+}
+
+/// Error returned by [`FramedByteTx::try_push`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FramedPushError {
+    /// Not enough free space in the ring buffer, nothing was written.
+    Full {
+        /// Bytes needed to push the frame, length prefix included.
+        needed: usize,
+        /// Bytes available in the ring buffer.
+        available: usize,
+    },
+    /// The frame exceeds the maximum frame length of the queue.
+    TooLong { len: usize, max: usize },
+}
+
+impl core::fmt::Display for FramedPushError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Full { needed, available } => write!(
+                f,
+                "queue full: {needed} bytes needed, {available} available"
+            ),
+            Self::TooLong { len, max } => {
+                write!(f, "frame too long: {len} bytes, at most {max} allowed")
+            }
+        }
+    }
+}
+
+impl core::error::Error for FramedPushError {}
+
+/// Length of the prefix preceding each frame in a framed byte queue.
+const FRAME_PREFIX_LEN: usize = size_of::<u16>();
+
+/// Creates a new single-producer, single-consumer queue of variable-length byte frames,
+/// backed by a byte ring buffer of `capacity` bytes, accepting frames of at most
+/// `max_frame_len` bytes.
+///
+/// Each frame occupies its length plus a 2-byte prefix in the ring buffer.
+///
+/// # Panics
+///
+/// If `max_frame_len` exceeds `u16::MAX`.
+pub fn framed_byte_queue(capacity: usize, max_frame_len: usize) -> (FramedByteTx, FramedByteRx) {
+    assert!(max_frame_len <= usize::from(u16::MAX));
+
+    let (tx, rx) = rtrb::RingBuffer::new(capacity);
+
+    (
+        FramedByteTx { tx, max_frame_len },
+        FramedByteRx {
+            rx,
+            scratch: iter::repeat_n(0, max_frame_len).collect(),
+        },
+    )
+}
+
+/// Producer side of a framed byte queue, see [`framed_byte_queue`].
+#[derive(Debug)]
+pub struct FramedByteTx {
+    tx: rtrb::Producer<u8>,
+    max_frame_len: usize,
+}
+
+impl FramedByteTx {
+    /// Pushes a frame, all or nothing.
+    ///
+    /// The frame only becomes visible to the consumer once entirely written.
+    pub fn try_push(&mut self, frame: &[u8]) -> Result<(), FramedPushError> {
+        if frame.len() > self.max_frame_len {
+            return Err(FramedPushError::TooLong {
+                len: frame.len(),
+                max: self.max_frame_len,
+            });
+        }
+
+        let needed = FRAME_PREFIX_LEN.strict_add(frame.len());
+        let available = self.tx.slots();
+
+        let mut chunk = self
+            .tx
+            .write_chunk_uninit(needed)
+            .map_err(|_| FramedPushError::Full { needed, available })?;
+
+        let prefix = u16::try_from(frame.len()).unwrap().to_le_bytes();
+        let (first, second) = chunk.as_mut_slices();
+
+        let mut offset = 0;
+        for src in [prefix.as_slice(), frame] {
+            let (first_range, second_range) = split_range(first.len(), offset, src.len());
+            let (src_first, src_second) = src.split_at(first_range.len());
+            first[first_range].write_copy_of_slice(src_first);
+            second[second_range].write_copy_of_slice(src_second);
+            offset = offset.strict_add(src.len());
+        }
+
+        // SAFETY: all `needed` bytes of the chunk have been initialized above
+        unsafe { chunk.commit_all() };
+
+        Ok(())
+    }
+
+    /// Returns the number of free bytes in the ring buffer.
+    #[inline(always)]
+    pub fn available_bytes(&self) -> usize {
+        self.tx.slots()
+    }
+
+    /// Returns the maximum frame length accepted by this queue.
+    #[inline(always)]
+    pub fn max_frame_len(&self) -> usize {
+        self.max_frame_len
+    }
+
+    /// Returns whether the consumer side of the queue has been destroyed.
+    #[inline(always)]
+    pub fn is_abandoned(&self) -> bool {
+        self.tx.is_abandoned()
+    }
+}
+
+/// Consumer side of a framed byte queue, see [`framed_byte_queue`].
+#[derive(Debug)]
+pub struct FramedByteRx {
+    rx: rtrb::Consumer<u8>,
+    /// Used to make frames straddling the end of the ring buffer contiguous.
+    scratch: Box<[u8]>,
+}
+
+impl FramedByteRx {
+    /// Pops the next frame, if any, and passes it, as a contiguous slice, to `f`.
+    ///
+    /// The frame is only copied if it wraps around the end of the ring buffer.
+    pub fn pop_with<R>(&mut self, f: impl FnOnce(&[u8]) -> R) -> Option<R> {
+        let prefix_chunk = self.rx.read_chunk(FRAME_PREFIX_LEN).ok()?;
+
+        let mut prefix = [0; FRAME_PREFIX_LEN];
+        copy_from_split(prefix_chunk.as_slices(), 0, &mut prefix);
+        let len = usize::from(u16::from_le_bytes(prefix));
+
+        // frames are committed at once, the payload is always there if its prefix is
+        let chunk = self
+            .rx
+            .read_chunk(FRAME_PREFIX_LEN.strict_add(len))
+            .unwrap();
+        let (first, second) = chunk.as_slices();
+        let (first_range, second_range) = split_range(first.len(), FRAME_PREFIX_LEN, len);
+
+        let res = if second_range.is_empty() {
+            f(&first[first_range])
+        } else if first_range.is_empty() {
+            f(&second[second_range])
+        } else {
+            let frame = &mut self.scratch[..len];
+            copy_from_split((first, second), FRAME_PREFIX_LEN, frame);
+            f(frame)
+        };
+
+        chunk.commit_all();
+
+        Some(res)
+    }
+
+    /// Returns the number of bytes, length prefixes included, waiting in the ring buffer.
+    #[inline(always)]
+    pub fn pending_bytes(&self) -> usize {
+        self.rx.slots()
+    }
+
+    /// Returns whether the producer side of the queue has been destroyed.
+    #[inline(always)]
+    pub fn is_abandoned(&self) -> bool {
+        self.rx.is_abandoned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// Waker counting the wakeups it receives.
+    struct Wakes<'a>(&'a core::cell::Cell<u64>);
+
+    impl Waker for Wakes<'_> {
+        fn wake(&mut self, times: num::NonZeroUsize) {
+            self.0.set(self.0.get() + times.get() as u64);
+        }
+    }
+
+    fn nz(n: u64) -> num::NonZeroU64 {
+        num::NonZeroU64::new(n).unwrap()
+    }
+
+    /// Returns a counter with a period of `220.5` steps, e.g. 5ms at 44.1kHz.
+    fn counter_220_5(
+        wakes: &core::cell::Cell<u64>,
+    ) -> FractionalPeriodicCounter<GenericCounter, Wakes<'_>> {
+        FractionalPeriodicCounter::from_ratio(nz(441), nz(2), GenericCounter::new(), Wakes(wakes))
+    }
+
+    #[test]
+    fn fractional_boundaries() {
+        let wakes = core::cell::Cell::new(0);
+        let mut counter = counter_220_5(&wakes);
+        assert_eq!(counter.period_fixed().get(), 441 << 31);
+        assert_eq!(counter.period(), 220.5);
+
+        let mut boundaries = Vec::new();
+
+        for i in 1..=882 {
+            let before = wakes.get();
+            counter.advance(1);
+            if wakes.get() != before {
+                boundaries.push(i);
+                assert_eq!(counter.position(), 0);
+            }
+        }
+
+        // rounded up, alternating between 221 and 220 steps
+        assert_eq!(boundaries, [221, 441, 662, 882]);
+        assert_eq!(counter.boundaries_crossed(), 4);
+        assert_eq!(counter.remaining().get(), 221);
+
+        counter.advance(100);
+        assert_eq!(counter.position(), 100);
+        assert_eq!(counter.remaining().get(), 121);
+    }
+
+    #[test]
+    fn fractional_no_accumulated_error() {
+        let wakes = core::cell::Cell::new(0);
+        let mut counter = counter_220_5(&wakes);
+
+        // in uneven increments, exactly 2 boundaries every 441 steps, forever
+        let mut elapsed = 0u64;
+        for i in 0..100_000 {
+            let n = i % 97 + 1;
+            counter.advance(n);
+            elapsed += n as u64;
+            assert_eq!(wakes.get(), elapsed * 2 / 441);
+        }
+
+        // far away from the origin
+        counter.advance(1 << 40);
+        elapsed += 1 << 40;
+        assert_eq!(wakes.get(), elapsed * 2 / 441);
+        assert_eq!(counter.boundaries_crossed(), elapsed * 2 / 441);
+        let remaining = counter.remaining().get() as u64;
+        assert_eq!((elapsed + remaining) * 2 / 441, wakes.get() + 1);
+        assert_eq!((elapsed + remaining - 1) * 2 / 441, wakes.get());
+    }
+
+    #[test]
+    fn fractional_rounding() {
+        let counter = |num, den| FractionalPeriodicCounter::from_ratio(nz(num), nz(den), (), ());
+
+        // exactly representable
+        assert_eq!(counter(3, 2).period_fixed().get(), 3 << 31);
+        // half an ulp, rounded up
+        let period = counter((1 << 33) + 1, 1 << 33).period_fixed();
+        assert_eq!(period.get(), (1 << 32) + 1);
+        // a third of an ulp, rounded down
+        assert_eq!(counter(10, 3).period_fixed().get(), (10 << 32) / 3);
+
+        // the smallest allowed period, waking on every step
+        let wakes = core::cell::Cell::new(0);
+        let mut counter = FractionalPeriodicCounter::from_fixed(
+            nz(1 << 32),
+            GenericCounter::new(),
+            Wakes(&wakes),
+        );
+        counter.advance(5);
+        assert_eq!(wakes.get(), 5);
+        assert_eq!(counter.remaining().get(), 1);
+    }
+
+    #[test]
+    #[should_panic = "period must be at least one step"]
+    fn fractional_period_too_short() {
+        FractionalPeriodicCounter::from_fixed(nz((1 << 32) - 1), (), ());
+    }
+
+    #[test]
+    fn fractional_reset() {
+        let wakes = core::cell::Cell::new(0);
+        let mut counter = counter_220_5(&wakes);
+
+        counter.advance(300);
+        assert_eq!(wakes.get(), 1);
+        assert_eq!(counter.position(), 79);
+
+        counter.reset();
+        assert_eq!(counter.current(), 300);
+        assert_eq!(counter.position(), 0);
+        assert_eq!(counter.remaining().get(), 221);
+        assert_eq!(counter.boundaries_crossed(), 0);
+        assert_eq!(wakes.get(), 1);
+
+        counter.advance(221);
+        assert_eq!(wakes.get(), 2);
+    }
+
+    /// Returns an `IndexedRx` holding `values`, starting at index `0`.
+    fn indexed_rx(capacity: usize, values: &[u32]) -> IndexedRx<GenericCounter, u32> {
+        let (mut tx, rx) = rtrb::RingBuffer::new(capacity);
+        for &v in values {
+            tx.push(v).unwrap();
+        }
+        IndexedRx::new(rx, GenericCounter::new())
+    }
+
+    /// Returns a byte ring buffer of capacity `8`, whose read and write positions are both
+    /// at `5`, so that chunks of more than `3` bytes wrap around.
+    fn wrapped_byte_queue() -> (rtrb::Producer<u8>, rtrb::Consumer<u8>) {
+        let (mut tx, mut rx) = rtrb::RingBuffer::new(8);
+        let chunk = tx.write_chunk_uninit(5).unwrap();
+        chunk.fill_from_iter(iter::repeat(0));
+        rx.read_chunk(5).unwrap().commit_all();
+        (tx, rx)
+    }
+
+    #[test]
+    fn write_guard_wrap_around() {
+        let (mut tx, mut rx) = wrapped_byte_queue();
+
+        let mut guard = ChunkWriteGuard::all(&mut tx);
+        assert_eq!(guard.remaining(), 8);
+        assert_eq!(guard.write_bytes(&[1, 2]), 2);
+        // spans the split
+        assert_eq!(guard.write_bytes(&[3, 4, 5]), 3);
+        assert_eq!(guard.write_bytes(&[6]), 1);
+        assert_eq!(guard.n_written(), 6);
+        assert_eq!(guard.commit(), 6);
+
+        assert_eq!(tx.slots(), 2);
+        assert_eq!(rx.slots(), 6);
+
+        let chunk = rx.read_chunk(6).unwrap();
+        let (first, second) = chunk.as_slices();
+        assert_eq!(first, [1, 2, 3]);
+        assert_eq!(second, [4, 5, 6]);
+    }
+
+    #[test]
+    fn write_guard_commits_on_drop() {
+        let (mut tx, mut rx) = wrapped_byte_queue();
+
+        {
+            let mut guard = ChunkWriteGuard::all(&mut tx);
+            // only the first 8 bytes fit
+            assert_eq!(guard.write_bytes(&[7; 10]), 8);
+            assert_eq!(guard.remaining(), 0);
+            assert_eq!(guard.write_bytes(&[7]), 0);
+        }
+
+        assert_eq!(rx.slots(), 8);
+        rx.read_chunk(8).unwrap().commit_all();
+
+        // nothing written, nothing committed
+        drop(ChunkWriteGuard::all(&mut tx));
+        assert_eq!(rx.slots(), 0);
+    }
+
+    #[test]
+    fn read_guard_wrap_around() {
+        let (mut tx, mut rx) = wrapped_byte_queue();
+        tx.write_chunk_uninit(6).unwrap().fill_from_iter(1..);
+
+        let mut buf = [0; 4];
+
+        {
+            let mut guard = ChunkReadGuard::all(&mut rx);
+            assert_eq!(guard.read_bytes(&mut buf[..1]), 1);
+            // spans the split
+            assert_eq!(guard.read_bytes(&mut buf[1..]), 3);
+            assert_eq!(guard.n_read(), 4);
+            assert_eq!(guard.remaining(), 2);
+        }
+
+        assert_eq!(buf, [1, 2, 3, 4]);
+        assert_eq!(rx.slots(), 2);
+        assert_eq!(tx.slots(), 6);
+
+        let mut guard = ChunkReadGuard::all(&mut rx);
+        assert_eq!(guard.read_bytes(&mut buf), 2);
+        assert_eq!(buf[..2], [5, 6]);
+        assert_eq!(guard.commit(), 2);
+        assert_eq!(rx.slots(), 0);
+    }
+
+    #[test]
+    fn copy_from_split_wrap_around() {
+        let src: (&[u8], &[u8]) = (&[0, 1, 2], &[3, 4, 5, 6]);
+
+        for offset in 0..=7 {
+            for n in 0..=7 - offset {
+                let mut dst = [0xff; 7];
+                copy_from_split(src, offset, &mut dst[..n]);
+
+                let expected: Vec<u8> = (offset as u8..).take(n).collect();
+                assert_eq!(dst[..n], expected, "offset: {offset}, n: {n}");
+                assert!(dst[n..].iter().all(|&b| b == 0xff));
+            }
+        }
+    }
+
+    /// Returns a frame of `len` bytes, whose contents depend on `seed`.
+    fn frame(seed: usize, len: usize) -> Vec<u8> {
+        (seed..).take(len).map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn framed_capacity() {
+        let (mut tx, mut rx) = framed_byte_queue(16, 8);
+        assert_eq!(tx.available_bytes(), 16);
+        assert_eq!(tx.max_frame_len(), 8);
+
+        assert_eq!(
+            tx.try_push(&[0; 9]),
+            Err(FramedPushError::TooLong { len: 9, max: 8 })
+        );
+
+        tx.try_push(&frame(0, 6)).unwrap();
+        assert_eq!(tx.available_bytes(), 8);
+        assert_eq!(rx.pending_bytes(), 8);
+
+        // all or nothing
+        assert_eq!(
+            tx.try_push(&[0; 7]),
+            Err(FramedPushError::Full {
+                needed: 9,
+                available: 8
+            })
+        );
+        assert_eq!(tx.available_bytes(), 8);
+
+        tx.try_push(&frame(1, 6)).unwrap();
+        assert_eq!(tx.available_bytes(), 0);
+
+        // empty frames still take room for their prefix
+        assert_eq!(
+            tx.try_push(&[]),
+            Err(FramedPushError::Full {
+                needed: 2,
+                available: 0
+            })
+        );
+
+        assert_eq!(rx.pop_with(<[u8]>::to_vec), Some(frame(0, 6)));
+        assert_eq!(rx.pending_bytes(), 8);
+        assert_eq!(tx.available_bytes(), 8);
+
+        tx.try_push(&[]).unwrap();
+        assert_eq!(tx.available_bytes(), 6);
+
+        assert_eq!(rx.pop_with(<[u8]>::to_vec), Some(frame(1, 6)));
+        assert_eq!(rx.pop_with(<[u8]>::to_vec), Some(Vec::new()));
+        assert_eq!(rx.pop_with(<[u8]>::to_vec), None);
+        assert_eq!(rx.pending_bytes(), 0);
+        assert_eq!(tx.available_bytes(), 16);
+    }
+
+    #[test]
+    fn framed_wrap_around() {
+        // the capacity isn't a multiple of any frame size, so that both the prefixes and
+        // the payloads end up straddling the end of the ring buffer, at every offset
+        let (mut tx, mut rx) = framed_byte_queue(11, 6);
+
+        for i in 0..200 {
+            let len = i % 7;
+            tx.try_push(&frame(i, len)).unwrap();
+            let popped = rx.pop_with(<[u8]>::to_vec);
+            assert_eq!(popped, Some(frame(i, len)), "frame {i}");
+        }
+
+        assert_eq!(rx.pending_bytes(), 0);
+    }
+
+    /// Spawns a thread blocking in `wait`, wakes it `times` times from this one, once
+    /// it had time to block, and returns what `wait` returned.
+    #[cfg(feature = "std")]
+    fn woken_waiter<W: Waker>(
+        mut waker: W,
+        times: usize,
+        wait: impl FnOnce() -> num::NonZeroUsize + Send,
+    ) -> usize {
+        std::thread::scope(|s| {
+            let waiter = s.spawn(wait);
+
+            std::thread::sleep(core::time::Duration::from_millis(50));
+            assert!(!waiter.is_finished(), "returned before being woken");
+
+            waker.wake(num::NonZeroUsize::new(times).unwrap());
+
+            waiter.join().unwrap().get()
+        })
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn condvar_waker_wakes_waiter() {
+        let waker = CondvarWaker::new();
+        let rx = waker.clone();
+
+        assert_eq!(woken_waiter(waker.clone(), 3, || rx.wait()), 3);
+        assert_eq!(waker.take_pending(), 0);
+
+        let timeout = core::time::Duration::from_millis(10);
+        assert_eq!(waker.wait_timeout(timeout), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn condvar_waker_counts_early_wakeups() {
+        let mut waker = CondvarWaker::new();
+        waker.wake(num::NonZeroUsize::new(2).unwrap());
+        waker.wake(num::NonZeroUsize::new(1).unwrap());
+
+        // no-one was waiting, not lost
+        assert_eq!(waker.wait().get(), 3);
+        assert_eq!(waker.take_pending(), 0);
+    }
+
+    #[cfg(all(target_os = "linux", feature = "eventfd"))]
+    #[test]
+    fn eventfd_waker_wakes_waiter() {
+        let waker = EventFdWaker::new().unwrap();
+        let rx = waker.clone();
+
+        assert_eq!(woken_waiter(waker.clone(), 3, || rx.wait()), 3);
+        assert_eq!(waker.take_pending(), 0);
+
+        let timeout = core::time::Duration::from_millis(10);
+        assert_eq!(waker.wait_timeout(timeout), None);
+
+        let mut early = waker.clone();
+        early.wake(num::NonZeroUsize::new(2).unwrap());
+        early.wake(num::NonZeroUsize::new(1).unwrap());
+        assert_eq!(
+            waker.wait_timeout(timeout).map(num::NonZeroUsize::get),
+            Some(3)
+        );
+    }
+
+    #[cfg(all(target_os = "linux", feature = "eventfd"))]
+    #[test]
+    fn eventfd_waker_polls_with_socket() {
+        use std::os::fd::{AsFd, AsRawFd};
+
+        let sock = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let waker = EventFdWaker::new().unwrap();
+        let rx = waker.clone();
+
+        // sleeps on "socket readable OR woken", and is woken by the latter
+        let readable = woken_waiter(WakeStrategy::EventFd(waker), 1, || {
+            let mut fds = [sock.as_raw_fd(), rx.as_fd().as_raw_fd()].map(|fd| libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            });
+
+            // SAFETY: fds is a valid array of pollfds, of the given length
+            let n = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, 5000) };
+            assert_eq!(n, 1);
+            assert_eq!(fds[0].revents, 0);
+            assert_ne!(fds[1].revents & libc::POLLIN, 0);
+
+            rx.wait()
+        });
+
+        assert_eq!(readable, 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn wake_strategy_forwards() {
+        let waker = CondvarWaker::new();
+        let mut strategy = WakeStrategy::Condvar(waker.clone());
+        strategy.wake(num::NonZeroUsize::new(4).unwrap());
+        assert_eq!(waker.take_pending(), 4);
+
+        // wakes the current thread's next park
+        let mut strategy = WakeStrategy::Unpark(std::thread::current());
+        strategy.wake(num::NonZeroUsize::new(1).unwrap());
+        std::thread::park();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn framed_stress() {
+        const N_FRAMES: usize = 100_000;
+        const MAX_LEN: usize = 32;
+
+        let (mut tx, mut rx) = framed_byte_queue(64, MAX_LEN);
+
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                for i in 0..N_FRAMES {
+                    let frame = frame(i, i % (MAX_LEN + 1));
+                    while let Err(e) = tx.try_push(&frame) {
+                        assert!(matches!(e, FramedPushError::Full { .. }));
+                        std::thread::yield_now();
+                    }
+                }
+            });
+
+            let mut i = 0;
+            while i < N_FRAMES {
+                match rx.pop_with(|bytes| bytes == frame(i, i % (MAX_LEN + 1))) {
+                    Some(ok) => {
+                        assert!(ok, "frame {i} corrupted");
+                        i += 1;
+                    }
+                    None => std::thread::yield_now(),
+                }
+            }
+        });
+
+        assert_eq!(rx.pop_with(|_| ()), None);
+    }
+
+    /// Returns an `IndexedTx` over a ring buffer of capacity `8`, whose read and write
+    /// positions are both at `5`, so that chunks of more than `3` elements wrap around,
+    /// along with the ring buffer's consumer.
+    fn wrapped_indexed_tx() -> (IndexedTx<GenericCounter, u32>, rtrb::Consumer<u32>) {
+        let (mut tx, mut rx) = rtrb::RingBuffer::new(8);
+        tx.write_chunk_uninit(5)
+            .unwrap()
+            .fill_from_iter(iter::repeat(0));
+        rx.read_chunk(5).unwrap().commit_all();
+        (IndexedTx::new(tx, GenericCounter::new()), rx)
+    }
+
+    /// Pops all the elements of `rx`.
+    fn drain(rx: &mut rtrb::Consumer<u32>) -> Vec<u32> {
+        rx.read_chunk(rx.slots()).unwrap().into_iter().collect()
+    }
+
+    #[test]
+    fn send_slice_wrap_around() {
+        let (mut tx, mut rx) = wrapped_indexed_tx();
+
+        assert_eq!(tx.send_slice(0, &[1, 2, 3, 4, 5, 6], 0), Ok(6));
+        assert_eq!(tx.current(), 6);
+
+        {
+            let chunk = rx.read_chunk(6).unwrap();
+            let (first, second) = chunk.as_slices();
+            assert_eq!(first, [1, 2, 3]);
+            assert_eq!(second, [4, 5, 6]);
+        }
+
+        rx.read_chunk(6).unwrap().commit_all();
+
+        // the ring buffer's positions are now at 3, the padding spans the split
+        assert_eq!(tx.send_slice(12, &[7, 8], 0xff), Ok(8));
+        assert_eq!(tx.current(), 14);
+
+        let chunk = rx.read_chunk(8).unwrap();
+        let (first, second) = chunk.as_slices();
+        assert_eq!(first, [0xff; 5]);
+        assert_eq!(second, [0xff, 7, 8]);
+    }
+
+    #[test]
+    fn send_slice_short_write() {
+        let (mut tx, mut rx) = wrapped_indexed_tx();
+        let values: Vec<u32> = (1..=10).collect();
+
+        // only 3 padding elements and the first 5 values fit
+        assert_eq!(tx.send_slice(3, &values, 0), Ok(8));
+        assert_eq!(tx.current(), 8);
+        assert_eq!(tx.available_slots(), 0);
+
+        // full, nothing is written, nor counted
+        assert_eq!(tx.send_slice(8, &values, 0), Ok(0));
+        assert_eq!(tx.current(), 8);
+
+        assert_eq!(drain(&mut rx), [0, 0, 0, 1, 2, 3, 4, 5]);
+
+        let stats = tx.take_stats();
+        assert_eq!((stats.transferred, stats.padded), (8, 3));
+        assert_eq!((stats.min_slots, stats.max_slots), (Some(0), Some(8)));
+    }
+
+    #[test]
+    fn send_slice_empty() {
+        let (mut tx, mut rx) = wrapped_indexed_tx();
+
+        assert_eq!(tx.send_slice(0, &[], 0), Ok(0));
+        assert_eq!(tx.current(), 0);
+        assert_eq!(rx.slots(), 0);
+
+        // padding is still written
+        assert_eq!(tx.send_slice(2, &[], 0xff), Ok(2));
+        assert_eq!(drain(&mut rx), [0xff, 0xff]);
+
+        // all values are behind, and skipped
+        assert_eq!(tx.send_slice(0, &[1, 2], 0), Ok(0));
+        assert_eq!(tx.current(), 2);
+        assert_eq!(tx.take_stats().skipped, 2);
+    }
+
+    #[test]
+    fn indexed_queue_stats() {
+        // no activity since the previous call
+        let reset = IndexedQueueStats {
+            transferred: 0,
+            skipped: 0,
+            padded: 0,
+            min_slots: None,
+            max_slots: None,
+        };
+
+        let (tx, rx) = rtrb::RingBuffer::new(8);
+        let mut tx = IndexedTx::new(tx, GenericCounter::new());
+        let mut rx = IndexedRx::new(rx, GenericCounter::new());
+
+        tx.send(0, [1, 2, 3], || 0).unwrap();
+        // ahead, padded
+        tx.send(5, [6, 7], || 0).unwrap();
+        // overflow, only one value fits
+        tx.send(7, [8, 9, 10], || 0).unwrap();
+        // behind, skipped (and full anyway)
+        tx.send(6, [7, 8], || 0).unwrap();
+
+        assert_eq!(
+            tx.take_stats(),
+            IndexedQueueStats {
+                transferred: 8,
+                skipped: 2,
+                padded: 2,
+                min_slots: Some(0),
+                max_slots: Some(8),
+            }
+        );
+        assert_eq!(tx.take_stats(), reset);
+
+        let mut out = [0; 8];
+        let count = rx.recv_into(0, &mut out[..3], 0xff).unwrap();
+        assert_eq!((count.copied, count.padded), (3, 0));
+        assert_eq!(out[..3], [1, 2, 3]);
+
+        // underflow, only 5 values are available, the rest of `out` is filled with
+        // padding, not counted in the stats, as it doesn't compensate for drift
+        let count = rx.recv_into(3, &mut out, 0xff).unwrap();
+        assert_eq!((count.copied, count.padded), (5, 3));
+        assert_eq!(out, [0, 0, 6, 7, 8, 0xff, 0xff, 0xff]);
+
+        tx.send(8, [9, 10, 11, 12], || 0).unwrap();
+
+        // behind, skipped
+        let count = rx.recv_into(10, &mut out[..2], 0xff).unwrap();
+        assert_eq!((count.copied, count.padded), (2, 0));
+        assert_eq!(out[..2], [11, 12]);
+
+        // ahead, padded, and underflowing
+        let count = rx.recv_into(10, &mut out[..3], 0xff).unwrap();
+        assert_eq!((count.copied, count.padded), (0, 3));
+
+        assert_eq!(
+            rx.take_stats(),
+            IndexedQueueStats {
+                transferred: 12,
+                skipped: 2,
+                padded: 2,
+                min_slots: Some(0),
+                max_slots: Some(8),
+            }
+        );
+
+        assert_eq!(rx.take_stats(), reset);
+
+        // only the values sent since the first call
+        let stats = tx.take_stats();
+        assert_eq!(stats.transferred, 4);
+        assert_eq!((stats.min_slots, stats.max_slots), (Some(8), Some(8)));
+    }
+
+    #[test]
+    fn recv_into_underrun() {
+        let mut rx = indexed_rx(8, &[1, 2, 3]);
+        // stale data, that must not survive the call
+        let mut out = [0xaa; 6];
+
+        let count = rx.recv_into(0, &mut out, 0).unwrap();
+
+        assert_eq!(out, [1, 2, 3, 0, 0, 0]);
+        assert_eq!((count.copied, count.padded), (3, 3));
+        assert_eq!(rx.current(), 3);
+        assert_eq!(rx.available_slots(), 0);
+
+        // drained, the whole output is silenced
+        let count = rx.recv_into(3, &mut out, 0).unwrap();
+
+        assert_eq!(out, [0; 6]);
+        assert_eq!((count.copied, count.padded), (0, 6));
+        assert_eq!(rx.current(), 3);
+    }
+
+    #[test]
+    fn recv_into_behind_and_underrun() {
+        let mut rx = indexed_rx(8, &[1, 2]);
+        let mut out = [0xaa; 6];
+
+        // requesting from index -2 (relative to the counter), so 2 padding values first
+        rx.counter.advance(2);
+        let count = rx.recv_into(0, &mut out, 0).unwrap();
+
+        assert_eq!(out, [0, 0, 1, 2, 0, 0]);
+        assert_eq!((count.copied, count.padded), (2, 4));
+        assert_eq!(rx.current(), 4);
+    }
+
+    #[test]
+    fn recv_into_leaves_excess() {
+        let values: Vec<u32> = (1..=8).collect();
+        let mut rx = indexed_rx(8, &values);
+        let mut out = [0; 5];
+
+        // skips the first 2 values, and leaves the last one in the ring buffer
+        let count = rx.recv_into(2, &mut out, 0).unwrap();
+
+        assert_eq!(out, [3, 4, 5, 6, 7]);
+        assert_eq!((count.copied, count.padded), (5, 0));
+        assert_eq!(rx.current(), 7);
+        assert_eq!(rx.available_slots(), 1);
+    }
 }
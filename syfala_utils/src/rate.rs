@@ -0,0 +1,231 @@
+//! Windowed throughput estimation.
+
+use core::time::Duration;
+
+/// Estimates a byte rate over a sliding window of `N` seconds.
+///
+/// Bytes are accumulated in a ring of `N` one-second buckets, the estimate being the
+/// total of the last `N` _completed_ seconds, divided by their number. It is thus
+/// updated once per second, and doesn't fluctuate with the position in the current one.
+/// Once bytes are recorded during the current second, its bucket holds them, and only
+/// the last `N - 1` completed seconds remain.
+///
+/// Times are durations elapsed since an arbitrary origin, chosen by the caller, (e.g. a
+/// connection's establishment) and are expected to be (mostly) monotonic. Bytes recorded
+/// later than the window's span are dropped.
+///
+/// It doesn't allocate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RateEstimator<const N: usize> {
+    /// Bytes recorded during each second, second `s` being stored at `s % N`.
+    buckets: [u64; N],
+    /// Most recent second bytes have been recorded for, buckets of later seconds are
+    /// stale.
+    head: u64,
+    /// First second bytes have been recorded for, if any.
+    start: Option<u64>,
+}
+
+impl<const N: usize> Default for RateEstimator<N> {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> RateEstimator<N> {
+    const WINDOW: u64 = {
+        assert!(N > 0, "the window must span at least one second");
+        N as u64
+    };
+
+    /// Creates a new estimator, with no bytes recorded.
+    #[inline(always)]
+    pub const fn new() -> Self {
+        Self {
+            buckets: [0; N],
+            head: 0,
+            start: None,
+        }
+    }
+
+    /// Forgets all recorded bytes.
+    #[inline(always)]
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    #[inline(always)]
+    fn bucket(&mut self, second: u64) -> &mut u64 {
+        // N fits in a u64, and the remainder is less than N
+        &mut self.buckets[(second % Self::WINDOW) as usize]
+    }
+
+    /// Records `n_bytes` at `at`.
+    pub fn record(&mut self, at: Duration, n_bytes: usize) {
+        let second = at.as_secs();
+        let n_bytes = u64::try_from(n_bytes).unwrap_or(u64::MAX);
+
+        let Some(start) = self.start else {
+            self.start = Some(second);
+            self.head = second;
+            *self.bucket(second) = n_bytes;
+            return;
+        };
+
+        if second > self.head {
+            // clear the buckets of the seconds skipped over, at most the whole ring
+            let n_stale = (second - self.head).min(Self::WINDOW);
+            for s in second - n_stale + 1..=second {
+                *self.bucket(s) = 0;
+            }
+            self.head = second;
+        } else if second + Self::WINDOW <= self.head || second < start {
+            // already overwritten, or before the first recording
+            return;
+        }
+
+        let bucket = self.bucket(second);
+        *bucket = bucket.saturating_add(n_bytes);
+    }
+
+    /// Returns the average rate, in bytes per second, over the completed seconds of the
+    /// window ending at `now`.
+    ///
+    /// Returns `0` until a full second has elapsed since the first recording.
+    pub fn bytes_per_sec(&self, now: Duration) -> u64 {
+        let Some(start) = self.start else {
+            return 0;
+        };
+
+        let now = now.as_secs();
+        let end = self.head.saturating_add(1);
+
+        // completed seconds of the window, excluding those before the first recording,
+        // and those whose buckets have been reused for later seconds
+        let first = now
+            .saturating_sub(Self::WINDOW)
+            .max(start)
+            .max(end.saturating_sub(Self::WINDOW));
+        let n_secs = now.saturating_sub(first);
+
+        if n_secs == 0 {
+            return 0;
+        }
+
+        // seconds after the head are silent
+        let total = (first..now.min(end))
+            .map(|s| self.buckets[(s % Self::WINDOW) as usize])
+            .fold(0u64, u64::saturating_add);
+
+        total / n_secs
+    }
+
+    /// Same as [`bytes_per_sec`](Self::bytes_per_sec), but in bits per second.
+    #[inline(always)]
+    pub fn bits_per_sec(&self, now: Duration) -> u64 {
+        self.bytes_per_sec(now).saturating_mul(8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Records `packet_len` bytes every 10ms, from `from` (included) to `to` (excluded),
+    /// both in milliseconds.
+    fn feed<const N: usize>(rate: &mut RateEstimator<N>, from: u64, to: u64, packet_len: usize) {
+        for t in (from..to).step_by(10) {
+            rate.record(ms(t), packet_len);
+        }
+    }
+
+    #[test]
+    fn nothing_recorded() {
+        let rate = RateEstimator::<5>::new();
+
+        assert_eq!(rate.bytes_per_sec(ms(0)), 0);
+        assert_eq!(rate.bytes_per_sec(ms(10_000)), 0);
+    }
+
+    #[test]
+    fn steady_schedule() {
+        let mut rate = RateEstimator::<5>::new();
+
+        // 480 bytes every 10ms, 48 kB/s
+        feed(&mut rate, 0, 999, 480);
+        // the first second isn't complete yet
+        assert_eq!(rate.bytes_per_sec(ms(999)), 0);
+
+        // by 9999, second 4's bucket has been reused for second 9, the estimate then only
+        // spans seconds 5 to 8
+        let mut t = 1000;
+        for now in [1000, 2500, 5000, 9999] {
+            feed(&mut rate, t, now, 480);
+            t = now;
+            assert_eq!(rate.bytes_per_sec(ms(now)), 48_000, "{now}");
+        }
+        assert_eq!(rate.bits_per_sec(ms(9999)), 384_000);
+    }
+
+    #[test]
+    fn window_slides() {
+        let mut rate = RateEstimator::<5>::new();
+
+        // 1 kB/s for 5 seconds, then 3 kB/s
+        feed(&mut rate, 0, 5000, 10);
+        assert_eq!(rate.bytes_per_sec(ms(5000)), 1000);
+
+        feed(&mut rate, 5000, 7000, 30);
+        // seconds 2 to 4 at 1 kB/s, 5 and 6 at 3 kB/s
+        assert_eq!(rate.bytes_per_sec(ms(7000)), 1800);
+
+        feed(&mut rate, 7000, 10_000, 30);
+        assert_eq!(rate.bytes_per_sec(ms(10_000)), 3000);
+    }
+
+    #[test]
+    fn starts_at_first_record() {
+        let mut rate = RateEstimator::<5>::new();
+
+        // seconds before the first recording don't dilute the estimate
+        feed(&mut rate, 3000, 5000, 20);
+        assert_eq!(rate.bytes_per_sec(ms(4000)), 2000);
+        assert_eq!(rate.bytes_per_sec(ms(5000)), 2000);
+
+        // nor do records preceding it
+        rate.record(ms(2500), 1_000_000);
+        assert_eq!(rate.bytes_per_sec(ms(5000)), 2000);
+    }
+
+    #[test]
+    fn silence() {
+        let mut rate = RateEstimator::<5>::new();
+
+        feed(&mut rate, 0, 3000, 10);
+        // silent seconds count as zero, until the window is past the last record
+        assert_eq!(rate.bytes_per_sec(ms(5000)), 600);
+        assert_eq!(rate.bytes_per_sec(ms(8000)), 0);
+
+        // recording again after a long gap clears the stale buckets
+        rate.record(ms(20_000), 5000);
+        assert_eq!(rate.bytes_per_sec(ms(21_000)), 1000);
+        // too late to be recorded
+        rate.record(ms(15_999), 5000);
+        assert_eq!(rate.bytes_per_sec(ms(21_000)), 1000);
+    }
+
+    #[test]
+    fn reset() {
+        let mut rate = RateEstimator::<2>::new();
+
+        feed(&mut rate, 0, 3000, 10);
+        rate.reset();
+        assert_eq!(rate, RateEstimator::new());
+        assert_eq!(rate.bytes_per_sec(ms(3000)), 0);
+    }
+}
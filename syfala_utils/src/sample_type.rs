@@ -29,208 +29,143 @@ pub trait SampleTypeSilence {
     const SILENCE: Self;
 }
 
-// TODO: is it correct that the silence value for unsigned integers is the middle value?
-
-impl SampleSize for u8 {
-    const SIZE: num::NonZeroU8 = num::NonZeroU8::new(1).unwrap();
-}
-
-impl SampleFromBytes for u8 {
-    fn from_bytes(slice: &[u8]) -> Self {
-        Self::from_le_bytes(slice.try_into().unwrap())
-    }
-}
-
-impl SampleToBytes for u8 {
-    fn to_bytes(self, slice: &mut [u8]) {
-        *slice.as_mut_array().unwrap() = self.to_le_bytes();
-    }
-}
-
-impl SampleTypeSilence for u8 {
-    const SILENCE: Self = Self::MAX / 2 + 1;
-}
-
-impl SampleSize for u16 {
-    const SIZE: num::NonZeroU8 = num::NonZeroU8::new(2).unwrap();
-}
-
-impl SampleFromBytes for u16 {
-    fn from_bytes(slice: &[u8]) -> Self {
-        Self::from_le_bytes(slice.try_into().unwrap())
-    }
-}
-
-impl SampleToBytes for u16 {
-    fn to_bytes(self, slice: &mut [u8]) {
-        *slice.as_mut_array().unwrap() = self.to_le_bytes();
-    }
-}
-
-impl SampleTypeSilence for u16 {
-    const SILENCE: Self = Self::MAX / 2 + 1;
-}
-
-// TODO: u24?
-
-impl SampleSize for u32 {
-    const SIZE: num::NonZeroU8 = num::NonZeroU8::new(4).unwrap();
-}
-
-impl SampleFromBytes for u32 {
-    fn from_bytes(slice: &[u8]) -> Self {
-        Self::from_le_bytes(slice.try_into().unwrap())
-    }
-}
-
-impl SampleToBytes for u32 {
-    fn to_bytes(self, slice: &mut [u8]) {
-        *slice.as_mut_array().unwrap() = self.to_le_bytes();
-    }
-}
-
-impl SampleTypeSilence for u32 {
-    const SILENCE: Self = Self::MAX / 2 + 1;
-}
-
-impl SampleSize for u64 {
-    const SIZE: num::NonZeroU8 = num::NonZeroU8::new(8).unwrap();
-}
-
-impl SampleFromBytes for u64 {
-    fn from_bytes(slice: &[u8]) -> Self {
-        Self::from_le_bytes(slice.try_into().unwrap())
-    }
-}
-
-impl SampleToBytes for u64 {
-    fn to_bytes(self, slice: &mut [u8]) {
-        *slice.as_mut_array().unwrap() = self.to_le_bytes();
-    }
-}
-
-impl SampleTypeSilence for u64 {
-    const SILENCE: Self = Self::MAX / 2 + 1;
-}
-
-impl SampleSize for i8 {
-    const SIZE: num::NonZeroU8 = num::NonZeroU8::new(1).unwrap();
-}
-
-impl SampleFromBytes for i8 {
-    fn from_bytes(slice: &[u8]) -> Self {
-        Self::from_le_bytes(slice.try_into().unwrap())
-    }
-}
-
-impl SampleToBytes for i8 {
-    fn to_bytes(self, slice: &mut [u8]) {
-        *slice.as_mut_array().unwrap() = self.to_le_bytes();
-    }
-}
-
-impl SampleTypeSilence for i8 {
-    const SILENCE: Self = 0;
-}
-
-impl SampleSize for i16 {
-    const SIZE: num::NonZeroU8 = num::NonZeroU8::new(2).unwrap();
-}
-
-impl SampleFromBytes for i16 {
-    fn from_bytes(slice: &[u8]) -> Self {
-        Self::from_le_bytes(slice.try_into().unwrap())
-    }
-}
-
-impl SampleToBytes for i16 {
-    fn to_bytes(self, slice: &mut [u8]) {
-        *slice.as_mut_array().unwrap() = self.to_le_bytes();
-    }
-}
-
-impl SampleTypeSilence for i16 {
-    const SILENCE: Self = 0;
-}
-
-// TODO: i24?
-
-impl SampleSize for i32 {
-    const SIZE: num::NonZeroU8 = num::NonZeroU8::new(4).unwrap();
-}
-
-impl SampleFromBytes for i32 {
-    fn from_bytes(slice: &[u8]) -> Self {
-        Self::from_le_bytes(slice.try_into().unwrap())
-    }
-}
-
-impl SampleToBytes for i32 {
-    fn to_bytes(self, slice: &mut [u8]) {
-        *slice.as_mut_array().unwrap() = self.to_le_bytes();
-    }
-}
-
-impl SampleTypeSilence for i32 {
-    const SILENCE: Self = 0;
-}
-
-impl SampleSize for i64 {
-    const SIZE: num::NonZeroU8 = num::NonZeroU8::new(8).unwrap();
-}
-
-impl SampleFromBytes for i64 {
-    fn from_bytes(slice: &[u8]) -> Self {
-        Self::from_le_bytes(slice.try_into().unwrap())
+/// Decodes a slice of packed samples, as received in an audio message's payload.
+///
+/// Unlike feeding bytes one by one to a padder, this walks the slice one sample at a time.
+/// Trailing bytes not forming a whole sample are ignored.
+#[inline(always)]
+pub fn samples_from_bytes<T: SampleFromBytes>(
+    bytes: &[u8],
+) -> impl ExactSizeIterator<Item = T> + DoubleEndedIterator {
+    bytes
+        .chunks_exact(usize::from(T::SIZE.get()))
+        .map(T::from_bytes)
+}
+
+/// Implements the sample conversion traits for primitive numeric types, with
+/// little-endian wire order.
+macro_rules! impl_sample_type {
+    ($($t:ty => $silence:expr),* $(,)?) => {$(
+        impl SampleSize for $t {
+            const SIZE: num::NonZeroU8 = num::NonZeroU8::new(size_of::<$t>() as u8).unwrap();
+        }
+
+        impl SampleFromBytes for $t {
+            fn from_bytes(slice: &[u8]) -> Self {
+                Self::from_le_bytes(slice.try_into().unwrap())
+            }
+        }
+
+        impl SampleToBytes for $t {
+            fn to_bytes(self, slice: &mut [u8]) {
+                *slice.as_mut_array().unwrap() = self.to_le_bytes();
+            }
+        }
+
+        impl SampleTypeSilence for $t {
+            const SILENCE: Self = $silence;
+        }
+    )*};
+}
+
+// Unsigned integer formats are offset binary: silence sits at the midpoint of the range
+
+// TODO: u24/i24?
+
+impl_sample_type! {
+    u8 => u8::MAX / 2 + 1,
+    u16 => u16::MAX / 2 + 1,
+    u32 => u32::MAX / 2 + 1,
+    u64 => u64::MAX / 2 + 1,
+    i8 => 0,
+    i16 => 0,
+    i32 => 0,
+    i64 => 0,
+    f32 => 0.,
+    f64 => 0.,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// Encodes `values` through a [`SampleByteStream`](crate::SampleByteStream), decodes
+    /// the bytes, fed in 3-byte packets, through an
+    /// [`AudioPacketSamplePadder`](crate::AudioPacketSamplePadder), and checks that the
+    /// samples come back bit-exact, and that the wire order is little-endian.
+    fn round_trip<T, const N: usize>(values: [T; N], le_bytes: impl Fn(T) -> Vec<u8>)
+    where
+        T: SampleToBytes + SampleFromBytes + SampleTypeSilence + Copy,
+    {
+        let mut stream = crate::SampleByteStream::new();
+        let bytes: Vec<u8> = stream.feed_samples(values).collect();
+
+        let expected: Vec<u8> = values.into_iter().flat_map(&le_bytes).collect();
+        assert_eq!(bytes, expected);
+
+        let mut padder = crate::AudioPacketSamplePadder::<T>::new();
+        let mut decoded = Vec::new();
+
+        for (i, packet) in bytes.chunks(3).enumerate() {
+            let byte_idx = u64::try_from(i * 3).unwrap();
+            decoded.extend(
+                padder
+                    .feed_bytes(byte_idx, packet.iter().copied(), || panic!("padded"))
+                    .into_iter()
+                    .flat_map(&le_bytes),
+            );
+        }
+
+        assert_eq!(decoded, expected);
+    }
+
+    macro_rules! round_trip_tests {
+        ($($t:ident),* $(,)?) => {$(
+            #[test]
+            fn $t() {
+                round_trip(
+                    [$t::MIN, 0 as $t, $t::MAX, $t::SILENCE],
+                    |v: $t| v.to_le_bytes().to_vec(),
+                );
+            }
+        )*};
+    }
+
+    round_trip_tests!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+    #[test]
+    fn float_special_values() {
+        round_trip(
+            [
+                f32::NAN,
+                -0.,
+                f32::INFINITY,
+                f32::NEG_INFINITY,
+                f32::MIN_POSITIVE / 2.,
+            ],
+            |v: f32| v.to_le_bytes().to_vec(),
+        );
+        round_trip(
+            [
+                f64::NAN,
+                -0.,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::MIN_POSITIVE / 2.,
+            ],
+            |v: f64| v.to_le_bytes().to_vec(),
+        );
+    }
+
+    #[test]
+    fn silence() {
+        assert_eq!(u8::SILENCE, 0x80);
+        assert_eq!(u16::SILENCE, 0x8000);
+        assert_eq!(u32::SILENCE, 0x8000_0000);
+        assert_eq!(u64::SILENCE, 0x8000_0000_0000_0000);
+        assert_eq!(i32::SILENCE, 0);
+        assert_eq!(f32::SILENCE.to_bits(), 0);
+        assert_eq!(f64::SILENCE.to_bits(), 0);
     }
 }
-
-impl SampleToBytes for i64 {
-    fn to_bytes(self, slice: &mut [u8]) {
-        *slice.as_mut_array().unwrap() = self.to_le_bytes();
-    }
-}
-
-impl SampleTypeSilence for i64 {
-    const SILENCE: Self = 0;
-}
-
-impl SampleSize for f32 {
-    const SIZE: num::NonZeroU8 = num::NonZeroU8::new(4).unwrap();
-}
-
-impl SampleFromBytes for f32 {
-    fn from_bytes(slice: &[u8]) -> Self {
-        Self::from_le_bytes(slice.try_into().unwrap())
-    }
-}
-
-impl SampleToBytes for f32 {
-    fn to_bytes(self, slice: &mut [u8]) {
-        *slice.as_mut_array().unwrap() = self.to_le_bytes();
-    }
-}
-
-impl SampleTypeSilence for f32 {
-    const SILENCE: Self = 0.;
-}
-
-impl SampleSize for f64 {
-    const SIZE: num::NonZeroU8 = num::NonZeroU8::new(8).unwrap();
-}
-
-impl SampleFromBytes for f64 {
-    fn from_bytes(slice: &[u8]) -> Self {
-        Self::from_le_bytes(slice.try_into().unwrap())
-    }
-}
-
-impl SampleToBytes for f64 {
-    fn to_bytes(self, slice: &mut [u8]) {
-        *slice.as_mut_array().unwrap() = self.to_le_bytes();
-    }
-}
-
-impl SampleTypeSilence for f64 {
-    const SILENCE: Self = 0.;
-}
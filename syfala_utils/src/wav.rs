@@ -0,0 +1,561 @@
+//! RIFF/WAVE file sinks and sources, for capturing and replaying streams offline.
+//!
+//! A [`WavFileSink`] can be placed at the end of a receiving pipeline to dump exactly
+//! what was reconstructed, and a [`WavFileSource`] can feed a recorded (or synthesized)
+//! signal into a sending pipeline.
+//!
+//! Only the canonical PCM layout is supported: 16-bit integer or 32-bit float samples,
+//! interleaved, with a single `data` chunk.
+
+use crate::{SampleFromBytes, SampleSink, SampleSource, SampleToBytes};
+
+use core::{iter, marker, num};
+use std::{
+    fs,
+    io::{self, Read, Seek, Write},
+    path,
+};
+
+/// Sample types that can be stored in a WAVE file.
+pub trait WavSample: SampleToBytes + SampleFromBytes {
+    /// Value of the `wFormatTag` field of the `fmt ` chunk.
+    const FORMAT_TAG: u16;
+}
+
+impl WavSample for i16 {
+    /// `WAVE_FORMAT_PCM`
+    const FORMAT_TAG: u16 = 1;
+}
+
+impl WavSample for f32 {
+    /// `WAVE_FORMAT_IEEE_FLOAT`
+    const FORMAT_TAG: u16 = 3;
+}
+
+/// Size of the canonical header, up to and including the `data` chunk's header.
+const HEADER_LEN: u32 = 44;
+
+/// Offset of the RIFF chunk's size field.
+const RIFF_SIZE_OFFSET: u64 = 4;
+
+/// Offset of the `data` chunk's size field.
+const DATA_SIZE_OFFSET: u64 = 40;
+
+/// Returns the `nBlockAlign` (frame size) and `nAvgBytesPerSec` fields for the given
+/// format, if representable.
+fn block_align_and_byte_rate<T: WavSample>(
+    n_channels: num::NonZeroU16,
+    sample_rate: num::NonZeroU32,
+) -> Option<(u16, u32)> {
+    let block_align = n_channels.get().checked_mul(T::SIZE.get().into())?;
+    let byte_rate = sample_rate.get().checked_mul(block_align.into())?;
+    Some((block_align, byte_rate))
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// [`SampleSink`] writing interleaved samples to a WAVE file, incrementally.
+///
+/// The header's size fields are patched when calling [`finish`](Self::finish), or,
+/// ignoring any error, when dropped.
+///
+/// Since [`SampleSink::consume_samples`] can't fail, the first I/O error encountered
+/// is stored, after which all samples are discarded. It is returned by `finish`.
+#[derive(Debug)]
+pub struct WavFileSink<T, W: Write + Seek = io::BufWriter<fs::File>> {
+    /// Always `Some`, until finished.
+    writer: Option<W>,
+    /// Number of bytes written to the `data` chunk.
+    n_data_bytes: u64,
+    error: Option<io::Error>,
+    _marker: marker::PhantomData<fn(T)>,
+}
+
+impl<T: WavSample> WavFileSink<T> {
+    /// Creates (or truncates) the file at `path`, and writes a WAVE header to it.
+    pub fn create(
+        path: impl AsRef<path::Path>,
+        n_channels: num::NonZeroU16,
+        sample_rate: num::NonZeroU32,
+    ) -> io::Result<Self> {
+        Self::new(
+            io::BufWriter::new(fs::File::create(path)?),
+            n_channels,
+            sample_rate,
+        )
+    }
+}
+
+impl<T: WavSample, W: Write + Seek> WavFileSink<T, W> {
+    /// Writes a WAVE header to `writer`, at its current position, and returns a sink
+    /// writing samples after it.
+    pub fn new(
+        mut writer: W,
+        n_channels: num::NonZeroU16,
+        sample_rate: num::NonZeroU32,
+    ) -> io::Result<Self> {
+        let (block_align, byte_rate) = block_align_and_byte_rate::<T>(n_channels, sample_rate)
+            .ok_or_else(|| invalid_data("channel count or sample rate too large"))?;
+
+        let bits_per_sample = u16::from(T::SIZE.get()).strict_mul(8);
+
+        let mut header = [0; HEADER_LEN as usize];
+        let mut cursor = io::Cursor::new(header.as_mut_slice());
+
+        cursor.write_all(b"RIFF")?;
+        // patched later
+        cursor.write_all(&(HEADER_LEN - 8).to_le_bytes())?;
+        cursor.write_all(b"WAVE")?;
+        cursor.write_all(b"fmt ")?;
+        cursor.write_all(&16u32.to_le_bytes())?;
+        cursor.write_all(&T::FORMAT_TAG.to_le_bytes())?;
+        cursor.write_all(&n_channels.get().to_le_bytes())?;
+        cursor.write_all(&sample_rate.get().to_le_bytes())?;
+        cursor.write_all(&byte_rate.to_le_bytes())?;
+        cursor.write_all(&block_align.to_le_bytes())?;
+        cursor.write_all(&bits_per_sample.to_le_bytes())?;
+        cursor.write_all(b"data")?;
+        // patched later
+        cursor.write_all(&0u32.to_le_bytes())?;
+
+        writer.write_all(&header)?;
+
+        Ok(Self {
+            writer: Some(writer),
+            n_data_bytes: 0,
+            error: None,
+            _marker: marker::PhantomData,
+        })
+    }
+
+    /// Returns the number of samples written so far.
+    #[inline(always)]
+    pub fn n_samples(&self) -> u64 {
+        self.n_data_bytes / num::NonZeroU64::from(T::SIZE)
+    }
+
+    /// Returns the first I/O error encountered while writing samples, if any.
+    #[inline(always)]
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    /// Patches the header's size fields, flushes the writer, and returns it.
+    ///
+    /// Returns the first error encountered while writing samples, if any.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.finish_inner()?;
+        Ok(self.writer.take().unwrap())
+    }
+
+    fn finish_inner(&mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+
+        patch_sizes(self.writer.as_mut().unwrap(), self.n_data_bytes)
+    }
+}
+
+/// Patches the size fields of a header written `n_data_bytes` before the writer's
+/// current position, and flushes the writer.
+fn patch_sizes(writer: &mut (impl Write + Seek), n_data_bytes: u64) -> io::Result<()> {
+    let data_size = u32::try_from(n_data_bytes)
+        .ok()
+        .filter(|&n| n <= u32::MAX - HEADER_LEN)
+        .ok_or_else(|| invalid_data("data too large for a WAVE file"))?;
+
+    let end = writer.stream_position()?;
+    let start = end.strict_sub(n_data_bytes.strict_add(HEADER_LEN.into()));
+
+    writer.seek(io::SeekFrom::Start(start.strict_add(RIFF_SIZE_OFFSET)))?;
+    writer.write_all(&(HEADER_LEN - 8).strict_add(data_size).to_le_bytes())?;
+    writer.seek(io::SeekFrom::Start(start.strict_add(DATA_SIZE_OFFSET)))?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    writer.seek(io::SeekFrom::Start(end))?;
+
+    writer.flush()
+}
+
+impl<T: WavSample, W: Write + Seek> SampleSink for WavFileSink<T, W> {
+    type Sample = T;
+
+    fn consume_samples(&mut self, spls: impl IntoIterator<Item = Self::Sample>) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let writer = self.writer.as_mut().unwrap();
+        let mut bytes = [0; 8];
+        let bytes = &mut bytes[..usize::from(T::SIZE.get())];
+
+        for spl in spls {
+            spl.to_bytes(bytes);
+
+            if let Err(e) = writer.write_all(bytes) {
+                self.error = Some(e);
+                return;
+            }
+
+            self.n_data_bytes = self.n_data_bytes.strict_add(T::SIZE.get().into());
+        }
+    }
+}
+
+impl<T, W: Write + Seek> Drop for WavFileSink<T, W> {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            let _ = patch_sizes(writer, self.n_data_bytes);
+        }
+    }
+}
+
+/// [`SampleSource`] streaming interleaved samples from a WAVE file.
+///
+/// Each call to [`get_samples`](SampleSource::get_samples) yields up to
+/// [`chunk_len`](Self::with_chunk_len) frames (all remaining samples, by default).
+///
+/// Since [`SampleSource::get_samples`] can't fail, the first I/O error encountered
+/// is stored, after which no samples are yielded. See [`error`](Self::error).
+#[derive(Debug)]
+pub struct WavFileSource<T, R: Read = io::BufReader<fs::File>> {
+    reader: R,
+    n_channels: num::NonZeroU16,
+    sample_rate: num::NonZeroU32,
+    /// Number of bytes left in the `data` chunk.
+    n_remaining_bytes: u64,
+    /// Maximum number of frames yielded per call to `get_samples`.
+    chunk_len: Option<num::NonZeroUsize>,
+    error: Option<io::Error>,
+    _marker: marker::PhantomData<fn() -> T>,
+}
+
+impl<T: WavSample> WavFileSource<T> {
+    /// Opens the WAVE file at `path`, and parses its header.
+    pub fn open(path: impl AsRef<path::Path>) -> io::Result<Self> {
+        Self::new(io::BufReader::new(fs::File::open(path)?))
+    }
+}
+
+impl<T: WavSample, R: Read> WavFileSource<T, R> {
+    /// Parses a WAVE header from `reader`, and returns a source reading the samples
+    /// following it.
+    ///
+    /// Fails if the file's sample format doesn't match `T`.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut buf = [0; 12];
+        reader.read_exact(&mut buf)?;
+
+        if &buf[0..4] != b"RIFF" || &buf[8..12] != b"WAVE" {
+            return Err(invalid_data("not a RIFF/WAVE file"));
+        }
+
+        let mut format = None;
+
+        loop {
+            let mut chunk_header = [0; 8];
+            reader.read_exact(&mut chunk_header)?;
+
+            let (id, len) = chunk_header.split_at(4);
+            let len = u32::from_le_bytes(len.try_into().unwrap());
+
+            match id {
+                b"fmt " => {
+                    if len < 16 {
+                        return Err(invalid_data("fmt chunk too short"));
+                    }
+
+                    let mut fmt = [0; 16];
+                    reader.read_exact(&mut fmt)?;
+                    skip(&mut reader, u64::from(len - 16) + u64::from(len % 2))?;
+
+                    let u16_at = |i: usize| u16::from_le_bytes([fmt[i], fmt[i + 1]]);
+
+                    let format_tag = u16_at(0);
+                    let bits_per_sample = u16_at(14);
+
+                    if format_tag != T::FORMAT_TAG
+                        || bits_per_sample != u16::from(T::SIZE.get()).strict_mul(8)
+                    {
+                        return Err(invalid_data("sample format mismatch"));
+                    }
+
+                    let n_channels = num::NonZeroU16::new(u16_at(2))
+                        .ok_or_else(|| invalid_data("zero channels"))?;
+                    let sample_rate =
+                        num::NonZeroU32::new(u32::from_le_bytes(fmt[4..8].try_into().unwrap()))
+                            .ok_or_else(|| invalid_data("zero sample rate"))?;
+
+                    format = Some((n_channels, sample_rate));
+                }
+                b"data" => {
+                    let (n_channels, sample_rate) =
+                        format.ok_or_else(|| invalid_data("data chunk before fmt chunk"))?;
+
+                    return Ok(Self {
+                        reader,
+                        n_channels,
+                        sample_rate,
+                        n_remaining_bytes: len.into(),
+                        chunk_len: None,
+                        error: None,
+                        _marker: marker::PhantomData,
+                    });
+                }
+                // chunks are padded to an even length
+                _ => skip(&mut reader, u64::from(len) + u64::from(len % 2))?,
+            }
+        }
+    }
+
+    /// Limits the number of frames yielded per call to
+    /// [`get_samples`](SampleSource::get_samples) to `chunk_len`.
+    #[inline(always)]
+    pub fn with_chunk_len(mut self, chunk_len: num::NonZeroUsize) -> Self {
+        self.chunk_len = Some(chunk_len);
+        self
+    }
+
+    /// Returns the number of interleaved channels.
+    #[inline(always)]
+    pub fn n_channels(&self) -> num::NonZeroU16 {
+        self.n_channels
+    }
+
+    /// Returns the sample rate, in Hz.
+    #[inline(always)]
+    pub fn sample_rate(&self) -> num::NonZeroU32 {
+        self.sample_rate
+    }
+
+    /// Returns the number of samples left to read.
+    #[inline(always)]
+    pub fn n_remaining_samples(&self) -> u64 {
+        self.n_remaining_bytes / num::NonZeroU64::from(T::SIZE)
+    }
+
+    /// Returns the first I/O error encountered while reading samples, if any.
+    #[inline(always)]
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+}
+
+/// Discards the next `n` bytes of `reader`.
+fn skip(reader: &mut impl Read, n: u64) -> io::Result<()> {
+    let n_skipped = io::copy(&mut reader.take(n), &mut io::sink())?;
+
+    if n_skipped != n {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(())
+}
+
+impl<T: WavSample, R: Read> SampleSource for WavFileSource<T, R> {
+    type Sample = T;
+
+    fn get_samples(&mut self) -> impl IntoIterator<Item = Self::Sample> {
+        let max_samples = self.chunk_len.map_or(usize::MAX, |n| {
+            n.get().saturating_mul(self.n_channels.get().into())
+        });
+
+        let sample_size = usize::from(T::SIZE.get());
+
+        iter::from_fn(move || {
+            if self.error.is_some() || self.n_remaining_samples() == 0 {
+                return None;
+            }
+
+            let mut bytes = [0; 8];
+            let bytes = &mut bytes[..sample_size];
+
+            if let Err(e) = self.reader.read_exact(bytes) {
+                self.error = Some(e);
+                return None;
+            }
+
+            self.n_remaining_bytes = self.n_remaining_bytes.strict_sub(T::SIZE.get().into());
+
+            Some(T::from_bytes(bytes))
+        })
+        .take(max_samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn nz_channels(n: u16) -> num::NonZeroU16 {
+        num::NonZeroU16::new(n).unwrap()
+    }
+
+    fn nz_rate(n: u32) -> num::NonZeroU32 {
+        num::NonZeroU32::new(n).unwrap()
+    }
+
+    /// Writes `samples` to an in-memory file, in chunks of `chunk_len` samples.
+    fn write<T: WavSample + Copy>(samples: &[T], n_channels: u16, chunk_len: usize) -> Vec<u8> {
+        let writer = io::Cursor::new(Vec::new());
+        let mut sink =
+            WavFileSink::<T, _>::new(writer, nz_channels(n_channels), nz_rate(48000)).unwrap();
+
+        for chunk in samples.chunks(chunk_len) {
+            sink.consume_samples(chunk.iter().copied());
+        }
+
+        assert_eq!(sink.n_samples(), samples.len() as u64);
+        sink.finish().unwrap().into_inner()
+    }
+
+    /// Reads all samples of an in-memory file, in chunks of `chunk_len` frames.
+    fn read<T: WavSample>(file: &[u8], n_channels: u16, chunk_len: usize) -> Vec<T> {
+        let mut source = WavFileSource::<T, _>::new(file)
+            .unwrap()
+            .with_chunk_len(num::NonZeroUsize::new(chunk_len).unwrap());
+
+        assert_eq!(source.n_channels(), nz_channels(n_channels));
+        assert_eq!(source.sample_rate(), nz_rate(48000));
+
+        let mut samples = Vec::new();
+
+        loop {
+            let n_remaining = source.n_remaining_samples();
+            let len = samples.len();
+            samples.extend(source.get_samples());
+
+            let n_read = samples.len() - len;
+            let max_read = (chunk_len * usize::from(n_channels)) as u64;
+            assert_eq!(n_read as u64, n_remaining.min(max_read));
+
+            if n_read == 0 {
+                break;
+            }
+        }
+
+        assert!(source.error().is_none());
+        samples
+    }
+
+    /// Compares floats bit-wise, so that `NaN`s (and their payloads) are compared too.
+    fn bits(samples: &[f32]) -> Vec<u32> {
+        samples.iter().map(|s| s.to_bits()).collect()
+    }
+
+    /// Checks the header's fields, assuming the samples span the rest of the file.
+    fn check_header(file: &[u8], format_tag: u16, n_channels: u16, sample_size: u16) {
+        let u16_at = |i: usize| u16::from_le_bytes(file[i..i + 2].try_into().unwrap());
+        let u32_at = |i: usize| u32::from_le_bytes(file[i..i + 4].try_into().unwrap());
+
+        let data_len = u32::try_from(file.len()).unwrap() - HEADER_LEN;
+        let block_align = n_channels * sample_size;
+
+        assert_eq!(&file[0..4], b"RIFF");
+        assert_eq!(u32_at(4), 36 + data_len);
+        assert_eq!(&file[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(16), 16);
+        assert_eq!(u16_at(20), format_tag);
+        assert_eq!(u16_at(22), n_channels);
+        assert_eq!(u32_at(24), 48000);
+        assert_eq!(u32_at(28), 48000 * u32::from(block_align));
+        assert_eq!(u16_at(32), block_align);
+        assert_eq!(u16_at(34), sample_size * 8);
+        assert_eq!(&file[36..40], b"data");
+        assert_eq!(u32_at(40), data_len);
+    }
+
+    #[test]
+    fn round_trip_i16() {
+        // a stereo ramp, covering the whole range, including both extremes
+        let ramp = (i16::MIN..=i16::MAX).step_by(97);
+        let samples: Vec<i16> = ramp.chain([i16::MAX]).collect();
+        let samples = &samples[..samples.len() / 2 * 2];
+
+        let file = write(samples, 2, 13);
+        check_header(&file, 1, 2, 2);
+
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        assert_eq!(&file[HEADER_LEN as usize..], data);
+
+        assert_eq!(read::<i16>(&file, 2, 7), samples);
+    }
+
+    #[test]
+    fn round_trip_f32() {
+        let samples = [
+            0.,
+            -0.,
+            1.,
+            -1.,
+            0.5,
+            f32::MIN_POSITIVE,
+            f32::from_bits(1),
+            f32::MAX,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NAN,
+            f32::from_bits(0x7fc0_1234),
+        ];
+
+        let file = write(&samples, 3, 5);
+        check_header(&file, 3, 3, 4);
+
+        assert_eq!(bits(&read::<f32>(&file, 3, 1)), bits(&samples));
+    }
+
+    #[test]
+    fn drop_patches_header() {
+        let samples = [1i16, 2, 3, 4, 5, 6];
+        let mut writer = io::Cursor::new(Vec::new());
+
+        {
+            let mut sink =
+                WavFileSink::<i16, _>::new(&mut writer, nz_channels(1), nz_rate(48000)).unwrap();
+            sink.consume_samples(samples);
+        }
+
+        let file = writer.into_inner();
+        check_header(&file, 1, 1, 2);
+        assert_eq!(read::<i16>(&file, 1, 4), samples);
+    }
+
+    #[test]
+    fn round_trip_file() {
+        let path = std::env::temp_dir().join(format!("syfala_wav_{}.wav", std::process::id()));
+        let samples: Vec<f32> = (0..4800).map(|i| (i as f32 * 0.01).sin()).collect();
+
+        let mut sink = WavFileSink::<f32>::create(&path, nz_channels(2), nz_rate(44100)).unwrap();
+        sink.consume_samples(samples.iter().copied());
+        sink.finish().unwrap();
+
+        let mut source = WavFileSource::<f32>::open(&path).unwrap();
+        assert_eq!(source.sample_rate(), nz_rate(44100));
+        let read: Vec<f32> = source.get_samples().into_iter().collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(bits(&read), bits(&samples));
+    }
+
+    #[test]
+    fn skip_unknown_chunks() {
+        let mut file = write(&[1i16, -1], 1, 2);
+
+        // odd length chunk, padded to an even length, inserted before the `data` chunk
+        let chunk = b"LIST\x03\x00\x00\x00abc\x00";
+        file.splice(36..36, chunk.iter().copied());
+
+        assert_eq!(read::<i16>(&file, 1, 1), [1, -1]);
+    }
+
+    #[test]
+    fn format_mismatch() {
+        let file = write(&[1i16, -1], 1, 2);
+
+        let err = WavFileSource::<f32, _>::new(file.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}